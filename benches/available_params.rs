@@ -0,0 +1,115 @@
+//! Reads the parameter lists of an axis with fixed lists through the owned
+//! accessors, bridged by the trait's defaults, and through borrowing
+//! overrides, as `get_available_params` and attribute reads hit them. Run
+//! with `cargo bench --bench available_params`.
+
+use std::borrow::Cow;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use futures::executor::block_on;
+use motarem::axis::{
+    movement_parameters::MovementParams, state::AxisState, state_info::AxisStateInfo, Axis,
+    DEFAULT_MOVEMENT_PARAMS,
+};
+
+const PARAMS: &[&str] = &[
+    "position",
+    "min_position",
+    "max_position",
+    "velocity",
+    "acceleration",
+    "deceleration",
+    "following_error",
+    "temperature",
+];
+
+/// Lists its parameters the way implementations written before the
+/// borrowing accessors do.
+struct OwnedAxis;
+
+#[async_trait::async_trait]
+impl Axis for OwnedAxis {
+    fn name(&self) -> &str {
+        "X"
+    }
+
+    async fn start(&self, _target: f64, _params: Option<MovementParams>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn get_state(&self) -> anyhow::Result<AxisStateInfo> {
+        Ok(AxisStateInfo::new(AxisState::On))
+    }
+
+    async fn get_attribute(&self, _name: &str) -> anyhow::Result<f64> {
+        Ok(0.0)
+    }
+
+    async fn get_available_params(&self) -> anyhow::Result<Vec<String>> {
+        Ok(PARAMS.iter().map(|p| p.to_string()).collect())
+    }
+}
+
+struct BorrowedAxis;
+
+#[async_trait::async_trait]
+impl Axis for BorrowedAxis {
+    fn name(&self) -> &str {
+        "X"
+    }
+
+    async fn start(&self, _target: f64, _params: Option<MovementParams>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn get_state(&self) -> anyhow::Result<AxisStateInfo> {
+        Ok(AxisStateInfo::new(AxisState::On))
+    }
+
+    async fn get_attribute(&self, _name: &str) -> anyhow::Result<f64> {
+        Ok(0.0)
+    }
+
+    async fn available_params(&self) -> anyhow::Result<Vec<Cow<'static, str>>> {
+        Ok(PARAMS.iter().copied().map(Cow::Borrowed).collect())
+    }
+
+    async fn supported_movement_params(&self) -> anyhow::Result<Vec<Cow<'static, str>>> {
+        Ok(DEFAULT_MOVEMENT_PARAMS
+            .iter()
+            .copied()
+            .map(Cow::Borrowed)
+            .collect())
+    }
+}
+
+fn parameter_lists(c: &mut Criterion) {
+    let mut group = c.benchmark_group("available_params");
+    group.bench_function("owned", |b| {
+        b.iter(|| block_on(black_box(&OwnedAxis).available_params()).unwrap())
+    });
+    group.bench_function("borrowed", |b| {
+        b.iter(|| block_on(black_box(&BorrowedAxis).available_params()).unwrap())
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("supported_movement_params");
+    group.bench_function("owned", |b| {
+        b.iter(|| block_on(black_box(&OwnedAxis).supported_movement_params()).unwrap())
+    });
+    group.bench_function("borrowed", |b| {
+        b.iter(|| block_on(black_box(&BorrowedAxis).supported_movement_params()).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parameter_lists);
+criterion_main!(benches);
@@ -1,14 +1,29 @@
 use anyhow::Result;
 use motarem::{
     axis::{
-        movement_parameters::MovementParams, state::AxisState, state_info::AxisStateInfo, Axis,
+        attribute_info::AttributeInfo,
+        attribute_value::AttributeValue,
+        capabilities::AxisCapabilities,
+        limit_switches::{LimitSwitches, LimitTripped},
+        limits::LimitDirection,
+        movement_parameters::MovementParams,
+        state::AxisState,
+        state_info::AxisStateInfo,
+        stop_mode::StopMode,
+        Axis,
     },
-    controller_manager::{command::Command, config::ManagerConfig, ControllerManager},
-    motor_controller::MotorController,
+    controller_manager::{config::ManagerConfig, ControllerManager},
+    motor_controller::{diagnostics::DiagnosticsReport, MotorController},
     socket_server::{config::SocketServerConfig, SocketServer},
 };
-use std::{sync::Arc, time::Duration};
-use tokio::sync::oneshot;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use tracing::{error, info};
 
 struct MockController {
@@ -42,6 +57,30 @@ impl MotorController for MockController {
         Ok(())
     }
 
+    /// Checks that every axis answers a state query.
+    async fn self_test(&self) -> Result<DiagnosticsReport> {
+        let mut report = DiagnosticsReport::new();
+        for axis in &self.axes {
+            let check = format!("{}.get_state", axis.name());
+            match axis.get_state().await {
+                Ok(state) => report.pass(check, Some(format!("{:?}", state.state).to_lowercase())),
+                Err(e) => report.fail(check, e.to_string()),
+            }
+        }
+        Ok(report)
+    }
+
+    async fn get_controller_attribute(&self, name: &str) -> Result<AttributeValue> {
+        match name {
+            "firmware_version" => Ok(AttributeValue::Text(env!("CARGO_PKG_VERSION").to_string())),
+            _ => Err(anyhow::anyhow!(
+                "Controller attribute not supported by {}: {}",
+                self.name,
+                name
+            )),
+        }
+    }
+
     async fn start(&self, axis: &str, target: f64, params: Option<MovementParams>) -> Result<()> {
         let ax = self.get_axis(axis)?;
         ax.start(target, params).await
@@ -64,15 +103,44 @@ impl MotorController for MockController {
 }
 
 // Example implementation of a mock axis with configurable capabilities
+/// Holds `MockAxis::decelerating` set for the life of a decelerating stop.
+/// Clearing it on drop means a stop that fails or is abandoned part way
+/// (e.g. by a command timeout) can't leave later moves ending at once.
+struct Decelerating<'a>(&'a AtomicBool);
+
+impl<'a> Decelerating<'a> {
+    fn start(flag: &'a AtomicBool) -> Self {
+        flag.store(true, Ordering::SeqCst);
+        Self(flag)
+    }
+}
+
+impl Drop for Decelerating<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
 struct MockAxis {
     name: String,
     position: tokio::sync::RwLock<f64>,
     state: tokio::sync::RwLock<AxisState>,
+    limit_switches: tokio::sync::RwLock<LimitSwitches>,
+    /// Why the axis is faulted, while it is.
+    fault: tokio::sync::RwLock<Option<String>>,
+    /// Start and target of the move in progress, for progress reporting.
+    current_move: tokio::sync::RwLock<Option<(f64, f64)>>,
+    /// Velocity of the move in progress.
+    current_velocity: tokio::sync::RwLock<f64>,
+    /// Set while a decelerating stop winds the move down.
+    decelerating: AtomicBool,
     supports_acceleration: bool,
     velocity: f64,
+    max_velocity: f64,
     acceleration: f64,
     max_position: f64,
     min_position: f64,
+    position_tolerance: f64,
 }
 
 impl MockAxis {
@@ -81,12 +149,44 @@ impl MockAxis {
             name,
             position: tokio::sync::RwLock::new(0.0),
             state: tokio::sync::RwLock::new(AxisState::On),
+            limit_switches: tokio::sync::RwLock::new(LimitSwitches::None),
+            fault: tokio::sync::RwLock::new(None),
+            current_move: tokio::sync::RwLock::new(None),
+            current_velocity: tokio::sync::RwLock::new(0.0),
+            decelerating: AtomicBool::new(false),
             supports_acceleration,
             velocity: 100.0,
+            max_velocity: 1000.0,
             acceleration: 1000.0,
             max_position: 1000.0,
             min_position: -1000.0,
+            position_tolerance: 0.01,
+        }
+    }
+}
+
+impl MockAxis {
+    /// Simulates the stage ringing around `end` after arriving, with the
+    /// overshoot halving every step. The axis reports `Settling` until two
+    /// readings in a row are within tolerance. Returns false if the move
+    /// was interrupted meanwhile.
+    async fn settle(&self, end: f64, step: Duration) -> bool {
+        *self.state.write().await = AxisState::Settling;
+        let mut overshoot = self.position_tolerance * 8.0;
+        let mut stable_readings = 0;
+        while stable_readings < 2 {
+            tokio::time::sleep(step).await;
+            if *self.state.read().await != AxisState::Settling {
+                return false;
+            }
+            *self.position.write().await = end + overshoot;
+            if overshoot.abs() <= self.position_tolerance {
+                stable_readings += 1;
+            }
+            overshoot *= -0.5;
         }
+        *self.position.write().await = end;
+        true
     }
 }
 
@@ -96,6 +196,15 @@ impl Axis for MockAxis {
         &self.name
     }
 
+    fn validate_custom_params(&self, custom: &HashMap<String, f64>) -> Result<(), String> {
+        match custom.get("dwell") {
+            Some(dwell) if *dwell < 0.0 => {
+                Err(format!("dwell must not be negative, got {}", dwell))
+            }
+            _ => Ok(()),
+        }
+    }
+
     async fn start(&self, target: f64, params: Option<MovementParams>) -> Result<()> {
         if let Some(ref params) = params {
             info!(
@@ -118,44 +227,262 @@ impl Axis for MockAxis {
             }
         }
 
-        *self.position.write().await = target;
-        *self.state.write().await = AxisState::Moving;
+        match *self.state.read().await {
+            AxisState::Disabled => return Err(anyhow::anyhow!("Axis {} is disabled", self.name)),
+            AxisState::Fault => return Err(anyhow::anyhow!("Axis {} is faulted", self.name)),
+            _ => {}
+        }
+
+        let velocity = params
+            .as_ref()
+            .and_then(|params| params.velocity)
+            .unwrap_or(self.velocity);
+        // Asking for more than the drive can do faults it, as a real drive
+        // would on a following error; it stays faulted until reset.
+        if velocity > self.max_velocity {
+            let message = format!(
+                "Velocity {} exceeds the maximum of {}",
+                velocity, self.max_velocity
+            );
+            *self.fault.write().await = Some(message.clone());
+            *self.state.write().await = AxisState::Fault;
+            return Err(anyhow::anyhow!("Axis {} faulted: {}", self.name, message));
+        }
 
-        let movement_time = if let Some(ref params) = params {
-            let velocity = params.velocity.unwrap_or(self.velocity);
-            let current_pos = *self.position.read().await;
-            let distance = (target - current_pos).abs();
-            (distance / velocity * 1000.0) as u64
+        // The limit switches sit at the soft limits, so a target beyond them
+        // ends the move on the switch.
+        let (end, limit) = if target > self.max_position {
+            (self.max_position, Some(LimitSwitches::Upper))
+        } else if target < self.min_position {
+            (self.min_position, Some(LimitSwitches::Lower))
         } else {
-            1000
+            (target, None)
         };
 
-        tokio::time::sleep(Duration::from_millis(movement_time)).await;
+        *self.state.write().await = AxisState::Moving;
+        *self.limit_switches.write().await = LimitSwitches::None;
+        *self.current_move.write().await = Some((*self.position.read().await, end));
+        *self.current_velocity.write().await = velocity;
+
+        // Walk the position towards the target in small steps so the move
+        // can be observed, paused and stopped while it is in progress.
+        let step = Duration::from_millis(20);
+        let step_distance = velocity * step.as_secs_f64();
+        loop {
+            tokio::time::sleep(step).await;
+            if self.decelerating.load(Ordering::SeqCst) {
+                // stop_with has taken the move over.
+                return Ok(());
+            }
+            match *self.state.read().await {
+                AxisState::Paused => continue,
+                AxisState::Moving => {}
+                _ => {
+                    *self.current_move.write().await = None;
+                    return Ok(());
+                }
+            }
+
+            let mut position = self.position.write().await;
+            let remaining = end - *position;
+            if remaining.abs() <= step_distance {
+                *position = end;
+                break;
+            }
+            *position += step_distance * remaining.signum();
+        }
+
+        *self.current_move.write().await = None;
+        if limit.is_none() && !self.settle(end, step).await {
+            return Ok(());
+        }
+        // An optional pause at the target before reporting the move done,
+        // e.g. to let a measurement finish.
+        if let Some(dwell) = params
+            .as_ref()
+            .and_then(|params| params.custom_get("dwell"))
+        {
+            tokio::time::sleep(Duration::from_secs_f64(dwell)).await;
+        }
         *self.state.write().await = AxisState::On;
+        if let Some(switches) = limit {
+            info!(
+                "Axis {} tripped its {} limit switch",
+                self.name,
+                switches.side()
+            );
+            *self.limit_switches.write().await = switches;
+            return Err(LimitTripped { switches }.into());
+        }
         Ok(())
     }
 
+    fn position_tolerance(&self) -> Option<f64> {
+        Some(self.position_tolerance)
+    }
+
+    async fn get_progress(&self) -> Option<f64> {
+        let (from, to) = (*self.current_move.read().await)?;
+        if from == to {
+            return Some(1.0);
+        }
+        let position = *self.position.read().await;
+        Some(((position - from) / (to - from)).clamp(0.0, 1.0))
+    }
+
+    /// The simulated switches sit exactly at the soft limits.
+    async fn move_to_limit(
+        &self,
+        direction: LimitDirection,
+        params: Option<MovementParams>,
+        zero_position: bool,
+    ) -> Result<LimitSwitches> {
+        let (end, switch) = match direction {
+            LimitDirection::Upper => (self.max_position, LimitSwitches::Upper),
+            LimitDirection::Lower => (self.min_position, LimitSwitches::Lower),
+        };
+        self.start(end, params).await?;
+
+        if *self.position.read().await != end {
+            return Err(anyhow::anyhow!(
+                "Axis {} stopped before reaching its {:?} limit",
+                self.name,
+                direction
+            ));
+        }
+
+        info!(
+            "Axis {} tripped its {:?} limit switch",
+            self.name, direction
+        );
+        *self.limit_switches.write().await = switch;
+        if zero_position {
+            *self.position.write().await = 0.0;
+        }
+        Ok(switch)
+    }
+
     async fn stop(&self) -> Result<()> {
         info!("Stopping axis {}", self.name);
         *self.state.write().await = AxisState::On;
         Ok(())
     }
 
+    /// Ramps the velocity down to zero, walking the position on in the
+    /// direction of travel, then stops. Only a moving axis decelerates.
+    async fn stop_with(&self, mode: StopMode) -> Result<()> {
+        let StopMode::Decelerate { rate } = mode else {
+            return self.stop().await;
+        };
+        let current_move = *self.current_move.read().await;
+        let Some((from, to)) = current_move else {
+            return self.stop().await;
+        };
+        if *self.state.read().await != AxisState::Moving {
+            return self.stop().await;
+        }
+
+        let rate = rate.unwrap_or(self.acceleration);
+        info!("Decelerating axis {} at {}", self.name, rate);
+        let _decelerating = Decelerating::start(&self.decelerating);
+        let direction = (to - from).signum();
+        let step = Duration::from_millis(20);
+        let mut velocity = *self.current_velocity.read().await;
+        while velocity > 0.0 {
+            tokio::time::sleep(step).await;
+            velocity = (velocity - rate * step.as_secs_f64()).max(0.0);
+            let mut position = self.position.write().await;
+            *position = (*position + direction * velocity * step.as_secs_f64())
+                .clamp(self.min_position, self.max_position);
+        }
+
+        *self.current_move.write().await = None;
+        self.stop().await
+    }
+
+    async fn pause(&self) -> Result<()> {
+        let mut state = self.state.write().await;
+        if *state != AxisState::Moving {
+            return Err(anyhow::anyhow!("Axis {} is not moving", self.name));
+        }
+        info!("Pausing axis {}", self.name);
+        *state = AxisState::Paused;
+        Ok(())
+    }
+
+    async fn resume(&self) -> Result<()> {
+        let mut state = self.state.write().await;
+        if *state != AxisState::Paused {
+            return Err(anyhow::anyhow!("Axis {} is not paused", self.name));
+        }
+        info!("Resuming axis {}", self.name);
+        *state = AxisState::Moving;
+        Ok(())
+    }
+
+    async fn enable(&self) -> Result<()> {
+        let mut state = self.state.write().await;
+        if *state == AxisState::Disabled {
+            info!("Enabling axis {}", self.name);
+            *state = AxisState::On;
+        }
+        Ok(())
+    }
+
+    async fn disable(&self) -> Result<()> {
+        info!("Disabling axis {}", self.name);
+        // Any move in progress notices the state change and stops walking.
+        *self.state.write().await = AxisState::Disabled;
+        Ok(())
+    }
+
+    async fn reset_fault(&self) -> Result<()> {
+        let mut state = self.state.write().await;
+        if *state == AxisState::Fault {
+            info!("Clearing fault on axis {}", self.name);
+            *state = AxisState::On;
+            *self.fault.write().await = None;
+        }
+        Ok(())
+    }
+
+    async fn set_position_reference(&self, value: f64) -> Result<()> {
+        if *self.state.read().await == AxisState::Moving {
+            return Err(anyhow::anyhow!("Axis {} is moving", self.name));
+        }
+        info!("Axis {} position redefined as {}", self.name, value);
+        *self.position.write().await = value;
+        Ok(())
+    }
+
+    async fn capabilities(&self) -> Result<AxisCapabilities> {
+        Ok(AxisCapabilities::query(self).await?.with_pause())
+    }
+
     async fn get_position(&self) -> Result<f64> {
         Ok(*self.position.read().await)
     }
 
     async fn get_state(&self) -> Result<AxisStateInfo> {
         let state = *self.state.read().await;
-        Ok(AxisStateInfo::new(state))
+        let mut info =
+            AxisStateInfo::new(state).with_limit_switches(*self.limit_switches.read().await);
+        if let Some(fault) = self.fault.read().await.clone() {
+            info = info.with_message(fault);
+        }
+        Ok(info)
     }
 
     async fn get_attribute(&self, name: &str) -> Result<f64> {
+        self.get_attribute_value(name).await?.to_f64()
+    }
+
+    async fn get_attribute_value(&self, name: &str) -> Result<AttributeValue> {
         match name {
-            "velocity" => Ok(self.velocity),
+            "velocity" => Ok(self.velocity.into()),
             "acceleration" => {
                 if self.supports_acceleration {
-                    Ok(self.acceleration)
+                    Ok(self.acceleration.into())
                 } else {
                     Err(anyhow::anyhow!(
                         "Parameter '{}' not supported by axis {}",
@@ -164,14 +491,34 @@ impl Axis for MockAxis {
                     ))
                 }
             }
-            "max_position" => Ok(self.max_position),
-            "min_position" => Ok(self.min_position),
-            "position" => self.get_position().await,
-            "supports_acceleration" => Ok(if self.supports_acceleration { 1.0 } else { 0.0 }),
+            "max_position" => Ok(self.max_position.into()),
+            "min_position" => Ok(self.min_position.into()),
+            "position" => Ok(self.get_position().await?.into()),
+            "supports_acceleration" => Ok(self.supports_acceleration.into()),
+            "firmware_version" => Ok("mock-1.0".into()),
             _ => Err(anyhow::anyhow!("Unknown parameter: {}", name)),
         }
     }
 
+    async fn attribute_info(&self, name: &str) -> Result<AttributeInfo> {
+        let range = (self.min_position, self.max_position);
+        Ok(match name {
+            "velocity" => AttributeInfo::new(name)
+                .with_unit("mm/s")
+                .with_range(0.0, self.max_velocity)
+                .writable(),
+            "acceleration" => AttributeInfo::new(name)
+                .with_unit("mm/s^2")
+                .with_range(0.0, 10000.0)
+                .writable(),
+            "position" => AttributeInfo::new(name)
+                .with_unit("mm")
+                .with_range(range.0, range.1),
+            "min_position" | "max_position" => AttributeInfo::new(name).with_unit("mm"),
+            _ => AttributeInfo::new(name),
+        })
+    }
+
     async fn get_available_params(&self) -> Result<Vec<String>> {
         let mut params = vec![
             "velocity".to_string(),
@@ -179,6 +526,7 @@ impl Axis for MockAxis {
             "min_position".to_string(),
             "position".to_string(),
             "supports_acceleration".to_string(),
+            "firmware_version".to_string(),
         ];
 
         if self.supports_acceleration {
@@ -189,7 +537,7 @@ impl Axis for MockAxis {
     }
 
     async fn get_supported_movement_params(&self) -> Result<Vec<String>> {
-        let mut params = vec!["velocity".to_string()];
+        let mut params = vec!["velocity".to_string(), "dwell".to_string()];
 
         if self.supports_acceleration {
             params.push("acceleration".to_string());
@@ -212,6 +560,7 @@ async fn main() -> Result<()> {
     let config = ManagerConfig {
         default_ttl: Duration::from_secs(5),
         cache_capacity: 1000,
+        ..Default::default()
     };
 
     let manager = Arc::new(ControllerManager::new(config));
@@ -227,6 +576,7 @@ async fn main() -> Result<()> {
         socket_path: "/tmp/motarem.sock".to_string(),
         max_connections: 50,
         buffer_size: 8192,
+        ..Default::default()
     };
 
     let mut socket_server = SocketServer::new(socket_config, manager.clone());
@@ -236,139 +586,73 @@ async fn main() -> Result<()> {
     info!("You can connect using the client examples or tools like socat:");
     info!("  socat - UNIX-CONNECT:/tmp/motarem.sock");
 
+    let handle = manager.handle();
+
     let movement_params = MovementParams::new()
         .with_velocity(150.0)
         .with_acceleration(2000.0);
-
-    let (tx, rx) = oneshot::channel();
-    let move_cmd = Command::Move {
-        controller: "mock_ctrl_1".to_string(),
-        axis: "X".to_string(),
-        target: 100.0,
-        params: Some(movement_params),
-        resp: tx,
-    };
-
-    manager.send_command(move_cmd).await?;
-    if let Ok(result) = rx.await {
-        match result {
-            Ok(value) => info!("X-axis move command result: {}", value),
-            Err(e) => error!("X-axis move command failed: {}", e),
-        }
+    match handle
+        .move_to("mock_ctrl_1", "X", 100.0, Some(movement_params))
+        .await
+    {
+        Ok(value) => info!("X-axis move command result: {}", value),
+        Err(e) => error!("X-axis move command failed: {}", e),
     }
 
     let movement_params = MovementParams::new()
         .with_velocity(120.0)
         .with_acceleration(1500.0);
-
-    let (tx, rx) = oneshot::channel();
-    let move_cmd = Command::Move {
-        controller: "mock_ctrl_1".to_string(),
-        axis: "Y".to_string(),
-        target: 50.0,
-        params: Some(movement_params),
-        resp: tx,
-    };
-
-    manager.send_command(move_cmd).await?;
-    if let Ok(result) = rx.await {
-        match result {
-            Ok(value) => info!("Y-axis move command result: {}", value),
-            Err(e) => error!("Y-axis move command failed: {}", e),
-        }
+    match handle
+        .move_to("mock_ctrl_1", "Y", 50.0, Some(movement_params))
+        .await
+    {
+        Ok(value) => info!("Y-axis move command result: {}", value),
+        Err(e) => error!("Y-axis move command failed: {}", e),
     }
 
     let movement_params = MovementParams::new()
         .with_velocity(80.0)
         .with_acceleration(500.0);
-
-    let (tx, rx) = oneshot::channel();
-    let move_cmd = Command::Move {
-        controller: "mock_ctrl_1".to_string(),
-        axis: "Z".to_string(),
-        target: 25.0,
-        params: Some(movement_params),
-        resp: tx,
-    };
-
-    manager.send_command(move_cmd).await?;
-    if let Ok(result) = rx.await {
-        match result {
-            Ok(value) => info!("Z-axis move command result: {}", value),
-            Err(e) => error!("Z-axis move command failed (expected): {}", e),
-        }
+    match handle
+        .move_to("mock_ctrl_1", "Z", 25.0, Some(movement_params))
+        .await
+    {
+        Ok(value) => info!("Z-axis move command result: {}", value),
+        Err(e) => error!("Z-axis move command failed (expected): {}", e),
     }
 
     let movement_params = MovementParams::new().with_velocity(80.0);
-
-    let (tx, rx) = oneshot::channel();
-    let move_cmd = Command::Move {
-        controller: "mock_ctrl_1".to_string(),
-        axis: "Z".to_string(),
-        target: 25.0,
-        params: Some(movement_params),
-        resp: tx,
-    };
-
-    manager.send_command(move_cmd).await?;
-    if let Ok(result) = rx.await {
-        match result {
-            Ok(value) => info!("Z-axis move command result: {}", value),
-            Err(e) => error!("Z-axis move command failed: {}", e),
-        }
+    match handle
+        .move_to("mock_ctrl_1", "Z", 25.0, Some(movement_params))
+        .await
+    {
+        Ok(value) => info!("Z-axis move command result: {}", value),
+        Err(e) => error!("Z-axis move command failed: {}", e),
     }
 
     for axis in ["X", "Y", "Z"] {
-        let (tx, rx) = oneshot::channel();
-        let params_cmd = Command::GetSupportedMovementParams {
-            controller: "mock_ctrl_1".to_string(),
-            axis: axis.to_string(),
-            resp: tx,
-        };
-
-        manager.send_command(params_cmd).await?;
-        if let Ok(result) = rx.await {
-            match result {
-                Ok(value) => info!("Supported movement parameters for {}: {}", axis, value),
-                Err(e) => error!(
-                    "Failed to get supported movement parameters for {}: {}",
-                    axis, e
-                ),
-            }
+        match handle
+            .get_supported_movement_params("mock_ctrl_1", axis)
+            .await
+        {
+            Ok(value) => info!("Supported movement parameters for {}: {}", axis, value),
+            Err(e) => error!(
+                "Failed to get supported movement parameters for {}: {}",
+                axis, e
+            ),
         }
     }
 
     for axis in ["X", "Y", "Z"] {
-        let (tx, rx) = oneshot::channel();
-        let params_cmd = Command::GetAvailableParams {
-            controller: "mock_ctrl_1".to_string(),
-            axis: axis.to_string(),
-            resp: tx,
-        };
-
-        manager.send_command(params_cmd).await?;
-        if let Ok(result) = rx.await {
-            match result {
-                Ok(value) => info!("Available parameters for {}: {}", axis, value),
-                Err(e) => error!("Failed to get available parameters for {}: {}", axis, e),
-            }
+        match handle.get_available_params("mock_ctrl_1", axis).await {
+            Ok(value) => info!("Available parameters for {}: {}", axis, value),
+            Err(e) => error!("Failed to get available parameters for {}: {}", axis, e),
         }
     }
 
-    let (tx, rx) = oneshot::channel();
-    let attr_cmd = Command::GetAttr {
-        controller: "mock_ctrl_1".to_string(),
-        axis: "X".to_string(),
-        attr: "velocity".to_string(),
-        resp: tx,
-    };
-
-    manager.send_command(attr_cmd).await?;
-    if let Ok(result) = rx.await {
-        match result {
-            Ok(value) => info!("Get attribute result: {}", value),
-            Err(e) => error!("Get attribute failed: {}", e),
-        }
+    match handle.get_attribute("mock_ctrl_1", "X", "velocity").await {
+        Ok(value) => info!("Get attribute result: {}", value),
+        Err(e) => error!("Get attribute failed: {}", e),
     }
 
     tokio::time::sleep(Duration::from_secs(2)).await;
@@ -381,7 +665,8 @@ async fn main() -> Result<()> {
 
     info!("Shutting down...");
     socket_server.shutdown().await?;
-    manager.unregister_controller("mock_ctrl_1").await?;
+    let summary = manager.shutdown_all().await;
+    info!("Controller shutdown summary: {:?}", summary);
     info!("Motarem shutdown complete");
 
     Ok(())
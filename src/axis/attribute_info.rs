@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+/// What a client needs to render a control for an attribute: its unit, the
+/// range it may take and whether it can be written.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttributeInfo {
+    pub name: String,
+    pub unit: Option<String>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub writable: bool,
+    /// For `position`, how close to a target the axis must come to rest
+    /// before a move counts as done; see `Axis::position_tolerance`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<f64>,
+}
+
+impl AttributeInfo {
+    /// A read-only attribute with no known unit or range.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            unit: None,
+            min: None,
+            max: None,
+            writable: false,
+            tolerance: None,
+        }
+    }
+
+    pub fn with_unit(mut self, unit: impl Into<String>) -> Self {
+        self.unit = Some(unit.into());
+        self
+    }
+
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.min = Some(min);
+        self.max = Some(max);
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = Some(tolerance);
+        self
+    }
+
+    pub fn writable(mut self) -> Self {
+        self.writable = true;
+        self
+    }
+}
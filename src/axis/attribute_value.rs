@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+/// An attribute reading. Serialized as the bare JSON value, so numeric
+/// attributes look exactly as they did when every attribute was an `f64`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl AttributeValue {
+    /// Numeric view of the value, for callers that only handle numbers.
+    pub fn to_f64(&self) -> anyhow::Result<f64> {
+        match self {
+            AttributeValue::Float(value) => Ok(*value),
+            AttributeValue::Int(value) => Ok(*value as f64),
+            other => Err(anyhow::anyhow!(
+                "Attribute value {:?} is not numeric",
+                other
+            )),
+        }
+    }
+}
+
+impl From<f64> for AttributeValue {
+    fn from(value: f64) -> Self {
+        AttributeValue::Float(value)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(value: i64) -> Self {
+        AttributeValue::Int(value)
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(value: bool) -> Self {
+        AttributeValue::Bool(value)
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::Text(value)
+    }
+}
+
+impl From<&str> for AttributeValue {
+    fn from(value: &str) -> Self {
+        AttributeValue::Text(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serializes_as_bare_json_value() {
+        assert_eq!(
+            serde_json::to_value(AttributeValue::from(1.5)).unwrap(),
+            1.5
+        );
+        assert_eq!(serde_json::to_value(AttributeValue::from(3i64)).unwrap(), 3);
+        assert_eq!(
+            serde_json::to_value(AttributeValue::from(true)).unwrap(),
+            true
+        );
+        assert_eq!(
+            serde_json::to_value(AttributeValue::from("v1.2")).unwrap(),
+            "v1.2"
+        );
+    }
+
+    #[test]
+    fn test_to_f64_rejects_non_numeric() {
+        assert_eq!(AttributeValue::Int(2).to_f64().unwrap(), 2.0);
+        assert!(AttributeValue::Bool(true).to_f64().is_err());
+        assert!(AttributeValue::from("x").to_f64().is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+use super::{limits::AxisLimits, Axis};
+
+/// Everything a client needs to know about an axis up front, in one reply.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AxisCapabilities {
+    pub readable_attributes: Vec<String>,
+    pub writable_attributes: Vec<String>,
+    pub supported_movement_params: Vec<String>,
+    pub supports_homing: bool,
+    pub supports_jog: bool,
+    pub supports_pause: bool,
+    pub limits: AxisLimits,
+}
+
+impl AxisCapabilities {
+    /// Composes the descriptor from the axis's individual queries. Feature
+    /// flags start out `false`; axes that support a feature override
+    /// `Axis::capabilities` and set the flag on top of this.
+    pub async fn query<A: Axis + ?Sized>(axis: &A) -> anyhow::Result<Self> {
+        Ok(Self {
+            readable_attributes: axis
+                .available_params()
+                .await?
+                .into_iter()
+                .map(|name| name.into_owned())
+                .collect(),
+            writable_attributes: Vec::new(),
+            supported_movement_params: axis
+                .supported_movement_params()
+                .await?
+                .into_iter()
+                .map(|name| name.into_owned())
+                .collect(),
+            supports_homing: false,
+            supports_jog: false,
+            supports_pause: false,
+            limits: axis.get_limits().await?,
+        })
+    }
+
+    pub fn with_pause(mut self) -> Self {
+        self.supports_pause = true;
+        self
+    }
+}
@@ -1,4 +1,11 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Hardware limit-switch state. On the wire this is an object with one
+/// boolean per side, e.g. `{"upper": true, "lower": false}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "LimitSwitchFlags", into = "LimitSwitchFlags")]
 pub enum LimitSwitches {
     None,
     Upper,
@@ -7,6 +14,15 @@ pub enum LimitSwitches {
 }
 
 impl LimitSwitches {
+    pub fn from_flags(upper: bool, lower: bool) -> Self {
+        match (upper, lower) {
+            (false, false) => LimitSwitches::None,
+            (true, false) => LimitSwitches::Upper,
+            (false, true) => LimitSwitches::Lower,
+            (true, true) => LimitSwitches::Both,
+        }
+    }
+
     pub fn has_upper(&self) -> bool {
         matches!(self, LimitSwitches::Upper | LimitSwitches::Both)
     }
@@ -22,4 +38,76 @@ impl LimitSwitches {
     pub fn any_active(&self) -> bool {
         !self.is_clear()
     }
+
+    /// Which side is active, as named on the wire.
+    pub fn side(&self) -> &'static str {
+        match self {
+            LimitSwitches::None => "none",
+            LimitSwitches::Upper => "upper",
+            LimitSwitches::Lower => "lower",
+            LimitSwitches::Both => "both",
+        }
+    }
+}
+
+/// Returned by `Axis::start` when the move ran into a limit switch and
+/// stopped short of its target. The manager reports it to clients as
+/// `LIMIT_TRIPPED`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitTripped {
+    pub switches: LimitSwitches,
+}
+
+impl fmt::Display for LimitTripped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} limit switch tripped", self.switches.side())
+    }
+}
+
+impl std::error::Error for LimitTripped {}
+
+#[derive(Serialize, Deserialize)]
+struct LimitSwitchFlags {
+    upper: bool,
+    lower: bool,
+}
+
+impl From<LimitSwitchFlags> for LimitSwitches {
+    fn from(flags: LimitSwitchFlags) -> Self {
+        LimitSwitches::from_flags(flags.upper, flags.lower)
+    }
+}
+
+impl From<LimitSwitches> for LimitSwitchFlags {
+    fn from(switches: LimitSwitches) -> Self {
+        LimitSwitchFlags {
+            upper: switches.has_upper(),
+            lower: switches.has_lower(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_switches_round_trip_as_flags() {
+        for switches in [
+            LimitSwitches::None,
+            LimitSwitches::Upper,
+            LimitSwitches::Lower,
+            LimitSwitches::Both,
+        ] {
+            let json = serde_json::to_value(switches).unwrap();
+            assert_eq!(
+                json,
+                serde_json::json!({"upper": switches.has_upper(), "lower": switches.has_lower()})
+            );
+            assert_eq!(
+                serde_json::from_value::<LimitSwitches>(json).unwrap(),
+                switches
+            );
+        }
+    }
 }
@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+/// Soft travel limits together with the hardware limit-switch state.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AxisLimits {
+    pub min_position: Option<f64>,
+    pub max_position: Option<f64>,
+    pub upper_switch: bool,
+    pub lower_switch: bool,
+}
+
+/// Which end of travel a `move_to_limit` drives towards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitDirection {
+    Upper,
+    Lower,
+}
@@ -1,21 +1,194 @@
+pub mod attribute_info;
+pub mod attribute_value;
+pub mod capabilities;
 pub mod limit_switches;
+pub mod limits;
 pub mod movement_parameters;
 pub mod state;
 pub mod state_info;
+pub mod stop_mode;
+pub mod target_kind;
 
+use std::{borrow::Cow, collections::HashMap};
+
+use attribute_info::AttributeInfo;
+use attribute_value::AttributeValue;
+use capabilities::AxisCapabilities;
+use limit_switches::LimitSwitches;
+use limits::{AxisLimits, LimitDirection};
 use movement_parameters::MovementParams;
 use state_info::AxisStateInfo;
+use stop_mode::StopMode;
+
+/// Movement parameters assumed when an axis doesn't list its own.
+pub const DEFAULT_MOVEMENT_PARAMS: &[&str] = &MovementParams::STANDARD;
 
 #[async_trait::async_trait]
 pub trait Axis: Send + Sync {
     fn name(&self) -> &str;
 
     async fn start(&self, target: f64, params: Option<MovementParams>) -> anyhow::Result<()>;
+
     async fn stop(&self) -> anyhow::Result<()>;
 
+    /// Checks the values of the custom movement parameters this axis
+    /// consumes before any move using them starts; an error fails the move
+    /// with `INVALID_PARAMS`. Which keys are accepted at all is declared by
+    /// `get_supported_movement_params`. Accepts everything by default.
+    fn validate_custom_params(&self, custom: &HashMap<String, f64>) -> Result<(), String> {
+        let _ = custom;
+        Ok(())
+    }
+
+    /// Stops in the given way, for drives that can ramp down rather than
+    /// halt. Returns once the axis is at rest. By default the mode is
+    /// ignored and this is `stop`.
+    async fn stop_with(&self, mode: StopMode) -> anyhow::Result<()> {
+        let _ = mode;
+        self.stop().await
+    }
+
+    /// Drives towards the limit switch in `direction` until it trips, then
+    /// stops; with `zero_position` the tripped position becomes the new zero.
+    /// Returns the switch state once stopped. Unsupported unless the axis
+    /// overrides it.
+    async fn move_to_limit(
+        &self,
+        direction: LimitDirection,
+        params: Option<MovementParams>,
+        zero_position: bool,
+    ) -> anyhow::Result<LimitSwitches> {
+        let _ = (direction, params, zero_position);
+        Err(anyhow::anyhow!(
+            "Moving to a limit is not supported by axis {}",
+            self.name()
+        ))
+    }
+
+    /// Suspends the move in progress so it can later be continued with
+    /// `resume`. Unsupported unless the axis overrides it.
+    async fn pause(&self) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Pause is not supported by axis {}",
+            self.name()
+        ))
+    }
+
+    async fn resume(&self) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Resume is not supported by axis {}",
+            self.name()
+        ))
+    }
+
+    /// Energizes a disabled drive. Unsupported unless the axis overrides it.
+    async fn enable(&self) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Enable is not supported by axis {}",
+            self.name()
+        ))
+    }
+
+    /// De-energizes the drive; the axis should then report
+    /// `AxisState::Disabled` and refuse to move until enabled again.
+    async fn disable(&self) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Disable is not supported by axis {}",
+            self.name()
+        ))
+    }
+
+    /// Acknowledges and clears an `Alarm` or `Fault`, after which the axis
+    /// should report a non-faulted state. Unsupported unless the axis
+    /// overrides it.
+    async fn reset_fault(&self) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Fault reset is not supported by axis {}",
+            self.name()
+        ))
+    }
+
+    /// Declares the current position to be `value`, in raw units, without
+    /// moving, e.g. to zero the axis after homing it to a known point.
+    /// Unsupported unless the axis overrides it.
+    async fn set_position_reference(&self, value: f64) -> anyhow::Result<()> {
+        let _ = value;
+        Err(anyhow::anyhow!(
+            "Setting a position reference is not supported by axis {}",
+            self.name()
+        ))
+    }
+
+    /// Converts a position reported by the hardware (e.g. encoder counts)
+    /// into the units clients work in. The manager applies it to positions,
+    /// the `position`, `min_position` and `max_position` attributes and
+    /// soft limits alike. Identity unless overridden.
+    fn to_user_units(&self, raw: f64) -> f64 {
+        raw
+    }
+
+    /// Inverse of `to_user_units`, applied to move targets before `start`.
+    fn to_raw_units(&self, user: f64) -> f64 {
+        user
+    }
+
     async fn get_state(&self) -> anyhow::Result<AxisStateInfo>;
+
+    /// How close to the target, in user units, the axis must come to rest
+    /// before it leaves `AxisState::Settling` for `On`. `None` for axes
+    /// that don't report settling. Clients see it as the `tolerance` in the
+    /// attribute info of `position`.
+    fn position_tolerance(&self) -> Option<f64> {
+        None
+    }
+
+    /// How far the current move has got, from 0.0 at its start to 1.0 at
+    /// the target. `None` means the axis doesn't report progress, not that
+    /// the move is done; axes that do report it typically compare the
+    /// current position against the start and target of the move.
+    async fn get_progress(&self) -> Option<f64> {
+        None
+    }
+
     async fn get_attribute(&self, name: &str) -> anyhow::Result<f64>;
 
+    /// Writes a numeric attribute, such as a gain or a current limit.
+    /// Axes that accept writes should also report the attribute as
+    /// `writable` in `attribute_info`. Unsupported unless overridden.
+    async fn set_attribute(&self, name: &str, value: f64) -> anyhow::Result<()> {
+        let _ = value;
+        Err(anyhow::anyhow!(
+            "Writing attribute {} is not supported by axis {}",
+            name,
+            self.name()
+        ))
+    }
+
+    /// Reads an attribute that may not be a number, such as a flag or a
+    /// firmware version. Defaults to the numeric `get_attribute`; axes with
+    /// non-numeric attributes override this and can answer `get_attribute`
+    /// via `AttributeValue::to_f64`.
+    async fn get_attribute_value(&self, name: &str) -> anyhow::Result<AttributeValue> {
+        self.get_attribute(name).await.map(AttributeValue::Float)
+    }
+
+    /// Reads an attribute made of several numbers, such as per-phase
+    /// currents, which `get_attribute` can't return. It must still be
+    /// listed by `get_available_params`. Unsupported unless overridden.
+    async fn get_attribute_array(&self, name: &str) -> anyhow::Result<Vec<f64>> {
+        Err(anyhow::anyhow!(
+            "Attribute {} of axis {} is not an array",
+            name,
+            self.name()
+        ))
+    }
+
+    /// Unit, range and writability of an attribute. The default knows
+    /// nothing beyond the name and reports the attribute as read-only.
+    async fn attribute_info(&self, name: &str) -> anyhow::Result<AttributeInfo> {
+        Ok(AttributeInfo::new(name))
+    }
+
     async fn get_position(&self) -> anyhow::Result<f64> {
         self.get_attribute("position").await
     }
@@ -25,10 +198,59 @@ pub trait Axis: Send + Sync {
     }
 
     async fn get_supported_movement_params(&self) -> anyhow::Result<Vec<String>> {
-        Ok(vec![
-            "velocity".to_string(),
-            "acceleration".to_string(),
-            "deceleration".to_string(),
-        ])
+        Ok(DEFAULT_MOVEMENT_PARAMS
+            .iter()
+            .map(|p| p.to_string())
+            .collect())
+    }
+
+    /// Borrowing counterpart of `get_available_params`, used on hot paths
+    /// such as attribute reads. Axes with a fixed list should override it
+    /// with `Cow::Borrowed` names; the default bridges to
+    /// `get_available_params` so existing implementations keep working.
+    async fn available_params(&self) -> anyhow::Result<Vec<Cow<'static, str>>> {
+        Ok(self
+            .get_available_params()
+            .await?
+            .into_iter()
+            .map(Cow::Owned)
+            .collect())
+    }
+
+    /// Borrowing counterpart of `get_supported_movement_params`; see
+    /// `available_params`.
+    async fn supported_movement_params(&self) -> anyhow::Result<Vec<Cow<'static, str>>> {
+        Ok(self
+            .get_supported_movement_params()
+            .await?
+            .into_iter()
+            .map(Cow::Owned)
+            .collect())
+    }
+
+    /// Soft limits come from the `min_position`/`max_position` attributes
+    /// when the axis exposes them; switch state comes from `get_state`.
+    async fn get_limits(&self) -> anyhow::Result<AxisLimits> {
+        let state = self.get_state().await?;
+        Ok(AxisLimits {
+            min_position: self.get_attribute("min_position").await.ok(),
+            max_position: self.get_attribute("max_position").await.ok(),
+            upper_switch: state.limit_switches.has_upper(),
+            lower_switch: state.limit_switches.has_lower(),
+        })
+    }
+
+    /// The limit switches alone, for polling them without a full state
+    /// read. Defaults to the switches reported by `get_state`; axes that
+    /// can read the switches more cheaply should override it.
+    async fn get_limit_switches(&self) -> anyhow::Result<LimitSwitches> {
+        Ok(self.get_state().await?.limit_switches)
+    }
+
+    /// Describes the axis in one call. The default composes the individual
+    /// queries and reports no optional features; see
+    /// `AxisCapabilities::query`.
+    async fn capabilities(&self) -> anyhow::Result<AxisCapabilities> {
+        AxisCapabilities::query(self).await
     }
 }
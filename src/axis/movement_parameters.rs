@@ -2,15 +2,28 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
+/// Listed among an axis's supported movement parameters to accept any
+/// custom parameter name.
+pub const ANY_CUSTOM_PARAM: &str = "*";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MovementParams {
     pub velocity: Option<f64>,
     pub acceleration: Option<f64>,
     pub deceleration: Option<f64>,
+    /// Controller-specific parameters, such as a dwell time, handed to the
+    /// axis unchanged. Interpreting them is up to the controller; the
+    /// manager only checks that each key is one the axis lists among its
+    /// supported movement parameters, that each value is finite, and then
+    /// `Axis::validate_custom_params`.
+    #[serde(default)]
     pub custom: HashMap<String, f64>,
 }
 
 impl MovementParams {
+    /// Names of the parameters with a field of their own, in field order.
+    pub const STANDARD: [&'static str; 3] = ["velocity", "acceleration", "deceleration"];
+
     pub fn new() -> Self {
         Self {
             velocity: None,
@@ -39,6 +52,60 @@ impl MovementParams {
         self.custom.insert(name, value);
         self
     }
+
+    /// The custom parameter `name`, if set.
+    pub fn custom_get(&self, name: &str) -> Option<f64> {
+        self.custom.get(name).copied()
+    }
+
+    /// The standard parameters by name, set or not.
+    fn standard(&self) -> impl Iterator<Item = (&'static str, Option<f64>)> {
+        Self::STANDARD
+            .into_iter()
+            .zip([self.velocity, self.acceleration, self.deceleration])
+    }
+
+    /// Names of the parameters that are actually set, including custom ones.
+    pub fn provided_names(&self) -> Vec<&str> {
+        self.standard()
+            .filter(|(_, value)| value.is_some())
+            .map(|(name, _)| name)
+            .chain(self.custom.keys().map(String::as_str))
+            .collect()
+    }
+
+    /// Fills in whatever is unset here from `defaults`, field by field.
+    /// Custom parameters are merged by name, with these taking precedence.
+    pub fn merged_over(self, defaults: &MovementParams) -> MovementParams {
+        let mut custom = defaults.custom.clone();
+        custom.extend(self.custom);
+        MovementParams {
+            velocity: self.velocity.or(defaults.velocity),
+            acceleration: self.acceleration.or(defaults.acceleration),
+            deceleration: self.deceleration.or(defaults.deceleration),
+            custom,
+        }
+    }
+
+    /// Checks that every provided value is finite, and that velocity,
+    /// acceleration and deceleration are strictly positive.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in self.standard() {
+            if let Some(value) = value {
+                if !value.is_finite() || value <= 0.0 {
+                    return Err(format!("{} must be a positive number, got {}", name, value));
+                }
+            }
+        }
+
+        for (name, value) in &self.custom {
+            if !value.is_finite() {
+                return Err(format!("{} must be a finite number, got {}", name, value));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Default for MovementParams {
@@ -46,3 +113,53 @@ impl Default for MovementParams {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_accepts_empty_and_positive_params() {
+        assert!(MovementParams::new().validate().is_ok());
+        let params = MovementParams::new()
+            .with_velocity(10.0)
+            .with_acceleration(100.0)
+            .with_deceleration(100.0)
+            .with_custom_param("dwell".to_string(), -1.0);
+        assert!(params.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_non_positive_or_non_finite_values() {
+        let invalid = [
+            MovementParams::new().with_velocity(-5.0),
+            MovementParams::new().with_velocity(f64::NAN),
+            MovementParams::new().with_acceleration(0.0),
+            MovementParams::new().with_acceleration(f64::INFINITY),
+            MovementParams::new().with_deceleration(-1.0),
+            MovementParams::new().with_custom_param("jerk".to_string(), f64::NAN),
+        ];
+        for params in invalid {
+            assert!(params.validate().is_err(), "{:?} should be invalid", params);
+        }
+    }
+
+    #[test]
+    fn test_merged_over_prefers_own_values() {
+        let defaults = MovementParams::new()
+            .with_velocity(10.0)
+            .with_acceleration(100.0)
+            .with_custom_param("jerk".to_string(), 1.0)
+            .with_custom_param("dwell".to_string(), 0.5);
+        let merged = MovementParams::new()
+            .with_velocity(20.0)
+            .with_custom_param("dwell".to_string(), 2.0)
+            .merged_over(&defaults);
+
+        assert_eq!(merged.velocity, Some(20.0));
+        assert_eq!(merged.acceleration, Some(100.0));
+        assert_eq!(merged.deceleration, None);
+        assert_eq!(merged.custom["jerk"], 1.0);
+        assert_eq!(merged.custom["dwell"], 2.0);
+    }
+}
@@ -1,7 +1,17 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AxisState {
     On,
     Moving,
+    /// The commanded position has been reached but the axis hasn't yet
+    /// come to rest within its `Axis::position_tolerance`.
+    Settling,
+    /// A move is in progress but suspended; see `Axis::pause`.
+    Paused,
+    /// The drive is de-energized in software; see `Axis::disable`.
+    Disabled,
     Alarm,
     Fault,
     Unknown,
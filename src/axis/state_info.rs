@@ -1,9 +1,14 @@
+use serde::Serialize;
+
 use super::limit_switches::LimitSwitches;
 use super::state::AxisState;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct AxisStateInfo {
     pub state: AxisState,
+    /// What the drive says about the state, such as the cause of a fault.
+    /// Drivers should set it for `Fault` and `Alarm`; it is reported by
+    /// `get_state` and added to the errors of moves the axis refuses.
     pub message: Option<String>,
     pub limit_switches: LimitSwitches,
 }
@@ -31,10 +36,26 @@ impl AxisStateInfo {
         self.state == AxisState::Moving
     }
 
+    /// A paused axis still has a move pending but is neither moving nor
+    /// ready for a new command.
+    pub fn is_paused(&self) -> bool {
+        self.state == AxisState::Paused
+    }
+
     pub fn is_faulted(&self) -> bool {
         matches!(self.state, AxisState::Alarm | AxisState::Fault)
     }
 
+    pub fn is_settling(&self) -> bool {
+        self.state == AxisState::Settling
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.state == AxisState::Disabled
+    }
+
+    /// Only an idle, enabled axis clear of its limit switches is ready;
+    /// paused, settling and disabled axes are not.
     pub fn is_ready(&self) -> bool {
         self.state == AxisState::On && !self.limit_switches.any_active()
     }
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// How an axis comes to rest when stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopMode {
+    /// Ramp down at `rate` (units/s²), or at the axis's own deceleration
+    /// when no rate is given.
+    Decelerate {
+        #[serde(default)]
+        rate: Option<f64>,
+    },
+    /// Halt as fast as the drive allows.
+    #[default]
+    Immediate,
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// How the `target` of a move is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetKind {
+    /// A position in user units.
+    #[default]
+    Absolute,
+    /// A percentage of the axis's travel, from 0 at its `min_position` to
+    /// 100 at its `max_position`.
+    Percent,
+}
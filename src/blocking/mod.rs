@@ -0,0 +1,157 @@
+//! A blocking front end to `ControllerManager` for callers that don't run
+//! async code, such as scripts and simple tests. Enabled with the `blocking`
+//! feature.
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use motarem::{blocking::BlockingClient, controller_manager::config::ManagerConfig};
+//! # use motarem::{
+//! #     axis::{movement_parameters::MovementParams, state::AxisState, state_info::AxisStateInfo, Axis},
+//! #     motor_controller::MotorController,
+//! # };
+//! # struct Stage { position: std::sync::Mutex<f64> }
+//! # #[async_trait::async_trait]
+//! # impl Axis for Stage {
+//! #     fn name(&self) -> &str { "X" }
+//! #     async fn start(&self, target: f64, _: Option<MovementParams>) -> anyhow::Result<()> {
+//! #         *self.position.lock().unwrap() = target;
+//! #         Ok(())
+//! #     }
+//! #     async fn stop(&self) -> anyhow::Result<()> { Ok(()) }
+//! #     async fn get_state(&self) -> anyhow::Result<AxisStateInfo> { Ok(AxisStateInfo::new(AxisState::On)) }
+//! #     async fn get_attribute(&self, _: &str) -> anyhow::Result<f64> { Ok(*self.position.lock().unwrap()) }
+//! # }
+//! # struct StageController(Vec<Arc<dyn Axis>>);
+//! # impl MotorController for StageController {
+//! #     fn name(&self) -> &str { "stage" }
+//! #     fn axes(&self) -> Vec<Arc<dyn Axis>> { self.0.clone() }
+//! # }
+//! # let stage = StageController(vec![Arc::new(Stage { position: Default::default() })]);
+//!
+//! let client = BlockingClient::new(ManagerConfig::default())?;
+//! client.register_controller("stage", Arc::new(stage))?;
+//!
+//! client.move_to("stage", "X", 12.5, None)?;
+//! assert_eq!(client.get_position("stage", "X")?, 12.5);
+//! client.shutdown();
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde_json::Value;
+use tokio::{runtime::Runtime, sync::oneshot};
+
+use crate::{
+    axis::movement_parameters::MovementParams,
+    controller_manager::{
+        command::Command, config::ManagerConfig, handle::ManagerHandle, ControllerManager,
+        ShutdownSummary,
+    },
+    motor_controller::MotorController,
+};
+
+/// Owns a manager and the runtime it runs on. Every method blocks the
+/// calling thread until the manager has answered, so it must not be used
+/// from within an async context.
+pub struct BlockingClient {
+    manager: ControllerManager,
+    handle: ManagerHandle,
+    runtime: Runtime,
+}
+
+impl BlockingClient {
+    pub fn new(config: ManagerConfig) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        // The manager spawns its background tasks on construction.
+        let manager = runtime.block_on(async { ControllerManager::new(config) });
+        let handle = manager.handle();
+        Ok(Self {
+            manager,
+            handle,
+            runtime,
+        })
+    }
+
+    /// The wrapped manager, for anything not covered by a method here.
+    pub fn manager(&self) -> &ControllerManager {
+        &self.manager
+    }
+
+    pub fn register_controller(
+        &self,
+        name: &str,
+        controller: Arc<dyn MotorController>,
+    ) -> Result<()> {
+        self.runtime.block_on(
+            self.manager
+                .register_controller(name.to_string(), controller),
+        )
+    }
+
+    /// Moves the axis and returns once the move has finished.
+    pub fn move_to(
+        &self,
+        controller: &str,
+        axis: &str,
+        target: f64,
+        params: Option<MovementParams>,
+    ) -> Result<Value> {
+        self.runtime
+            .block_on(self.handle.move_to(controller, axis, target, params))
+    }
+
+    pub fn stop(&self, controller: &str, axis: &str) -> Result<Value> {
+        self.runtime.block_on(self.handle.stop(controller, axis))
+    }
+
+    pub fn get_position(&self, controller: &str, axis: &str) -> Result<f64> {
+        self.runtime
+            .block_on(self.handle.get_position(controller, axis))
+    }
+
+    /// The axis status as returned by `get_state`.
+    pub fn get_state(&self, controller: &str, axis: &str) -> Result<Value> {
+        self.runtime
+            .block_on(self.handle.get_state(controller, axis))
+    }
+
+    /// Sends any command and waits for its reply.
+    pub fn request<F>(&self, build: F) -> Result<Value>
+    where
+        F: FnOnce(oneshot::Sender<Result<Value>>) -> Command,
+    {
+        self.runtime.block_on(self.handle.request(build))
+    }
+
+    pub fn shutdown(&self) -> ShutdownSummary {
+        self.runtime.block_on(self.manager.shutdown_all())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::ScriptedController;
+
+    #[test]
+    fn test_round_trip_without_async_code() {
+        let client = BlockingClient::new(ManagerConfig::default()).unwrap();
+        client
+            .register_controller("ctrl", Arc::new(ScriptedController::new("ctrl", &["X"])))
+            .unwrap();
+
+        client.move_to("ctrl", "X", 4.0, None).unwrap();
+        assert_eq!(client.get_position("ctrl", "X").unwrap(), 4.0);
+        assert_eq!(client.get_state("ctrl", "X").unwrap()["state"], "on");
+        client.stop("ctrl", "X").unwrap();
+        assert!(client.get_position("ctrl", "Q").is_err());
+
+        let summary = client.shutdown();
+        assert_eq!(summary.clean, vec!["ctrl".to_string()]);
+    }
+}
@@ -0,0 +1,787 @@
+//! A client of the socket protocol for long-running embedders, such as a
+//! GUI, that should survive the server restarting. Enabled with the
+//! `client` feature.
+//!
+//! `ReconnectingClient` keeps one connection up, reconnecting with
+//! exponential backoff whenever it drops, and subscribes again to every
+//! axis it was subscribed to, so the event stream resumes by itself:
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use motarem::{
+//!     client::{ClientConfig, ClientEvent, Endpoint, ReconnectingClient},
+//!     protocol::client_command::ClientCommand,
+//! };
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> anyhow::Result<()> {
+//! let config = ClientConfig::new(Endpoint::Tcp("127.0.0.1:7000".parse()?));
+//! let (client, mut events) = ReconnectingClient::start(config);
+//!
+//! client.subscribe("stage", "X").await?;
+//! let reply = client
+//!     .request(ClientCommand::Ping { id: None })
+//!     .await?;
+//!
+//! while let Some(event) = events.next().await {
+//!     match event {
+//!         ClientEvent::Axis(event) => println!("{}", event),
+//!         ClientEvent::Lagged(dropped) => println!("missed {} events", dropped),
+//!         ClientEvent::Connected | ClientEvent::Disconnected => {}
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Commands in flight when the connection drops fail rather than being
+//! sent again, since a move must not run twice; commands issued while it is
+//! down wait for the next connection, up to `request_timeout`. A command's
+//! own `id` goes to the server as it is, so a retry after a timeout is
+//! recognised by a server with `idempotency_ttl` or `reject_duplicate_ids`;
+//! commands without one are sent with a generated id.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    net::SocketAddr,
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use futures::{SinkExt, Stream, StreamExt};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpStream, UnixStream},
+    sync::{mpsc, oneshot},
+    task::AbortHandle,
+};
+use tokio_util::codec::{Framed, LinesCodec};
+use tracing::{debug, warn};
+
+use crate::protocol::{client_command::ClientCommand, server_response::ServerResponse};
+
+/// How many events may wait for the embedder. Events that find the buffer
+/// full are dropped and counted; see `ClientEvent::Lagged`.
+const EVENT_BUFFER: usize = 256;
+
+/// Where the server listens.
+#[derive(Debug, Clone)]
+pub enum Endpoint {
+    Unix(PathBuf),
+    Tcp(SocketAddr),
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+            Endpoint::Tcp(addr) => write!(f, "tcp://{}", addr),
+        }
+    }
+}
+
+pub struct ClientConfig {
+    pub endpoint: Endpoint,
+    /// Sent with `auth` on every connection, for servers that require it.
+    pub auth_token: Option<String>,
+    /// Wait before the first reconnection attempt, doubled after each
+    /// failed one up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// How long `request` and `subscribe` wait for their reply, including
+    /// any time spent reconnecting.
+    pub request_timeout: Duration,
+}
+
+impl ClientConfig {
+    pub fn new(endpoint: Endpoint) -> Self {
+        Self {
+            endpoint,
+            auth_token: None,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// What the event stream of a `ReconnectingClient` yields.
+#[derive(Debug, Clone)]
+pub enum ClientEvent {
+    /// A connection is up and every subscription has been made again.
+    Connected,
+    /// The connection dropped; the client is reconnecting.
+    Disconnected,
+    /// The data of an event frame. Its `subscription` is the id returned
+    /// by `subscribe`, which stays the same across reconnections.
+    Axis(Value),
+    /// This many events were dropped because the embedder fell behind.
+    /// Comes before the first event that made it through again.
+    Lagged(u64),
+}
+
+enum Request {
+    Command {
+        command: ClientCommand,
+        resp: oneshot::Sender<ServerResponse>,
+    },
+    Subscribe {
+        controller: String,
+        axis: String,
+        resp: oneshot::Sender<Result<u64>>,
+    },
+    Unsubscribe {
+        subscription: u64,
+        resp: oneshot::Sender<bool>,
+    },
+}
+
+/// A connection to the server that comes back by itself. Cheap to share
+/// behind an `Arc`; dropping it closes the connection.
+pub struct ReconnectingClient {
+    requests: mpsc::Sender<Request>,
+    request_timeout: Duration,
+    task: AbortHandle,
+}
+
+impl ReconnectingClient {
+    /// Starts connecting in the background and returns the client with its
+    /// event stream. Needs a tokio runtime.
+    pub fn start(
+        config: ClientConfig,
+    ) -> (
+        Self,
+        impl Stream<Item = ClientEvent> + Send + Unpin + 'static,
+    ) {
+        let (requests, requests_rx) = mpsc::channel(64);
+        let (events_tx, mut events_rx) = mpsc::channel(EVENT_BUFFER);
+        let request_timeout = config.request_timeout;
+        let worker = Worker {
+            config,
+            requests: requests_rx,
+            events: events_tx,
+            dropped_events: 0,
+            subscriptions: BTreeMap::new(),
+            next_subscription: 1,
+            next_id: 1,
+        };
+        let task = tokio::spawn(worker.run()).abort_handle();
+        let events = futures::stream::poll_fn(move |cx| events_rx.poll_recv(cx));
+        (
+            Self {
+                requests,
+                request_timeout,
+                task,
+            },
+            events,
+        )
+    }
+
+    /// Sends `command` and returns its reply, with the command's own `id`.
+    /// Fails if the connection drops before the reply arrives.
+    pub async fn request(&self, command: ClientCommand) -> Result<ServerResponse> {
+        self.call(|resp| Request::Command { command, resp }).await
+    }
+
+    /// Subscribes to the events of an axis, on this connection and every
+    /// later one, returning the subscription's id.
+    pub async fn subscribe(&self, controller: &str, axis: &str) -> Result<u64> {
+        self.call(|resp| Request::Subscribe {
+            controller: controller.to_string(),
+            axis: axis.to_string(),
+            resp,
+        })
+        .await?
+    }
+
+    /// Ends a subscription, returning whether it existed.
+    pub async fn unsubscribe(&self, subscription: u64) -> Result<bool> {
+        self.call(|resp| Request::Unsubscribe { subscription, resp })
+            .await
+    }
+
+    async fn call<T>(&self, build: impl FnOnce(oneshot::Sender<T>) -> Request) -> Result<T> {
+        let (tx, rx) = oneshot::channel();
+        let exchange = async {
+            self.requests
+                .send(build(tx))
+                .await
+                .map_err(|_| anyhow::anyhow!("Client has stopped"))?;
+            rx.await
+                .map_err(|_| anyhow::anyhow!("Connection lost before the reply arrived"))
+        };
+        tokio::time::timeout(self.request_timeout, exchange)
+            .await
+            .map_err(|_| anyhow::anyhow!("No reply within {:?}", self.request_timeout))?
+    }
+}
+
+impl Drop for ReconnectingClient {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+type Connection = Framed<Box<dyn Io>, LinesCodec>;
+
+/// A request sent to the server and waiting for its reply, by the id it
+/// was sent with.
+enum Pending {
+    Command {
+        id: Option<String>,
+        resp: oneshot::Sender<ServerResponse>,
+    },
+    Subscribe {
+        subscription: u64,
+        controller: String,
+        axis: String,
+        resp: oneshot::Sender<Result<u64>>,
+    },
+    Unsubscribe,
+}
+
+impl Pending {
+    /// Whether the caller has stopped waiting, e.g. after `request_timeout`.
+    fn is_abandoned(&self) -> bool {
+        match self {
+            Pending::Command { resp, .. } => resp.is_closed(),
+            Pending::Subscribe { resp, .. } => resp.is_closed(),
+            Pending::Unsubscribe => false,
+        }
+    }
+}
+
+/// Owns the connection on its own task.
+struct Worker {
+    config: ClientConfig,
+    requests: mpsc::Receiver<Request>,
+    events: mpsc::Sender<ClientEvent>,
+    /// Events dropped since the embedder last had room for one.
+    dropped_events: u64,
+    /// The axes subscribed to, by the id `subscribe` returned.
+    subscriptions: BTreeMap<u64, (String, String)>,
+    next_subscription: u64,
+    next_id: u64,
+}
+
+/// The state of one connection.
+#[derive(Default)]
+struct Session {
+    pending: HashMap<String, Pending>,
+    /// Client subscription ids by the server's ids on this connection.
+    subscription_ids: HashMap<u64, u64>,
+}
+
+impl Worker {
+    async fn run(mut self) {
+        let mut backoff = self.config.initial_backoff;
+        loop {
+            match self.connect().await {
+                Ok(connection) => {
+                    backoff = self.config.initial_backoff;
+                    if !self.serve(connection).await {
+                        return;
+                    }
+                    self.emit(ClientEvent::Disconnected);
+                }
+                Err(e) => debug!("Connecting to {} failed: {}", self.config.endpoint, e),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.config.max_backoff);
+        }
+    }
+
+    /// Connects, authenticates and subscribes again to every axis.
+    async fn connect(&mut self) -> Result<(Connection, Session)> {
+        let stream: Box<dyn Io> = match &self.config.endpoint {
+            Endpoint::Unix(path) => Box::new(UnixStream::connect(path).await?),
+            Endpoint::Tcp(addr) => Box::new(TcpStream::connect(addr).await?),
+        };
+        let mut connection = Framed::new(stream, LinesCodec::new());
+        let mut session = Session::default();
+
+        if let Some(token) = self.config.auth_token.clone() {
+            let reply = self
+                .exchange(
+                    &mut connection,
+                    &mut session,
+                    json!({"type": "auth", "token": token}),
+                )
+                .await?;
+            if let ServerResponse::Error { message, .. } = reply {
+                anyhow::bail!("Authentication failed: {}", message);
+            }
+        }
+        for (subscription, (controller, axis)) in self.subscriptions.clone() {
+            let command = json!({"type": "subscribe", "controller": controller, "axis": axis});
+            match self
+                .exchange(&mut connection, &mut session, command)
+                .await?
+            {
+                ServerResponse::Success { data, .. } => {
+                    let server_id = data["subscription"]
+                        .as_u64()
+                        .context("Subscribe reply has no subscription id")?;
+                    session.subscription_ids.insert(server_id, subscription);
+                }
+                ServerResponse::Error { message, .. } => {
+                    warn!(
+                        "Could not subscribe again to {}::{}: {}",
+                        controller, axis, message
+                    );
+                }
+            }
+        }
+        self.emit(ClientEvent::Connected);
+        Ok((connection, session))
+    }
+
+    /// Sends `command` and waits for its reply, passing on any events that
+    /// arrive first.
+    async fn exchange(
+        &mut self,
+        connection: &mut Connection,
+        session: &mut Session,
+        mut command: Value,
+    ) -> Result<ServerResponse> {
+        let id = self.next_request_id();
+        command["id"] = json!(id);
+        connection.send(command.to_string()).await?;
+        loop {
+            let line = connection
+                .next()
+                .await
+                .context("Connection closed before the reply arrived")??;
+            if let Some(reply) = self.dispatch(&line, session) {
+                if reply.0 == id {
+                    return Ok(reply.1);
+                }
+            }
+        }
+    }
+
+    /// Serves requests until the connection drops, returning false once
+    /// the client has been dropped instead.
+    async fn serve(&mut self, (mut connection, mut session): (Connection, Session)) -> bool {
+        loop {
+            tokio::select! {
+                request = self.requests.recv() => {
+                    let Some(request) = request else { return false };
+                    if let Err(e) = self.send(request, &mut connection, &mut session).await {
+                        debug!("Failed to send to {}: {}", self.config.endpoint, e);
+                        return true;
+                    }
+                }
+                line = connection.next() => {
+                    let Some(Ok(line)) = line else { return true };
+                    if let Some((id, reply)) = self.dispatch(&line, &mut session) {
+                        self.complete(id, reply, &mut session);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn send(
+        &mut self,
+        request: Request,
+        connection: &mut Connection,
+        session: &mut Session,
+    ) -> Result<()> {
+        // Nobody is left to answer, and a retry may reuse the id.
+        session.pending.retain(|_, pending| !pending.is_abandoned());
+
+        let (id, mut command, pending) = match request {
+            Request::Command { command, resp } => {
+                let id = match command.id() {
+                    Some(id) if session.pending.contains_key(id) => {
+                        let _ = resp.send(ServerResponse::error_with_code(
+                            Some(id.clone()),
+                            format!("Command id {} is already in flight", id),
+                            "DUPLICATE_ID".to_string(),
+                        ));
+                        return Ok(());
+                    }
+                    Some(id) => id.clone(),
+                    None => self.next_request_id(),
+                };
+                let pending = Pending::Command {
+                    id: command.id().cloned(),
+                    resp,
+                };
+                (id, serde_json::to_value(&command)?, pending)
+            }
+            Request::Subscribe {
+                controller,
+                axis,
+                resp,
+            } => {
+                let subscription = self.next_subscription;
+                self.next_subscription += 1;
+                let command = json!({"type": "subscribe", "controller": controller, "axis": axis});
+                let pending = Pending::Subscribe {
+                    subscription,
+                    controller,
+                    axis,
+                    resp,
+                };
+                (self.next_request_id(), command, pending)
+            }
+            Request::Unsubscribe { subscription, resp } => {
+                let existed = self.subscriptions.remove(&subscription).is_some();
+                let server_id = session
+                    .subscription_ids
+                    .iter()
+                    .find(|(_, client_id)| **client_id == subscription)
+                    .map(|(server_id, _)| *server_id);
+                let _ = resp.send(existed);
+                let Some(server_id) = server_id else {
+                    return Ok(());
+                };
+                session.subscription_ids.remove(&server_id);
+                (
+                    self.next_request_id(),
+                    json!({"type": "unsubscribe", "subscription": server_id}),
+                    Pending::Unsubscribe,
+                )
+            }
+        };
+        command["id"] = json!(id);
+        session.pending.insert(id, pending);
+        connection.send(command.to_string()).await?;
+        Ok(())
+    }
+
+    /// Passes `event` on without waiting, so an embedder that stops reading
+    /// events never holds up requests or the connection. An event that
+    /// finds the buffer full is dropped and counted instead, and the count
+    /// goes out as `Lagged` once there is room again.
+    fn emit(&mut self, event: ClientEvent) {
+        if self.dropped_events > 0 {
+            match self
+                .events
+                .try_send(ClientEvent::Lagged(self.dropped_events))
+            {
+                Ok(()) => self.dropped_events = 0,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    self.dropped_events += 1;
+                    return;
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => return,
+            }
+        }
+        if let Err(mpsc::error::TrySendError::Full(_)) = self.events.try_send(event) {
+            self.dropped_events += 1;
+        }
+    }
+
+    /// Passes an event frame on to the embedder, or returns a reply with
+    /// the id it answers.
+    fn dispatch(&mut self, line: &str, session: &mut Session) -> Option<(String, ServerResponse)> {
+        let frame: Value = match serde_json::from_str(line) {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Ignoring unreadable frame from server: {}", e);
+                return None;
+            }
+        };
+        let data = &frame["data"];
+        if data.get("heartbeat").is_some() {
+            return None;
+        }
+        if data.get("event").is_some() || data.get("lagged").is_some() {
+            let mut data = data.clone();
+            if let Some(server_id) = data["subscription"].as_u64() {
+                // Events still queued for a subscription that has ended.
+                let subscription = session.subscription_ids.get(&server_id)?;
+                data["subscription"] = json!(subscription);
+            }
+            self.emit(ClientEvent::Axis(data));
+            return None;
+        }
+        let id = frame["id"].as_str()?.to_string();
+        match serde_json::from_value(frame) {
+            Ok(reply) => Some((id, reply)),
+            Err(e) => {
+                warn!("Ignoring malformed reply from server: {}", e);
+                None
+            }
+        }
+    }
+
+    fn complete(&mut self, id: String, reply: ServerResponse, session: &mut Session) {
+        match session.pending.remove(&id) {
+            Some(Pending::Command { id, resp }) => {
+                let _ = resp.send(with_id(reply, id));
+            }
+            Some(Pending::Subscribe {
+                subscription,
+                controller,
+                axis,
+                resp,
+            }) => {
+                let result = match reply {
+                    ServerResponse::Success { data, .. } => match data["subscription"].as_u64() {
+                        Some(server_id) => {
+                            session.subscription_ids.insert(server_id, subscription);
+                            self.subscriptions.insert(subscription, (controller, axis));
+                            Ok(subscription)
+                        }
+                        None => Err(anyhow::anyhow!("Subscribe reply has no subscription id")),
+                    },
+                    ServerResponse::Error { message, .. } => Err(anyhow::anyhow!(message)),
+                };
+                let _ = resp.send(result);
+            }
+            Some(Pending::Unsubscribe) => {}
+            None => debug!("Ignoring reply to unknown request {}", id),
+        }
+    }
+
+    fn next_request_id(&mut self) -> String {
+        let id = format!("motarem-client-{}", self.next_id);
+        self.next_id += 1;
+        id
+    }
+}
+
+/// `reply` as if answering a command sent with `id`.
+fn with_id(mut reply: ServerResponse, id: Option<String>) -> ServerResponse {
+    match &mut reply {
+        ServerResponse::Success { id: reply_id, .. }
+        | ServerResponse::Error { id: reply_id, .. } => *reply_id = id,
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn worker(events: mpsc::Sender<ClientEvent>) -> Worker {
+        let (_requests, requests_rx) = mpsc::channel(1);
+        Worker {
+            config: ClientConfig::new(Endpoint::Tcp("127.0.0.1:1".parse().unwrap())),
+            requests: requests_rx,
+            events,
+            dropped_events: 0,
+            subscriptions: BTreeMap::new(),
+            next_subscription: 1,
+            next_id: 1,
+        }
+    }
+
+    #[test]
+    fn test_events_dropped_while_the_embedder_lags_are_counted() {
+        let (events_tx, mut events_rx) = mpsc::channel(2);
+        let mut worker = worker(events_tx);
+        let axis_event = |n: u64| ClientEvent::Axis(json!({"n": n}));
+
+        for n in 0..5 {
+            worker.emit(axis_event(n));
+        }
+        let mut received = Vec::new();
+        while let Ok(event) = events_rx.try_recv() {
+            received.push(event);
+        }
+        assert!(
+            matches!(&received[..], [ClientEvent::Axis(a), ClientEvent::Axis(b)]
+            if a["n"] == 0 && b["n"] == 1)
+        );
+
+        worker.emit(axis_event(5));
+        assert!(matches!(events_rx.try_recv(), Ok(ClientEvent::Lagged(3))));
+        assert!(matches!(events_rx.try_recv(), Ok(ClientEvent::Axis(e)) if e["n"] == 5));
+    }
+
+    /// The id of the next command the server receives.
+    async fn sent_id(server: &mut Framed<tokio::io::DuplexStream, LinesCodec>) -> Value {
+        let line = server.next().await.unwrap().unwrap();
+        serde_json::from_str::<Value>(&line).unwrap()["id"].clone()
+    }
+
+    #[tokio::test]
+    async fn test_commands_are_sent_with_their_own_id() {
+        let (events_tx, _events_rx) = mpsc::channel(1);
+        let mut worker = worker(events_tx);
+        let (client_io, server_io) = tokio::io::duplex(4096);
+        let mut connection: Connection = Framed::new(Box::new(client_io), LinesCodec::new());
+        let mut server = Framed::new(server_io, LinesCodec::new());
+        let mut session = Session::default();
+        let ping = |id: Option<&str>| {
+            let (resp, rx) = oneshot::channel();
+            let command = ClientCommand::Ping {
+                id: id.map(str::to_string),
+            };
+            (Request::Command { command, resp }, rx)
+        };
+
+        let (request, timed_out) = ping(Some("retry"));
+        worker
+            .send(request, &mut connection, &mut session)
+            .await
+            .unwrap();
+        assert_eq!(sent_id(&mut server).await, "retry");
+        let (request, _unnamed) = ping(None);
+        worker
+            .send(request, &mut connection, &mut session)
+            .await
+            .unwrap();
+        assert_eq!(sent_id(&mut server).await, "motarem-client-1");
+
+        // The first is still waiting for its reply.
+        let (request, refused) = ping(Some("retry"));
+        worker
+            .send(request, &mut connection, &mut session)
+            .await
+            .unwrap();
+        assert!(matches!(
+            refused.await.unwrap(),
+            ServerResponse::Error { code: Some(code), .. } if code == "DUPLICATE_ID"
+        ));
+
+        // Once its caller gives up, the entry goes and a retry is sent.
+        drop(timed_out);
+        let (request, _retried) = ping(Some("retry"));
+        worker
+            .send(request, &mut connection, &mut session)
+            .await
+            .unwrap();
+        assert_eq!(sent_id(&mut server).await, "retry");
+        assert_eq!(session.pending.len(), 2);
+    }
+}
+
+/// Round trips against a real server, which needs the `test-support`
+/// controllers as well as the `client` feature.
+#[cfg(all(test, feature = "test-support"))]
+mod server_tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        controller_manager::{config::ManagerConfig, ControllerManager},
+        socket_server::{config::SocketServerConfig, SocketServer},
+        test_support::ScriptedController,
+    };
+
+    async fn start_server(
+        manager: &Arc<ControllerManager>,
+        dir: &std::path::Path,
+        name: &str,
+        tcp_addr: SocketAddr,
+    ) -> Result<SocketServer> {
+        let config = SocketServerConfig {
+            socket_path: dir.join(name).to_str().unwrap().to_string(),
+            tcp_addr: Some(tcp_addr),
+            ..Default::default()
+        };
+        let mut server = SocketServer::new(config, manager.clone());
+        server.start().await?;
+        Ok(server)
+    }
+
+    /// The next axis event, skipping connection changes.
+    async fn next_axis_event(events: &mut (impl Stream<Item = ClientEvent> + Unpin)) -> Value {
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+                .await
+                .unwrap()
+                .unwrap();
+            if let ClientEvent::Axis(event) = event {
+                return event;
+            }
+        }
+    }
+
+    async fn disable(client: &ReconnectingClient, disabled: bool) -> ServerResponse {
+        let command = if disabled {
+            ClientCommand::Disable {
+                controller: Some("stage".to_string()),
+                axis: "X".to_string(),
+                id: Some("d".to_string()),
+            }
+        } else {
+            ClientCommand::Enable {
+                controller: Some("stage".to_string()),
+                axis: "X".to_string(),
+                id: Some("e".to_string()),
+            }
+        };
+        client.request(command).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_and_resubscribes_after_server_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        manager
+            .register_controller(
+                "stage".to_string(),
+                Arc::new(ScriptedController::new("stage", &["X"])),
+            )
+            .await
+            .unwrap();
+        let server = start_server(
+            &manager,
+            dir.path(),
+            "first.sock",
+            "127.0.0.1:0".parse().unwrap(),
+        )
+        .await
+        .unwrap();
+        let addr = server.tcp_addr().unwrap();
+
+        let mut config = ClientConfig::new(Endpoint::Tcp(addr));
+        config.initial_backoff = Duration::from_millis(20);
+        let (client, mut events) = ReconnectingClient::start(config);
+        let subscription = client.subscribe("stage", "X").await.unwrap();
+
+        let reply = disable(&client, true).await;
+        assert!(matches!(&reply, ServerResponse::Success { id: Some(id), .. } if id == "d"));
+        let event = next_axis_event(&mut events).await;
+        assert_eq!(event["subscription"], subscription);
+        assert_eq!(event["new"], "disabled");
+
+        // Kill the server mid-stream and bring up another on the same port.
+        server.shutdown().await.unwrap();
+        let mut restarted = None;
+        for attempt in 0..50 {
+            let name = format!("restart-{}.sock", attempt);
+            if let Ok(server) = start_server(&manager, dir.path(), &name, addr).await {
+                restarted = Some(server);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let restarted = restarted.expect("server did not restart");
+
+        let mut saw_disconnect = false;
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+                .await
+                .unwrap()
+                .unwrap();
+            match event {
+                ClientEvent::Disconnected => saw_disconnect = true,
+                ClientEvent::Connected if saw_disconnect => break,
+                _ => {}
+            }
+        }
+
+        // The subscription came back under the same id.
+        disable(&client, false).await;
+        let event = next_axis_event(&mut events).await;
+        assert_eq!(event["subscription"], subscription);
+        assert_eq!(event["new"], "on");
+
+        restarted.shutdown().await.unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+use std::time::{Duration, Instant};
+
+use moka::{future::Cache, Expiry};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Storage for the values the manager reads from axes (positions, states,
+/// attributes), keyed by `cache_key`. Entries are expected to expire on
+/// their own; the manager only invalidates what a command has changed.
+#[async_trait::async_trait]
+pub trait PositionCache: Send + Sync {
+    async fn get(&self, key: &str) -> Option<Value>;
+    async fn insert(&self, key: String, value: Value);
+    /// Like `insert`, for an entry that should live for `ttl` rather than
+    /// the cache's default. Caches without per-entry lifetimes can keep the
+    /// default, which ignores `ttl`.
+    async fn insert_with_ttl(&self, key: String, value: Value, ttl: Duration) {
+        let _ = ttl;
+        self.insert(key, value).await;
+    }
+    /// Drops one entry, returning whether it was cached.
+    async fn invalidate(&self, key: &str) -> bool;
+    async fn invalidate_all(&self);
+    /// How many live entries the cache holds, if it can tell.
+    async fn entry_count(&self) -> Option<u64> {
+        None
+    }
+    /// The most entries the cache will hold, if it is bounded.
+    fn capacity(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// How full the cache is and how well it is doing, as reported by
+/// `ControllerManager::cache_stats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub entries: Option<u64>,
+    pub capacity: Option<u64>,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_ratio: Option<f64>,
+}
+
+/// The default in-process cache, bounded by `cache_capacity` entries that
+/// live for `default_ttl` unless inserted with a lifetime of their own.
+pub struct MokaCache {
+    cache: Cache<String, Entry>,
+    ttl: Duration,
+}
+
+#[derive(Clone)]
+struct Entry {
+    value: Value,
+    ttl: Duration,
+}
+
+/// Expires each entry its own `ttl` after it was last written.
+struct EntryTtl;
+
+impl Expiry<String, Entry> for EntryTtl {
+    fn expire_after_create(&self, _key: &String, entry: &Entry, _now: Instant) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        entry: &Entry,
+        _now: Instant,
+        _remaining: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+}
+
+impl MokaCache {
+    pub fn new(capacity: u64, ttl: Duration) -> Self {
+        Self {
+            cache: Cache::builder()
+                .max_capacity(capacity)
+                .expire_after(EntryTtl)
+                .build(),
+            ttl,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl PositionCache for MokaCache {
+    async fn get(&self, key: &str) -> Option<Value> {
+        self.cache.get(key).await.map(|entry| entry.value)
+    }
+
+    async fn insert(&self, key: String, value: Value) {
+        self.insert_with_ttl(key, value, self.ttl).await;
+    }
+
+    async fn insert_with_ttl(&self, key: String, value: Value, ttl: Duration) {
+        self.cache.insert(key, Entry { value, ttl }).await;
+    }
+
+    async fn invalidate(&self, key: &str) -> bool {
+        self.cache.remove(key).await.is_some()
+    }
+
+    async fn invalidate_all(&self) {
+        self.cache.invalidate_all();
+    }
+
+    /// Applies pending evictions and expirations first, which moka
+    /// otherwise defers, so the count isn't stale.
+    async fn entry_count(&self) -> Option<u64> {
+        self.cache.run_pending_tasks().await;
+        Some(self.cache.entry_count())
+    }
+
+    fn capacity(&self) -> Option<u64> {
+        self.cache.policy().max_capacity()
+    }
+}
+
+/// Caches nothing, so every read goes to the hardware. Useful when
+/// debugging a controller.
+pub struct NoOpCache;
+
+#[async_trait::async_trait]
+impl PositionCache for NoOpCache {
+    async fn get(&self, _key: &str) -> Option<Value> {
+        None
+    }
+
+    async fn insert(&self, _key: String, _value: Value) {}
+
+    async fn invalidate(&self, _key: &str) -> bool {
+        false
+    }
+
+    async fn invalidate_all(&self) {}
+
+    async fn entry_count(&self) -> Option<u64> {
+        Some(0)
+    }
+
+    fn capacity(&self) -> Option<u64> {
+        Some(0)
+    }
+}
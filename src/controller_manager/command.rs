@@ -1,4 +1,28 @@
-use crate::axis::movement_parameters::MovementParams;
+//! The commands a `ControllerManager` executes, and the order it executes
+//! them in.
+//!
+//! The manager runs each command in a task of its own, so commands sent
+//! concurrently, e.g. through clones of a `ManagerHandle`, may run in any
+//! order. Moves on one axis still never overlap, as each holds the axis's
+//! lock. Urgent commands (see `Command::is_urgent`) skip the queue, and
+//! they and the few others listed by `Command::skips_concurrency_limit`
+//! skip the controller's concurrency limit. To order two commands, await
+//! the first's reply before sending the second.
+//!
+//! A socket connection does exactly that: it reads its next command only
+//! after replying to the previous one, even when the client pipelines
+//! several without waiting. So commands from one connection run in the
+//! order they were sent, and a read sent after a move sees the move's
+//! result. The exception is a move sent with `"wait": false`, whose reply
+//! only means the move has started; a read after it may see the axis
+//! part-way.
+
+use std::time::Duration;
+
+use crate::axis::{
+    limits::LimitDirection, movement_parameters::MovementParams, state::AxisState,
+    stop_mode::StopMode, target_kind::TargetKind,
+};
 use anyhow::Result;
 use serde_json::Value;
 use tokio::sync::oneshot;
@@ -9,22 +33,141 @@ pub enum Command {
         controller: String,
         axis: String,
         target: f64,
+        /// Whether `target` is a position or a percentage of travel.
+        target_kind: TargetKind,
         params: Option<MovementParams>,
+        /// Validate the move without starting it.
+        dry_run: bool,
+        /// Reply once the move has finished. Otherwise reply as soon as it
+        /// has started; it can then be aborted with `Cancel`.
+        wait: bool,
+        /// Move even if the axis is in `Fault` or `Alarm`, for recovery.
+        force: bool,
+        /// Move even if the target is further than `max_move_distance`.
+        override_distance: bool,
+        /// The id of the socket connection that sent the move, so that
+        /// `StopOwnedMoves` can find it while it is in flight.
+        owner: Option<u64>,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    MoveToLimit {
+        controller: String,
+        axis: String,
+        direction: LimitDirection,
+        params: Option<MovementParams>,
+        /// Make the tripped position the new zero.
+        zero_position: bool,
+        /// As for `Move`.
+        owner: Option<u64>,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Moves every axis matching `axis_pattern` to `target` concurrently.
+    /// See `glob_match` for the pattern syntax.
+    MoveAll {
+        axis_pattern: String,
+        target: f64,
+        params: Option<MovementParams>,
+        /// Required for patterns that match every axis name.
+        confirm: bool,
+        /// As for `Move`; each axis's move is tagged with it.
+        owner: Option<u64>,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Moves every member of a group defined with `DefineGroup` (or
+    /// `ManagerConfig::axis_groups`) to `target` concurrently.
+    MoveGroup {
+        group: String,
+        target: f64,
+        params: Option<MovementParams>,
+        /// As for `MoveAll`.
+        owner: Option<u64>,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    StopGroup {
+        group: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// See `ControllerManager::define_group`.
+    DefineGroup {
+        group: String,
+        members: Vec<(String, String)>,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Cancels every in-flight move sent by connection `owner`, e.g.
+    /// because that connection has closed.
+    StopOwnedMoves {
+        owner: u64,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Stops every axis of one controller, leaving other controllers
+    /// running.
+    StopController {
+        controller: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Aborts the in-flight move on an axis and stops it.
+    Cancel {
+        controller: String,
+        axis: String,
         resp: oneshot::Sender<Result<Value>>,
     },
     Stop {
+        controller: String,
+        axis: String,
+        mode: StopMode,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    Pause {
         controller: String,
         axis: String,
         resp: oneshot::Sender<Result<Value>>,
     },
+    Resume {
+        controller: String,
+        axis: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    Enable {
+        controller: String,
+        axis: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    Disable {
+        controller: String,
+        axis: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Clears an `Alarm` or `Fault` on the axis.
+    ResetFault {
+        controller: String,
+        axis: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Declares the axis's current position to be `value` without moving.
+    SetReference {
+        controller: String,
+        axis: String,
+        value: f64,
+        resp: oneshot::Sender<Result<Value>>,
+    },
     GetState {
         controller: String,
         axis: String,
+        /// Read the hardware even if a cached value is still fresh.
+        fresh: bool,
         resp: oneshot::Sender<Result<Value>>,
     },
     GetPos {
         controller: String,
         axis: String,
+        /// Read the hardware even if a cached value is still fresh.
+        fresh: bool,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Positions of several axes of one controller, read concurrently.
+    GetPositions {
+        controller: String,
+        axes: Vec<String>,
         resp: oneshot::Sender<Result<Value>>,
     },
     GetAttr {
@@ -33,6 +176,60 @@ pub enum Command {
         attr: String,
         resp: oneshot::Sender<Result<Value>>,
     },
+    /// Reads an array-valued attribute; see `Axis::get_attribute_array`.
+    GetAttrArray {
+        controller: String,
+        axis: String,
+        attr: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Writes a numeric attribute. With `verify` the attribute is read
+    /// back from the hardware afterwards and must be within `tolerance` of
+    /// `value`, or the command fails with `VERIFY_FAILED`.
+    SetAttr {
+        controller: String,
+        axis: String,
+        attr: String,
+        value: f64,
+        verify: bool,
+        tolerance: Option<f64>,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// An attribute of the controller itself, not of any axis.
+    GetControllerAttr {
+        controller: String,
+        attr: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Fresh state of every axis of one controller, read concurrently, with
+    /// flags summarising them all.
+    ControllerHealth {
+        controller: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Resolves once the axis is in `state`, or with `WAIT_TIMEOUT` after
+    /// `timeout`.
+    WaitForState {
+        controller: String,
+        axis: String,
+        state: AxisState,
+        timeout: Duration,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// The axis's most recent moves, newest first.
+    GetHistory {
+        controller: String,
+        axis: String,
+        limit: Option<usize>,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Unit, range and writability of one attribute.
+    GetAttrInfo {
+        controller: String,
+        axis: String,
+        attr: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
     GetAvailableParams {
         controller: String,
         axis: String,
@@ -43,11 +240,353 @@ pub enum Command {
         axis: String,
         resp: oneshot::Sender<Result<Value>>,
     },
+    GetCapabilities {
+        controller: String,
+        axis: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    GetLimits {
+        controller: String,
+        axis: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// The positions the axis could reach from where it is within
+    /// `time_ms`, within its soft limits.
+    ReachableRange {
+        controller: String,
+        axis: String,
+        time_ms: u64,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Reads only the limit switches, for cheap safety polls.
+    GetLimitSwitches {
+        controller: String,
+        axis: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Sets the parameters merged into every move of the axis. Empty
+    /// parameters clear them.
+    SetDefaults {
+        controller: String,
+        axis: String,
+        params: MovementParams,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// The parameters a move with `params` would use, after defaults and
+    /// safety limits, without moving.
+    ResolveParams {
+        controller: String,
+        axis: String,
+        params: Option<MovementParams>,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Runs the controller's diagnostics; see `MotorController::self_test`.
+    SelfTest {
+        controller: String,
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    Invalidate {
+        controller: String,
+        axis: Option<String>,
+        resp: oneshot::Sender<Result<Value>>,
+    },
     ListControllers {
         resp: oneshot::Sender<Result<Value>>,
     },
+    /// Every alias and the controller it stands for.
+    ListAliases {
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// Topology of every registered controller and its axes.
+    Describe {
+        resp: oneshot::Sender<Result<Value>>,
+    },
+    /// `ControllerHealth` for every registered controller at once.
+    SystemHealth {
+        resp: oneshot::Sender<Result<Value>>,
+    },
     ListAxes {
         controller: String,
+        /// Include each axis's current state and position, not just its name.
+        detailed: bool,
+        /// Only axes whose name matches this glob.
+        filter: Option<String>,
+        /// Axes to skip, after filtering.
+        offset: usize,
+        /// Most axes to return, after the offset.
+        limit: Option<usize>,
         resp: oneshot::Sender<Result<Value>>,
     },
 }
+
+impl Command {
+    /// The controller the command addresses, if any.
+    pub fn controller(&self) -> Option<&str> {
+        match self {
+            Command::Move { controller, .. }
+            | Command::MoveToLimit { controller, .. }
+            | Command::StopController { controller, .. }
+            | Command::Cancel { controller, .. }
+            | Command::Stop { controller, .. }
+            | Command::Pause { controller, .. }
+            | Command::Resume { controller, .. }
+            | Command::Enable { controller, .. }
+            | Command::Disable { controller, .. }
+            | Command::ResetFault { controller, .. }
+            | Command::SetReference { controller, .. }
+            | Command::GetState { controller, .. }
+            | Command::GetPos { controller, .. }
+            | Command::GetPositions { controller, .. }
+            | Command::ControllerHealth { controller, .. }
+            | Command::GetAttr { controller, .. }
+            | Command::GetAttrArray { controller, .. }
+            | Command::SetAttr { controller, .. }
+            | Command::GetControllerAttr { controller, .. }
+            | Command::GetAttrInfo { controller, .. }
+            | Command::WaitForState { controller, .. }
+            | Command::GetHistory { controller, .. }
+            | Command::GetAvailableParams { controller, .. }
+            | Command::GetSupportedMovementParams { controller, .. }
+            | Command::GetCapabilities { controller, .. }
+            | Command::GetLimits { controller, .. }
+            | Command::ReachableRange { controller, .. }
+            | Command::GetLimitSwitches { controller, .. }
+            | Command::SetDefaults { controller, .. }
+            | Command::ResolveParams { controller, .. }
+            | Command::SelfTest { controller, .. }
+            | Command::Invalidate { controller, .. }
+            | Command::ListAxes { controller, .. } => Some(controller),
+            Command::MoveAll { .. }
+            | Command::MoveGroup { .. }
+            | Command::StopGroup { .. }
+            | Command::DefineGroup { .. }
+            | Command::StopOwnedMoves { .. }
+            | Command::ListControllers { .. }
+            | Command::ListAliases { .. }
+            | Command::Describe { .. }
+            | Command::SystemHealth { .. } => None,
+        }
+    }
+
+    /// Mutable access to the addressed controller name, so the manager can
+    /// resolve aliases before handling the command.
+    pub fn controller_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Command::Move { controller, .. }
+            | Command::MoveToLimit { controller, .. }
+            | Command::StopController { controller, .. }
+            | Command::Cancel { controller, .. }
+            | Command::Stop { controller, .. }
+            | Command::Pause { controller, .. }
+            | Command::Resume { controller, .. }
+            | Command::Enable { controller, .. }
+            | Command::Disable { controller, .. }
+            | Command::ResetFault { controller, .. }
+            | Command::SetReference { controller, .. }
+            | Command::GetState { controller, .. }
+            | Command::GetPos { controller, .. }
+            | Command::GetPositions { controller, .. }
+            | Command::ControllerHealth { controller, .. }
+            | Command::GetAttr { controller, .. }
+            | Command::GetAttrArray { controller, .. }
+            | Command::SetAttr { controller, .. }
+            | Command::GetControllerAttr { controller, .. }
+            | Command::GetAttrInfo { controller, .. }
+            | Command::WaitForState { controller, .. }
+            | Command::GetHistory { controller, .. }
+            | Command::GetAvailableParams { controller, .. }
+            | Command::GetSupportedMovementParams { controller, .. }
+            | Command::GetCapabilities { controller, .. }
+            | Command::GetLimits { controller, .. }
+            | Command::ReachableRange { controller, .. }
+            | Command::GetLimitSwitches { controller, .. }
+            | Command::SetDefaults { controller, .. }
+            | Command::ResolveParams { controller, .. }
+            | Command::SelfTest { controller, .. }
+            | Command::Invalidate { controller, .. }
+            | Command::ListAxes { controller, .. } => Some(controller),
+            Command::MoveAll { .. }
+            | Command::MoveGroup { .. }
+            | Command::StopGroup { .. }
+            | Command::DefineGroup { .. }
+            | Command::StopOwnedMoves { .. }
+            | Command::ListControllers { .. }
+            | Command::ListAliases { .. }
+            | Command::Describe { .. }
+            | Command::SystemHealth { .. } => None,
+        }
+    }
+
+    /// Mutable access to the addressed axis names, so the manager can
+    /// resolve them when `case_insensitive_axes` is set.
+    pub fn axes_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Command::Move { axis, .. }
+            | Command::MoveToLimit { axis, .. }
+            | Command::Cancel { axis, .. }
+            | Command::Stop { axis, .. }
+            | Command::Pause { axis, .. }
+            | Command::Resume { axis, .. }
+            | Command::Enable { axis, .. }
+            | Command::Disable { axis, .. }
+            | Command::ResetFault { axis, .. }
+            | Command::SetReference { axis, .. }
+            | Command::GetState { axis, .. }
+            | Command::GetPos { axis, .. }
+            | Command::GetAttr { axis, .. }
+            | Command::GetAttrArray { axis, .. }
+            | Command::SetAttr { axis, .. }
+            | Command::WaitForState { axis, .. }
+            | Command::GetHistory { axis, .. }
+            | Command::GetAttrInfo { axis, .. }
+            | Command::GetAvailableParams { axis, .. }
+            | Command::GetSupportedMovementParams { axis, .. }
+            | Command::GetCapabilities { axis, .. }
+            | Command::GetLimits { axis, .. }
+            | Command::ReachableRange { axis, .. }
+            | Command::GetLimitSwitches { axis, .. }
+            | Command::SetDefaults { axis, .. }
+            | Command::ResolveParams { axis, .. } => vec![axis],
+            Command::GetPositions { axes, .. } => axes.iter_mut().collect(),
+            Command::Invalidate { axis, .. } => axis.iter_mut().collect(),
+            Command::MoveAll { .. }
+            | Command::MoveGroup { .. }
+            | Command::StopGroup { .. }
+            | Command::DefineGroup { .. }
+            | Command::StopOwnedMoves { .. }
+            | Command::StopController { .. }
+            | Command::GetControllerAttr { .. }
+            | Command::ControllerHealth { .. }
+            | Command::SelfTest { .. }
+            | Command::ListControllers { .. }
+            | Command::ListAliases { .. }
+            | Command::Describe { .. }
+            | Command::SystemHealth { .. }
+            | Command::ListAxes { .. } => Vec::new(),
+        }
+    }
+
+    /// Commands that halt motion, which go through their own queue so they
+    /// are dispatched ahead of any backlog of moves.
+    pub fn is_urgent(&self) -> bool {
+        matches!(
+            self,
+            Command::Stop { .. }
+                | Command::StopOwnedMoves { .. }
+                | Command::StopController { .. }
+                | Command::StopGroup { .. }
+                | Command::Cancel { .. }
+        )
+    }
+
+    /// Commands that must reach the hardware even while a move is holding
+    /// the axis, so they skip the controller's concurrency limit. Urgent
+    /// commands always do; the others here still wait their turn in the
+    /// queue.
+    pub fn skips_concurrency_limit(&self) -> bool {
+        self.is_urgent()
+            || matches!(
+                self,
+                Command::Pause { .. }
+                    | Command::Resume { .. }
+                    | Command::Enable { .. }
+                    | Command::Disable { .. }
+                    | Command::ResetFault { .. }
+            )
+    }
+
+    /// The command's reply channel, for answering it without running it.
+    pub(crate) fn into_resp(self) -> oneshot::Sender<Result<Value>> {
+        match self {
+            Command::Move { resp, .. }
+            | Command::MoveToLimit { resp, .. }
+            | Command::MoveAll { resp, .. }
+            | Command::MoveGroup { resp, .. }
+            | Command::StopGroup { resp, .. }
+            | Command::DefineGroup { resp, .. }
+            | Command::StopOwnedMoves { resp, .. }
+            | Command::StopController { resp, .. }
+            | Command::Cancel { resp, .. }
+            | Command::Stop { resp, .. }
+            | Command::Pause { resp, .. }
+            | Command::Resume { resp, .. }
+            | Command::Enable { resp, .. }
+            | Command::Disable { resp, .. }
+            | Command::ResetFault { resp, .. }
+            | Command::SetReference { resp, .. }
+            | Command::GetState { resp, .. }
+            | Command::GetPos { resp, .. }
+            | Command::GetPositions { resp, .. }
+            | Command::GetAttr { resp, .. }
+            | Command::GetAttrArray { resp, .. }
+            | Command::SetAttr { resp, .. }
+            | Command::GetControllerAttr { resp, .. }
+            | Command::ControllerHealth { resp, .. }
+            | Command::WaitForState { resp, .. }
+            | Command::GetHistory { resp, .. }
+            | Command::GetAttrInfo { resp, .. }
+            | Command::GetAvailableParams { resp, .. }
+            | Command::GetSupportedMovementParams { resp, .. }
+            | Command::GetCapabilities { resp, .. }
+            | Command::GetLimits { resp, .. }
+            | Command::ReachableRange { resp, .. }
+            | Command::GetLimitSwitches { resp, .. }
+            | Command::SetDefaults { resp, .. }
+            | Command::ResolveParams { resp, .. }
+            | Command::SelfTest { resp, .. }
+            | Command::Invalidate { resp, .. }
+            | Command::ListControllers { resp, .. }
+            | Command::ListAliases { resp, .. }
+            | Command::Describe { resp, .. }
+            | Command::SystemHealth { resp, .. }
+            | Command::ListAxes { resp, .. } => resp,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Command::Move { .. } => "move",
+            Command::MoveToLimit { .. } => "move_to_limit",
+            Command::MoveAll { .. } => "move_all",
+            Command::MoveGroup { .. } => "move_group",
+            Command::StopGroup { .. } => "stop_group",
+            Command::DefineGroup { .. } => "define_group",
+            Command::StopOwnedMoves { .. } => "stop_owned_moves",
+            Command::StopController { .. } => "stop_controller",
+            Command::Cancel { .. } => "cancel",
+            Command::Stop { .. } => "stop",
+            Command::Pause { .. } => "pause",
+            Command::Resume { .. } => "resume",
+            Command::Enable { .. } => "enable",
+            Command::Disable { .. } => "disable",
+            Command::ResetFault { .. } => "reset_fault",
+            Command::SetReference { .. } => "set_reference",
+            Command::GetState { .. } => "get_state",
+            Command::GetPos { .. } => "get_position",
+            Command::GetPositions { .. } => "get_positions",
+            Command::ControllerHealth { .. } => "controller_health",
+            Command::GetAttr { .. } => "get_attribute",
+            Command::GetAttrArray { .. } => "get_attribute_array",
+            Command::SetAttr { .. } => "set_attribute",
+            Command::GetControllerAttr { .. } => "get_controller_attribute",
+            Command::GetAttrInfo { .. } => "get_attribute_info",
+            Command::WaitForState { .. } => "wait_for_state",
+            Command::GetHistory { .. } => "get_history",
+            Command::GetAvailableParams { .. } => "get_available_params",
+            Command::GetSupportedMovementParams { .. } => "get_supported_movement_params",
+            Command::GetCapabilities { .. } => "get_capabilities",
+            Command::GetLimits { .. } => "get_limits",
+            Command::ReachableRange { .. } => "reachable_range",
+            Command::GetLimitSwitches { .. } => "get_limit_switches",
+            Command::SetDefaults { .. } => "set_defaults",
+            Command::ResolveParams { .. } => "resolve_params",
+            Command::SelfTest { .. } => "self_test",
+            Command::Invalidate { .. } => "invalidate_cache",
+            Command::ListControllers { .. } => "list_controllers",
+            Command::ListAliases { .. } => "list_aliases",
+            Command::ListAxes { .. } => "list_axes",
+            Command::Describe { .. } => "describe",
+            Command::SystemHealth { .. } => "system_health",
+        }
+    }
+}
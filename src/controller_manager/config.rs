@@ -1,6 +1,111 @@
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
+
+/// What `max_velocity` and `max_acceleration` do to a move that asks for
+/// more.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SafetyLimitMode {
+    /// Lower the value to the limit and carry on with the move.
+    #[default]
+    Clamp,
+    /// Refuse the move with `SAFETY_LIMIT`.
+    Reject,
+}
+
+/// What reading an attribute the axis doesn't have returns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingAttributePolicy {
+    /// Fail the read.
+    #[default]
+    Error,
+    /// Succeed with a `null` value and `"supported": false`, for clients
+    /// that probe attributes.
+    Null,
+}
 
 pub struct ManagerConfig {
     pub default_ttl: Duration,
+    /// Cache lifetimes by attribute name, overriding `default_ttl`, so that
+    /// e.g. `max_position` can be cached for minutes and `position` for
+    /// milliseconds. `position` and `status` (the axis state) count as
+    /// attributes here.
+    pub attribute_ttls: HashMap<String, Duration>,
     pub cache_capacity: usize,
+    /// How often the background poller reads every axis state to detect
+    /// transitions. Polling is disabled when `None`.
+    pub poll_interval: Option<Duration>,
+    pub event_capacity: usize,
+    /// How long `ControllerManager::shutdown_all` waits for each controller's
+    /// `shutdown` before giving up on it.
+    pub shutdown_timeout: Duration,
+    /// How long a system health report waits for each controller before
+    /// reporting it as unresponsive.
+    pub health_timeout: Duration,
+    /// Upper bound on how long a command may take before its caller gets a
+    /// `TIMEOUT` error instead. Moves are exempt. Unbounded when `None`.
+    pub command_timeout: Option<Duration>,
+    /// Limit on concurrent commands per controller, for controllers that
+    /// don't declare their own via `MotorController::max_concurrent_commands`.
+    /// Unlimited when `None`.
+    pub max_concurrent_commands: Option<usize>,
+    /// Limit on socket subscriptions across all clients, protecting the
+    /// event bus from a flood of subscribers. Unlimited when `None`.
+    pub max_subscriptions: Option<usize>,
+    /// Cap on the velocity of any move, whatever the client or the axis
+    /// defaults ask for.
+    pub max_velocity: Option<f64>,
+    /// Cap on the acceleration and deceleration of any move.
+    pub max_acceleration: Option<f64>,
+    pub safety_limit_mode: SafetyLimitMode,
+    /// Guard against runaway moves: a move whose target is further than
+    /// this from the axis's current position, in user units, is refused
+    /// with `MOVE_TOO_LARGE` unless it is sent with `override`.
+    pub max_move_distance: Option<f64>,
+    /// Per-axis `max_move_distance`, by controller and axis name, replacing
+    /// the global one for those axes.
+    pub axis_max_move_distance: HashMap<(String, String), f64>,
+    /// Decimal places positions and numeric attribute values are rounded
+    /// to in replies, e.g. 3 for an encoder resolving micrometres in
+    /// millimetre units. Full precision when unset.
+    pub position_decimals: Option<u32>,
+    /// Per-axis `position_decimals`, by controller and axis name, replacing
+    /// the global one for those axes.
+    pub axis_position_decimals: HashMap<(String, String), u32>,
+    /// Match axis names in commands regardless of case, so `x` reaches
+    /// axis `X`. Controllers with two axes differing only by case are then
+    /// refused at registration. Names must match exactly when unset.
+    pub case_insensitive_axes: bool,
+    /// Named sets of axes, by controller and axis name, for `move_group`
+    /// and `stop_group`. More can be added with `define_group`.
+    pub axis_groups: HashMap<String, Vec<(String, String)>>,
+    /// How many moves per axis `get_history` remembers; 0 disables it.
+    pub move_history_size: usize,
+    pub missing_attribute_policy: MissingAttributePolicy,
+}
+
+impl Default for ManagerConfig {
+    fn default() -> Self {
+        Self {
+            default_ttl: Duration::from_secs(5),
+            attribute_ttls: HashMap::new(),
+            cache_capacity: 1000,
+            poll_interval: None,
+            event_capacity: 256,
+            shutdown_timeout: Duration::from_secs(5),
+            health_timeout: Duration::from_secs(2),
+            command_timeout: None,
+            max_concurrent_commands: None,
+            max_subscriptions: None,
+            max_velocity: None,
+            max_acceleration: None,
+            safety_limit_mode: SafetyLimitMode::default(),
+            max_move_distance: None,
+            axis_max_move_distance: HashMap::new(),
+            position_decimals: None,
+            axis_position_decimals: HashMap::new(),
+            case_insensitive_axes: false,
+            axis_groups: HashMap::new(),
+            move_history_size: 32,
+            missing_attribute_policy: MissingAttributePolicy::default(),
+        }
+    }
 }
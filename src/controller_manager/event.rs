@@ -0,0 +1,280 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::axis::{limit_switches::LimitSwitches, state::AxisState, state_info::AxisStateInfo};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AxisEvent {
+    pub controller: String,
+    pub axis: String,
+    #[serde(flatten)]
+    pub kind: AxisEventKind,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AxisEventKind {
+    /// The observed state of the axis changed. `old` is `None` the first
+    /// time the manager sees the axis.
+    StateChanged {
+        old: Option<AxisState>,
+        new: AxisState,
+    },
+    /// A limit switch engaged or released. `old` is `None` the first time
+    /// the manager sees the axis, which is only reported if a switch is
+    /// already engaged.
+    LimitSwitchChanged {
+        old: Option<LimitSwitches>,
+        new: LimitSwitches,
+    },
+    /// Progress of the move in progress, between 0.0 and 1.0. Published by
+    /// the poller for moving axes that report it.
+    Progress { fraction: f64 },
+    /// An attribute was written through the manager, by any client.
+    AttributeChanged { attribute: String, value: f64 },
+    /// The controller reported an axis it didn't have before.
+    AxisAdded,
+    /// The controller no longer reports the axis.
+    AxisRemoved,
+}
+
+/// The variant of an `AxisEventKind`, without its data, for filtering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AxisEventType {
+    StateChanged,
+    LimitSwitchChanged,
+    Progress,
+    AttributeChanged,
+    AxisAdded,
+    AxisRemoved,
+}
+
+impl AxisEventKind {
+    pub fn event_type(&self) -> AxisEventType {
+        match self {
+            AxisEventKind::StateChanged { .. } => AxisEventType::StateChanged,
+            AxisEventKind::LimitSwitchChanged { .. } => AxisEventType::LimitSwitchChanged,
+            AxisEventKind::Progress { .. } => AxisEventType::Progress,
+            AxisEventKind::AttributeChanged { .. } => AxisEventType::AttributeChanged,
+            AxisEventKind::AxisAdded => AxisEventType::AxisAdded,
+            AxisEventKind::AxisRemoved => AxisEventType::AxisRemoved,
+        }
+    }
+}
+
+/// Selects the events yielded by `ControllerManager::subscribe_filtered`.
+/// Each criterion left unset matches everything, so the default filter
+/// passes every event.
+///
+/// ```
+/// use motarem::controller_manager::event::{AxisEventType, EventFilter};
+///
+/// let filter = EventFilter::new()
+///     .controller("stage")
+///     .axis("X")
+///     .event_type(AxisEventType::StateChanged)
+///     .event_type(AxisEventType::LimitSwitchChanged);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    controller: Option<String>,
+    axis: Option<String>,
+    event_types: Option<Vec<AxisEventType>>,
+}
+
+impl EventFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn controller(mut self, controller: impl Into<String>) -> Self {
+        self.controller = Some(controller.into());
+        self
+    }
+
+    /// Matches axes of this name on any controller, unless `controller`
+    /// narrows it down.
+    pub fn axis(mut self, axis: impl Into<String>) -> Self {
+        self.axis = Some(axis.into());
+        self
+    }
+
+    /// Adds a type of event to match; any type matches until one is added.
+    pub fn event_type(mut self, event_type: AxisEventType) -> Self {
+        self.event_types
+            .get_or_insert_with(Vec::new)
+            .push(event_type);
+        self
+    }
+
+    pub fn matches(&self, event: &AxisEvent) -> bool {
+        self.controller
+            .as_ref()
+            .is_none_or(|controller| *controller == event.controller)
+            && self.axis.as_ref().is_none_or(|axis| *axis == event.axis)
+            && self
+                .event_types
+                .as_ref()
+                .is_none_or(|types| types.contains(&event.kind.event_type()))
+    }
+}
+
+/// The events from `receiver` that pass `filter`. Events that don't are
+/// dropped as they arrive, so they take up no room in the stream; a
+/// consumer that falls behind the channel anyway skips what it missed,
+/// with a warning. Ends when the bus is dropped.
+pub fn filtered(
+    receiver: broadcast::Receiver<AxisEvent>,
+    filter: EventFilter,
+) -> impl Stream<Item = AxisEvent> + Send + 'static {
+    futures::stream::unfold(receiver, move |mut receiver| {
+        let filter = filter.clone();
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if filter.matches(&event) => return Some((event, receiver)),
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!(
+                            "Filtered event subscriber lagged, skipped {} events",
+                            missed
+                        );
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }
+    })
+}
+
+/// Fan-out of axis events to any number of subscribers.
+///
+/// The bus remembers the last state observed for every axis so that only
+/// actual transitions are published, regardless of whether the observation
+/// came from a command handler or the background poller.
+pub struct EventBus {
+    sender: broadcast::Sender<AxisEvent>,
+    last_states: Mutex<HashMap<(String, String), AxisState>>,
+    last_limit_switches: Mutex<HashMap<(String, String), LimitSwitches>>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender,
+            last_states: Mutex::new(HashMap::new()),
+            last_limit_switches: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AxisEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Records a full state read: the state and the limit switches.
+    pub fn observe(&self, controller: &str, axis: &str, info: &AxisStateInfo) {
+        self.observe_state(controller, axis, info.state);
+        self.observe_limit_switches(controller, axis, info.limit_switches);
+    }
+
+    pub fn observe_limit_switches(&self, controller: &str, axis: &str, switches: LimitSwitches) {
+        let old = self
+            .last_limit_switches
+            .lock()
+            .unwrap()
+            .insert((controller.to_string(), axis.to_string()), switches);
+
+        if old.unwrap_or(LimitSwitches::None) != switches {
+            self.publish(
+                controller,
+                axis,
+                AxisEventKind::LimitSwitchChanged { old, new: switches },
+            );
+        }
+    }
+
+    pub fn observe_state(&self, controller: &str, axis: &str, state: AxisState) {
+        let old = self
+            .last_states
+            .lock()
+            .unwrap()
+            .insert((controller.to_string(), axis.to_string()), state);
+
+        if old != Some(state) {
+            self.publish(
+                controller,
+                axis,
+                AxisEventKind::StateChanged { old, new: state },
+            );
+        }
+    }
+
+    /// Drops what was last observed of an axis that has gone away, so it
+    /// is reported afresh if it comes back.
+    pub fn forget(&self, controller: &str, axis: &str) {
+        let key = (controller.to_string(), axis.to_string());
+        self.last_states.lock().unwrap().remove(&key);
+        self.last_limit_switches.lock().unwrap().remove(&key);
+    }
+
+    pub fn publish(&self, controller: &str, axis: &str, kind: AxisEventKind) {
+        // Sending only fails when nobody is subscribed, which is fine.
+        let _ = self.sender.send(AxisEvent {
+            controller: controller.to_string(),
+            axis: axis.to_string(),
+            kind,
+            timestamp: Utc::now(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_filtered_stream_yields_only_matching_events() {
+        // Far smaller than the number of events published, so the consumer
+        // would lag if skipped events piled up anywhere.
+        let bus = EventBus::new(4);
+        let filter = EventFilter::new()
+            .controller("stage")
+            .axis("X")
+            .event_type(AxisEventType::StateChanged);
+        let consumer = tokio::spawn(filtered(bus.subscribe(), filter).collect::<Vec<_>>());
+
+        for i in 0..100 {
+            let (controller, axis) = match i % 4 {
+                0 => ("stage", "X"),
+                1 => ("stage", "Y"),
+                2 => ("other", "X"),
+                _ => ("stage", "X"),
+            };
+            let kind = if i % 4 == 3 {
+                AxisEventKind::Progress { fraction: 0.5 }
+            } else {
+                AxisEventKind::StateChanged {
+                    old: None,
+                    new: AxisState::Moving,
+                }
+            };
+            bus.publish(controller, axis, kind);
+            tokio::task::yield_now().await;
+        }
+        drop(bus);
+
+        let events = consumer.await.unwrap();
+        assert_eq!(events.len(), 25);
+        assert!(events.iter().all(|event| event.controller == "stage"
+            && event.axis == "X"
+            && event.kind.event_type() == AxisEventType::StateChanged));
+    }
+}
@@ -0,0 +1,253 @@
+//! A typed front end to the command queue of a `ControllerManager`.
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use motarem::controller_manager::{config::ManagerConfig, ControllerManager};
+//! # use motarem::{
+//! #     axis::{movement_parameters::MovementParams, state::AxisState, state_info::AxisStateInfo, Axis},
+//! #     motor_controller::MotorController,
+//! # };
+//! # struct Stage { position: std::sync::Mutex<f64> }
+//! # #[async_trait::async_trait]
+//! # impl Axis for Stage {
+//! #     fn name(&self) -> &str { "X" }
+//! #     async fn start(&self, target: f64, _: Option<MovementParams>) -> anyhow::Result<()> {
+//! #         *self.position.lock().unwrap() = target;
+//! #         Ok(())
+//! #     }
+//! #     async fn stop(&self) -> anyhow::Result<()> { Ok(()) }
+//! #     async fn get_state(&self) -> anyhow::Result<AxisStateInfo> { Ok(AxisStateInfo::new(AxisState::On)) }
+//! #     async fn get_attribute(&self, _: &str) -> anyhow::Result<f64> { Ok(*self.position.lock().unwrap()) }
+//! # }
+//! # struct StageController(Vec<Arc<dyn Axis>>);
+//! # impl MotorController for StageController {
+//! #     fn name(&self) -> &str { "stage" }
+//! #     fn axes(&self) -> Vec<Arc<dyn Axis>> { self.0.clone() }
+//! # }
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> anyhow::Result<()> {
+//! # let stage = StageController(vec![Arc::new(Stage { position: Default::default() })]);
+//! let manager = ControllerManager::new(ManagerConfig::default());
+//! manager.register_controller("stage".to_string(), Arc::new(stage)).await?;
+//!
+//! let handle = manager.handle();
+//! handle.move_to("stage", "X", 12.5, None).await?;
+//! assert_eq!(handle.get_position("stage", "X").await?, 12.5);
+//! assert_eq!(handle.get_state("stage", "X").await?["state"], "on");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+
+use super::command::Command;
+use crate::{
+    axis::{movement_parameters::MovementParams, stop_mode::StopMode, target_kind::TargetKind},
+    protocol::error::CommandError,
+};
+
+/// Sends commands to a manager and awaits their replies. Cloning it only
+/// clones the queue senders, so each task can keep its own. Obtained from
+/// `ControllerManager::handle`.
+#[derive(Clone)]
+pub struct ManagerHandle {
+    cmd_sender: mpsc::Sender<Command>,
+    /// Carries urgent commands, which the command loop drains first.
+    urgent_sender: mpsc::Sender<Command>,
+}
+
+impl ManagerHandle {
+    pub(super) fn new(
+        cmd_sender: mpsc::Sender<Command>,
+        urgent_sender: mpsc::Sender<Command>,
+    ) -> Self {
+        Self {
+            cmd_sender,
+            urgent_sender,
+        }
+    }
+
+    /// Moves the axis and returns once the move has finished.
+    pub async fn move_to(
+        &self,
+        controller: &str,
+        axis: &str,
+        target: f64,
+        params: Option<MovementParams>,
+    ) -> Result<Value> {
+        self.request(|resp| Command::Move {
+            controller: controller.to_string(),
+            axis: axis.to_string(),
+            target,
+            target_kind: TargetKind::Absolute,
+            params,
+            dry_run: false,
+            wait: true,
+            force: false,
+            override_distance: false,
+            owner: None,
+            resp,
+        })
+        .await
+    }
+
+    pub async fn stop(&self, controller: &str, axis: &str) -> Result<Value> {
+        self.request(|resp| Command::Stop {
+            controller: controller.to_string(),
+            axis: axis.to_string(),
+            mode: StopMode::Immediate,
+            resp,
+        })
+        .await
+    }
+
+    pub async fn enable(&self, controller: &str, axis: &str) -> Result<Value> {
+        self.request(|resp| Command::Enable {
+            controller: controller.to_string(),
+            axis: axis.to_string(),
+            resp,
+        })
+        .await
+    }
+
+    pub async fn disable(&self, controller: &str, axis: &str) -> Result<Value> {
+        self.request(|resp| Command::Disable {
+            controller: controller.to_string(),
+            axis: axis.to_string(),
+            resp,
+        })
+        .await
+    }
+
+    pub async fn get_position(&self, controller: &str, axis: &str) -> Result<f64> {
+        let response = self
+            .request(|resp| Command::GetPos {
+                controller: controller.to_string(),
+                axis: axis.to_string(),
+                fresh: false,
+                resp,
+            })
+            .await?;
+        response["position"]
+            .as_f64()
+            .ok_or_else(|| anyhow::anyhow!("Position of {}::{} is not a number", controller, axis))
+    }
+
+    /// The axis status as returned by `get_state`.
+    pub async fn get_state(&self, controller: &str, axis: &str) -> Result<Value> {
+        let mut response = self
+            .request(|resp| Command::GetState {
+                controller: controller.to_string(),
+                axis: axis.to_string(),
+                fresh: false,
+                resp,
+            })
+            .await?;
+        Ok(response["status"].take())
+    }
+
+    /// The attribute's value alone, which need not be a number.
+    pub async fn get_attribute(&self, controller: &str, axis: &str, attr: &str) -> Result<Value> {
+        let mut response = self
+            .request(|resp| Command::GetAttr {
+                controller: controller.to_string(),
+                axis: axis.to_string(),
+                attr: attr.to_string(),
+                resp,
+            })
+            .await?;
+        Ok(response["value"].take())
+    }
+
+    pub async fn get_available_params(&self, controller: &str, axis: &str) -> Result<Value> {
+        self.request(|resp| Command::GetAvailableParams {
+            controller: controller.to_string(),
+            axis: axis.to_string(),
+            resp,
+        })
+        .await
+    }
+
+    pub async fn get_supported_movement_params(
+        &self,
+        controller: &str,
+        axis: &str,
+    ) -> Result<Value> {
+        self.request(|resp| Command::GetSupportedMovementParams {
+            controller: controller.to_string(),
+            axis: axis.to_string(),
+            resp,
+        })
+        .await
+    }
+
+    /// Sends any command and waits for its reply.
+    pub async fn request<F>(&self, build: F) -> Result<Value>
+    where
+        F: FnOnce(oneshot::Sender<Result<Value>>) -> Command,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.send_command(build(tx)).await?;
+        rx.await?
+    }
+
+    /// See `ControllerManager::send_command`.
+    pub async fn send_command(&self, cmd: Command) -> Result<()> {
+        if !self.is_running() {
+            return Err(manager_down());
+        }
+        let sent = if cmd.is_urgent() {
+            self.urgent_sender.send(cmd).await
+        } else {
+            self.cmd_sender.send(cmd).await
+        };
+        sent.map_err(|_| manager_down())
+    }
+
+    /// See `ControllerManager::try_send_command`.
+    pub fn try_send_command(&self, cmd: Command) -> Result<()> {
+        let sender = if cmd.is_urgent() {
+            &self.urgent_sender
+        } else {
+            &self.cmd_sender
+        };
+        sender.try_send(cmd).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => {
+                CommandError::new("QUEUE_FULL", "Command queue is full").into()
+            }
+            mpsc::error::TrySendError::Closed(_) => manager_down(),
+        })
+    }
+
+    /// See `ControllerManager::send_command_timeout`.
+    pub async fn send_command_timeout(&self, cmd: Command, timeout: Duration) -> Result<()> {
+        tokio::time::timeout(timeout, self.send_command(cmd))
+            .await
+            .map_err(|_| {
+                CommandError::new(
+                    "QUEUE_FULL",
+                    format!("Command queue still full after {:?}", timeout),
+                )
+            })?
+    }
+
+    /// See `ControllerManager::queue_depth`.
+    pub fn queue_depth(&self) -> usize {
+        let queued = |sender: &mpsc::Sender<Command>| sender.max_capacity() - sender.capacity();
+        queued(&self.cmd_sender) + queued(&self.urgent_sender)
+    }
+
+    /// See `ControllerManager::is_running`.
+    pub fn is_running(&self) -> bool {
+        !self.cmd_sender.is_closed() && !self.urgent_sender.is_closed()
+    }
+}
+
+fn manager_down() -> anyhow::Error {
+    CommandError::new("MANAGER_DOWN", "Controller manager is not running").into()
+}
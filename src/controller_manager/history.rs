@@ -0,0 +1,89 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::axis::movement_parameters::MovementParams;
+
+/// One move issued to an axis, as requested, and how it ended.
+#[derive(Debug, Clone, Serialize)]
+pub struct MoveRecord {
+    pub timestamp: DateTime<Utc>,
+    pub target: f64,
+    pub params: Option<MovementParams>,
+    /// The error the move failed with, if any. A move that isn't waited for
+    /// gets its error once it has ended.
+    pub error: Option<String>,
+}
+
+/// The last few moves of every axis, oldest dropped first.
+///
+/// Recording takes a short synchronous lock and never awaits, so it adds
+/// next to nothing to a move.
+pub struct MoveHistory {
+    capacity: usize,
+    records: Mutex<HashMap<(String, String), VecDeque<MoveRecord>>>,
+}
+
+impl MoveHistory {
+    /// Keeps up to `capacity` moves per axis; 0 records nothing.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, controller: &str, axis: &str, record: MoveRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = self.records.lock().unwrap();
+        let moves = records
+            .entry((controller.to_string(), axis.to_string()))
+            .or_default();
+        if moves.len() == self.capacity {
+            moves.pop_front();
+        }
+        moves.push_back(record);
+    }
+
+    /// Records that the axis's move entered at `timestamp` failed with
+    /// `error`. Does nothing if that move has already been dropped.
+    pub fn set_error(&self, controller: &str, axis: &str, timestamp: DateTime<Utc>, error: String) {
+        let mut records = self.records.lock().unwrap();
+        let record = records
+            .get_mut(&(controller.to_string(), axis.to_string()))
+            .and_then(|moves| moves.iter_mut().rev().find(|r| r.timestamp == timestamp));
+        if let Some(record) = record {
+            record.error = Some(error);
+        }
+    }
+
+    /// Up to `limit` of the axis's most recent moves, newest first.
+    pub fn recent(&self, controller: &str, axis: &str, limit: Option<usize>) -> Vec<MoveRecord> {
+        let records = self.records.lock().unwrap();
+        records
+            .get(&(controller.to_string(), axis.to_string()))
+            .map(|moves| {
+                moves
+                    .iter()
+                    .rev()
+                    .take(limit.unwrap_or(usize::MAX))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Drops the history of every axis of `controller`.
+    pub fn forget(&self, controller: &str) {
+        self.records
+            .lock()
+            .unwrap()
+            .retain(|(c, _), _| c != controller);
+    }
+}
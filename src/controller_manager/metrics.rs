@@ -0,0 +1,112 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+};
+
+use serde::Serialize;
+
+/// Monotonic counters describing the work done by a `ControllerManager`.
+///
+/// Counters are never reset, so they can be scraped periodically and
+/// turned into rates by the consumer.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    commands_total: AtomicU64,
+    errors_total: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    active_connections: AtomicU64,
+    commands_by_kind: RwLock<HashMap<&'static str, AtomicU64>>,
+    errors_by_kind: RwLock<HashMap<&'static str, AtomicU64>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsSnapshot {
+    pub commands_total: u64,
+    pub errors_total: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub cache_hit_ratio: Option<f64>,
+    /// Socket clients currently connected.
+    pub active_connections: u64,
+    pub commands_by_kind: BTreeMap<String, u64>,
+    pub errors_by_kind: BTreeMap<String, u64>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_command(&self, kind: &'static str) {
+        self.commands_total.fetch_add(1, Ordering::Relaxed);
+        Self::increment(&self.commands_by_kind, kind);
+    }
+
+    pub fn record_error(&self, kind: &'static str) {
+        self.errors_total.fetch_add(1, Ordering::Relaxed);
+        Self::increment(&self.errors_by_kind, kind);
+    }
+
+    pub fn record_cache_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_cache_miss(&self) {
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_opened(&self) {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Saturates at zero, so an unmatched close can't wrap the gauge.
+    pub fn connection_closed(&self) {
+        let _ = self
+            .active_connections
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let cache_hits = self.cache_hits.load(Ordering::Relaxed);
+        let cache_misses = self.cache_misses.load(Ordering::Relaxed);
+        let lookups = cache_hits + cache_misses;
+
+        MetricsSnapshot {
+            commands_total: self.commands_total.load(Ordering::Relaxed),
+            errors_total: self.errors_total.load(Ordering::Relaxed),
+            cache_hits,
+            cache_misses,
+            cache_hit_ratio: (lookups > 0).then(|| cache_hits as f64 / lookups as f64),
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            commands_by_kind: Self::collect(&self.commands_by_kind),
+            errors_by_kind: Self::collect(&self.errors_by_kind),
+        }
+    }
+
+    fn increment(counters: &RwLock<HashMap<&'static str, AtomicU64>>, kind: &'static str) {
+        if let Some(counter) = counters.read().unwrap().get(kind) {
+            counter.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        counters
+            .write()
+            .unwrap()
+            .entry(kind)
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn collect(counters: &RwLock<HashMap<&'static str, AtomicU64>>) -> BTreeMap<String, u64> {
+        counters
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(kind, count)| (kind.to_string(), count.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
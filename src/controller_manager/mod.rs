@@ -1,324 +1,7387 @@
+pub mod cache;
 pub mod command;
 pub mod config;
+pub mod event;
+pub mod handle;
+pub mod history;
+pub mod metrics;
+pub mod position_store;
 
+use cache::{CacheStats, MokaCache, PositionCache};
 use command::Command;
-use config::ManagerConfig;
+use config::{ManagerConfig, MissingAttributePolicy, SafetyLimitMode};
+use event::{AxisEvent, AxisEventKind, EventBus, EventFilter};
+use handle::ManagerHandle;
+use history::{MoveHistory, MoveRecord};
+use metrics::Metrics;
+use position_store::{PositionStore, Positions};
 
 use anyhow::Result;
-use moka::future::Cache;
+use futures::{future::join_all, Stream};
 use serde_json::{json, Value};
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::{mpsc, RwLock};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering as AtomicOrdering},
+        Arc,
+    },
+};
+use tokio::sync::{
+    broadcast, mpsc, oneshot, watch, Mutex, OwnedSemaphorePermit, RwLock, Semaphore,
+};
+use tokio::task::{AbortHandle, JoinHandle};
+use tracing::{debug, info, warn};
 
-use crate::{axis::movement_parameters::MovementParams, motor_controller::MotorController};
+use crate::{
+    axis::{
+        attribute_value::AttributeValue,
+        limit_switches::LimitTripped,
+        limits::{AxisLimits, LimitDirection},
+        movement_parameters::{MovementParams, ANY_CUSTOM_PARAM},
+        state::AxisState,
+        state_info::AxisStateInfo,
+        stop_mode::StopMode,
+        target_kind::TargetKind,
+        Axis,
+    },
+    motor_controller::MotorController,
+    protocol::error::CommandError,
+};
+
+/// Identifies an axis across controllers as `(controller, axis)`.
+type AxisKey = (String, String);
+
+type MoveTasks = Arc<std::sync::Mutex<HashMap<AxisKey, TrackedMove>>>;
+
+/// An in-flight move. The generation tells a finished move's own record
+/// apart from that of a later move on the same axis.
+struct TrackedMove {
+    generation: u64,
+    task: AbortHandle,
+    /// The connection that sent the move, if any; see `Command::Move`.
+    owner: Option<u64>,
+}
 
 pub struct ControllerManager {
-    controllers: Arc<RwLock<HashMap<String, Arc<dyn MotorController>>>>,
-    cmd_sender: mpsc::Sender<Command>,
-    cache: Cache<String, Value>,
+    state: Arc<ManagerState>,
+    handle: ManagerHandle,
     config: ManagerConfig,
+    shutdown_tx: broadcast::Sender<()>,
+    /// The command loop and poller, taken by `stop` to wait for them.
+    tasks: std::sync::Mutex<Vec<JoinHandle<()>>>,
+    /// Slots for socket subscriptions, when `max_subscriptions` is set.
+    subscription_slots: Option<Arc<Semaphore>>,
+}
+
+/// What a move hands over to the task that runs it.
+struct MoveTask {
+    /// The controller's concurrency permit, held until the move has ended.
+    permit: Option<OwnedSemaphorePermit>,
+    /// The time the move was entered in the history under, for the task to
+    /// fill in its error.
+    recorded_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The flags of a `Command::Move`.
+#[derive(Debug, Clone, Copy)]
+struct MoveOptions {
+    target_kind: TargetKind,
+    dry_run: bool,
+    wait: bool,
+    force: bool,
+    /// Skips the `max_move_distance` guard.
+    override_distance: bool,
+    owner: Option<u64>,
+}
+
+/// Outcome of `ControllerManager::shutdown_all`, by controller name.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ShutdownSummary {
+    pub clean: Vec<String>,
+    /// Controllers whose `shutdown` returned an error, with the message.
+    pub failed: Vec<(String, String)>,
+    pub timed_out: Vec<String>,
+}
+
+/// State shared between the manager, the command loop and the per-command
+/// tasks it spawns.
+struct ManagerState {
+    controllers: RwLock<HashMap<String, Arc<dyn MotorController>>>,
+    cache: Arc<dyn PositionCache>,
+    metrics: Metrics,
+    events: EventBus,
+    axis_locks: std::sync::Mutex<HashMap<AxisKey, Arc<Mutex<()>>>>,
+    /// Cache keys inserted per axis. moka can't invalidate by prefix, so
+    /// this is what lets a whole axis or controller be dropped at once.
+    cache_keys: std::sync::Mutex<HashMap<AxisKey, HashSet<String>>>,
+    /// Alternative names for controllers; see `register_alias`.
+    aliases: std::sync::RwLock<HashMap<String, String>>,
+    /// Named sets of axes; see `define_group`.
+    groups: std::sync::RwLock<HashMap<String, Vec<AxisKey>>>,
+    /// The result of each hardware read in flight, by cache key, so
+    /// concurrent misses share that read instead of starting their own.
+    reads: std::sync::Mutex<HashMap<String, watch::Receiver<Option<Value>>>>,
+    /// Attribute names per axis. They are treated as static for the life of
+    /// a controller, so they are only dropped when it is replaced or
+    /// unregistered, never by TTL.
+    capabilities: std::sync::Mutex<HashMap<AxisKey, Arc<Vec<Cow<'static, str>>>>>,
+    /// Axis names per controller as last seen, so that a controller gaining
+    /// or losing axes at runtime is noticed; see `reconcile_axes`.
+    known_axes: std::sync::Mutex<HashMap<String, Vec<String>>>,
+    /// Axis sets `reconcile_axes` refused, per controller, so each is only
+    /// warned about once.
+    rejected_axes: std::sync::Mutex<HashMap<String, Vec<String>>>,
+    /// Names a `register_controller` is initializing a controller for, so
+    /// of two registrations racing for one name only one initializes.
+    registering: std::sync::Mutex<HashSet<String>>,
+    command_timeout: Option<std::time::Duration>,
+    health_timeout: std::time::Duration,
+    attribute_ttls: HashMap<String, std::time::Duration>,
+    /// Concurrency limit per controller, absent for unlimited controllers.
+    permits: std::sync::Mutex<HashMap<String, Arc<Semaphore>>>,
+    default_max_concurrent: Option<usize>,
+    max_velocity: Option<f64>,
+    max_acceleration: Option<f64>,
+    safety_limit_mode: SafetyLimitMode,
+    max_move_distance: Option<f64>,
+    axis_max_move_distance: HashMap<AxisKey, f64>,
+    position_decimals: Option<u32>,
+    axis_position_decimals: HashMap<AxisKey, u32>,
+    case_insensitive_axes: bool,
+    missing_attribute_policy: MissingAttributePolicy,
+    history: Arc<MoveHistory>,
+    /// Parameters merged into every move of an axis; see `set_axis_defaults`.
+    axis_defaults: std::sync::Mutex<HashMap<AxisKey, MovementParams>>,
+    /// In-flight moves by axis, tagged with a generation so a finished move
+    /// only removes its own entry.
+    moves: MoveTasks,
+    next_move: AtomicU64,
+    /// Feeds the background writer of the optional position store.
+    position_writes: Option<mpsc::Sender<PositionWrite>>,
 }
 
 impl ControllerManager {
     pub fn new(config: ManagerConfig) -> Self {
-        let cache = Cache::builder()
-            .max_capacity(config.cache_capacity as u64)
-            .time_to_live(config.default_ttl)
-            .build();
+        let cache = Self::default_cache(&config);
+        Self::build(config, cache, None)
+    }
+
+    /// Like `new`, but values are cached in `cache` instead of the default
+    /// in-process cache, e.g. `NoOpCache` to send every read to the
+    /// hardware. `cache_capacity` and `default_ttl` are then up to `cache`.
+    pub fn with_cache(config: ManagerConfig, cache: Arc<dyn PositionCache>) -> Self {
+        Self::build(config, cache, None)
+    }
+
+    /// Like `new`, but positions are persisted to `store` after successful
+    /// moves and reads, and the positions it already holds are loaded into
+    /// the cache so the first reads after a restart don't hit the hardware.
+    /// Persisted entries expire with the normal cache TTL.
+    pub async fn with_position_store(
+        config: ManagerConfig,
+        store: Box<dyn PositionStore>,
+    ) -> Result<Self> {
+        let positions = store.load().await?;
 
+        let (tx, rx) = mpsc::channel(256);
+        tokio::spawn(Self::position_writer(Arc::from(store), rx));
+
+        let cache = Self::default_cache(&config);
+        let manager = Self::build(config, cache, Some(tx));
+        for (controller, axes) in positions {
+            for (axis, position) in axes {
+                manager
+                    .state
+                    .cache_insert(&controller, &axis, "position", "position", json!(position))
+                    .await;
+            }
+        }
+        Ok(manager)
+    }
+
+    fn default_cache(config: &ManagerConfig) -> Arc<dyn PositionCache> {
+        Arc::new(MokaCache::new(
+            config.cache_capacity as u64,
+            config.default_ttl,
+        ))
+    }
+
+    fn build(
+        config: ManagerConfig,
+        cache: Arc<dyn PositionCache>,
+        position_writes: Option<mpsc::Sender<PositionWrite>>,
+    ) -> Self {
         let (tx, rx) = mpsc::channel::<Command>(100);
+        let (urgent_tx, urgent_rx) = mpsc::channel::<Command>(100);
 
-        let controllers = Arc::new(RwLock::new(HashMap::new()));
-        let cache_clone = cache.clone();
-        let controllers_clone = controllers.clone();
+        let state = Arc::new(ManagerState {
+            controllers: RwLock::new(HashMap::new()),
+            cache,
+            metrics: Metrics::new(),
+            events: EventBus::new(config.event_capacity),
+            axis_locks: std::sync::Mutex::new(HashMap::new()),
+            reads: std::sync::Mutex::new(HashMap::new()),
+            aliases: std::sync::RwLock::new(HashMap::new()),
+            groups: std::sync::RwLock::new(config.axis_groups.clone()),
+            cache_keys: std::sync::Mutex::new(HashMap::new()),
+            capabilities: std::sync::Mutex::new(HashMap::new()),
+            known_axes: std::sync::Mutex::new(HashMap::new()),
+            rejected_axes: std::sync::Mutex::new(HashMap::new()),
+            registering: std::sync::Mutex::new(HashSet::new()),
+            command_timeout: config.command_timeout,
+            health_timeout: config.health_timeout,
+            attribute_ttls: config.attribute_ttls.clone(),
+            permits: std::sync::Mutex::new(HashMap::new()),
+            default_max_concurrent: config.max_concurrent_commands,
+            max_velocity: config.max_velocity,
+            max_acceleration: config.max_acceleration,
+            safety_limit_mode: config.safety_limit_mode,
+            max_move_distance: config.max_move_distance,
+            axis_max_move_distance: config.axis_max_move_distance.clone(),
+            position_decimals: config.position_decimals,
+            axis_position_decimals: config.axis_position_decimals.clone(),
+            case_insensitive_axes: config.case_insensitive_axes,
+            missing_attribute_policy: config.missing_attribute_policy,
+            history: Arc::new(MoveHistory::new(config.move_history_size)),
+            axis_defaults: std::sync::Mutex::new(HashMap::new()),
+            moves: Default::default(),
+            next_move: AtomicU64::new(0),
+            position_writes,
+        });
+
+        let (shutdown_tx, _) = broadcast::channel(1);
+
+        let mut tasks = vec![tokio::spawn(Self::command_loop(
+            state.clone(),
+            rx,
+            urgent_rx,
+            shutdown_tx.subscribe(),
+        ))];
 
-        tokio::spawn(Self::command_loop(controllers_clone, cache_clone, rx));
+        if let Some(interval) = config.poll_interval {
+            tasks.push(tokio::spawn(Self::poll_loop(
+                state.clone(),
+                interval,
+                shutdown_tx.subscribe(),
+            )));
+        }
 
         ControllerManager {
-            controllers,
-            cmd_sender: tx,
-            cache,
+            state,
+            handle: ManagerHandle::new(tx, urgent_tx),
+            subscription_slots: config
+                .max_subscriptions
+                .map(|max| Arc::new(Semaphore::new(max))),
             config,
+            shutdown_tx,
+            tasks: std::sync::Mutex::new(tasks),
         }
     }
 
+    /// Initializes and registers a controller. Fails if the name is already
+    /// taken; use `replace_controller` to swap a controller out. Names are
+    /// checked first, see `validate_names`. The name is claimed before
+    /// initializing, so of two registrations racing for one name only the
+    /// winner is ever initialized, while the map lock is only held for the
+    /// insert.
     pub async fn register_controller(
         &self,
         name: String,
         controller: Arc<dyn MotorController>,
     ) -> Result<()> {
-        // controller.initialize().await?;
-        let mut ctrls = self.controllers.write().await;
-        ctrls.insert(name, controller);
+        validate_names(
+            &name,
+            controller.as_ref(),
+            self.config.case_insensitive_axes,
+        )?;
+        self.state.check_not_alias(&name)?;
+
+        if self.state.controllers.read().await.contains_key(&name)
+            || !self.state.registering.lock().unwrap().insert(name.clone())
+        {
+            anyhow::bail!("Controller already registered: {}", name);
+        }
+        let _pending = PendingRegistration {
+            registering: &self.state.registering,
+            name: &name,
+        };
+        controller.initialize().await?;
+
+        let mut ctrls = self.state.controllers.write().await;
+        // A `replace_controller` may have taken the name in the meantime.
+        if ctrls.contains_key(&name) {
+            drop(ctrls);
+            controller.shutdown().await?;
+            anyhow::bail!("Controller already registered: {}", name);
+        }
+        self.state.set_permits(&name, controller.as_ref());
+        self.state.remember_axes(&name, controller.as_ref());
+        ctrls.insert(name.clone(), controller);
+        Ok(())
+    }
+
+    /// Swaps the controller registered under `name` for `controller`,
+    /// registering it if the name is new. The replacement is initialized
+    /// before the swap and the old controller is shut down after it, so the
+    /// map lock is only held for the swap itself and no command reaches a
+    /// controller that has already been shut down.
+    pub async fn replace_controller(
+        &self,
+        name: String,
+        controller: Arc<dyn MotorController>,
+    ) -> Result<()> {
+        validate_names(
+            &name,
+            controller.as_ref(),
+            self.config.case_insensitive_axes,
+        )?;
+        self.state.check_not_alias(&name)?;
+        controller.initialize().await?;
+
+        self.state.set_permits(&name, controller.as_ref());
+        self.state.remember_axes(&name, controller.as_ref());
+        let old = self
+            .state
+            .controllers
+            .write()
+            .await
+            .insert(name.clone(), controller);
+
+        self.state.invalidate_cached(&name, None).await;
+        self.state.forget_capabilities(&name);
+
+        if let Some(old) = old {
+            old.shutdown().await?;
+        }
         Ok(())
     }
 
+    /// Removes the controller and everything kept about it, including its
+    /// cached values, then shuts it down.
     pub async fn unregister_controller(&self, name: &str) -> Result<()> {
-        let mut ctrls = self.controllers.write().await;
-        if let Some(ctrl) = ctrls.remove(name) {
+        let removed = self.state.controllers.write().await.remove(name);
+        if let Some(ctrl) = removed {
+            self.state.invalidate_cached(name, None).await;
+            self.state.forget_capabilities(name);
+            self.state.known_axes.lock().unwrap().remove(name);
+            self.state.rejected_axes.lock().unwrap().remove(name);
+            self.state.permits.lock().unwrap().remove(name);
+            self.state
+                .axis_defaults
+                .lock()
+                .unwrap()
+                .retain(|(c, _), _| c != name);
+            self.state.history.forget(name);
+            self.state
+                .aliases
+                .write()
+                .unwrap()
+                .retain(|_, real| real != name);
             ctrl.shutdown().await?;
         }
         Ok(())
     }
 
-    pub async fn send_command(&self, cmd: Command) -> Result<()> {
-        self.cmd_sender.send(cmd).await?;
-        Ok(())
+    /// Stops the command loop and poller, unregisters every controller and
+    /// shuts them all down concurrently, then flushes every cached position
+    /// to the position store, if there is one. Each controller gets
+    /// `shutdown_timeout`; one that hangs is reported rather than holding up
+    /// the others. Commands still queued fail with `SERVER_SHUTTING_DOWN`,
+    /// and commands sent afterwards fail.
+    pub async fn shutdown_all(&self) -> ShutdownSummary {
+        let _ = self.shutdown_tx.send(());
+
+        let controllers: Vec<(String, Arc<dyn MotorController>)> =
+            self.state.controllers.write().await.drain().collect();
+        let timeout = self.config.shutdown_timeout;
+        let results = join_all(controllers.into_iter().map(|(name, ctrl)| async move {
+            let result = tokio::time::timeout(timeout, ctrl.shutdown()).await;
+            (name, result)
+        }))
+        .await;
+
+        let mut summary = ShutdownSummary::default();
+        for (name, result) in results {
+            match result {
+                Ok(Ok(())) => summary.clean.push(name),
+                Ok(Err(e)) => {
+                    warn!("Controller {} failed to shut down: {}", name, e);
+                    summary.failed.push((name, e.to_string()));
+                }
+                Err(_) => {
+                    warn!("Controller {} did not shut down within {:?}", name, timeout);
+                    summary.timed_out.push(name);
+                }
+            }
+        }
+        self.state.flush_positions().await;
+        self.state.cache.invalidate_all().await;
+        summary
     }
 
-    pub fn cache(&self) -> &Cache<String, Value> {
-        &self.cache
+    /// Lets commands address the controller registered as `real_name` by
+    /// `alias` as well, e.g. a site-independent name for hardware named
+    /// differently at each site. Aliases are resolved before a command is
+    /// handled, so caching, locking and events all use the real name;
+    /// `move_all` patterns match real names only. Re-registering an alias
+    /// points it elsewhere; unregistering the controller drops it. Fails if
+    /// `real_name` isn't registered or `alias` is itself a controller name.
+    pub async fn register_alias(&self, alias: String, real_name: String) -> Result<()> {
+        if alias.is_empty() || alias.contains("::") {
+            return Err(
+                CommandError::new("INVALID_NAME", format!("Invalid alias: '{}'", alias)).into(),
+            );
+        }
+        let controllers = self.state.controllers.read().await;
+        if controllers.contains_key(&alias) {
+            return Err(CommandError::new(
+                "INVALID_NAME",
+                format!("Alias {} is already a controller name", alias),
+            )
+            .into());
+        }
+        if !controllers.contains_key(&real_name) {
+            anyhow::bail!("Controller not found: {}", real_name);
+        }
+        self.state.aliases.write().unwrap().insert(alias, real_name);
+        Ok(())
     }
 
-    pub fn config(&self) -> &ManagerConfig {
-        &self.config
+    /// Names a set of axes so `move_group` and `stop_group` can act on all
+    /// of them at once, e.g. every linear axis across controllers. Members
+    /// are looked up when the group is used, so they may name controllers
+    /// (or aliases) that aren't registered yet. Redefining a group replaces
+    /// its members.
+    pub fn define_group(&self, name: String, members: Vec<(String, String)>) -> Result<()> {
+        self.state.handle_define_group(name, members).map(|_| ())
     }
 
-    async fn command_loop(
-        controllers: Arc<RwLock<HashMap<String, Arc<dyn MotorController>>>>,
-        cache: Cache<String, Value>,
-        mut rx: mpsc::Receiver<Command>,
-    ) {
-        while let Some(cmd) = rx.recv().await {
-            match cmd {
-                Command::Move {
-                    controller,
-                    axis,
-                    target,
-                    params,
-                    resp,
-                } => {
-                    let result =
-                        Self::handle_move(&controllers, &cache, &controller, &axis, target, params)
-                            .await;
-                    let _ = resp.send(result);
-                }
-                Command::Stop {
-                    controller,
-                    axis,
-                    resp,
-                } => {
-                    let result = Self::handle_stop(&controllers, &controller, &axis).await;
-                    let _ = resp.send(result);
-                }
-                Command::GetState {
-                    controller,
-                    axis,
-                    resp,
-                } => {
-                    let result =
-                        Self::handle_get_state(&controllers, &cache, &controller, &axis).await;
-                    let _ = resp.send(result);
-                }
-                Command::GetPos {
-                    controller,
-                    axis,
-                    resp,
-                } => {
-                    let result =
-                        Self::handle_get_pos(&controllers, &cache, &controller, &axis).await;
-                    let _ = resp.send(result);
-                }
-                Command::GetAttr {
-                    controller,
-                    axis,
-                    attr,
-                    resp,
-                } => {
-                    let result =
-                        Self::handle_get_attr(&controllers, &cache, &controller, &axis, &attr)
-                            .await;
-                    let _ = resp.send(result);
-                }
-                Command::GetAvailableParams {
-                    controller,
-                    axis,
-                    resp,
-                } => {
-                    let result =
-                        Self::handle_get_available_params(&controllers, &controller, &axis).await;
-                    let _ = resp.send(result);
-                }
-                Command::GetSupportedMovementParams {
-                    controller,
-                    axis,
-                    resp,
-                } => {
-                    let result = Self::handle_get_supported_movement_params(
-                        &controllers,
-                        &controller,
-                        &axis,
-                    )
-                    .await;
-                    let _ = resp.send(result);
-                }
-                Command::ListControllers { resp } => {
-                    let result = Self::handle_list_controllers(&controllers).await;
-                    let _ = resp.send(result);
-                }
-                Command::ListAxes { controller, resp } => {
-                    let result = Self::handle_list_axes(&controllers, &controller).await;
-                    let _ = resp.send(result);
-                }
+    /// Signals the command loop and poller to exit and waits until they
+    /// have. Unlike `shutdown_all` the controllers are left registered and
+    /// running. Commands sent afterwards fail with `MANAGER_DOWN` and those
+    /// still queued with `SERVER_SHUTTING_DOWN`; commands already
+    /// dispatched run to completion on their own tasks.
+    pub async fn stop(&self) {
+        let _ = self.shutdown_tx.send(());
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        for task in tasks {
+            if let Err(e) = task.await {
+                warn!("Manager task ended abnormally: {}", e);
             }
         }
     }
 
-    async fn handle_move(
-        controllers: &Arc<RwLock<HashMap<String, Arc<dyn MotorController>>>>,
-        cache: &Cache<String, Value>,
+    /// Registers movement parameters to use for every move of the axis.
+    /// Parameters a move sets itself take precedence, field by field; empty
+    /// `params` clear the defaults. They are kept when the controller is
+    /// replaced and dropped when it is unregistered.
+    pub async fn set_axis_defaults(
+        &self,
         controller: &str,
         axis: &str,
-        target: f64,
-        params: Option<MovementParams>,
-    ) -> Result<Value> {
-        let ctrls = controllers.read().await;
-        let ctrl = ctrls
-            .get(controller)
-            .ok_or_else(|| anyhow::anyhow!("Controller not found: {}", controller))?;
+        params: MovementParams,
+    ) -> Result<()> {
+        self.state
+            .handle_set_defaults(controller, axis, params)
+            .await
+            .map(|_| ())
+    }
 
-        ctrl.start(axis, target, params).await?;
+    /// A cloneable handle with typed methods for the common commands, for
+    /// tasks that shouldn't hold on to the manager itself. It stays usable
+    /// until the command loop exits.
+    pub fn handle(&self) -> ManagerHandle {
+        self.handle.clone()
+    }
 
-        let cache_key = format!("{}::{}::position", controller, axis);
-        cache.invalidate(&cache_key).await;
-        Ok(json!({"status": "ok", "action": "move", "target": target}))
+    /// Queues a command. Urgent commands (see `Command::is_urgent`) go
+    /// through their own queue so a stop isn't stuck behind a backlog of
+    /// moves. Fails with `MANAGER_DOWN` once the command loop has exited.
+    pub async fn send_command(&self, cmd: Command) -> Result<()> {
+        self.handle.send_command(cmd).await
     }
 
-    async fn handle_stop(
-        controllers: &Arc<RwLock<HashMap<String, Arc<dyn MotorController>>>>,
-        controller: &str,
-        axis: &str,
-    ) -> Result<Value> {
-        let ctrls = controllers.read().await;
-        let ctrl = ctrls
-            .get(controller)
-            .ok_or_else(|| anyhow::anyhow!("Controller not found: {}", controller))?;
-        ctrl.stop(axis).await?;
-        Ok(json!({"status": "ok", "action": "stop"}))
+    /// Like `send_command`, but fails at once with `QUEUE_FULL` instead of
+    /// waiting when the command's queue has no room.
+    pub fn try_send_command(&self, cmd: Command) -> Result<()> {
+        self.handle.try_send_command(cmd)
     }
 
-    async fn handle_get_pos(
-        controllers: &Arc<RwLock<HashMap<String, Arc<dyn MotorController>>>>,
-        cache: &Cache<String, Value>,
-        controller: &str,
-        axis: &str,
-    ) -> Result<Value> {
-        let cache_key = format!("{}::{}::position", controller, axis);
+    /// Like `send_command`, but gives up with `QUEUE_FULL` if the command's
+    /// queue has had no room for `timeout`.
+    pub async fn send_command_timeout(
+        &self,
+        cmd: Command,
+        timeout: std::time::Duration,
+    ) -> Result<()> {
+        self.handle.send_command_timeout(cmd, timeout).await
+    }
+
+    /// Commands queued for the command loop and not yet picked up, urgent
+    /// ones included. A depth that keeps growing means the loop is falling
+    /// behind, well before sends start failing with `QUEUE_FULL`.
+    pub fn queue_depth(&self) -> usize {
+        self.handle.queue_depth()
+    }
+
+    /// Whether the command loop is still accepting commands. It stops after
+    /// `shutdown_all`, or if the loop itself has died.
+    pub fn is_running(&self) -> bool {
+        self.handle.is_running()
+    }
 
-        if let Some(val) = cache.get(&cache_key).await {
-            return Ok(json!({"controller": controller, "axis": axis, "position": val}));
+    pub fn cache(&self) -> &dyn PositionCache {
+        self.state.cache.as_ref()
+    }
+
+    pub fn config(&self) -> &ManagerConfig {
+        &self.config
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.state.metrics
+    }
+
+    /// Fill level of the cache alongside the hit and miss counts from
+    /// `metrics`. Reads the cache directly rather than through the command
+    /// queue, so it never waits behind commands.
+    pub async fn cache_stats(&self) -> CacheStats {
+        let snapshot = self.state.metrics.snapshot();
+        CacheStats {
+            entries: self.state.cache.entry_count().await,
+            capacity: self.state.cache.capacity(),
+            hits: snapshot.cache_hits,
+            misses: snapshot.cache_misses,
+            hit_ratio: snapshot.cache_hit_ratio,
         }
+    }
 
-        let ctrls = controllers.read().await;
-        let ctrl = ctrls
-            .get(controller)
-            .ok_or_else(|| anyhow::anyhow!("Controller not found: {}", controller))?;
+    /// Subscribes to axis state transitions observed by command handlers and
+    /// the background poller.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<AxisEvent> {
+        self.state.events.subscribe()
+    }
 
-        let ax = ctrl.get_axis(axis)?;
+    /// Like `subscribe_events`, as a stream of just the events that pass
+    /// `filter`.
+    pub fn subscribe_filtered(
+        &self,
+        filter: EventFilter,
+    ) -> impl Stream<Item = AxisEvent> + Send + 'static {
+        event::filtered(self.subscribe_events(), filter)
+    }
+
+    /// Takes one of the `max_subscriptions` slots shared by all clients,
+    /// failing with `SUBSCRIPTION_LIMIT` when none is left. The slot is
+    /// released when the returned permit is dropped; there is nothing to
+    /// hold when subscriptions are unlimited.
+    pub fn reserve_subscription(&self) -> Result<Option<OwnedSemaphorePermit>> {
+        let Some(slots) = &self.subscription_slots else {
+            return Ok(None);
+        };
+        match slots.clone().try_acquire_owned() {
+            Ok(permit) => Ok(Some(permit)),
+            Err(_) => Err(CommandError::new(
+                "SUBSCRIPTION_LIMIT",
+                format!(
+                    "Server subscription limit of {} reached",
+                    self.config.max_subscriptions.unwrap_or_default()
+                ),
+            )
+            .into()),
+        }
+    }
 
-        let pos = ax.get_position().await?;
-        let value = json!(pos);
+    async fn poll_loop(
+        state: Arc<ManagerState>,
+        interval: std::time::Duration,
+        mut shutdown_rx: broadcast::Receiver<()>,
+    ) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown_rx.recv() => break,
+            }
 
-        let _ = cache.insert(cache_key.clone(), value.clone()).await;
+            let snapshot: Vec<(String, Arc<dyn MotorController>)> = state
+                .controllers
+                .read()
+                .await
+                .iter()
+                .map(|(name, ctrl)| (name.clone(), ctrl.clone()))
+                .collect();
 
-        Ok(json!({"controller": controller, "axis": axis, "position": value}))
+            for (name, ctrl) in snapshot {
+                state.reconcile_axes(&name, ctrl.as_ref()).await;
+                for axis in ctrl.axes() {
+                    match ctrl.state(axis.name()).await {
+                        Ok(info) => {
+                            state.events.observe(&name, axis.name(), &info);
+                            if info.state == AxisState::Moving {
+                                if let Ok(Some(fraction)) = ctrl.get_progress(axis.name()).await {
+                                    state.events.publish(
+                                        &name,
+                                        axis.name(),
+                                        AxisEventKind::Progress { fraction },
+                                    );
+                                }
+                            }
+                        }
+                        Err(e) => debug!("Poller failed to read {}::{}: {}", name, axis.name(), e),
+                    }
+                }
+            }
+        }
     }
 
-    async fn handle_get_state(
-        controllers: &Arc<RwLock<HashMap<String, Arc<dyn MotorController>>>>,
-        cache: &Cache<String, Value>,
-        controller: &str,
-        axis: &str,
-    ) -> Result<Value> {
-        let cache_key = format!("{}::{}::status", controller, axis);
-        if let Some(val) = cache.get(&cache_key).await {
-            return Ok(json!({"controller": controller, "axis": axis, "status": val}));
-        }
-        let ctrls = controllers.read().await;
-        let ctrl = ctrls
-            .get(controller)
-            .ok_or_else(|| anyhow::anyhow!("Controller not found: {}", controller))?;
-        let ax = ctrl.get_axis(axis)?;
-        let state_info = ax.get_state().await?;
-        let status_json = json!({
-            "state": format!("{:?}", state_info.state),
-            "message": state_info.message,
-            "limit_switches": format!("{:?}", state_info.limit_switches),
-        });
-        let _ = cache.insert(cache_key.clone(), status_json.clone()).await;
-        Ok(json!({"controller": controller, "axis": axis, "status": status_json}))
+    /// Saves positions one at a time, in the order they were recorded, so
+    /// slow storage never holds up a command.
+    async fn position_writer(store: Arc<dyn PositionStore>, mut rx: mpsc::Receiver<PositionWrite>) {
+        while let Some(write) = rx.recv().await {
+            match write {
+                PositionWrite::Save(controller, axis, position) => {
+                    if let Err(e) = store.save(&controller, &axis, position).await {
+                        warn!(
+                            "Failed to persist position of {}::{}: {}",
+                            controller, axis, e
+                        );
+                    }
+                }
+                PositionWrite::Flush(positions, done) => {
+                    let _ = done.send(store.flush(&positions).await);
+                }
+            }
+        }
     }
 
-    async fn handle_get_attr(
-        controllers: &Arc<RwLock<HashMap<String, Arc<dyn MotorController>>>>,
-        cache: &Cache<String, Value>,
-        controller: &str,
-        axis: &str,
-        attr: &str,
-    ) -> Result<Value> {
-        let cache_key = format!("{}::{}::{}", controller, axis, attr);
-        if let Some(val) = cache.get(&cache_key).await {
-            return Ok(
-                json!({"controller": controller, "axis": axis, "attribute": attr, "value": val}),
+    /// Receives commands and runs each one in its own task so that slow
+    /// hardware on one axis does not hold up commands for other axes.
+    /// Motion on a single axis is serialized by the per-axis lock instead.
+    async fn command_loop(
+        state: Arc<ManagerState>,
+        mut rx: mpsc::Receiver<Command>,
+        mut urgent_rx: mpsc::Receiver<Command>,
+        mut shutdown_rx: broadcast::Receiver<()>,
+    ) {
+        loop {
+            // Biased so that pending urgent commands are always dispatched
+            // before any queued normal command.
+            tokio::select! {
+                biased;
+                _ = shutdown_rx.recv() => break,
+                cmd = urgent_rx.recv() => match cmd {
+                    Some(cmd) => {
+                        let state = state.clone();
+                        tokio::spawn(async move { state.dispatch(cmd).await });
+                    }
+                    None => break,
+                },
+                cmd = rx.recv() => match cmd {
+                    Some(cmd) => {
+                        let state = state.clone();
+                        tokio::spawn(async move { state.dispatch(cmd).await });
+                    }
+                    None => break,
+                },
+            }
+        }
+
+        // Closed first so nothing more is queued, then every command still
+        // waiting is told why it won't run rather than losing its reply.
+        rx.close();
+        urgent_rx.close();
+        let mut rejected = 0;
+        while let Ok(cmd) = urgent_rx.try_recv().or_else(|_| rx.try_recv()) {
+            let kind = cmd.kind();
+            let error = CommandError::new(
+                "SERVER_SHUTTING_DOWN",
+                "Controller manager is shutting down",
             );
+            state.reply(kind, cmd.into_resp(), Err(error.into()));
+            rejected += 1;
+        }
+        if rejected > 0 {
+            info!("Rejected {} queued commands at shutdown", rejected);
         }
-        // Not in cache or expired: compute
-        let ctrls = controllers.read().await;
-        let ctrl = ctrls
-            .get(controller)
-            .ok_or_else(|| anyhow::anyhow!("Controller not found: {}", controller))?;
-        let value = ctrl.get_attribute(axis, attr).await?;
-        let json_value = json!(value);
-        // Insert to cache with TTL
-        let _ = cache.insert(cache_key.clone(), json_value.clone()).await;
-        Ok(json!({"controller": controller, "axis": axis, "attribute": attr, "value": json_value}))
     }
+}
 
-    async fn handle_get_available_params(
-        controllers: &Arc<RwLock<HashMap<String, Arc<dyn MotorController>>>>,
-        controller: &str,
-        axis: &str,
-    ) -> Result<Value> {
-        let ctrls = controllers.read().await;
-        let ctrl = ctrls
-            .get(controller)
-            .ok_or_else(|| anyhow::anyhow!("Controller not found: {}", controller))?;
-        let params = ctrl.get_available_attributes(axis).await?;
-        Ok(json!({"controller": controller, "axis": axis, "available_params": params}))
+impl ManagerState {
+    async fn dispatch(&self, mut cmd: Command) {
+        let kind = cmd.kind();
+        if let Some(name) = cmd.controller_mut() {
+            if let Some(real) = self.aliases.read().unwrap().get(name.as_str()) {
+                *name = real.clone();
+            }
+        }
+        if self.case_insensitive_axes {
+            self.resolve_axis_names(&mut cmd);
+        }
+        self.metrics.record_command(kind);
+
+        // Held until the command has been handled; commands over the limit
+        // wait here rather than failing. Waits touch the hardware once, so
+        // they don't take a permit for their whole duration. A move that
+        // isn't waited for takes its permit along to its background task.
+        let mut permit = match cmd.controller() {
+            Some(_) if matches!(cmd, Command::WaitForState { .. }) => None,
+            Some(controller) if !cmd.skips_concurrency_limit() => {
+                self.acquire_permit(controller).await
+            }
+            _ => None,
+        };
+
+        match cmd {
+            Command::Move {
+                controller,
+                axis,
+                target,
+                target_kind,
+                params,
+                dry_run,
+                wait,
+                force,
+                override_distance,
+                owner,
+                resp,
+            } => {
+                let options = MoveOptions {
+                    target_kind,
+                    dry_run,
+                    wait,
+                    force,
+                    override_distance,
+                    owner,
+                };
+                let result = self
+                    .handle_move(&controller, &axis, target, params, options, permit.take())
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::MoveToLimit {
+                controller,
+                axis,
+                direction,
+                params,
+                zero_position,
+                owner,
+                resp,
+            } => {
+                let result = self
+                    .handle_move_to_limit(
+                        &controller,
+                        &axis,
+                        direction,
+                        params,
+                        zero_position,
+                        owner,
+                    )
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::MoveAll {
+                axis_pattern,
+                target,
+                params,
+                confirm,
+                owner,
+                resp,
+            } => {
+                let result = self
+                    .handle_move_all(&axis_pattern, target, params, confirm, owner)
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::MoveGroup {
+                group,
+                target,
+                params,
+                owner,
+                resp,
+            } => {
+                let result = self.handle_move_group(&group, target, params, owner).await;
+                self.reply(kind, resp, result);
+            }
+            Command::StopGroup { group, resp } => {
+                let result = self.timed(self.handle_stop_group(&group)).await;
+                self.reply(kind, resp, result);
+            }
+            Command::DefineGroup {
+                group,
+                members,
+                resp,
+            } => {
+                let result = self.handle_define_group(group, members);
+                self.reply(kind, resp, result);
+            }
+            Command::StopOwnedMoves { owner, resp } => {
+                let result = self.timed(self.handle_stop_owned_moves(owner)).await;
+                self.reply(kind, resp, result);
+            }
+            Command::StopController { controller, resp } => {
+                let result = self.timed(self.handle_stop_controller(&controller)).await;
+                self.reply(kind, resp, result);
+            }
+            Command::Cancel {
+                controller,
+                axis,
+                resp,
+            } => {
+                let result = self.timed(self.handle_cancel(&controller, &axis)).await;
+                self.reply(kind, resp, result);
+            }
+            Command::Stop {
+                controller,
+                axis,
+                mode,
+                resp,
+            } => {
+                let result = self.timed(self.handle_stop(&controller, &axis, mode)).await;
+                self.reply(kind, resp, result);
+            }
+            Command::Pause {
+                controller,
+                axis,
+                resp,
+            } => {
+                let result = self.timed(self.handle_pause(&controller, &axis)).await;
+                self.reply(kind, resp, result);
+            }
+            Command::Resume {
+                controller,
+                axis,
+                resp,
+            } => {
+                let result = self.timed(self.handle_resume(&controller, &axis)).await;
+                self.reply(kind, resp, result);
+            }
+            Command::Enable {
+                controller,
+                axis,
+                resp,
+            } => {
+                let result = self.timed(self.handle_enable(&controller, &axis)).await;
+                self.reply(kind, resp, result);
+            }
+            Command::Disable {
+                controller,
+                axis,
+                resp,
+            } => {
+                let result = self.timed(self.handle_disable(&controller, &axis)).await;
+                self.reply(kind, resp, result);
+            }
+            Command::ResetFault {
+                controller,
+                axis,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_reset_fault(&controller, &axis))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::SetReference {
+                controller,
+                axis,
+                value,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_set_reference(&controller, &axis, value))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetState {
+                controller,
+                axis,
+                fresh,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_get_state(&controller, &axis, fresh))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetPos {
+                controller,
+                axis,
+                fresh,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_get_pos(&controller, &axis, fresh))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetPositions {
+                controller,
+                axes,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_get_positions(&controller, &axes))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetControllerAttr {
+                controller,
+                attr,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_get_controller_attr(&controller, &attr))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::ControllerHealth { controller, resp } => {
+                let result = self.timed(self.handle_controller_health(&controller)).await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetAttr {
+                controller,
+                axis,
+                attr,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_get_attr(&controller, &axis, &attr))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetAttrArray {
+                controller,
+                axis,
+                attr,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_get_attr_array(&controller, &axis, &attr))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::SetAttr {
+                controller,
+                axis,
+                attr,
+                value,
+                verify,
+                tolerance,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_set_attr(
+                        &controller,
+                        &axis,
+                        &attr,
+                        value,
+                        verify.then(|| tolerance.unwrap_or(DEFAULT_VERIFY_TOLERANCE)),
+                    ))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetAttrInfo {
+                controller,
+                axis,
+                attr,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_get_attr_info(&controller, &axis, &attr))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::WaitForState {
+                controller,
+                axis,
+                state,
+                timeout,
+                resp,
+            } => {
+                let result = self
+                    .handle_wait_for_state(&controller, &axis, state, timeout)
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetHistory {
+                controller,
+                axis,
+                limit,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_get_history(&controller, &axis, limit))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetAvailableParams {
+                controller,
+                axis,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_get_available_params(&controller, &axis))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetSupportedMovementParams {
+                controller,
+                axis,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_get_supported_movement_params(&controller, &axis))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetCapabilities {
+                controller,
+                axis,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_get_capabilities(&controller, &axis))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetLimits {
+                controller,
+                axis,
+                resp,
+            } => {
+                let result = self.timed(self.handle_get_limits(&controller, &axis)).await;
+                self.reply(kind, resp, result);
+            }
+            Command::ReachableRange {
+                controller,
+                axis,
+                time_ms,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_reachable_range(&controller, &axis, time_ms))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::GetLimitSwitches {
+                controller,
+                axis,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_get_limit_switches(&controller, &axis))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::SetDefaults {
+                controller,
+                axis,
+                params,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_set_defaults(&controller, &axis, params))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::ResolveParams {
+                controller,
+                axis,
+                params,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_resolve_params(&controller, &axis, params))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::SelfTest { controller, resp } => {
+                let result = self.handle_self_test(&controller).await;
+                self.reply(kind, resp, result);
+            }
+            Command::Invalidate {
+                controller,
+                axis,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_invalidate(&controller, axis.as_deref()))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+            Command::ListControllers { resp } => {
+                let result = self.timed(self.handle_list_controllers()).await;
+                self.reply(kind, resp, result);
+            }
+            Command::ListAliases { resp } => {
+                let aliases: std::collections::BTreeMap<_, _> =
+                    self.aliases.read().unwrap().clone().into_iter().collect();
+                self.reply(kind, resp, Ok(json!({"aliases": aliases})));
+            }
+            Command::Describe { resp } => {
+                let result = self.timed(self.handle_describe()).await;
+                self.reply(kind, resp, result);
+            }
+            Command::SystemHealth { resp } => {
+                let result = self.timed(self.handle_system_health()).await;
+                self.reply(kind, resp, result);
+            }
+            Command::ListAxes {
+                controller,
+                detailed,
+                filter,
+                offset,
+                limit,
+                resp,
+            } => {
+                let result = self
+                    .timed(self.handle_list_axes(
+                        &controller,
+                        detailed,
+                        filter.as_deref(),
+                        offset,
+                        limit,
+                    ))
+                    .await;
+                self.reply(kind, resp, result);
+            }
+        }
     }
 
-    async fn handle_get_supported_movement_params(
-        controllers: &Arc<RwLock<HashMap<String, Arc<dyn MotorController>>>>,
-        controller: &str,
-        axis: &str,
-    ) -> Result<Value> {
-        let ctrls = controllers.read().await;
-        let ctrl = ctrls
-            .get(controller)
-            .ok_or_else(|| anyhow::anyhow!("Controller not found: {}", controller))?;
-        let params = ctrl.get_supported_movement_params(axis).await?;
-        Ok(json!({"controller": controller, "axis": axis, "supported_movement_params": params}))
+    /// Controller names can't be taken by an alias, so lookups stay
+    /// unambiguous.
+    fn check_not_alias(&self, name: &str) -> Result<()> {
+        if self.aliases.read().unwrap().contains_key(name) {
+            return Err(CommandError::new(
+                "INVALID_NAME",
+                format!("Controller name {} is already an alias", name),
+            )
+            .into());
+        }
+        Ok(())
     }
 
-    async fn handle_list_controllers(
-        controllers: &Arc<RwLock<HashMap<String, Arc<dyn MotorController>>>>,
-    ) -> Result<Value> {
-        let ctrls = controllers.read().await;
-        let controller_names: Vec<String> = ctrls.keys().cloned().collect();
-        Ok(json!({"controllers": controller_names}))
+    /// Looks up a registered controller. The map lock is released before
+    /// returning so long-running commands don't block registration.
+    async fn controller(&self, name: &str) -> Result<Arc<dyn MotorController>> {
+        self.controllers
+            .read()
+            .await
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Controller not found: {}", name))
     }
 
-    async fn handle_list_axes(
-        controllers: &Arc<RwLock<HashMap<String, Arc<dyn MotorController>>>>,
-        controller: &str,
-    ) -> Result<Value> {
-        let ctrls = controllers.read().await;
-        let ctrl = ctrls
-            .get(controller)
-            .ok_or_else(|| anyhow::anyhow!("Controller not found: {}", controller))?;
+    /// Applies `command_timeout` to a handler. Moves are not wrapped: they
+    /// resolve when the hardware finishes, and abandoning one part-way would
+    /// release the axis lock while the axis is still in motion.
+    async fn timed(&self, handler: impl Future<Output = Result<Value>>) -> Result<Value> {
+        match self.command_timeout {
+            Some(budget) => tokio::time::timeout(budget, handler)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(CommandError::new(
+                        "TIMEOUT",
+                        format!("Command did not complete within {:?}", budget),
+                    )
+                    .into())
+                }),
+            None => handler.await,
+        }
+    }
+
+    fn reply(
+        &self,
+        kind: &'static str,
+        resp: oneshot::Sender<Result<Value>>,
+        result: Result<Value>,
+    ) {
+        if result.is_err() {
+            self.metrics.record_error(kind);
+        }
+        let _ = resp.send(result);
+    }
+
+    async fn cache_lookup(&self, key: &str) -> Option<Value> {
+        let value = self.cache.get(key).await;
+        if value.is_some() {
+            self.metrics.record_cache_hit();
+        } else {
+            self.metrics.record_cache_miss();
+        }
+        value
+    }
+
+    /// Runs `read`, which is expected to fill the cache under `key`, unless
+    /// another read of `key` is already in flight; then every caller gets
+    /// that read's value. Falls back to `read` if the shared read fails or
+    /// is dropped.
+    async fn read_coalesced(
+        &self,
+        key: &str,
+        read: impl Future<Output = Result<Value>>,
+    ) -> Result<Value> {
+        let in_flight = {
+            let mut reads = self.reads.lock().unwrap();
+            match reads.get(key) {
+                Some(shared) => Err(shared.clone()),
+                None => {
+                    let (tx, rx) = watch::channel(None);
+                    reads.insert(key.to_string(), rx);
+                    Ok(tx)
+                }
+            }
+        };
+
+        let mut shared = match in_flight {
+            Ok(tx) => {
+                let _entry = InFlightRead {
+                    reads: &self.reads,
+                    key,
+                };
+                let result = match self.cache.get(key).await {
+                    Some(value) => Ok(value),
+                    None => read.await,
+                };
+                if let Ok(value) = &result {
+                    tx.send_replace(Some(value.clone()));
+                }
+                return result;
+            }
+            Err(shared) => shared,
+        };
+        let value = shared
+            .wait_for(Option::is_some)
+            .await
+            .ok()
+            .and_then(|value| value.clone());
+        match value {
+            Some(value) => Ok(value),
+            None => read.await,
+        }
+    }
+
+    /// Hands every cached position to the position store and waits for it
+    /// to be written. Failures are only logged so that shutdown goes on.
+    async fn flush_positions(&self) {
+        let Some(writes) = &self.position_writes else {
+            return;
+        };
+        let axes: Vec<AxisKey> = self.cache_keys.lock().unwrap().keys().cloned().collect();
+        let mut positions = Positions::new();
+        for (controller, axis) in axes {
+            let key = cache_key(&controller, &axis, "position");
+            if let Some(position) = self.cache.get(&key).await.and_then(|v| v.as_f64()) {
+                positions
+                    .entry(controller)
+                    .or_default()
+                    .insert(axis, position);
+            }
+        }
+        if positions.is_empty() {
+            return;
+        }
+
+        let (tx, rx) = oneshot::channel();
+        if writes
+            .send(PositionWrite::Flush(positions, tx))
+            .await
+            .is_err()
+        {
+            warn!("Position store writer has stopped; positions not flushed");
+            return;
+        }
+        match rx.await {
+            Ok(Ok(())) => debug!("Flushed cached positions to the position store"),
+            Ok(Err(e)) => warn!("Failed to flush positions to the position store: {}", e),
+            Err(_) => warn!("Position store writer stopped during flush"),
+        }
+    }
+
+    /// Queues a position for the position store, if one is configured. A
+    /// full queue drops the update rather than blocking the command.
+    fn persist_position(&self, controller: &str, axis: &str, position: f64) {
+        queue_position(self.position_writes.as_ref(), controller, axis, position);
+    }
+
+    /// Reads the axis and queues where it actually is, for a move that was
+    /// cancelled or failed short of its target.
+    async fn persist_current_position(
+        &self,
+        ctrl: &dyn MotorController,
+        controller: &str,
+        axis: &str,
+    ) {
+        persist_reading(self.position_writes.as_ref(), ctrl, controller, axis).await;
+    }
+
+    /// Caches `field` of the axis for the entry of `ttl_name` in
+    /// `attribute_ttls`, or the cache's default lifetime. `ttl_name` is the
+    /// field itself except where one attribute is cached under several
+    /// fields, such as the array reading of an attribute.
+    async fn cache_insert(
+        &self,
+        controller: &str,
+        axis: &str,
+        field: &str,
+        ttl_name: &str,
+        value: Value,
+    ) {
+        let key = cache_key(controller, axis, field);
+        self.track_cache_key(controller, axis, &key);
+        match self.attribute_ttls.get(ttl_name) {
+            Some(ttl) => self.cache.insert_with_ttl(key, value, *ttl).await,
+            None => self.cache.insert(key, value).await,
+        }
+    }
+
+    /// Remembers that `key` holds a reading of the axis, so that
+    /// `invalidate_cached` can find it.
+    fn track_cache_key(&self, controller: &str, axis: &str, key: &str) {
+        self.cache_keys
+            .lock()
+            .unwrap()
+            .entry((controller.to_string(), axis.to_string()))
+            .or_default()
+            .insert(key.to_string());
+    }
+
+    /// Removes every tracked cache entry for the axis, or for the whole
+    /// controller when `axis` is `None`. Returns how many were still cached.
+    async fn invalidate_cached(&self, controller: &str, axis: Option<&str>) -> usize {
+        let keys: Vec<String> = {
+            let mut cache_keys = self.cache_keys.lock().unwrap();
+            let matching: Vec<AxisKey> = cache_keys
+                .keys()
+                .filter(|(c, a)| c == controller && axis.is_none_or(|axis| a == axis))
+                .cloned()
+                .collect();
+            matching
+                .iter()
+                .filter_map(|key| cache_keys.remove(key))
+                .flatten()
+                .collect()
+        };
+
+        let mut invalidated = 0;
+        for key in keys {
+            if self.cache.invalidate(&key).await {
+                invalidated += 1;
+            }
+        }
+        invalidated
+    }
+
+    /// Attribute names for an axis, read from the hardware only the first
+    /// time they are needed.
+    async fn available_attributes(
+        &self,
+        ctrl: &dyn MotorController,
+        controller: &str,
+        axis: &str,
+    ) -> Result<Arc<Vec<Cow<'static, str>>>> {
+        let key = (controller.to_string(), axis.to_string());
+        if let Some(cached) = self.capabilities.lock().unwrap().get(&key) {
+            return Ok(cached.clone());
+        }
+        let attributes = Arc::new(ctrl.available_attributes(axis).await?);
+        self.capabilities
+            .lock()
+            .unwrap()
+            .insert(key, attributes.clone());
+        Ok(attributes)
+    }
+
+    fn set_permits(&self, name: &str, controller: &dyn MotorController) {
+        let mut permits = self.permits.lock().unwrap();
+        match controller
+            .max_concurrent_commands()
+            .or(self.default_max_concurrent)
+        {
+            Some(limit) => {
+                permits.insert(name.to_string(), Arc::new(Semaphore::new(limit.max(1))));
+            }
+            None => {
+                permits.remove(name);
+            }
+        }
+    }
+
+    async fn acquire_permit(&self, controller: &str) -> Option<OwnedSemaphorePermit> {
+        let semaphore = self.permits.lock().unwrap().get(controller).cloned()?;
+        semaphore.acquire_owned().await.ok()
+    }
+
+    fn remember_axes(&self, controller: &str, ctrl: &dyn MotorController) {
+        let names = ctrl.axes().iter().map(|ax| ax.name().to_string()).collect();
+        self.known_axes
+            .lock()
+            .unwrap()
+            .insert(controller.to_string(), names);
+    }
+
+    /// Replaces each axis name in `cmd` that isn't one of its controller's
+    /// axes with the axis matching it but for case, if there is one.
+    /// Unmatched names are left for the handler to reject.
+    fn resolve_axis_names(&self, cmd: &mut Command) {
+        let Some(controller) = cmd.controller().map(str::to_string) else {
+            return;
+        };
+        let known_axes = self.known_axes.lock().unwrap();
+        let Some(known) = known_axes.get(&controller) else {
+            return;
+        };
+        for axis in cmd.axes_mut() {
+            if let Some(real) = fold_axis_name(known, axis) {
+                *axis = real;
+            }
+        }
+    }
+
+    /// Compares the controller's axes with those last seen and publishes
+    /// `AxisAdded`/`AxisRemoved` for the difference. Whatever was cached or
+    /// observed for a removed axis is dropped. Run by the poller on every
+    /// tick and by `list_axes`, so hot-plugged axes are picked up without
+    /// re-registering the controller. A new set failing `validate_names`
+    /// is ignored, leaving the axes as last seen, until it is fixed.
+    async fn reconcile_axes(&self, controller: &str, ctrl: &dyn MotorController) {
+        let current: Vec<String> = ctrl.axes().iter().map(|ax| ax.name().to_string()).collect();
+        match self.known_axes.lock().unwrap().get(controller) {
+            Some(previous) if *previous != current => {}
+            // Unchanged, or the controller was unregistered meanwhile.
+            _ => return,
+        }
+        if let Err(e) = validate_names(controller, ctrl, self.case_insensitive_axes) {
+            let mut rejected = self.rejected_axes.lock().unwrap();
+            if rejected.get(controller) != Some(&current) {
+                warn!("Ignoring new axes of {}: {}", controller, e);
+                rejected.insert(controller.to_string(), current);
+            }
+            return;
+        }
+        self.rejected_axes.lock().unwrap().remove(controller);
+
+        let previous = {
+            let mut known = self.known_axes.lock().unwrap();
+            match known.get_mut(controller) {
+                Some(previous) if *previous != current => {
+                    std::mem::replace(previous, current.clone())
+                }
+                _ => return,
+            }
+        };
+
+        for axis in current.iter().filter(|axis| !previous.contains(axis)) {
+            info!("Axis {}::{} appeared", controller, axis);
+            self.events
+                .publish(controller, axis, AxisEventKind::AxisAdded);
+        }
+        for axis in previous.iter().filter(|axis| !current.contains(axis)) {
+            info!("Axis {}::{} went away", controller, axis);
+            self.invalidate_cached(controller, Some(axis)).await;
+            let key = (controller.to_string(), axis.clone());
+            self.capabilities.lock().unwrap().remove(&key);
+            self.axis_locks.lock().unwrap().remove(&key);
+            self.events.forget(controller, axis);
+            self.events
+                .publish(controller, axis, AxisEventKind::AxisRemoved);
+        }
+    }
+
+    fn forget_capabilities(&self, controller: &str) {
+        self.capabilities
+            .lock()
+            .unwrap()
+            .retain(|(c, _), _| c != controller);
+    }
+
+    /// Returns the lock serializing motion commands on one axis. Callers must
+    /// resolve the axis first so that unknown names never create entries.
+    fn axis_lock(&self, controller: &str, axis: &str) -> Arc<Mutex<()>> {
+        self.axis_locks
+            .lock()
+            .unwrap()
+            .entry((controller.to_string(), axis.to_string()))
+            .or_default()
+            .clone()
+    }
+
+    /// Reads the axis state after an action so that transitions caused by
+    /// the action reach event subscribers, and returns it. Failures are only
+    /// logged since the action itself already succeeded.
+    async fn observe_state(
+        &self,
+        ctrl: &dyn MotorController,
+        controller: &str,
+        axis: &str,
+    ) -> Option<AxisStateInfo> {
+        match ctrl.state(axis).await {
+            Ok(info) => {
+                self.events.observe(controller, axis, &info);
+                Some(info)
+            }
+            Err(e) => {
+                debug!("Failed to read state of {}::{}: {}", controller, axis, e);
+                None
+            }
+        }
+    }
+
+    /// Merges the axis's default parameters, if any, under `params`.
+    fn with_defaults(
+        &self,
+        controller: &str,
+        axis: &str,
+        params: Option<MovementParams>,
+    ) -> Option<MovementParams> {
+        let defaults = self.axis_defaults.lock().unwrap();
+        match defaults.get(&(controller.to_string(), axis.to_string())) {
+            Some(defaults) => Some(params.unwrap_or_default().merged_over(defaults)),
+            None => params,
+        }
+    }
+
+    /// Enforces `max_velocity` and `max_acceleration` (which also bounds
+    /// deceleration). In clamp mode the values that had to be lowered are
+    /// returned by name, with their new value, so the reply can report them.
+    fn apply_safety_limits(
+        &self,
+        controller: &str,
+        axis: &str,
+        params: Option<MovementParams>,
+    ) -> Result<(Option<MovementParams>, serde_json::Map<String, Value>)> {
+        let mut clamped = serde_json::Map::new();
+        let Some(mut params) = params else {
+            return Ok((None, clamped));
+        };
+
+        for (name, value, max) in [
+            ("velocity", &mut params.velocity, self.max_velocity),
+            (
+                "acceleration",
+                &mut params.acceleration,
+                self.max_acceleration,
+            ),
+            (
+                "deceleration",
+                &mut params.deceleration,
+                self.max_acceleration,
+            ),
+        ] {
+            let (Some(requested), Some(max)) = (*value, max) else {
+                continue;
+            };
+            if requested <= max {
+                continue;
+            }
+            match self.safety_limit_mode {
+                SafetyLimitMode::Reject => {
+                    return Err(CommandError::new(
+                        "SAFETY_LIMIT",
+                        format!("{} {} exceeds the limit of {}", name, requested, max),
+                    )
+                    .into());
+                }
+                SafetyLimitMode::Clamp => {
+                    warn!(
+                        "Clamped {} of move on {}::{} from {} to {}",
+                        name, controller, axis, requested, max
+                    );
+                    *value = Some(max);
+                    clamped.insert(name.to_string(), json!(max));
+                }
+            }
+        }
+        Ok((Some(params), clamped))
+    }
+
+    /// Checks that `params` are well-formed, before anything is looked up.
+    fn validate_params(params: Option<&MovementParams>) -> Result<()> {
+        if let Some(params) = params {
+            params
+                .validate()
+                .map_err(|msg| CommandError::new("INVALID_PARAMS", msg))?;
+        }
+        Ok(())
+    }
+
+    /// Refuses a move to `target` with `MOVE_TOO_LARGE` when it is further
+    /// from the axis's position than its `max_move_distance`. The position
+    /// comes from the cache when it holds one; a position that isn't a
+    /// number, such as a NaN reading, fails with `INVALID_READING` rather
+    /// than letting the move through unchecked.
+    async fn check_distance(&self, controller: &str, axis: &str, target: f64) -> Result<()> {
+        let key = (controller.to_string(), axis.to_string());
+        let Some(limit) = self
+            .axis_max_move_distance
+            .get(&key)
+            .copied()
+            .or(self.max_move_distance)
+        else {
+            return Ok(());
+        };
+        let position = self.handle_get_pos(controller, axis, false).await?;
+        let Some(current) = position["position"].as_f64() else {
+            return Err(CommandError::new(
+                "INVALID_READING",
+                format!("Position of {}::{} is not a number", controller, axis),
+            )
+            .into());
+        };
+        let distance = (target - current).abs();
+        if distance > limit {
+            return Err(CommandError::new(
+                "MOVE_TOO_LARGE",
+                format!(
+                    "Move of {}::{} from {} to {} exceeds the maximum distance of {}; \
+                     send it with override to move anyway",
+                    controller, axis, current, target, limit
+                ),
+            )
+            .with_details(json!({"distance": distance, "limit": limit}))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Checks shared by every command that sets an axis in motion: the
+    /// parameters must pass `check_params`, and the axis must not be
+    /// disabled and, unless `force`d, must not be in `Fault` or `Alarm`. The
+    /// state comes from the cache when it holds one, otherwise from the axis.
+    async fn check_motion(
+        &self,
+        ctrl: &dyn MotorController,
+        controller: &str,
+        axis: &str,
+        params: Option<&MovementParams>,
+        force: bool,
+    ) -> Result<()> {
+        Self::check_params(ctrl, axis, params).await?;
+
+        let cached = self
+            .cache_lookup(&cache_key(controller, axis, "status"))
+            .await
+            .and_then(|mut status| {
+                let state = serde_json::from_value(status["state"].take()).ok()?;
+                Some((state, status["message"].as_str().map(String::from)))
+            });
+        let (state, message) = match cached {
+            Some(cached) => cached,
+            None => {
+                let info = ctrl.state(axis).await?;
+                (info.state, info.message)
+            }
+        };
+        match state {
+            AxisState::Disabled => Err(CommandError::new(
+                "AXIS_DISABLED",
+                format!("Axis {} is disabled; enable it before moving", axis),
+            )
+            .into()),
+            AxisState::Fault | AxisState::Alarm if !force => Err(with_axis_message(
+                CommandError::new(
+                    "AXIS_NOT_READY",
+                    format!(
+                        "Axis {} is in {:?}; reset it, or force the move to recover",
+                        axis, state
+                    ),
+                )
+                .into(),
+                message,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The axis must support each provided parameter and accept the custom
+    /// values.
+    async fn check_params(
+        ctrl: &dyn MotorController,
+        axis: &str,
+        params: Option<&MovementParams>,
+    ) -> Result<()> {
+        if let Some(params) = params {
+            let supported = ctrl.supported_movement_params(axis).await?;
+            let is_supported = |name: &str| supported.iter().any(|s| s == name);
+            if let Some(unsupported) = params
+                .provided_names()
+                .into_iter()
+                .find(|name| !params.custom.contains_key(*name) && !is_supported(name))
+            {
+                return Err(CommandError::new(
+                    "UNSUPPORTED_PARAM",
+                    format!(
+                        "Movement parameter '{}' is not supported by axis {}",
+                        unsupported, axis
+                    ),
+                )
+                .into());
+            }
+
+            // Custom names are free-form, so report every unknown one at
+            // once; a typo is the likely cause.
+            if !is_supported(ANY_CUSTOM_PARAM) {
+                let mut unknown: Vec<&str> = params
+                    .custom
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|name| !is_supported(name))
+                    .collect();
+                if !unknown.is_empty() {
+                    unknown.sort_unstable();
+                    return Err(CommandError::new(
+                        "UNKNOWN_CUSTOM_PARAM",
+                        format!(
+                            "Unknown custom movement parameters for axis {}: {}",
+                            axis,
+                            unknown.join(", ")
+                        ),
+                    )
+                    .into());
+                }
+            }
+
+            ctrl.get_axis(axis)?
+                .validate_custom_params(&params.custom)
+                .map_err(|msg| CommandError::new("INVALID_PARAMS", msg))?;
+        }
+        Ok(())
+    }
+
+    /// The parameters a move with `params` would run with: the axis's
+    /// defaults merged under them, validated, then held to the safety
+    /// limits. Also returns what had to be clamped; see `apply_safety_limits`.
+    fn resolve_params(
+        &self,
+        controller: &str,
+        axis: &str,
+        params: Option<MovementParams>,
+    ) -> Result<(Option<MovementParams>, serde_json::Map<String, Value>)> {
+        let params = self.with_defaults(controller, axis, params);
+        Self::validate_params(params.as_ref())?;
+        self.apply_safety_limits(controller, axis, params)
+    }
+
+    /// Runs a move's parameter pipeline without moving, for clients that
+    /// want to preview what a move would use. Unlike a dry run, the axis
+    /// state isn't checked.
+    async fn handle_resolve_params(
+        &self,
+        controller: &str,
+        axis: &str,
+        params: Option<MovementParams>,
+    ) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        ctrl.get_axis(axis)?;
+        let (params, clamped) = self.resolve_params(controller, axis, params)?;
+        Self::check_params(ctrl.as_ref(), axis, params.as_ref()).await?;
+        Ok(with_clamped(
+            json!({"controller": controller, "axis": axis, "params": params}),
+            clamped,
+        ))
+    }
+
+    /// Runs the move and records it in the axis's history, dry runs aside.
+    /// A percentage target is resolved first, and recorded and reported as
+    /// the position it stands for. `permit` is the controller's concurrency
+    /// permit, held until the move has ended.
+    async fn handle_move(
+        &self,
+        controller: &str,
+        axis: &str,
+        target: f64,
+        params: Option<MovementParams>,
+        options: MoveOptions,
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> Result<Value> {
+        let (target, percent) = match options.target_kind {
+            TargetKind::Absolute => (target, None),
+            TargetKind::Percent => (
+                self.percent_target(controller, axis, target).await?,
+                Some(target),
+            ),
+        };
+        // Recorded up front so that a move that isn't waited for can fill
+        // in its error once it fails.
+        let timestamp = chrono::Utc::now();
+        if !options.dry_run {
+            let record = MoveRecord {
+                timestamp,
+                target,
+                params: params.clone(),
+                error: None,
+            };
+            self.history.record(controller, axis, record);
+        }
+        let task = MoveTask {
+            permit,
+            recorded_at: timestamp,
+        };
+        let mut result = self
+            .run_move(controller, axis, target, params, options, task)
+            .await;
+        if let (Ok(response), Some(percent)) = (result.as_mut(), percent) {
+            response["percent"] = json!(percent);
+        }
+        if let (Err(e), false) = (&result, options.dry_run) {
+            self.history
+                .set_error(controller, axis, timestamp, e.to_string());
+        }
+        result
+    }
+
+    /// The position `percent` of the way from the axis's `min_position` to
+    /// its `max_position`, in user units. Fails with `NO_LIMITS` unless the
+    /// axis reports both.
+    async fn percent_target(&self, controller: &str, axis: &str, percent: f64) -> Result<f64> {
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(CommandError::new(
+                "INVALID_PARAMS",
+                format!("Percent target must be between 0 and 100, got {}", percent),
+            )
+            .into());
+        }
+        let ctrl = self.controller(controller).await?;
+        let limits = Self::user_limits(ctrl.as_ref(), axis).await?;
+        let (Some(min), Some(max)) = (limits.min_position, limits.max_position) else {
+            return Err(CommandError::new(
+                "NO_LIMITS",
+                format!(
+                    "{}::{} doesn't report min_position and max_position to take a percentage of",
+                    controller, axis
+                ),
+            )
+            .into());
+        };
+        Ok(min + (max - min) * percent / 100.0)
+    }
+
+    async fn run_move(
+        &self,
+        controller: &str,
+        axis: &str,
+        target: f64,
+        params: Option<MovementParams>,
+        options: MoveOptions,
+        move_task: MoveTask,
+    ) -> Result<Value> {
+        let (params, clamped) = self.resolve_params(controller, axis, params)?;
+
+        let ctrl = self.controller(controller).await?;
+        let ax = ctrl.get_axis(axis)?;
+        // The move runs in its own task so that `cancel` can abort it. That
+        // task owns the axis lock, so a move that isn't waited for still
+        // keeps other moves off the axis until it finishes. The lock is
+        // taken before the checks, so a move queued behind another, dry run
+        // or not, is checked against where the axis stopped.
+        let guard = self.axis_lock(controller, axis).lock_owned().await;
+        self.check_motion(
+            ctrl.as_ref(),
+            controller,
+            axis,
+            params.as_ref(),
+            options.force,
+        )
+        .await?;
+        if !options.override_distance {
+            self.check_distance(controller, axis, target).await?;
+        }
+        let raw_target = ax.to_raw_units(target);
+        Self::check_move_allowed(ctrl.as_ref(), controller, axis, raw_target, params.as_ref())
+            .await?;
+
+        if options.dry_run {
+            return Ok(with_clamped(
+                json!({"status": "ok", "action": "move", "target": target, "dry_run": true}),
+                clamped,
+            ));
+        }
+
+        let position_key = cache_key(controller, axis, "position");
+        self.cache.invalidate(&position_key).await;
+
+        let task = {
+            let ctrl = ctrl.clone();
+            let axis = axis.to_string();
+            let cache = self.cache.clone();
+            let writes = self.position_writes.clone();
+            let history = self.history.clone();
+            let controller = controller.to_string();
+            self.track_move(
+                controller.clone(),
+                axis.clone(),
+                options.owner,
+                async move {
+                    let _guard = guard;
+                    let _permit = move_task.permit;
+                    let result = ctrl.start(&axis, raw_target, params).await;
+                    cache.invalidate(&position_key).await;
+                    // The move may have run onto or off a switch.
+                    cache
+                        .invalidate(&cache_key(&controller, &axis, "limit_switches"))
+                        .await;
+                    match &result {
+                        Ok(()) => queue_position(writes.as_ref(), &controller, &axis, target),
+                        Err(e) => {
+                            // A tripped or faulted move stopped short of
+                            // its target, so persist where it really is.
+                            persist_reading(writes.as_ref(), ctrl.as_ref(), &controller, &axis)
+                                .await;
+                            // Nobody is waiting for the reply, so the
+                            // failure goes to the history and the log
+                            // instead.
+                            if !options.wait {
+                                warn!("Move of {}::{} failed: {}", controller, axis, e);
+                                cache
+                                    .invalidate(&cache_key(&controller, &axis, "status"))
+                                    .await;
+                                history.set_error(
+                                    &controller,
+                                    &axis,
+                                    move_task.recorded_at,
+                                    e.to_string(),
+                                );
+                            }
+                        }
+                    }
+                    result
+                },
+            )
+        };
+
+        if !options.wait {
+            return Ok(with_clamped(
+                json!({"status": "ok", "action": "move", "target": target, "wait": false}),
+                clamped,
+            ));
+        }
+
+        match task.await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                let info = self.refresh_status(ctrl.as_ref(), controller, axis).await;
+                let err = match Self::limit_error(controller, axis, e) {
+                    err if err.is::<CommandError>() => err,
+                    err => CommandError::new("MOVE_FAILED", err.to_string()).into(),
+                };
+                return Err(with_axis_message(err, info.and_then(|info| info.message)));
+            }
+            Err(e) if e.is_cancelled() => {
+                return Err(CommandError::new(
+                    "CANCELLED",
+                    format!("Move of {}::{} was cancelled", controller, axis),
+                )
+                .into());
+            }
+            Err(e) => return Err(e.into()),
+        }
+        self.observe_state(ctrl.as_ref(), controller, axis).await;
+        Ok(with_clamped(
+            json!({"status": "ok", "action": "move", "target": target}),
+            clamped,
+        ))
+    }
+
+    /// Asks the controller whether a move of `axis` to `raw_target` may go
+    /// ahead, failing with `MOVE_VETOED` if it refuses.
+    async fn check_move_allowed(
+        ctrl: &dyn MotorController,
+        controller: &str,
+        axis: &str,
+        raw_target: f64,
+        params: Option<&MovementParams>,
+    ) -> Result<()> {
+        ctrl.check_move_allowed(axis, raw_target, params)
+            .await
+            .map_err(|reason| {
+                CommandError::new(
+                    "MOVE_VETOED",
+                    format!("Move of {}::{} vetoed: {}", controller, axis, reason),
+                )
+                .into()
+            })
+    }
+
+    /// Turns an axis's `LimitTripped` into a `LIMIT_TRIPPED` error naming
+    /// the side; other errors pass through.
+    fn limit_error(controller: &str, axis: &str, err: anyhow::Error) -> anyhow::Error {
+        match err.downcast_ref::<LimitTripped>() {
+            Some(tripped) => CommandError::new(
+                "LIMIT_TRIPPED",
+                format!(
+                    "Move of {}::{} stopped on its {} limit switch",
+                    controller,
+                    axis,
+                    tripped.switches.side()
+                ),
+            )
+            .into(),
+            None => err,
+        }
+    }
+
+    /// Spawns a move and records it so it can be cancelled; the record is
+    /// removed when the move finishes. The map lock is held across the spawn
+    /// so a move that finishes immediately can't leave a stale record.
+    fn track_move<F, T>(
+        &self,
+        controller: String,
+        axis: String,
+        owner: Option<u64>,
+        start: F,
+    ) -> JoinHandle<Result<T>>
+    where
+        F: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let generation = self.next_move.fetch_add(1, AtomicOrdering::Relaxed);
+        let key = (controller, axis);
+        let moves = self.moves.clone();
+
+        let mut tracked = self.moves.lock().unwrap();
+        let task = tokio::spawn({
+            let key = key.clone();
+            async move {
+                let result = start.await;
+                let mut moves = moves.lock().unwrap();
+                if moves
+                    .get(&key)
+                    .is_some_and(|tracked| tracked.generation == generation)
+                {
+                    moves.remove(&key);
+                }
+                result
+            }
+        });
+        tracked.insert(
+            key,
+            TrackedMove {
+                generation,
+                task: task.abort_handle(),
+                owner,
+            },
+        );
+        task
+    }
+
+    /// Resolves `pattern` against every registered axis and runs the moves
+    /// concurrently, each through `handle_move` so it takes its own axis
+    /// lock and checks. One failed move doesn't stop the others; each
+    /// target gets its own entry in `results`.
+    async fn handle_move_all(
+        &self,
+        pattern: &str,
+        target: f64,
+        params: Option<MovementParams>,
+        confirm: bool,
+        owner: Option<u64>,
+    ) -> Result<Value> {
+        let (controller_pattern, axis_pattern) = match pattern.split_once("::") {
+            Some((controller, axis)) => (controller, axis),
+            None => ("*", pattern),
+        };
+        if !confirm && axis_pattern.chars().all(|c| c == '*' || c == '?') {
+            return Err(CommandError::new(
+                "CONFIRMATION_REQUIRED",
+                format!(
+                    "Pattern '{}' matches any axis name; resend with \"confirm\": true",
+                    pattern
+                ),
+            )
+            .into());
+        }
+
+        let mut targets: Vec<AxisKey> = Vec::new();
+        for (name, ctrl) in self.controllers.read().await.iter() {
+            if !glob_match(controller_pattern, name) {
+                continue;
+            }
+            for axis in ctrl.axes() {
+                if glob_match(axis_pattern, axis.name()) {
+                    targets.push((name.clone(), axis.name().to_string()));
+                }
+            }
+        }
+        if targets.is_empty() {
+            return Err(
+                CommandError::new("NO_MATCH", format!("No axis matches '{}'", pattern)).into(),
+            );
+        }
+        targets.sort();
+
+        let results = self.move_axes(&targets, target, params, owner).await;
+        Ok(json!({"status": "ok", "action": "move_all", "target": target, "results": results}))
+    }
+
+    /// Moves every axis in `targets` to `target` concurrently, each through
+    /// `handle_move` on behalf of `owner`, and returns one `axis_result` per
+    /// axis.
+    async fn move_axes(
+        &self,
+        targets: &[AxisKey],
+        target: f64,
+        params: Option<MovementParams>,
+        owner: Option<u64>,
+    ) -> Vec<Value> {
+        let results = join_all(targets.iter().map(|(controller, axis)| {
+            let options = MoveOptions {
+                target_kind: TargetKind::Absolute,
+                dry_run: false,
+                wait: true,
+                force: false,
+                override_distance: false,
+                owner,
+            };
+            self.handle_move(controller, axis, target, params.clone(), options, None)
+        }))
+        .await;
+        targets
+            .iter()
+            .zip(results)
+            .map(|((controller, axis), result)| axis_result(controller, axis, result))
+            .collect()
+    }
+
+    fn handle_define_group(&self, group: String, members: Vec<AxisKey>) -> Result<Value> {
+        if group.is_empty() {
+            return Err(CommandError::new("INVALID_NAME", "Group name is empty").into());
+        }
+        if members.is_empty() {
+            return Err(CommandError::new(
+                "INVALID_PARAMS",
+                format!("Group {} has no members", group),
+            )
+            .into());
+        }
+        let count = members.len();
+        self.groups.write().unwrap().insert(group.clone(), members);
+        Ok(json!({"status": "ok", "action": "define_group", "group": group, "members": count}))
+    }
+
+    /// The group's members with aliases (and, with `case_insensitive_axes`,
+    /// axis names) resolved as they would be for a single-axis command.
+    fn group_members(&self, group: &str) -> Result<Vec<AxisKey>> {
+        let members = self
+            .groups
+            .read()
+            .unwrap()
+            .get(group)
+            .cloned()
+            .ok_or_else(|| {
+                CommandError::new("GROUP_NOT_FOUND", format!("No group named {}", group))
+            })?;
+        let aliases = self.aliases.read().unwrap();
+        let known_axes = self.known_axes.lock().unwrap();
+        Ok(members
+            .into_iter()
+            .map(|(controller, axis)| {
+                let controller = aliases.get(&controller).cloned().unwrap_or(controller);
+                let axis = match known_axes.get(&controller) {
+                    Some(known) if self.case_insensitive_axes => {
+                        fold_axis_name(known, &axis).unwrap_or(axis)
+                    }
+                    _ => axis,
+                };
+                (controller, axis)
+            })
+            .collect())
+    }
+
+    /// Moves every member of `group` to `target` concurrently, like
+    /// `move_all`. One failed move doesn't stop the others.
+    async fn handle_move_group(
+        &self,
+        group: &str,
+        target: f64,
+        params: Option<MovementParams>,
+        owner: Option<u64>,
+    ) -> Result<Value> {
+        let members = self.group_members(group)?;
+        let results = self.move_axes(&members, target, params, owner).await;
+        Ok(json!({
+            "status": "ok",
+            "action": "move_group",
+            "group": group,
+            "target": target,
+            "results": results,
+        }))
+    }
+
+    /// Stops every member of `group` at once, as `stop` would each.
+    async fn handle_stop_group(&self, group: &str) -> Result<Value> {
+        let members = self.group_members(group)?;
+        let results = join_all(
+            members
+                .iter()
+                .map(|(controller, axis)| self.handle_stop(controller, axis, StopMode::Immediate)),
+        )
+        .await;
+        let results: Vec<Value> = members
+            .iter()
+            .zip(results)
+            .map(|((controller, axis), result)| axis_result(controller, axis, result))
+            .collect();
+        Ok(json!({"status": "ok", "action": "stop_group", "group": group, "results": results}))
+    }
+
+    /// Stops every axis of `controller` at once, as `stop` would each. One
+    /// axis failing to stop doesn't keep the others from stopping; each
+    /// axis's outcome is in `results`.
+    async fn handle_stop_controller(&self, controller: &str) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        let axes: Vec<String> = ctrl
+            .axes()
+            .iter()
+            .map(|axis| axis.name().to_string())
+            .collect();
+
+        let results = join_all(axes.iter().map(|axis| {
+            let ctrl = ctrl.clone();
+            async move {
+                ctrl.stop(axis).await?;
+                self.refresh_status(ctrl.as_ref(), controller, axis).await;
+                anyhow::Ok(())
+            }
+        }))
+        .await;
+        let results: Vec<Value> = axes
+            .iter()
+            .zip(results)
+            .map(|(axis, result)| axis_result(controller, axis, result))
+            .collect();
+        Ok(json!({"status": "ok", "action": "stop_controller", "results": results}))
+    }
+
+    /// Aborts the in-flight move on the axis, if any, and stops the axis.
+    /// The stop is issued even when no move was found.
+    async fn handle_cancel(&self, controller: &str, axis: &str) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        ctrl.get_axis(axis)?;
+
+        let task = self
+            .moves
+            .lock()
+            .unwrap()
+            .remove(&(controller.to_string(), axis.to_string()));
+        let found = task.is_some();
+        if let Some(tracked) = task {
+            tracked.task.abort();
+        }
+
+        ctrl.stop(axis).await?;
+        self.cache
+            .invalidate(&cache_key(controller, axis, "position"))
+            .await;
+        self.refresh_status(ctrl.as_ref(), controller, axis).await;
+        if found {
+            self.persist_current_position(ctrl.as_ref(), controller, axis)
+                .await;
+        }
+        Ok(json!({"status": "ok", "action": "cancel", "cancelled": found}))
+    }
+
+    /// Cancels, as `cancel` would, every in-flight move sent by connection
+    /// `owner`, leaving moves of other connections alone. Each axis is
+    /// stopped even if another fails to stop.
+    async fn handle_stop_owned_moves(&self, owner: u64) -> Result<Value> {
+        let owned: Vec<AxisKey> = {
+            let mut moves = self.moves.lock().unwrap();
+            let keys: Vec<AxisKey> = moves
+                .iter()
+                .filter(|(_, tracked)| tracked.owner == Some(owner))
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in &keys {
+                if let Some(tracked) = moves.remove(key) {
+                    tracked.task.abort();
+                }
+            }
+            keys
+        };
+
+        let mut stopped = Vec::new();
+        for (controller, axis) in owned {
+            let result = async {
+                let ctrl = self.controller(&controller).await?;
+                ctrl.stop(&axis).await?;
+                self.cache
+                    .invalidate(&cache_key(&controller, &axis, "position"))
+                    .await;
+                self.refresh_status(ctrl.as_ref(), &controller, &axis).await;
+                self.persist_current_position(ctrl.as_ref(), &controller, &axis)
+                    .await;
+                anyhow::Ok(())
+            }
+            .await;
+            match result {
+                Ok(()) => {
+                    info!(
+                        "Stopped {}::{} after connection {} went away",
+                        controller, axis, owner
+                    );
+                    stopped.push(json!({"controller": controller, "axis": axis}));
+                }
+                Err(e) => warn!(
+                    "Failed to stop {}::{} for connection {}: {}",
+                    controller, axis, owner, e
+                ),
+            }
+        }
+        Ok(json!({"status": "ok", "action": "stop_owned_moves", "stopped": stopped}))
+    }
+
+    /// Runs like a waited move, under the axis lock and tracked so that
+    /// `cancel` or its owner's `StopOwnedMoves` can abort it. Position and
+    /// status are both invalidated afterwards since the axis may also have
+    /// been re-zeroed.
+    async fn handle_move_to_limit(
+        &self,
+        controller: &str,
+        axis: &str,
+        direction: LimitDirection,
+        params: Option<MovementParams>,
+        zero_position: bool,
+        owner: Option<u64>,
+    ) -> Result<Value> {
+        let (params, clamped) = self.resolve_params(controller, axis, params)?;
+
+        let ctrl = self.controller(controller).await?;
+        let ax = ctrl.get_axis(axis)?;
+        // Driving onto a switch is a way out of a limit alarm, so faulted
+        // axes aren't refused here.
+        self.check_motion(ctrl.as_ref(), controller, axis, params.as_ref(), true)
+            .await?;
+        // The controller is asked about the soft limit the axis will drive
+        // towards, or an infinity that way when it has none.
+        let limits = ctrl.get_limits(axis).await.ok();
+        let raw_target = match direction {
+            LimitDirection::Upper => limits
+                .and_then(|limits| limits.max_position)
+                .unwrap_or(f64::INFINITY),
+            LimitDirection::Lower => limits
+                .and_then(|limits| limits.min_position)
+                .unwrap_or(f64::NEG_INFINITY),
+        };
+        Self::check_move_allowed(ctrl.as_ref(), controller, axis, raw_target, params.as_ref())
+            .await?;
+
+        let guard = self.axis_lock(controller, axis).lock_owned().await;
+        let task = {
+            let ctrl = ctrl.clone();
+            let axis = axis.to_string();
+            self.track_move(controller.to_string(), axis.clone(), owner, async move {
+                let switches = ctrl
+                    .move_to_limit(&axis, direction, params, zero_position)
+                    .await?;
+                Ok((switches, guard))
+            })
+        };
+        // The lock comes back with the result and is held until the new
+        // position has been persisted.
+        let (switches, _guard) = match task.await {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => {
+                self.persist_current_position(ctrl.as_ref(), controller, axis)
+                    .await;
+                return Err(e);
+            }
+            Err(e) if e.is_cancelled() => {
+                return Err(CommandError::new(
+                    "CANCELLED",
+                    format!("Move of {}::{} was cancelled", controller, axis),
+                )
+                .into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        self.cache
+            .invalidate(&cache_key(controller, axis, "position"))
+            .await;
+        self.refresh_status(ctrl.as_ref(), controller, axis).await;
+        if let Ok(position) = ctrl.get_position(axis).await {
+            self.persist_position(controller, axis, ax.to_user_units(position));
+        }
+
+        Ok(with_clamped(
+            json!({
+                "status": "ok",
+                "action": "move_to_limit",
+                "direction": direction,
+                "limit_switches": switches,
+            }),
+            clamped,
+        ))
+    }
+
+    /// Stop deliberately bypasses the per-axis lock so that it can interrupt
+    /// a move that is still in progress.
+    ///
+    /// `was_moving` reports whether the axis was moving or paused mid-move
+    /// beforehand, using a cached state when one is valid. It is `null` if
+    /// that state could not be read; the stop is issued regardless.
+    ///
+    /// A decelerating stop replies once the axis has come to rest.
+    async fn handle_stop(&self, controller: &str, axis: &str, mode: StopMode) -> Result<Value> {
+        if let StopMode::Decelerate { rate: Some(rate) } = mode {
+            if !rate.is_finite() || rate <= 0.0 {
+                return Err(CommandError::new(
+                    "INVALID_PARAMS",
+                    format!("Deceleration rate must be positive, got {}", rate),
+                )
+                .into());
+            }
+        }
+        let ctrl = self.controller(controller).await?;
+        let was_moving = self
+            .handle_get_state(controller, axis, false)
+            .await
+            .ok()
+            .and_then(|state| {
+                serde_json::from_value::<AxisState>(state["status"]["state"].clone()).ok()
+            })
+            .map(|state| {
+                matches!(
+                    state,
+                    AxisState::Moving | AxisState::Paused | AxisState::Settling
+                )
+            });
+
+        ctrl.stop_with(axis, mode).await?;
+        self.refresh_status(ctrl.as_ref(), controller, axis).await;
+        Ok(json!({"status": "ok", "action": "stop", "was_moving": was_moving}))
+    }
+
+    /// Drops the cached status and limit switches after a command that
+    /// changed them, and records the new state for event subscribers.
+    async fn refresh_status(
+        &self,
+        ctrl: &dyn MotorController,
+        controller: &str,
+        axis: &str,
+    ) -> Option<AxisStateInfo> {
+        self.cache
+            .invalidate(&cache_key(controller, axis, "status"))
+            .await;
+        self.cache
+            .invalidate(&cache_key(controller, axis, "limit_switches"))
+            .await;
+        self.observe_state(ctrl, controller, axis).await
+    }
+
+    /// Like stop, pause and resume bypass the per-axis lock because the move
+    /// they act on is still holding it.
+    async fn handle_pause(&self, controller: &str, axis: &str) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        ctrl.pause(axis).await?;
+        self.refresh_status(ctrl.as_ref(), controller, axis).await;
+        Ok(json!({"status": "ok", "action": "pause"}))
+    }
+
+    async fn handle_resume(&self, controller: &str, axis: &str) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        ctrl.resume(axis).await?;
+        self.refresh_status(ctrl.as_ref(), controller, axis).await;
+        Ok(json!({"status": "ok", "action": "resume"}))
+    }
+
+    /// Disabling de-energizes the drive, so like stop it must not wait for a
+    /// move holding the axis lock.
+    async fn handle_disable(&self, controller: &str, axis: &str) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        ctrl.disable(axis).await?;
+        self.refresh_status(ctrl.as_ref(), controller, axis).await;
+        Ok(json!({"status": "ok", "action": "disable"}))
+    }
+
+    async fn handle_enable(&self, controller: &str, axis: &str) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        ctrl.enable(axis).await?;
+        self.refresh_status(ctrl.as_ref(), controller, axis).await;
+        Ok(json!({"status": "ok", "action": "enable"}))
+    }
+
+    /// Only a faulted axis can be reset; its state is read from the hardware
+    /// rather than the cache to decide.
+    async fn handle_reset_fault(&self, controller: &str, axis: &str) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        let state = ctrl.state(axis).await?;
+        if !state.is_faulted() {
+            return Err(CommandError::new(
+                "NOT_FAULTED",
+                format!(
+                    "Axis {}::{} is not faulted (state: {:?})",
+                    controller, axis, state.state
+                ),
+            )
+            .into());
+        }
+
+        ctrl.reset_fault(axis).await?;
+        self.refresh_status(ctrl.as_ref(), controller, axis).await;
+        Ok(json!({"status": "ok", "action": "reset_fault"}))
+    }
+
+    /// Waits for any move on the axis to finish, so the reference can't
+    /// shift under it. `value` is in user units.
+    async fn handle_set_reference(
+        &self,
+        controller: &str,
+        axis: &str,
+        value: f64,
+    ) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        let ax = ctrl.get_axis(axis)?;
+
+        let lock = self.axis_lock(controller, axis);
+        let _guard = lock.lock().await;
+
+        ctrl.set_position_reference(axis, ax.to_raw_units(value))
+            .await?;
+        self.cache
+            .invalidate(&cache_key(controller, axis, "position"))
+            .await;
+        self.persist_position(controller, axis, value);
+        Ok(json!({"status": "ok", "action": "set_reference", "position": value}))
+    }
+
+    /// JSON for a reading of the axis, rounded to its `position_decimals`.
+    fn reading(&self, controller: &str, axis: &str, value: f64) -> Value {
+        let decimals = self
+            .axis_position_decimals
+            .get(&(controller.to_string(), axis.to_string()))
+            .copied()
+            .or(self.position_decimals);
+        match decimals {
+            Some(decimals) => number_value(round_to(value, decimals)),
+            None => number_value(value),
+        }
+    }
+
+    /// A `fresh` read skips the cache lookup but still refreshes the entry.
+    async fn handle_get_pos(&self, controller: &str, axis: &str, fresh: bool) -> Result<Value> {
+        let cache_key = cache_key(controller, axis, "position");
+
+        if !fresh {
+            if let Some(val) = self.cache_lookup(&cache_key).await {
+                return Ok(json!({"controller": controller, "axis": axis, "position": val}));
+            }
+        }
+
+        let ctrl = self.controller(controller).await?;
+
+        let ax = ctrl.get_axis(axis)?;
+
+        let read = async {
+            let pos = ax.to_user_units(ctrl.get_position(axis).await?);
+            if pos.is_finite() {
+                self.persist_position(controller, axis, pos);
+            }
+            let value = self.reading(controller, axis, pos);
+
+            self.cache_insert(controller, axis, "position", "position", value.clone())
+                .await;
+            Ok(value)
+        };
+        let value = if fresh {
+            read.await?
+        } else {
+            self.read_coalesced(&cache_key, read).await?
+        };
+
+        Ok(json!({"controller": controller, "axis": axis, "position": value}))
+    }
+
+    /// Reads go through `handle_get_pos`, so cached positions are reused. An
+    /// axis that can't be read gets a `null` position and an `error` rather
+    /// than failing the whole request.
+    async fn handle_get_positions(&self, controller: &str, axes: &[String]) -> Result<Value> {
+        self.controller(controller).await?;
+
+        let results = join_all(
+            axes.iter()
+                .map(|axis| self.handle_get_pos(controller, axis, false)),
+        )
+        .await;
+        let positions: serde_json::Map<String, Value> = axes
+            .iter()
+            .zip(results)
+            .map(|(axis, result)| {
+                let entry = match result {
+                    Ok(mut value) => json!({"position": value["position"].take()}),
+                    Err(e) => json!({"position": null, "error": e.to_string()}),
+                };
+                (axis.clone(), entry)
+            })
+            .collect();
+        Ok(json!({"controller": controller, "positions": positions}))
+    }
+
+    /// Reads every axis's state fresh and concurrently. Axes whose state
+    /// can't be read are reported with an `error` and set
+    /// `any_unreadable`, rather than failing the whole query.
+    async fn handle_controller_health(&self, controller: &str) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        let axes = ctrl.axes();
+
+        let ctrl = ctrl.as_ref();
+        let states = join_all(axes.iter().map(|ax| async move {
+            let state_info = ctrl.state(ax.name()).await?;
+            self.events.observe(controller, ax.name(), &state_info);
+            self.cache_insert(
+                controller,
+                ax.name(),
+                "status",
+                "status",
+                serde_json::to_value(&state_info)?,
+            )
+            .await;
+            anyhow::Ok(state_info)
+        }))
+        .await;
+
+        let (mut any_moving, mut any_faulted, mut any_limit_active, mut any_unreadable) =
+            (false, false, false, false);
+        let mut summary = serde_json::Map::new();
+        for (ax, state) in axes.iter().zip(states) {
+            let entry = match state {
+                Ok(info) => {
+                    let limit_active = info.limit_switches.any_active();
+                    any_moving |= info.is_moving();
+                    any_faulted |= info.is_faulted();
+                    any_limit_active |= limit_active;
+                    json!({
+                        "state": info.state,
+                        "moving": info.is_moving(),
+                        "faulted": info.is_faulted(),
+                        "ready": info.is_ready(),
+                        "limit_active": limit_active,
+                    })
+                }
+                Err(e) => {
+                    any_unreadable = true;
+                    json!({"state": null, "error": e.to_string()})
+                }
+            };
+            summary.insert(ax.name().to_string(), entry);
+        }
+        Ok(json!({
+            "controller": controller,
+            "any_moving": any_moving,
+            "any_faulted": any_faulted,
+            "any_limit_active": any_limit_active,
+            "any_unreadable": any_unreadable,
+            "axes": summary,
+        }))
+    }
+
+    /// `handle_controller_health` for every controller concurrently, each
+    /// bounded by `health_timeout`. A controller that fails or doesn't
+    /// answer in time gets an `error` entry and sets `any_unreadable`, so
+    /// one bad controller can't hold up or fail the report.
+    async fn handle_system_health(&self) -> Result<Value> {
+        let controllers: Vec<String> = self.controllers.read().await.keys().cloned().collect();
+        let timeout = self.health_timeout;
+
+        let mut entries = join_all(controllers.iter().map(|name| async move {
+            match tokio::time::timeout(timeout, self.handle_controller_health(name)).await {
+                Ok(Ok(health)) => health,
+                Ok(Err(e)) => json!({"controller": name, "error": e.to_string()}),
+                Err(_) => json!({
+                    "controller": name,
+                    "error": format!("No health report within {:?}", timeout),
+                }),
+            }
+        }))
+        .await;
+        entries.sort_by(|a, b| a["controller"].as_str().cmp(&b["controller"].as_str()));
+
+        let any = |flag: &str| entries.iter().any(|entry| entry[flag] == true);
+        Ok(json!({
+            "any_moving": any("any_moving"),
+            "any_faulted": any("any_faulted"),
+            "any_limit_active": any("any_limit_active"),
+            "any_unreadable": any("any_unreadable") || entries.iter().any(|entry| entry.get("error").is_some()),
+            "controllers": entries,
+        }))
+    }
+
+    /// Progress is read on every call rather than cached with the status,
+    /// since it changes continuously during a move, but only while the
+    /// status says the axis is moving.
+    async fn handle_get_state(&self, controller: &str, axis: &str, fresh: bool) -> Result<Value> {
+        let cache_key = cache_key(controller, axis, "status");
+        let ctrl = self.controller(controller).await?;
+        ctrl.get_axis(axis)?;
+
+        let cached = if fresh {
+            None
+        } else {
+            self.cache_lookup(&cache_key).await
+        };
+        let status = match cached {
+            Some(val) => val,
+            None => {
+                let state_info = ctrl.state(axis).await?;
+                self.events.observe(controller, axis, &state_info);
+                let status_json = serde_json::to_value(&state_info)?;
+                self.cache_insert(controller, axis, "status", "status", status_json.clone())
+                    .await;
+                status_json
+            }
+        };
+        let progress = if status["state"] == json!(AxisState::Moving) {
+            // A failed progress read leaves the state readable.
+            ctrl.get_progress(axis).await.ok().flatten()
+        } else {
+            None
+        };
+        Ok(json!({"controller": controller, "axis": axis, "status": status, "progress": progress}))
+    }
+
+    async fn handle_get_attr(&self, controller: &str, axis: &str, attr: &str) -> Result<Value> {
+        let cache_key = cache_key(controller, axis, attr);
+        if let Some(val) = self.cache_lookup(&cache_key).await {
+            return Ok(
+                json!({"controller": controller, "axis": axis, "attribute": attr, "value": val}),
+            );
+        }
+        // Not in cache or expired: compute. Membership is checked against the
+        // cached attribute list, so the controller isn't asked for it again.
+        let ctrl = self.controller(controller).await?;
+        let available = self
+            .available_attributes(ctrl.as_ref(), controller, axis)
+            .await?;
+        if !available.iter().any(|a| a == attr) {
+            // Nothing is cached, so the attribute shows up as soon as the
+            // attribute list changes.
+            return match self.missing_attribute_policy {
+                MissingAttributePolicy::Error => {
+                    anyhow::bail!("Attribute not supported: {}", attr)
+                }
+                MissingAttributePolicy::Null => Ok(json!({
+                    "controller": controller,
+                    "axis": axis,
+                    "attribute": attr,
+                    "value": null,
+                    "supported": false,
+                })),
+            };
+        }
+        let read = async {
+            let json_value = match ctrl.get_attribute_value(axis, attr).await? {
+                AttributeValue::Float(value) if is_position_attribute(attr) => {
+                    let ax = ctrl.get_axis(axis)?;
+                    self.reading(controller, axis, ax.to_user_units(value))
+                }
+                AttributeValue::Float(value) => self.reading(controller, axis, value),
+                value => serde_json::to_value(value)?,
+            };
+            self.cache_insert(controller, axis, attr, attr, json_value.clone())
+                .await;
+            Ok(json_value)
+        };
+        let json_value = self.read_coalesced(&cache_key, read).await?;
+        Ok(json!({"controller": controller, "axis": axis, "attribute": attr, "value": json_value}))
+    }
+
+    /// Like `handle_get_attr` for an array-valued attribute. The array is
+    /// cached apart from any scalar reading of the same name, with the
+    /// attribute's lifetime from `attribute_ttls`.
+    async fn handle_get_attr_array(
+        &self,
+        controller: &str,
+        axis: &str,
+        attr: &str,
+    ) -> Result<Value> {
+        let field = format!("{}[]", attr);
+        let cache_key = cache_key(controller, axis, &field);
+        if let Some(values) = self.cache_lookup(&cache_key).await {
+            return Ok(
+                json!({"controller": controller, "axis": axis, "attribute": attr, "value": values}),
+            );
+        }
+        let ctrl = self.controller(controller).await?;
+        let available = self
+            .available_attributes(ctrl.as_ref(), controller, axis)
+            .await?;
+        if !available.iter().any(|a| a == attr) {
+            anyhow::bail!("Attribute not supported: {}", attr);
+        }
+        let read = async {
+            let values: Vec<Value> = ctrl
+                .get_attribute_array(axis, attr)
+                .await?
+                .into_iter()
+                .map(number_value)
+                .collect();
+            let values = Value::Array(values);
+            self.cache_insert(controller, axis, &field, attr, values.clone())
+                .await;
+            Ok(values)
+        };
+        let values = self.read_coalesced(&cache_key, read).await?;
+        Ok(json!({"controller": controller, "axis": axis, "attribute": attr, "value": values}))
+    }
+
+    /// Writes the attribute under the axis lock and drops its cached value.
+    /// With `verify` set to a tolerance, the value is then read back from
+    /// the hardware, since some drives acknowledge writes they don't apply.
+    async fn handle_set_attr(
+        &self,
+        controller: &str,
+        axis: &str,
+        attr: &str,
+        value: f64,
+        verify: Option<f64>,
+    ) -> Result<Value> {
+        if !value.is_finite() {
+            return Err(CommandError::new(
+                "INVALID_PARAMS",
+                format!("Value of {} must be finite, got {}", attr, value),
+            )
+            .into());
+        }
+        if let Some(tolerance) = verify.filter(|t| !(t.is_finite() && *t >= 0.0)) {
+            return Err(CommandError::new(
+                "INVALID_PARAMS",
+                format!(
+                    "tolerance must be finite and not negative, got {}",
+                    tolerance
+                ),
+            )
+            .into());
+        }
+        let ctrl = self.controller(controller).await?;
+        let ax = ctrl.get_axis(axis)?;
+        let available = self
+            .available_attributes(ctrl.as_ref(), controller, axis)
+            .await?;
+        if !available.iter().any(|a| a == attr) {
+            anyhow::bail!("Attribute not supported: {}", attr);
+        }
+
+        let lock = self.axis_lock(controller, axis);
+        let _guard = lock.lock().await;
+
+        let cache_key = cache_key(controller, axis, attr);
+        let raw_value = if is_position_attribute(attr) {
+            ax.to_raw_units(value)
+        } else {
+            value
+        };
+        let written = ctrl.set_attribute(axis, attr, raw_value).await;
+        // Dropped even if the write failed, as it may have half applied.
+        self.cache.invalidate(&cache_key).await;
+        written?;
+        self.events.publish(
+            controller,
+            axis,
+            AxisEventKind::AttributeChanged {
+                attribute: attr.to_string(),
+                value,
+            },
+        );
+
+        let mut response =
+            json!({"controller": controller, "axis": axis, "attribute": attr, "value": value});
+        if let Some(tolerance) = verify {
+            let read_back = ctrl.get_attribute(axis, attr).await?;
+            let read_back = if is_position_attribute(attr) {
+                ax.to_user_units(read_back)
+            } else {
+                read_back
+            };
+            self.cache_insert(
+                controller,
+                axis,
+                attr,
+                attr,
+                self.reading(controller, axis, read_back),
+            )
+            .await;
+            let error = (read_back - value).abs();
+            if error.is_nan() || error > tolerance {
+                return Err(CommandError::new(
+                    "VERIFY_FAILED",
+                    format!(
+                        "Wrote {} to {}::{}.{} but it reads back as {}",
+                        value, controller, axis, attr, read_back
+                    ),
+                )
+                .into());
+            }
+            response["verified"] = json!(true);
+            response["read_back"] = self.reading(controller, axis, read_back);
+        }
+        Ok(response)
+    }
+
+    /// Cached like axis attributes, under the `CONTROLLER_SCOPE` pseudo-axis.
+    async fn handle_get_controller_attr(&self, controller: &str, attr: &str) -> Result<Value> {
+        let cache_key = cache_key(controller, CONTROLLER_SCOPE, attr);
+        if let Some(val) = self.cache_lookup(&cache_key).await {
+            return Ok(json!({"controller": controller, "attribute": attr, "value": val}));
+        }
+        let ctrl = self.controller(controller).await?;
+        let read = async {
+            let json_value = match ctrl.get_controller_attribute(attr).await? {
+                AttributeValue::Float(value) => self.reading(controller, CONTROLLER_SCOPE, value),
+                value => serde_json::to_value(value)?,
+            };
+            self.cache_insert(controller, CONTROLLER_SCOPE, attr, attr, json_value.clone())
+                .await;
+            Ok(json_value)
+        };
+        let json_value = self.read_coalesced(&cache_key, read).await?;
+        Ok(json!({"controller": controller, "attribute": attr, "value": json_value}))
+    }
+
+    /// Membership is checked against the cached attribute list, as for
+    /// attribute reads.
+    async fn handle_get_attr_info(
+        &self,
+        controller: &str,
+        axis: &str,
+        attr: &str,
+    ) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        let ax = ctrl.get_axis(axis)?;
+        let available = self
+            .available_attributes(ctrl.as_ref(), controller, axis)
+            .await?;
+        if !available.iter().any(|a| a == attr) {
+            anyhow::bail!("Attribute not supported: {}", attr);
+        }
+        let mut info = ctrl.attribute_info(axis, attr).await?;
+        if attr == "position" && info.tolerance.is_none() {
+            info.tolerance = ax.position_tolerance();
+        }
+        Ok(json!({"controller": controller, "axis": axis, "info": info}))
+    }
+
+    /// Reads the state once, then follows state events rather than polling,
+    /// so the wait only ends early if something observes the transition:
+    /// the poller (see `poll_interval`), a command on the axis, or the move
+    /// itself finishing. Not subject to `command_timeout`.
+    async fn handle_wait_for_state(
+        &self,
+        controller: &str,
+        axis: &str,
+        target: AxisState,
+        timeout: std::time::Duration,
+    ) -> Result<Value> {
+        // Subscribe before reading so a transition in between isn't missed.
+        let mut events = self.events.subscribe();
+        let ctrl = self.controller(controller).await?;
+        ctrl.get_axis(axis)?;
+        let reached =
+            json!({"controller": controller, "axis": axis, "state": target, "reached": true});
+
+        let info = ctrl.state(axis).await?;
+        self.events.observe(controller, axis, &info);
+        let mut state = info.state;
+        let wait = async {
+            while state != target {
+                state = match events.recv().await {
+                    Ok(AxisEvent {
+                        controller: c,
+                        axis: a,
+                        kind: AxisEventKind::StateChanged { new, .. },
+                        ..
+                    }) if c == controller && a == axis => new,
+                    Ok(_) => continue,
+                    // Missed events may include ours; look again.
+                    Err(broadcast::error::RecvError::Lagged(_)) => ctrl.state(axis).await?.state,
+                    Err(broadcast::error::RecvError::Closed) => {
+                        anyhow::bail!("Event bus closed")
+                    }
+                };
+            }
+            Ok(())
+        };
+        match tokio::time::timeout(timeout, wait).await {
+            Ok(result) => result.map(|()| reached),
+            Err(_) => Err(CommandError::new(
+                "WAIT_TIMEOUT",
+                format!(
+                    "Axis {}::{} did not reach {:?} within {} ms",
+                    controller,
+                    axis,
+                    target,
+                    timeout.as_millis()
+                ),
+            )
+            .into()),
+        }
+    }
+
+    async fn handle_get_history(
+        &self,
+        controller: &str,
+        axis: &str,
+        limit: Option<usize>,
+    ) -> Result<Value> {
+        self.controller(controller).await?.get_axis(axis)?;
+        let moves = self.history.recent(controller, axis, limit);
+        Ok(json!({"controller": controller, "axis": axis, "moves": moves}))
+    }
+
+    async fn handle_get_available_params(&self, controller: &str, axis: &str) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        ctrl.get_axis(axis)?;
+        let params = self
+            .available_attributes(ctrl.as_ref(), controller, axis)
+            .await?;
+        Ok(json!({"controller": controller, "axis": axis, "available_params": *params}))
+    }
+
+    async fn handle_get_supported_movement_params(
+        &self,
+        controller: &str,
+        axis: &str,
+    ) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        let params = ctrl.supported_movement_params(axis).await?;
+        Ok(json!({"controller": controller, "axis": axis, "supported_movement_params": params}))
+    }
+
+    async fn handle_get_capabilities(&self, controller: &str, axis: &str) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        let ax = ctrl.get_axis(axis)?;
+        let mut capabilities = ctrl.capabilities(axis).await?;
+        capabilities.limits = limits_in_user_units(ax.as_ref(), capabilities.limits);
+        Ok(json!({"controller": controller, "axis": axis, "capabilities": capabilities}))
+    }
+
+    async fn handle_get_limits(&self, controller: &str, axis: &str) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        let limits = Self::user_limits(ctrl.as_ref(), axis).await?;
+        Ok(json!({"controller": controller, "axis": axis, "limits": limits}))
+    }
+
+    /// The axis's soft limits in user units, as positions are reported.
+    async fn user_limits(ctrl: &dyn MotorController, axis: &str) -> Result<AxisLimits> {
+        let ax = ctrl.get_axis(axis)?;
+        Ok(limits_in_user_units(
+            ax.as_ref(),
+            ctrl.get_limits(axis).await?,
+        ))
+    }
+
+    /// How far the axis could get from its current position in `time_ms`,
+    /// either way, at its `velocity` attribute (capped by `max_velocity`).
+    /// An axis that also reports `acceleration` is assumed to start from
+    /// rest and ramp up. The range is clamped to the soft limits; fails with
+    /// `NO_VELOCITY` or `NO_LIMITS` when the axis lacks those attributes.
+    async fn handle_reachable_range(
+        &self,
+        controller: &str,
+        axis: &str,
+        time_ms: u64,
+    ) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        let ax = ctrl.get_axis(axis)?;
+        let limits = Self::user_limits(ctrl.as_ref(), axis).await?;
+        let (Some(min), Some(max)) = (limits.min_position, limits.max_position) else {
+            return Err(CommandError::new(
+                "NO_LIMITS",
+                format!(
+                    "{}::{} doesn't report min_position and max_position",
+                    controller, axis
+                ),
+            )
+            .into());
+        };
+        let velocity = match ctrl.get_attribute(axis, "velocity").await {
+            Ok(velocity) if velocity.is_finite() => velocity.abs(),
+            _ => {
+                return Err(CommandError::new(
+                    "NO_VELOCITY",
+                    format!("{}::{} doesn't report a velocity", controller, axis),
+                )
+                .into())
+            }
+        };
+        // The caps are in the hardware's units, like the rates, so they
+        // apply before converting.
+        let velocity = self.max_velocity.map_or(velocity, |cap| velocity.min(cap));
+        let velocity = rate_in_user_units(ax.as_ref(), velocity);
+        let acceleration = ctrl
+            .get_attribute(axis, "acceleration")
+            .await
+            .ok()
+            .filter(|acceleration| acceleration.is_finite() && *acceleration > 0.0)
+            .map(|acceleration| {
+                let acceleration = self
+                    .max_acceleration
+                    .map_or(acceleration, |cap| acceleration.min(cap));
+                rate_in_user_units(ax.as_ref(), acceleration)
+            });
+
+        let position = self.handle_get_pos(controller, axis, false).await?;
+        let Some(position) = position["position"].as_f64() else {
+            return Err(CommandError::new(
+                "INVALID_READING",
+                format!("Position of {}::{} is not a number", controller, axis),
+            )
+            .into());
+        };
+        let distance = reach_distance(velocity, acceleration, time_ms as f64 / 1000.0);
+        Ok(json!({
+            "controller": controller,
+            "axis": axis,
+            "time_ms": time_ms,
+            "position": position,
+            "min": (position - distance).max(min),
+            "max": (position + distance).min(max),
+        }))
+    }
+
+    async fn handle_get_limit_switches(&self, controller: &str, axis: &str) -> Result<Value> {
+        let key = cache_key(controller, axis, "limit_switches");
+        let switches = match self.cache_lookup(&key).await {
+            Some(switches) => switches,
+            None => {
+                let ctrl = self.controller(controller).await?;
+                let switches = serde_json::to_value(ctrl.get_limit_switches(axis).await?)?;
+                let ttl = self
+                    .attribute_ttls
+                    .get("limit_switches")
+                    .copied()
+                    .unwrap_or(LIMIT_SWITCHES_TTL);
+                self.track_cache_key(controller, axis, &key);
+                self.cache.insert_with_ttl(key, switches.clone(), ttl).await;
+                switches
+            }
+        };
+        Ok(json!({"controller": controller, "axis": axis, "limit_switches": switches}))
+    }
+
+    async fn handle_set_defaults(
+        &self,
+        controller: &str,
+        axis: &str,
+        params: MovementParams,
+    ) -> Result<Value> {
+        Self::validate_params(Some(&params))?;
+        let ctrl = self.controller(controller).await?;
+        ctrl.get_axis(axis)?;
+
+        let key = (controller.to_string(), axis.to_string());
+        let mut defaults = self.axis_defaults.lock().unwrap();
+        if params.provided_names().is_empty() {
+            defaults.remove(&key);
+        } else {
+            defaults.insert(key, params.clone());
+        }
+        Ok(json!({"status": "ok", "action": "set_defaults", "params": params}))
+    }
+
+    /// Not subject to `command_timeout`: diagnostics may exercise the
+    /// hardware for a while, and like every command they run on their own
+    /// task, so a slow self-test holds up nothing but its own reply.
+    async fn handle_self_test(&self, controller: &str) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        let report = ctrl.self_test().await?;
+        Ok(json!({
+            "controller": controller,
+            "passed": report.passed(),
+            "checks": report.checks,
+        }))
+    }
+
+    /// Drops every cached reading for one axis, or for all axes of the
+    /// controller when `axis` is `None`, and reports how many were present.
+    async fn handle_invalidate(&self, controller: &str, axis: Option<&str>) -> Result<Value> {
+        self.controller(controller).await?;
+        let invalidated = self.invalidate_cached(controller, axis).await;
+        Ok(json!({"controller": controller, "axis": axis, "invalidated": invalidated}))
+    }
+
+    async fn handle_list_controllers(&self) -> Result<Value> {
+        let ctrls = self.controllers.read().await;
+        let controller_names: Vec<String> = ctrls.keys().cloned().collect();
+        Ok(json!({"controllers": controller_names}))
+    }
+
+    /// Describes every controller concurrently. A controller that fails is
+    /// reported as `{"controller": name, "error": message}` in place of its
+    /// axes instead of failing the whole description.
+    async fn handle_describe(&self) -> Result<Value> {
+        let controllers: Vec<(String, Arc<dyn MotorController>)> = self
+            .controllers
+            .read()
+            .await
+            .iter()
+            .map(|(name, ctrl)| (name.clone(), ctrl.clone()))
+            .collect();
+
+        let mut entries = join_all(controllers.iter().map(|(name, ctrl)| async move {
+            match self.describe_controller(name, ctrl.as_ref()).await {
+                Ok(axes) => json!({"controller": name, "axes": axes}),
+                Err(e) => json!({"controller": name, "error": e.to_string()}),
+            }
+        }))
+        .await;
+        entries.sort_by(|a, b| a["controller"].as_str().cmp(&b["controller"].as_str()));
+        Ok(json!({"controllers": entries}))
+    }
+
+    async fn describe_controller(
+        &self,
+        controller: &str,
+        ctrl: &dyn MotorController,
+    ) -> Result<Vec<Value>> {
+        let mut axes = Vec::new();
+        for axis in ctrl.axes() {
+            let name = axis.name();
+            let available = self.available_attributes(ctrl, controller, name).await?;
+            let movement = ctrl.supported_movement_params(name).await?;
+            axes.push(json!({
+                "name": name,
+                "available_params": *available,
+                "supported_movement_params": movement,
+            }));
+        }
+        Ok(axes)
+    }
+
+    /// `total` counts the axes matching `filter`, before `offset` and
+    /// `limit` are applied, so a client can tell when it has seen them all.
+    /// Only the returned page is read for a detailed listing.
+    async fn handle_list_axes(
+        &self,
+        controller: &str,
+        detailed: bool,
+        filter: Option<&str>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<Value> {
+        let ctrl = self.controller(controller).await?;
+        self.reconcile_axes(controller, ctrl.as_ref()).await;
 
         let axes = ctrl.axes();
-        let axis_names: Vec<String> = axes.iter().map(|ax| ax.name().to_string()).collect();
-        Ok(json!({"controller": controller, "axes": axis_names}))
+        let matching: Vec<String> = axes
+            .iter()
+            .map(|ax| ax.name())
+            .filter(|name| filter.is_none_or(|pattern| glob_match(pattern, name)))
+            .map(String::from)
+            .collect();
+        let total = matching.len();
+        let axis_names: Vec<String> = matching
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+        if !detailed {
+            return Ok(json!({"controller": controller, "axes": axis_names, "total": total}));
+        }
+
+        let entries = join_all(
+            axis_names
+                .iter()
+                .map(|axis| self.describe_axis(controller, axis)),
+        )
+        .await;
+        Ok(json!({"controller": controller, "axes": entries, "total": total}))
+    }
+
+    /// State and position of one axis for a detailed listing. Reads go
+    /// through the regular handlers so a warm cache is reused; a failed read
+    /// is reported as `null` plus an `error` rather than failing the listing.
+    async fn describe_axis(&self, controller: &str, axis: &str) -> Value {
+        let (status, position) = tokio::join!(
+            self.handle_get_state(controller, axis, false),
+            self.handle_get_pos(controller, axis, false)
+        );
+
+        let mut errors = Vec::new();
+        let mut field = |result: Result<Value>, key: &str| match result {
+            Ok(mut value) => value[key].take(),
+            Err(e) => {
+                errors.push(e.to_string());
+                Value::Null
+            }
+        };
+        let status = field(status, "status");
+        let position = field(position, "position");
+
+        let mut entry = json!({"name": axis, "status": status, "position": position});
+        if !errors.is_empty() {
+            entry["error"] = json!(errors.join("; "));
+        }
+        entry
+    }
+}
+
+/// JSON for a reading. JSON has no NaN or infinity, and serde_json would
+/// turn them into `null`, which reads as "no value". They are sent as
+/// `{"special": "nan"}`, `{"special": "inf"}` or `{"special": "-inf"}`
+/// instead, so a faulty reading stays recognizable.
+fn number_value(value: f64) -> Value {
+    if value.is_nan() {
+        json!({"special": "nan"})
+    } else if value.is_infinite() {
+        json!({"special": if value > 0.0 { "inf" } else { "-inf" }})
+    } else {
+        json!(value)
+    }
+}
+
+/// A rate of change of position (a velocity or acceleration) in the axis's
+/// user units. Only the scale of `to_user_units` applies, not its offset.
+fn rate_in_user_units(ax: &dyn Axis, raw: f64) -> f64 {
+    (ax.to_user_units(raw) - ax.to_user_units(0.0)).abs()
+}
+
+/// How far an axis covers in `seconds` at up to `velocity`. With an
+/// `acceleration` it starts from rest and ramps up to `velocity` first.
+fn reach_distance(velocity: f64, acceleration: Option<f64>, seconds: f64) -> f64 {
+    match acceleration {
+        Some(acceleration) if velocity / acceleration > seconds => {
+            acceleration * seconds * seconds / 2.0
+        }
+        Some(acceleration) => velocity * seconds - velocity * velocity / (2.0 * acceleration),
+        None => velocity * seconds,
+    }
+}
+
+/// Attributes holding a position, which are converted between user and raw
+/// units like positions themselves.
+fn is_position_attribute(attr: &str) -> bool {
+    matches!(attr, "position" | "min_position" | "max_position")
+}
+
+fn limits_in_user_units(ax: &dyn Axis, limits: AxisLimits) -> AxisLimits {
+    AxisLimits {
+        min_position: limits.min_position.map(|min| ax.to_user_units(min)),
+        max_position: limits.max_position.map(|max| ax.to_user_units(max)),
+        ..limits
+    }
+}
+
+/// `value` rounded to `decimals` decimal places. Values too large to have
+/// that many come back unchanged, as do NaN and infinities.
+fn round_to(value: f64, decimals: u32) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    let rounded = (value * scale).round() / scale;
+    if rounded.is_finite() {
+        rounded
+    } else {
+        value
+    }
+}
+
+/// How far a `set_attribute` read-back may be from the written value
+/// before verification fails, unless the command gives its own tolerance.
+pub const DEFAULT_VERIFY_TOLERANCE: f64 = 1e-6;
+
+/// How long `get_limit_switches` readings are cached unless
+/// `attribute_ttls` has an entry for `limit_switches`. Short, since the
+/// readings are used for safety polls.
+pub const LIMIT_SWITCHES_TTL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Stands in for the axis in the cache keys of controller attributes. No
+/// axis may take this name.
+pub const CONTROLLER_SCOPE: &str = "_ctrl";
+
+/// Rejects, with `INVALID_NAME`, a controller name or axis names that
+/// commands couldn't address unambiguously: empty names, names containing
+/// `::` (which separates controller and axis in `move_all` patterns), axes
+/// named `CONTROLLER_SCOPE`, and axes sharing a name, of which only the
+/// first would ever be reached. With `case_insensitive` set, names that
+/// differ only by case count as shared.
+fn validate_names(
+    name: &str,
+    controller: &dyn MotorController,
+    case_insensitive: bool,
+) -> Result<()> {
+    let invalid =
+        |msg: String| -> Result<()> { Err(CommandError::new("INVALID_NAME", msg).into()) };
+    let check = |what: &str, name: &str| {
+        if name.is_empty() {
+            invalid(format!("{} name must not be empty", what))
+        } else if name.contains("::") {
+            invalid(format!("{} name must not contain '::': {}", what, name))
+        } else {
+            Ok(())
+        }
+    };
+
+    check("Controller", name)?;
+    let mut seen = HashSet::new();
+    for axis in controller.axes() {
+        check("Axis", axis.name())?;
+        if axis.name() == CONTROLLER_SCOPE {
+            return invalid(format!(
+                "Axis name {} is reserved for controller attributes",
+                CONTROLLER_SCOPE
+            ));
+        }
+        let key = if case_insensitive {
+            axis.name().to_lowercase()
+        } else {
+            axis.name().to_string()
+        };
+        if !seen.insert(key) {
+            return invalid(format!(
+                "Duplicate axis name in controller {}: {}",
+                name,
+                axis.name()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// One axis's entry in the `results` of a command acting on several.
+fn axis_result<T>(controller: &str, axis: &str, result: Result<T>) -> Value {
+    match result {
+        Ok(_) => json!({"controller": controller, "axis": axis, "status": "ok"}),
+        Err(e) => {
+            let mut entry = json!({
+                "controller": controller,
+                "axis": axis,
+                "status": "error",
+                "error": e.to_string(),
+            });
+            if let Some(err) = e.downcast_ref::<CommandError>() {
+                entry["code"] = json!(err.code);
+            }
+            entry
+        }
+    }
+}
+
+/// Adds what the axis says about its state to the error of a move it
+/// refused: after the message, and as `axis_message` in the details.
+/// Errors without a code are left alone.
+fn with_axis_message(err: anyhow::Error, axis_message: Option<String>) -> anyhow::Error {
+    let (Some(axis_message), Some(cmd_err)) = (axis_message, err.downcast_ref::<CommandError>())
+    else {
+        return err;
+    };
+    let mut details = match &cmd_err.details {
+        Some(Value::Object(details)) => details.clone(),
+        _ => serde_json::Map::new(),
+    };
+    details.insert("axis_message".to_string(), json!(axis_message));
+    CommandError::new(
+        cmd_err.code,
+        format!("{} ({})", cmd_err.message, axis_message),
+    )
+    .with_details(Value::Object(details))
+    .into()
+}
+
+/// The name in `known` that `axis` matches regardless of case, if `axis`
+/// isn't already one of them.
+fn fold_axis_name(known: &[String], axis: &str) -> Option<String> {
+    if known.iter().any(|name| name == axis) {
+        return None;
+    }
+    let folded = axis.to_lowercase();
+    known
+        .iter()
+        .find(|name| name.to_lowercase() == folded)
+        .cloned()
+}
+
+/// The cache key for one value of an axis, `controller::axis::field`. `:`
+/// and `\` inside each part are backslash-escaped, so names containing
+/// colons can't collide: controller `a:` with axis `b` and controller `a`
+/// with axis `:b` get different keys. (Registration rejects `::` in names
+/// outright.) Names without either character are used as they are.
+pub fn cache_key(controller: &str, axis: &str, field: &str) -> String {
+    fn escape(part: &str) -> Cow<'_, str> {
+        if part.contains([':', '\\']) {
+            Cow::Owned(part.replace('\\', "\\\\").replace(':', "\\:"))
+        } else {
+            Cow::Borrowed(part)
+        }
+    }
+    format!(
+        "{}::{}::{}",
+        escape(controller),
+        escape(axis),
+        escape(field)
+    )
+}
+
+/// Matches `name` against a glob where `*` stands for any run of
+/// characters and `?` for exactly one.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was and how much of `name` it has swallowed, to
+    // backtrack to when a later literal fails to match.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Adds the parameters lowered by the safety limits, if any, to a reply.
+fn with_clamped(mut response: Value, clamped: serde_json::Map<String, Value>) -> Value {
+    if !clamped.is_empty() {
+        response["clamped"] = Value::Object(clamped);
+    }
+    response
+}
+
+/// Work for the position store's background writer. Flushes go through the
+/// same queue as saves so that none of the saves queued before a flush can
+/// land after it and overwrite newer positions.
+enum PositionWrite {
+    Save(String, String, f64),
+    Flush(Positions, oneshot::Sender<Result<()>>),
+}
+
+/// Releases a name claimed by `register_controller` once it is done, also
+/// if it is dropped part way.
+struct PendingRegistration<'a> {
+    registering: &'a std::sync::Mutex<HashSet<String>>,
+    name: &'a str,
+}
+
+impl Drop for PendingRegistration<'_> {
+    fn drop(&mut self) {
+        self.registering.lock().unwrap().remove(self.name);
+    }
+}
+
+/// Removes a read from `ManagerState::reads` when the read that owns it
+/// ends, also if it is dropped part way.
+struct InFlightRead<'a> {
+    reads: &'a std::sync::Mutex<HashMap<String, watch::Receiver<Option<Value>>>>,
+    key: &'a str,
+}
+
+impl Drop for InFlightRead<'_> {
+    fn drop(&mut self) {
+        self.reads.lock().unwrap().remove(self.key);
+    }
+}
+
+fn queue_position(
+    writes: Option<&mpsc::Sender<PositionWrite>>,
+    controller: &str,
+    axis: &str,
+    position: f64,
+) {
+    if let Some(writes) = writes {
+        let update = PositionWrite::Save(controller.to_string(), axis.to_string(), position);
+        if writes.try_send(update).is_err() {
+            warn!(
+                "Position store is falling behind; dropped update for {}::{}",
+                controller, axis
+            );
+        }
+    }
+}
+
+/// Reads the axis and queues its position in user units, skipping readings
+/// that fail or aren't finite.
+async fn persist_reading(
+    writes: Option<&mpsc::Sender<PositionWrite>>,
+    ctrl: &dyn MotorController,
+    controller: &str,
+    axis: &str,
+) {
+    if writes.is_none() {
+        return;
+    }
+    let Ok(ax) = ctrl.get_axis(axis) else {
+        return;
+    };
+    if let Ok(raw) = ctrl.get_position(axis).await {
+        let position = ax.to_user_units(raw);
+        if position.is_finite() {
+            queue_position(writes, controller, axis, position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::axis::{
+        attribute_info::AttributeInfo, capabilities::AxisCapabilities,
+        limit_switches::LimitSwitches, state_info::AxisStateInfo, Axis, DEFAULT_MOVEMENT_PARAMS,
+    };
+    use crate::motor_controller::diagnostics::DiagnosticsReport;
+    use event::AxisEventKind;
+    use std::{
+        borrow::Cow,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    struct TestAxis {
+        name: String,
+        position: RwLock<f64>,
+        state: RwLock<AxisState>,
+        limit_switches: RwLock<LimitSwitches>,
+        supported_movement_params: std::sync::Mutex<Vec<String>>,
+        move_delay: Duration,
+        capability_reads: AtomicUsize,
+        fail_capabilities: std::sync::atomic::AtomicBool,
+        read_delay: std::sync::Mutex<Duration>,
+        attribute_reads: AtomicUsize,
+        /// `(scale, offset)` with raw = user * scale + offset.
+        units: std::sync::Mutex<(f64, f64)>,
+        active_moves: AtomicUsize,
+        max_active_moves: AtomicUsize,
+        /// Motion calls in the order they reached the axis.
+        calls: std::sync::Mutex<Vec<&'static str>>,
+        panic_on_stop: std::sync::atomic::AtomicBool,
+        last_params: std::sync::Mutex<Option<MovementParams>>,
+        /// Limit switches at +- this position stop moves that go past it.
+        switch_position: std::sync::Mutex<Option<f64>>,
+        progress: std::sync::Mutex<Option<f64>>,
+        tolerance: std::sync::Mutex<Option<f64>>,
+        /// Makes state reads never return, like an axis that stopped
+        /// answering.
+        hang_on_state: std::sync::atomic::AtomicBool,
+        /// Reported with the state, as a drive explains a fault.
+        state_message: std::sync::Mutex<Option<String>>,
+        /// A writable `gain` attribute, listed only while set.
+        gain: std::sync::Mutex<Option<f64>>,
+        /// Makes `gain` writes succeed without applying, as a drive that
+        /// acknowledges a value it then ignores.
+        ignore_writes: std::sync::atomic::AtomicBool,
+        /// Makes the axis report no soft limits.
+        no_limits: std::sync::atomic::AtomicBool,
+    }
+
+    impl TestAxis {
+        fn new(name: &str, move_delay: Duration) -> Self {
+            Self {
+                name: name.to_string(),
+                position: RwLock::new(0.0),
+                state: RwLock::new(AxisState::On),
+                limit_switches: RwLock::new(LimitSwitches::None),
+                supported_movement_params: std::sync::Mutex::new(
+                    DEFAULT_MOVEMENT_PARAMS
+                        .iter()
+                        .map(|p| p.to_string())
+                        .collect(),
+                ),
+                move_delay,
+                capability_reads: AtomicUsize::new(0),
+                fail_capabilities: Default::default(),
+                read_delay: std::sync::Mutex::new(Duration::ZERO),
+                attribute_reads: AtomicUsize::new(0),
+                units: std::sync::Mutex::new((1.0, 0.0)),
+                active_moves: AtomicUsize::new(0),
+                max_active_moves: AtomicUsize::new(0),
+                calls: Default::default(),
+                panic_on_stop: Default::default(),
+                last_params: Default::default(),
+                switch_position: Default::default(),
+                progress: Default::default(),
+                tolerance: Default::default(),
+                hang_on_state: Default::default(),
+                state_message: Default::default(),
+                gain: Default::default(),
+                ignore_writes: Default::default(),
+                no_limits: Default::default(),
+            }
+        }
+
+        async fn set_state(&self, state: AxisState) {
+            *self.state.write().await = state;
+        }
+
+        async fn fault(&self, message: &str) {
+            *self.state_message.lock().unwrap() = Some(message.to_string());
+            self.set_state(AxisState::Fault).await;
+        }
+
+        fn set_read_delay(&self, delay: Duration) {
+            *self.read_delay.lock().unwrap() = delay;
+        }
+
+        fn set_units(&self, scale: f64, offset: f64) {
+            *self.units.lock().unwrap() = (scale, offset);
+        }
+
+        fn set_supported_movement_params(&self, params: &[&str]) {
+            *self.supported_movement_params.lock().unwrap() =
+                params.iter().map(|p| p.to_string()).collect();
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Axis for TestAxis {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        async fn start(&self, target: f64, params: Option<MovementParams>) -> Result<()> {
+            self.calls.lock().unwrap().push("start");
+            let dwell = params
+                .as_ref()
+                .and_then(|params| params.custom_get("dwell"));
+            *self.last_params.lock().unwrap() = params;
+            let travel = *self.switch_position.lock().unwrap();
+            if let Some(travel) = travel.filter(|travel| target.abs() > *travel) {
+                let switches = if target > 0.0 {
+                    LimitSwitches::Upper
+                } else {
+                    LimitSwitches::Lower
+                };
+                *self.position.write().await = target.clamp(-travel, travel);
+                *self.limit_switches.write().await = switches;
+                return Err(LimitTripped { switches }.into());
+            }
+            let active = self.active_moves.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_active_moves.fetch_max(active, Ordering::SeqCst);
+            tokio::time::sleep(self.move_delay).await;
+            if let Some(dwell) = dwell {
+                tokio::time::sleep(Duration::from_secs_f64(dwell)).await;
+            }
+            *self.position.write().await = target;
+            self.active_moves.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn validate_custom_params(
+            &self,
+            custom: &HashMap<String, f64>,
+        ) -> std::result::Result<(), String> {
+            match custom.get("dwell") {
+                Some(dwell) if *dwell < 0.0 => {
+                    Err(format!("dwell must not be negative, got {}", dwell))
+                }
+                _ => Ok(()),
+            }
+        }
+
+        async fn move_to_limit(
+            &self,
+            direction: LimitDirection,
+            _params: Option<MovementParams>,
+            zero_position: bool,
+        ) -> Result<LimitSwitches> {
+            self.calls.lock().unwrap().push("move_to_limit");
+            let (end, switches) = match direction {
+                LimitDirection::Upper => (100.0, LimitSwitches::Upper),
+                LimitDirection::Lower => (-100.0, LimitSwitches::Lower),
+            };
+            *self.position.write().await = if zero_position { 0.0 } else { end };
+            *self.limit_switches.write().await = switches;
+            Ok(switches)
+        }
+
+        async fn get_progress(&self) -> Option<f64> {
+            *self.progress.lock().unwrap()
+        }
+
+        fn position_tolerance(&self) -> Option<f64> {
+            *self.tolerance.lock().unwrap()
+        }
+
+        async fn reset_fault(&self) -> Result<()> {
+            self.set_state(AxisState::On).await;
+            Ok(())
+        }
+
+        async fn set_position_reference(&self, value: f64) -> Result<()> {
+            *self.position.write().await = value;
+            Ok(())
+        }
+
+        async fn stop(&self) -> Result<()> {
+            if self.panic_on_stop.load(Ordering::SeqCst) {
+                panic!("stop failed on axis {}", self.name);
+            }
+            self.calls.lock().unwrap().push("stop");
+            self.set_state(AxisState::On).await;
+            Ok(())
+        }
+
+        async fn stop_with(&self, mode: StopMode) -> Result<()> {
+            match mode {
+                StopMode::Immediate => self.stop().await,
+                StopMode::Decelerate { .. } => {
+                    self.calls.lock().unwrap().push("decelerate");
+                    self.set_state(AxisState::On).await;
+                    Ok(())
+                }
+            }
+        }
+
+        async fn pause(&self) -> Result<()> {
+            self.set_state(AxisState::Paused).await;
+            Ok(())
+        }
+
+        async fn resume(&self) -> Result<()> {
+            self.set_state(AxisState::Moving).await;
+            Ok(())
+        }
+
+        async fn enable(&self) -> Result<()> {
+            self.set_state(AxisState::On).await;
+            Ok(())
+        }
+
+        async fn disable(&self) -> Result<()> {
+            self.set_state(AxisState::Disabled).await;
+            Ok(())
+        }
+
+        async fn capabilities(&self) -> Result<AxisCapabilities> {
+            Ok(AxisCapabilities::query(self).await?.with_pause())
+        }
+
+        fn to_user_units(&self, raw: f64) -> f64 {
+            let (scale, offset) = *self.units.lock().unwrap();
+            (raw - offset) / scale
+        }
+
+        fn to_raw_units(&self, user: f64) -> f64 {
+            let (scale, offset) = *self.units.lock().unwrap();
+            user * scale + offset
+        }
+
+        async fn get_state(&self) -> Result<AxisStateInfo> {
+            if self.hang_on_state.load(Ordering::SeqCst) {
+                std::future::pending::<()>().await;
+            }
+            let info = AxisStateInfo::new(*self.state.read().await)
+                .with_limit_switches(*self.limit_switches.read().await);
+            Ok(match self.state_message.lock().unwrap().clone() {
+                Some(message) => info.with_message(message),
+                None => info,
+            })
+        }
+
+        async fn attribute_info(&self, name: &str) -> Result<AttributeInfo> {
+            Ok(match name {
+                "position" => AttributeInfo::new(name)
+                    .with_unit("mm")
+                    .with_range(-100.0, 100.0),
+                _ => AttributeInfo::new(name),
+            })
+        }
+
+        async fn get_attribute_value(&self, name: &str) -> Result<AttributeValue> {
+            match name {
+                "firmware" => Ok("test-2.1".into()),
+                _ => self.get_attribute(name).await.map(AttributeValue::from),
+            }
+        }
+
+        async fn get_attribute(&self, name: &str) -> Result<f64> {
+            self.attribute_reads.fetch_add(1, Ordering::SeqCst);
+            let delay = *self.read_delay.lock().unwrap();
+            tokio::time::sleep(delay).await;
+            match name {
+                "position" => Ok(*self.position.read().await),
+                "min_position" | "max_position" if self.no_limits.load(Ordering::SeqCst) => {
+                    Err(anyhow::anyhow!("Axis {} has no soft limits", self.name))
+                }
+                "min_position" => Ok(-100.0),
+                "max_position" => Ok(100.0),
+                "gain" => (*self.gain.lock().unwrap())
+                    .ok_or_else(|| anyhow::anyhow!("Unknown parameter: {}", name)),
+                _ => Err(anyhow::anyhow!("Unknown parameter: {}", name)),
+            }
+        }
+
+        async fn set_attribute(&self, name: &str, value: f64) -> Result<()> {
+            let mut gain = self.gain.lock().unwrap();
+            anyhow::ensure!(name == "gain" && gain.is_some(), "{} is read-only", name);
+            if !self.ignore_writes.load(Ordering::SeqCst) {
+                *gain = Some(value);
+            }
+            Ok(())
+        }
+
+        async fn available_params(&self) -> Result<Vec<Cow<'static, str>>> {
+            self.capability_reads.fetch_add(1, Ordering::SeqCst);
+            if self.fail_capabilities.load(Ordering::SeqCst) {
+                anyhow::bail!("Axis {} is offline", self.name);
+            }
+            let mut params: Vec<_> = ["position", "min_position", "max_position", "firmware"]
+                .into_iter()
+                .map(Cow::Borrowed)
+                .collect();
+            if self.gain.lock().unwrap().is_some() {
+                params.push(Cow::Borrowed("gain"));
+            }
+            Ok(params)
+        }
+
+        async fn get_supported_movement_params(&self) -> Result<Vec<String>> {
+            Ok(self.supported_movement_params.lock().unwrap().clone())
+        }
+    }
+
+    struct TestController {
+        name: String,
+        axes: Vec<Arc<TestAxis>>,
+        initialized: std::sync::atomic::AtomicBool,
+        shut_down: std::sync::atomic::AtomicBool,
+        hang_on_shutdown: std::sync::atomic::AtomicBool,
+        hang_on_initialize: std::sync::atomic::AtomicBool,
+        max_concurrent: Option<usize>,
+        controller_attribute_reads: AtomicUsize,
+        /// While set, moves of Z are vetoed as if its enclosure were open.
+        z_interlock: std::sync::atomic::AtomicBool,
+        /// The raw targets `check_move_allowed` was asked about, by axis.
+        checked_targets: std::sync::Mutex<Vec<(String, f64)>>,
+        /// Attribute values the controller serves itself, in place of its
+        /// axes' readings.
+        attribute_overrides: std::sync::Mutex<HashMap<String, f64>>,
+        /// A state the controller reports itself, in place of its axes'.
+        state_override: std::sync::Mutex<Option<AxisState>>,
+    }
+
+    impl TestController {
+        fn new(name: &str, axes: &[&str]) -> Self {
+            Self::with_move_delay(name, axes, Duration::ZERO)
+        }
+
+        fn with_move_delay(name: &str, axes: &[&str], move_delay: Duration) -> Self {
+            Self {
+                name: name.to_string(),
+                axes: axes
+                    .iter()
+                    .map(|axis| Arc::new(TestAxis::new(axis, move_delay)))
+                    .collect(),
+                initialized: Default::default(),
+                shut_down: Default::default(),
+                hang_on_shutdown: Default::default(),
+                hang_on_initialize: Default::default(),
+                max_concurrent: None,
+                controller_attribute_reads: AtomicUsize::new(0),
+                z_interlock: Default::default(),
+                checked_targets: Default::default(),
+                attribute_overrides: Default::default(),
+                state_override: Default::default(),
+            }
+        }
+
+        fn axis(&self, name: &str) -> Arc<TestAxis> {
+            self.axes.iter().find(|a| a.name == name).unwrap().clone()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MotorController for TestController {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn axes(&self) -> Vec<Arc<dyn Axis>> {
+            self.axes
+                .iter()
+                .map(|axis| axis.clone() as Arc<dyn Axis>)
+                .collect()
+        }
+
+        fn max_concurrent_commands(&self) -> Option<usize> {
+            self.max_concurrent
+        }
+
+        async fn initialize(&self) -> Result<()> {
+            // Real controllers talk to hardware here; let racing callers in.
+            tokio::task::yield_now().await;
+            if self.hang_on_initialize.load(Ordering::SeqCst) {
+                std::future::pending::<()>().await;
+            }
+            self.initialized.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn get_controller_attribute(&self, name: &str) -> Result<AttributeValue> {
+            self.controller_attribute_reads
+                .fetch_add(1, Ordering::SeqCst);
+            match name {
+                "firmware_version" => Ok(AttributeValue::Text("1.2.3".to_string())),
+                _ => anyhow::bail!("Controller attribute not supported: {}", name),
+            }
+        }
+
+        async fn get_attribute(&self, axis: &str, attribute: &str) -> Result<f64> {
+            let value = self
+                .attribute_overrides
+                .lock()
+                .unwrap()
+                .get(attribute)
+                .copied();
+            match value {
+                Some(value) => Ok(value),
+                None => self.get_axis(axis)?.get_attribute(attribute).await,
+            }
+        }
+
+        async fn attribute_info(&self, axis: &str, attribute: &str) -> Result<AttributeInfo> {
+            let mut info = self.get_axis(axis)?.attribute_info(attribute).await?;
+            if attribute == "min_position" {
+                info.unit = Some("counts".to_string());
+            }
+            Ok(info)
+        }
+
+        async fn state(&self, axis: &str) -> Result<AxisStateInfo> {
+            let state = *self.state_override.lock().unwrap();
+            match state {
+                Some(state) => Ok(AxisStateInfo::new(state)),
+                None => self.get_axis(axis)?.get_state().await,
+            }
+        }
+
+        /// Served from `attribute_overrides` when it has a `position`.
+        async fn get_position(&self, axis: &str) -> Result<f64> {
+            let value = self
+                .attribute_overrides
+                .lock()
+                .unwrap()
+                .get("position")
+                .copied();
+            match value {
+                Some(value) => Ok(value),
+                None => self.get_axis(axis)?.get_position().await,
+            }
+        }
+
+        /// Served from `attribute_overrides` when it has a `progress`.
+        async fn get_progress(&self, axis: &str) -> Result<Option<f64>> {
+            let value = self
+                .attribute_overrides
+                .lock()
+                .unwrap()
+                .get("progress")
+                .copied();
+            match value {
+                Some(value) => Ok(Some(value)),
+                None => Ok(self.get_axis(axis)?.get_progress().await),
+            }
+        }
+
+        async fn get_attribute_value(&self, axis: &str, attribute: &str) -> Result<AttributeValue> {
+            let value = self
+                .attribute_overrides
+                .lock()
+                .unwrap()
+                .get(attribute)
+                .copied();
+            match value {
+                Some(value) => Ok(AttributeValue::Float(value)),
+                None => self.get_axis(axis)?.get_attribute_value(attribute).await,
+            }
+        }
+
+        async fn check_move_allowed(
+            &self,
+            axis: &str,
+            target: f64,
+            _params: Option<&MovementParams>,
+        ) -> std::result::Result<(), String> {
+            self.checked_targets
+                .lock()
+                .unwrap()
+                .push((axis.to_string(), target));
+            if axis == "Z" && self.z_interlock.load(Ordering::SeqCst) {
+                return Err("enclosure door is open".to_string());
+            }
+            Ok(())
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            if self.hang_on_shutdown.load(Ordering::SeqCst) {
+                std::future::pending::<()>().await;
+            }
+            self.shut_down.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn self_test(&self) -> Result<DiagnosticsReport> {
+            let mut report = DiagnosticsReport::new();
+            for axis in &self.axes {
+                let state = axis.get_state().await?;
+                if state.is_faulted() {
+                    report.fail(axis.name.clone(), "faulted");
+                } else {
+                    report.pass(axis.name.clone(), None);
+                }
+            }
+            Ok(report)
+        }
+    }
+
+    fn test_config() -> ManagerConfig {
+        ManagerConfig {
+            default_ttl: Duration::from_secs(60),
+            cache_capacity: 100,
+            ..Default::default()
+        }
+    }
+
+    async fn manager_with(
+        config: ManagerConfig,
+        controller: TestController,
+    ) -> (ControllerManager, Arc<TestController>) {
+        let manager = ControllerManager::new(config);
+        let controller = Arc::new(controller);
+        manager
+            .register_controller(controller.name.clone(), controller.clone())
+            .await
+            .unwrap();
+        (manager, controller)
+    }
+
+    async fn get_pos(manager: &ControllerManager, controller: &str, axis: &str) -> Result<Value> {
+        let (tx, rx) = oneshot::channel();
+        manager
+            .send_command(Command::GetPos {
+                controller: controller.to_string(),
+                axis: axis.to_string(),
+                fresh: false,
+                resp: tx,
+            })
+            .await?;
+        rx.await?
+    }
+
+    async fn request<F>(manager: &ControllerManager, build: F) -> Result<Value>
+    where
+        F: FnOnce(oneshot::Sender<Result<Value>>) -> Command,
+    {
+        let (tx, rx) = oneshot::channel();
+        manager.send_command(build(tx)).await?;
+        rx.await?
+    }
+
+    fn move_cmd(
+        controller: &str,
+        axis: &str,
+        target: f64,
+    ) -> (Command, oneshot::Receiver<Result<Value>>) {
+        move_cmd_with(controller, axis, target, None)
+    }
+
+    fn move_cmd_with(
+        controller: &str,
+        axis: &str,
+        target: f64,
+        params: Option<MovementParams>,
+    ) -> (Command, oneshot::Receiver<Result<Value>>) {
+        let (tx, rx) = oneshot::channel();
+        let cmd = Command::Move {
+            controller: controller.to_string(),
+            axis: axis.to_string(),
+            target,
+            target_kind: TargetKind::Absolute,
+            params,
+            dry_run: false,
+            wait: true,
+            force: false,
+            override_distance: false,
+            owner: None,
+            resp: tx,
+        };
+        (cmd, rx)
+    }
+
+    async fn move_with(
+        manager: &ControllerManager,
+        axis: &str,
+        target: f64,
+        params: Option<MovementParams>,
+    ) -> Result<Value> {
+        let (cmd, rx) = move_cmd_with("ctrl", axis, target, params);
+        manager.send_command(cmd).await?;
+        rx.await?
+    }
+
+    fn error_code(result: &Result<Value>) -> Option<&'static str> {
+        result
+            .as_ref()
+            .err()
+            .and_then(|e| e.downcast_ref::<CommandError>())
+            .map(|e| e.code)
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_commands_and_cache_lookups() {
+        let (manager, _) = manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+
+        get_pos(&manager, "ctrl", "X").await.unwrap();
+        get_pos(&manager, "ctrl", "X").await.unwrap();
+        assert!(get_pos(&manager, "missing", "X").await.is_err());
+
+        let snapshot = manager.metrics().snapshot();
+        assert_eq!(snapshot.commands_total, 3);
+        assert_eq!(snapshot.commands_by_kind["get_position"], 3);
+        assert_eq!(snapshot.errors_total, 1);
+        assert_eq!(snapshot.cache_hits, 1);
+        assert_eq!(snapshot.cache_misses, 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_stats_count_cached_entries() {
+        let (manager, _) =
+            manager_with(test_config(), TestController::new("ctrl", &["X", "Y"])).await;
+        let before = manager.cache_stats().await;
+        assert_eq!(before.capacity, Some(100));
+
+        get_pos(&manager, "ctrl", "X").await.unwrap();
+        get_pos(&manager, "ctrl", "Y").await.unwrap();
+        get_pos(&manager, "ctrl", "Y").await.unwrap();
+        request(&manager, |resp| Command::GetAttr {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            attr: "min_position".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+
+        let stats = manager.cache_stats().await;
+        assert_eq!(stats.entries, before.entries.map(|n| n + 3));
+        assert_eq!(stats.hits - before.hits, 1);
+        assert_eq!(stats.misses - before.misses, 3);
+
+        let uncached = ControllerManager::with_cache(test_config(), Arc::new(cache::NoOpCache));
+        assert_eq!(uncached.cache_stats().await.entries, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_poller_publishes_state_transitions() {
+        let config = ManagerConfig {
+            poll_interval: Some(Duration::from_millis(10)),
+            ..test_config()
+        };
+        let manager = ControllerManager::new(config);
+        let mut events = manager.subscribe_events();
+        let ctrl = Arc::new(TestController::new("ctrl", &["X"]));
+        manager
+            .register_controller("ctrl".to_string(), ctrl.clone())
+            .await
+            .unwrap();
+
+        let first = events.recv().await.unwrap();
+        assert_eq!(
+            first.kind,
+            AxisEventKind::StateChanged {
+                old: None,
+                new: AxisState::On
+            }
+        );
+
+        ctrl.axis("X").set_state(AxisState::Fault).await;
+        let event = tokio::time::timeout(Duration::from_secs(1), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (event.controller.as_str(), event.axis.as_str()),
+            ("ctrl", "X")
+        );
+        assert_eq!(
+            event.kind,
+            AxisEventKind::StateChanged {
+                old: Some(AxisState::On),
+                new: AxisState::Fault
+            }
+        );
+    }
+
+    /// A controller whose axes can be plugged in and out while registered.
+    struct HotPlugController {
+        axes: std::sync::Mutex<Vec<Arc<TestAxis>>>,
+    }
+
+    impl MotorController for HotPlugController {
+        fn name(&self) -> &str {
+            "hotplug"
+        }
+
+        fn axes(&self) -> Vec<Arc<dyn Axis>> {
+            self.axes
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|axis| axis.clone() as Arc<dyn Axis>)
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hot_plugged_axes_are_validated() {
+        let config = ManagerConfig {
+            case_insensitive_axes: true,
+            ..test_config()
+        };
+        let manager = ControllerManager::new(config);
+        let ctrl = Arc::new(HotPlugController {
+            axes: std::sync::Mutex::new(vec![Arc::new(TestAxis::new("X", Duration::ZERO))]),
+        });
+        manager
+            .register_controller("ctrl".to_string(), ctrl.clone())
+            .await
+            .unwrap();
+        let mut events = manager.subscribe_events();
+        let list_axes = || {
+            request(&manager, |resp| Command::ListAxes {
+                controller: "ctrl".to_string(),
+                detailed: false,
+                filter: None,
+                offset: 0,
+                limit: None,
+                resp,
+            })
+        };
+
+        // "x" would make "X" ambiguous, so the new set is ignored.
+        ctrl.axes
+            .lock()
+            .unwrap()
+            .push(Arc::new(TestAxis::new("x", Duration::ZERO)));
+        list_axes().await.unwrap();
+        assert_eq!(manager.state.known_axes.lock().unwrap()["ctrl"], ["X"]);
+        assert!(events.try_recv().is_err());
+
+        // Once it is fixed, the axes are picked up as usual.
+        ctrl.axes.lock().unwrap().pop();
+        ctrl.axes
+            .lock()
+            .unwrap()
+            .push(Arc::new(TestAxis::new("Y", Duration::ZERO)));
+        list_axes().await.unwrap();
+        assert_eq!(manager.state.known_axes.lock().unwrap()["ctrl"], ["X", "Y"]);
+        assert_eq!(events.try_recv().unwrap().kind, AxisEventKind::AxisAdded);
+    }
+
+    #[tokio::test]
+    async fn test_poller_picks_up_axis_set_changes() {
+        let config = ManagerConfig {
+            poll_interval: Some(Duration::from_millis(10)),
+            ..test_config()
+        };
+        let manager = ControllerManager::new(config);
+        let ctrl = Arc::new(HotPlugController {
+            axes: std::sync::Mutex::new(vec![Arc::new(TestAxis::new("X", Duration::ZERO))]),
+        });
+        manager
+            .register_controller("ctrl".to_string(), ctrl.clone())
+            .await
+            .unwrap();
+        let mut events = manager.subscribe_events();
+
+        ctrl.axes
+            .lock()
+            .unwrap()
+            .push(Arc::new(TestAxis::new("Y", Duration::ZERO)));
+        let added = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if event.kind == AxisEventKind::AxisAdded {
+                    return event;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(added.axis, "Y");
+        // The poller reads the new axis like any other.
+        let observed = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if event.axis == "Y" && matches!(event.kind, AxisEventKind::StateChanged { .. }) {
+                    return event;
+                }
+            }
+        })
+        .await;
+        assert!(observed.is_ok());
+
+        let axes = request(&manager, |resp| Command::ListAxes {
+            controller: "ctrl".to_string(),
+            detailed: false,
+            filter: None,
+            offset: 0,
+            limit: None,
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(axes["axes"], json!(["X", "Y"]));
+
+        ctrl.axes.lock().unwrap().retain(|axis| axis.name != "X");
+        let removed = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if event.kind == AxisEventKind::AxisRemoved {
+                    return event;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(removed.axis, "X");
+    }
+
+    #[tokio::test]
+    async fn test_moves_on_same_axis_are_serialized() {
+        let (manager, ctrl) = manager_with(
+            test_config(),
+            TestController::with_move_delay("ctrl", &["X", "Y"], Duration::from_millis(20)),
+        )
+        .await;
+
+        let (first, first_rx) = move_cmd("ctrl", "X", 10.0);
+        let (second, second_rx) = move_cmd("ctrl", "X", 20.0);
+        let (other, other_rx) = move_cmd("ctrl", "Y", 5.0);
+        manager.send_command(first).await.unwrap();
+        manager.send_command(second).await.unwrap();
+        manager.send_command(other).await.unwrap();
+
+        other_rx.await.unwrap().unwrap();
+        first_rx.await.unwrap().unwrap();
+        second_rx.await.unwrap().unwrap();
+
+        let x = ctrl.axis("X");
+        assert_eq!(x.max_active_moves.load(Ordering::SeqCst), 1);
+        assert_eq!(*x.position.read().await, 20.0);
+
+        let position = get_pos(&manager, "ctrl", "X").await.unwrap();
+        assert_eq!(position["position"], json!(20.0));
+    }
+
+    #[tokio::test]
+    async fn test_get_limits_reports_soft_limits_and_switches() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        *ctrl.axis("X").limit_switches.write().await = LimitSwitches::Upper;
+
+        let result = request(&manager, |resp| Command::GetLimits {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            result["limits"],
+            json!({
+                "min_position": -100.0,
+                "max_position": 100.0,
+                "upper_switch": true,
+                "lower_switch": false,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_limit_switches_reports_structured_flags() {
+        let config = ManagerConfig {
+            attribute_ttls: HashMap::from([(
+                "limit_switches".to_string(),
+                Duration::from_secs(60),
+            )]),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        *ctrl.axis("X").limit_switches.write().await = LimitSwitches::Lower;
+        let switches = || {
+            request(&manager, |resp| Command::GetLimitSwitches {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                resp,
+            })
+        };
+
+        let result = switches().await.unwrap();
+        assert_eq!(
+            result["limit_switches"],
+            json!({"upper": false, "lower": true})
+        );
+
+        // Served from the cache until a command changes the axis.
+        *ctrl.axis("X").limit_switches.write().await = LimitSwitches::Both;
+        let result = switches().await.unwrap();
+        assert_eq!(
+            result["limit_switches"],
+            json!({"upper": false, "lower": true})
+        );
+
+        request(&manager, |resp| Command::Stop {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            mode: StopMode::Immediate,
+            resp,
+        })
+        .await
+        .unwrap();
+        let result = switches().await.unwrap();
+        assert_eq!(
+            result["limit_switches"],
+            json!({"upper": true, "lower": true})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_finished_move_drops_cached_limit_switches() {
+        let config = ManagerConfig {
+            attribute_ttls: HashMap::from([(
+                "limit_switches".to_string(),
+                Duration::from_secs(60),
+            )]),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        let switches = || {
+            request(&manager, |resp| Command::GetLimitSwitches {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                resp,
+            })
+        };
+        assert_eq!(
+            switches().await.unwrap()["limit_switches"],
+            json!({"upper": false, "lower": false})
+        );
+
+        // A background move trips the upper switch; nobody waits for it.
+        *ctrl.axis("X").switch_position.lock().unwrap() = Some(5.0);
+        request(&manager, |resp| Command::Move {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            target: 7.0,
+            target_kind: TargetKind::Absolute,
+            params: None,
+            dry_run: false,
+            wait: false,
+            force: false,
+            override_distance: false,
+            owner: None,
+            resp,
+        })
+        .await
+        .unwrap();
+
+        let tripped = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let result = switches().await.unwrap();
+                if result["limit_switches"]["upper"] == true {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await;
+        assert!(tripped.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_get_state_serializes_structured_values() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X").set_state(AxisState::Moving).await;
+        *ctrl.axis("X").limit_switches.write().await = LimitSwitches::Both;
+
+        let result = request(&manager, |resp| Command::GetState {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            fresh: false,
+            resp,
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            result["status"],
+            json!({
+                "state": "moving",
+                "message": null,
+                "limit_switches": {"upper": true, "lower": true},
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_clears_axis_then_controller_entries() {
+        let (manager, _) =
+            manager_with(test_config(), TestController::new("ctrl", &["X", "Y"])).await;
+        get_pos(&manager, "ctrl", "X").await.unwrap();
+        get_pos(&manager, "ctrl", "Y").await.unwrap();
+        request(&manager, |resp| Command::GetState {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            fresh: false,
+            resp,
+        })
+        .await
+        .unwrap();
+
+        let invalidate = |axis: Option<&str>| {
+            let axis = axis.map(str::to_string);
+            request(&manager, move |resp| Command::Invalidate {
+                controller: "ctrl".to_string(),
+                axis,
+                resp,
+            })
+        };
+
+        let result = invalidate(Some("X")).await.unwrap();
+        assert_eq!(result["invalidated"], 2);
+        let result = invalidate(None).await.unwrap();
+        assert_eq!(result["invalidated"], 1);
+        let result = invalidate(None).await.unwrap();
+        assert_eq!(result["invalidated"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_move_rejects_invalid_params() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+
+        let params = MovementParams::new().with_velocity(-5.0);
+        let result = move_with(&manager, "X", 10.0, Some(params)).await;
+
+        assert_eq!(error_code(&result), Some("INVALID_PARAMS"));
+        assert_eq!(*ctrl.axis("X").position.read().await, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_move_rejects_unsupported_params() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["Z"])).await;
+        ctrl.axis("Z").set_supported_movement_params(&["velocity"]);
+
+        let params = MovementParams::new()
+            .with_velocity(10.0)
+            .with_acceleration(100.0);
+        let result = move_with(&manager, "Z", 10.0, Some(params)).await;
+        assert_eq!(error_code(&result), Some("UNSUPPORTED_PARAM"));
+        assert!(result.unwrap_err().to_string().contains("acceleration"));
+
+        let params = MovementParams::new().with_velocity(10.0);
+        move_with(&manager, "Z", 10.0, Some(params)).await.unwrap();
+        assert_eq!(*ctrl.axis("Z").position.read().await, 10.0);
+    }
+
+    #[tokio::test]
+    async fn test_move_rejects_unknown_custom_params() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X")
+            .set_supported_movement_params(&["velocity", "jerk", "settle_time"]);
+
+        let params = MovementParams::new()
+            .with_custom_param("jerk".to_string(), 5.0)
+            .with_custom_param("setle_time".to_string(), 0.1)
+            .with_custom_param("jrk".to_string(), 1.0);
+        let result = move_with(&manager, "X", 10.0, Some(params)).await;
+        assert_eq!(error_code(&result), Some("UNKNOWN_CUSTOM_PARAM"));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .ends_with("axis X: jrk, setle_time"));
+        assert_eq!(*ctrl.axis("X").position.read().await, 0.0);
+
+        ctrl.axis("X")
+            .set_supported_movement_params(&["velocity", ANY_CUSTOM_PARAM]);
+        let params = MovementParams::new().with_custom_param("anything".to_string(), 1.0);
+        move_with(&manager, "X", 10.0, Some(params)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_custom_params_reach_the_axis() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X")
+            .set_supported_movement_params(&["velocity", "dwell"]);
+
+        let started = tokio::time::Instant::now();
+        let params = MovementParams::new().with_custom_param("dwell".to_string(), 0.1);
+        move_with(&manager, "X", 10.0, Some(params)).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        let forwarded = ctrl.axis("X").last_params.lock().unwrap().clone().unwrap();
+        assert_eq!(forwarded.custom_get("dwell"), Some(0.1));
+
+        let params = MovementParams::new().with_custom_param("dwell".to_string(), -1.0);
+        let result = move_with(&manager, "X", 20.0, Some(params)).await;
+        assert_eq!(error_code(&result), Some("INVALID_PARAMS"));
+        assert_eq!(*ctrl.axis("X").position.read().await, 10.0);
+    }
+
+    #[tokio::test]
+    async fn test_register_rejects_duplicate_names() {
+        let (manager, original) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        assert!(original.initialized.load(Ordering::SeqCst));
+
+        let duplicate = Arc::new(TestController::new("ctrl", &["Y"]));
+        let result = manager
+            .register_controller("ctrl".to_string(), duplicate.clone())
+            .await;
+
+        assert!(result.is_err());
+        assert!(!duplicate.initialized.load(Ordering::SeqCst));
+        assert!(get_pos(&manager, "ctrl", "X").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_racing_registrations_initialize_one_controller() {
+        let manager = ControllerManager::new(test_config());
+        let first = Arc::new(TestController::new("ctrl", &["X"]));
+        let second = Arc::new(TestController::new("ctrl", &["Y"]));
+
+        let (a, b) = tokio::join!(
+            manager.register_controller("ctrl".to_string(), first.clone()),
+            manager.register_controller("ctrl".to_string(), second.clone()),
+        );
+
+        assert!(a.is_ok() != b.is_ok());
+        assert!(
+            first.initialized.load(Ordering::SeqCst) != second.initialized.load(Ordering::SeqCst)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_registrations_do_not_block_commands() {
+        let (manager, _) = manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let slow = Arc::new(TestController::new("slow", &["X"]));
+        slow.hang_on_initialize.store(true, Ordering::SeqCst);
+        let hung = Arc::new(TestController::new("hung", &["X"]));
+        hung.hang_on_shutdown.store(true, Ordering::SeqCst);
+        manager
+            .register_controller("hung".to_string(), hung)
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            tokio::select! {
+                _ = manager.register_controller("slow".to_string(), slow) => unreachable!(),
+                result = get_pos(&manager, "ctrl", "X") => result.unwrap(),
+            };
+            tokio::select! {
+                _ = manager.unregister_controller("hung") => unreachable!(),
+                result = get_pos(&manager, "ctrl", "X") => result.unwrap(),
+            };
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unregister_drops_cached_values() {
+        let (manager, _) = manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        get_pos(&manager, "ctrl", "X").await.unwrap();
+        manager.unregister_controller("ctrl").await.unwrap();
+
+        let successor = Arc::new(TestController::new("ctrl", &["X"]));
+        *successor.axis("X").position.write().await = 7.0;
+        manager
+            .register_controller("ctrl".to_string(), successor)
+            .await
+            .unwrap();
+        let response = get_pos(&manager, "ctrl", "X").await.unwrap();
+        assert_eq!(response["position"], 7.0);
+    }
+
+    #[tokio::test]
+    async fn test_replace_controller_shuts_down_old_one() {
+        let (manager, old) = manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        get_pos(&manager, "ctrl", "X").await.unwrap();
+
+        let new = Arc::new(TestController::new("ctrl", &["Y"]));
+        manager
+            .replace_controller("ctrl".to_string(), new.clone())
+            .await
+            .unwrap();
+
+        assert!(old.shut_down.load(Ordering::SeqCst));
+        assert!(new.initialized.load(Ordering::SeqCst));
+        assert!(!new.shut_down.load(Ordering::SeqCst));
+        assert!(get_pos(&manager, "ctrl", "X").await.is_err());
+        assert!(get_pos(&manager, "ctrl", "Y").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_refresh_cached_status() {
+        let (manager, _) = manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let state = || {
+            request(&manager, |resp| Command::GetState {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                fresh: false,
+                resp,
+            })
+        };
+        assert_eq!(state().await.unwrap()["status"]["state"], "on");
+
+        request(&manager, |resp| Command::Pause {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(state().await.unwrap()["status"]["state"], "paused");
+
+        request(&manager, |resp| Command::Resume {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(state().await.unwrap()["status"]["state"], "moving");
+    }
+
+    #[tokio::test]
+    async fn test_positions_converted_between_user_and_raw_units() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X").set_units(1000.0, 50.0);
+
+        let response = move_with(&manager, "X", 2.5, None).await.unwrap();
+        assert_eq!(response["target"], 2.5);
+        assert_eq!(*ctrl.axis("X").position.read().await, 2550.0);
+
+        // Read as an attribute first, which fills the same cache entry.
+        let response = request(&manager, |resp| Command::GetAttr {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            attr: "position".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(response["value"], 2.5);
+        let response = get_pos(&manager, "ctrl", "X").await.unwrap();
+        assert_eq!(response["position"], 2.5);
+        let response = get_pos(&manager, "ctrl", "X").await.unwrap();
+        assert_eq!(response["position"], 2.5);
+        assert_eq!(manager.metrics().snapshot().cache_hits, 2);
+
+        let response = request(&manager, |resp| Command::GetLimits {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(response["limits"]["min_position"], -0.15);
+        assert_eq!(response["limits"]["max_position"], 0.05);
+    }
+
+    #[tokio::test]
+    async fn test_reachable_range_in_user_units() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X").set_units(10.0, 50.0);
+        // Served by the controller rather than the axis, as 5 units/s.
+        ctrl.attribute_overrides
+            .lock()
+            .unwrap()
+            .insert("velocity".to_string(), 50.0);
+
+        let response = request(&manager, |resp| Command::ReachableRange {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            time_ms: 1000,
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(response["position"], -5.0);
+        assert_eq!(response["min"], -10.0);
+        assert_eq!(response["max"], 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_list_axes_detailed_includes_state_and_position() {
+        let (manager, _) =
+            manager_with(test_config(), TestController::new("ctrl", &["X", "Y"])).await;
+        move_with(&manager, "Y", 12.0, None).await.unwrap();
+
+        let list = |detailed| {
+            request(&manager, move |resp| Command::ListAxes {
+                controller: "ctrl".to_string(),
+                detailed,
+                filter: None,
+                offset: 0,
+                limit: None,
+                resp,
+            })
+        };
+
+        let names = list(false).await.unwrap();
+        assert_eq!(names["axes"], json!(["X", "Y"]));
+
+        let detailed = list(true).await.unwrap();
+        let axes = detailed["axes"].as_array().unwrap();
+        assert_eq!(axes.len(), 2);
+        assert_eq!(axes[0]["name"], "X");
+        assert_eq!(axes[0]["status"]["state"], "on");
+        assert_eq!(axes[1]["name"], "Y");
+        assert_eq!(axes[1]["position"], 12.0);
+        assert!(axes[1].get("error").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_axes_filters_and_pages() {
+        let (manager, _) = manager_with(
+            test_config(),
+            TestController::new("ctrl", &["X1", "X2", "X3", "Y1"]),
+        )
+        .await;
+        let list = |filter: Option<&str>, offset, limit| {
+            let filter = filter.map(String::from);
+            request(&manager, move |resp| Command::ListAxes {
+                controller: "ctrl".to_string(),
+                detailed: false,
+                filter,
+                offset,
+                limit,
+                resp,
+            })
+        };
+
+        let page = list(Some("X*"), 0, None).await.unwrap();
+        assert_eq!(page["axes"], json!(["X1", "X2", "X3"]));
+        assert_eq!(page["total"], 3);
+
+        let page = list(Some("X*"), 1, Some(1)).await.unwrap();
+        assert_eq!(page["axes"], json!(["X2"]));
+        assert_eq!(page["total"], 3);
+
+        let page = list(None, 2, Some(10)).await.unwrap();
+        assert_eq!(page["axes"], json!(["X3", "Y1"]));
+        assert_eq!(page["total"], 4);
+
+        let page = list(None, 4, None).await.unwrap();
+        assert_eq!(page["axes"], json!([]));
+        let page = list(None, 0, Some(0)).await.unwrap();
+        assert_eq!(page["axes"], json!([]));
+        let page = list(Some("Z"), 0, None).await.unwrap();
+        assert_eq!(page["total"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_borrowed_available_params_gate_attribute_reads() {
+        let (manager, _) = manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let get_attr = |attr: &'static str| {
+            request(&manager, move |resp| Command::GetAttr {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                attr: attr.to_string(),
+                resp,
+            })
+        };
+
+        assert_eq!(get_attr("max_position").await.unwrap()["value"], 100.0);
+        assert!(get_attr("velocity").await.is_err());
+
+        let params = request(&manager, |resp| Command::GetAvailableParams {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            params["available_params"],
+            json!(["position", "min_position", "max_position", "firmware"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_validates_without_moving() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X").set_supported_movement_params(&["velocity"]);
+        let dry_run = |axis: &'static str, params: Option<MovementParams>| {
+            request(&manager, move |resp| Command::Move {
+                controller: "ctrl".to_string(),
+                axis: axis.to_string(),
+                target: 5.0,
+                target_kind: TargetKind::Absolute,
+                params,
+                dry_run: true,
+                wait: true,
+                force: false,
+                override_distance: false,
+                owner: None,
+                resp,
+            })
+        };
+
+        let response = dry_run("X", None).await.unwrap();
+        assert_eq!(response["dry_run"], true);
+        assert_eq!(*ctrl.axis("X").position.read().await, 0.0);
+
+        let rejections = [
+            ("X", Some(MovementParams::new().with_velocity(-1.0))),
+            ("X", Some(MovementParams::new().with_acceleration(10.0))),
+            ("Missing", None),
+        ];
+        for (axis, params) in rejections {
+            let dry = dry_run(axis, params.clone()).await;
+            let real = move_with(&manager, axis, 5.0, params).await;
+            assert_eq!(error_code(&dry), error_code(&real));
+            assert_eq!(dry.unwrap_err().to_string(), real.unwrap_err().to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_all_reports_hung_controllers() {
+        let config = ManagerConfig {
+            shutdown_timeout: Duration::from_millis(50),
+            ..test_config()
+        };
+        let (manager, good) = manager_with(config, TestController::new("good", &["X"])).await;
+        let hung = Arc::new(TestController::new("hung", &["X"]));
+        hung.hang_on_shutdown.store(true, Ordering::SeqCst);
+        manager
+            .register_controller("hung".to_string(), hung.clone())
+            .await
+            .unwrap();
+
+        let summary = manager.shutdown_all().await;
+        assert_eq!(summary.clean, vec!["good".to_string()]);
+        assert_eq!(summary.timed_out, vec!["hung".to_string()]);
+        assert!(summary.failed.is_empty());
+        assert!(good.shut_down.load(Ordering::SeqCst));
+
+        // The command loop has stopped, so nothing is accepted any more.
+        assert!(get_pos(&manager, "good", "X").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_attribute_list_read_once_until_replaced() {
+        let config = ManagerConfig {
+            default_ttl: Duration::from_millis(1),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        let get_attr = |attr: &'static str| {
+            request(&manager, move |resp| Command::GetAttr {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                attr: attr.to_string(),
+                resp,
+            })
+        };
+
+        for _ in 0..5 {
+            get_attr("max_position").await.unwrap();
+            assert!(get_attr("velocity").await.is_err());
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(ctrl.axis("X").capability_reads.load(Ordering::SeqCst), 1);
+
+        let replacement = Arc::new(TestController::new("ctrl", &["X"]));
+        manager
+            .replace_controller("ctrl".to_string(), replacement.clone())
+            .await
+            .unwrap();
+        get_attr("max_position").await.unwrap();
+        assert_eq!(
+            replacement
+                .axis("X")
+                .capability_reads
+                .load(Ordering::SeqCst),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_attribute_reads_go_through_the_controller() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.attribute_overrides
+            .lock()
+            .unwrap()
+            .insert("max_position".to_string(), 42.0);
+
+        let response = request(&manager, |resp| Command::GetAttr {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            attr: "max_position".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(response["value"], 42.0);
+        assert_eq!(ctrl.axis("X").attribute_reads.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_state_and_position_reads_go_through_the_controller() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.attribute_overrides
+            .lock()
+            .unwrap()
+            .insert("position".to_string(), 9.0);
+        *ctrl.state_override.lock().unwrap() = Some(AxisState::Fault);
+
+        let response = get_pos(&manager, "ctrl", "X").await.unwrap();
+        assert_eq!(response["position"], 9.0);
+        let response = request(&manager, |resp| Command::GetState {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            fresh: true,
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(response["status"]["state"], "fault");
+        let response = request(&manager, |resp| Command::ControllerHealth {
+            controller: "ctrl".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(response["any_faulted"], true);
+        let response = request(&manager, |resp| Command::WaitForState {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            state: AxisState::Fault,
+            timeout: Duration::from_millis(100),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(response["reached"], true);
+    }
+
+    #[tokio::test]
+    async fn test_stop_reports_whether_axis_was_moving() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let stop = || {
+            request(&manager, |resp| Command::Stop {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                mode: StopMode::Immediate,
+                resp,
+            })
+        };
+
+        ctrl.axis("X").set_state(AxisState::Moving).await;
+        assert_eq!(stop().await.unwrap()["was_moving"], true);
+        // The stop refreshed the cached state, so a second stop sees the idle
+        // axis rather than the stale "moving" entry.
+        assert_eq!(stop().await.unwrap()["was_moving"], false);
+    }
+
+    #[tokio::test]
+    async fn test_stop_modes_reach_the_axis() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let stop = |mode| {
+            request(&manager, move |resp| Command::Stop {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                mode,
+                resp,
+            })
+        };
+
+        ctrl.axis("X").set_state(AxisState::Moving).await;
+        stop(StopMode::Decelerate { rate: Some(200.0) })
+            .await
+            .unwrap();
+        stop(StopMode::Decelerate { rate: None }).await.unwrap();
+        stop(StopMode::Immediate).await.unwrap();
+        assert_eq!(
+            *ctrl.axis("X").calls.lock().unwrap(),
+            ["decelerate", "decelerate", "stop"]
+        );
+
+        let rejected = stop(StopMode::Decelerate { rate: Some(-1.0) }).await;
+        assert_eq!(error_code(&rejected), Some("INVALID_PARAMS"));
+        assert_eq!(ctrl.axis("X").calls.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_axis_rejects_moves_until_enabled() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let toggle = |enable: bool| {
+            request(&manager, move |resp| {
+                let (controller, axis) = ("ctrl".to_string(), "X".to_string());
+                if enable {
+                    Command::Enable {
+                        controller,
+                        axis,
+                        resp,
+                    }
+                } else {
+                    Command::Disable {
+                        controller,
+                        axis,
+                        resp,
+                    }
+                }
+            })
+        };
+        let state = || {
+            request(&manager, |resp| Command::GetState {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                fresh: false,
+                resp,
+            })
+        };
+
+        assert_eq!(state().await.unwrap()["status"]["state"], "on");
+        toggle(false).await.unwrap();
+        assert_eq!(state().await.unwrap()["status"]["state"], "disabled");
+
+        let result = move_with(&manager, "X", 5.0, None).await;
+        assert_eq!(error_code(&result), Some("AXIS_DISABLED"));
+        assert_eq!(*ctrl.axis("X").position.read().await, 0.0);
+
+        toggle(true).await.unwrap();
+        assert_eq!(state().await.unwrap()["status"]["state"], "on");
+        move_with(&manager, "X", 5.0, None).await.unwrap();
+        assert_eq!(*ctrl.axis("X").position.read().await, 5.0);
+    }
+
+    #[tokio::test]
+    async fn test_hung_read_times_out() {
+        let config = ManagerConfig {
+            command_timeout: Some(Duration::from_millis(50)),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X").set_read_delay(Duration::from_secs(60));
+
+        let result = get_pos(&manager, "ctrl", "X").await;
+        assert_eq!(error_code(&result), Some("TIMEOUT"));
+        assert_eq!(manager.metrics().snapshot().errors_total, 1);
+
+        ctrl.axis("X").set_read_delay(Duration::ZERO);
+        assert!(get_pos(&manager, "ctrl", "X").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_get_capabilities_composes_axis_queries() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X").set_supported_movement_params(&["velocity"]);
+
+        let response = request(&manager, |resp| Command::GetCapabilities {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        let capabilities: AxisCapabilities =
+            serde_json::from_value(response["capabilities"].clone()).unwrap();
+
+        assert_eq!(
+            capabilities.readable_attributes,
+            ["position", "min_position", "max_position", "firmware"]
+        );
+        assert_eq!(capabilities.supported_movement_params, ["velocity"]);
+        assert!(capabilities.supports_pause);
+        assert!(!capabilities.supports_homing);
+        assert_eq!(capabilities.limits.max_position, Some(100.0));
+    }
+
+    #[tokio::test]
+    async fn test_persisted_positions_warm_cache_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("positions.json");
+        let store = || Box::new(position_store::FilePositionStore::new(&path));
+
+        let manager = ControllerManager::with_position_store(test_config(), store())
+            .await
+            .unwrap();
+        manager
+            .register_controller(
+                "ctrl".to_string(),
+                Arc::new(TestController::new("ctrl", &["X"])),
+            )
+            .await
+            .unwrap();
+        move_with(&manager, "X", 7.0, None).await.unwrap();
+
+        // Writes happen in the background; wait for this one to land.
+        for _ in 0..100 {
+            let saved = store().load().await.unwrap();
+            if saved.get("ctrl").and_then(|axes| axes.get("X")) == Some(&7.0) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        manager.shutdown_all().await;
+
+        let restarted = ControllerManager::with_position_store(test_config(), store())
+            .await
+            .unwrap();
+        restarted
+            .register_controller(
+                "ctrl".to_string(),
+                Arc::new(TestController::new("ctrl", &["X"])),
+            )
+            .await
+            .unwrap();
+
+        let response = get_pos(&restarted, "ctrl", "X").await.unwrap();
+        assert_eq!(response["position"], 7.0);
+        assert_eq!(restarted.metrics().snapshot().cache_hits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_attr_serializes_non_numeric_values() {
+        let (manager, _) = manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let get_attr = |attr: &'static str| {
+            request(&manager, move |resp| Command::GetAttr {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                attr: attr.to_string(),
+                resp,
+            })
+        };
+
+        assert_eq!(get_attr("firmware").await.unwrap()["value"], "test-2.1");
+        assert_eq!(get_attr("max_position").await.unwrap()["value"], 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_move_to_limit_reports_switch_and_refreshes_cache() {
+        let (manager, _) = manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let to_limit = |direction, zero_position| {
+            request(&manager, move |resp| Command::MoveToLimit {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                direction,
+                params: None,
+                zero_position,
+                owner: None,
+                resp,
+            })
+        };
+        // Warm the position cache so the invalidation is observable.
+        get_pos(&manager, "ctrl", "X").await.unwrap();
+
+        let response = to_limit(LimitDirection::Upper, false).await.unwrap();
+        assert_eq!(response["direction"], "upper");
+        assert_eq!(
+            response["limit_switches"],
+            json!({"upper": true, "lower": false})
+        );
+        let response = get_pos(&manager, "ctrl", "X").await.unwrap();
+        assert_eq!(response["position"], 100.0);
+
+        to_limit(LimitDirection::Lower, true).await.unwrap();
+        let response = get_pos(&manager, "ctrl", "X").await.unwrap();
+        assert_eq!(response["position"], 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_single_permit_controller_serializes_commands() {
+        let delay = Duration::from_millis(50);
+        let controller = TestController {
+            max_concurrent: Some(1),
+            ..TestController::with_move_delay("ctrl", &["X", "Y"], delay)
+        };
+        let (manager, ctrl) = manager_with(test_config(), controller).await;
+
+        let started = tokio::time::Instant::now();
+        let (x, y) = tokio::join!(
+            move_with(&manager, "X", 1.0, None),
+            move_with(&manager, "Y", 2.0, None)
+        );
+        x.unwrap();
+        y.unwrap();
+        // Different axes would normally move in parallel.
+        assert!(started.elapsed() >= delay * 2);
+
+        // Stop is never queued behind a move holding the only permit.
+        let (moved, stopped) = tokio::join!(move_with(&manager, "X", 3.0, None), async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let stopped = request(&manager, |resp| Command::Stop {
+                controller: "ctrl".to_string(),
+                axis: "Y".to_string(),
+                mode: StopMode::Immediate,
+                resp,
+            })
+            .await;
+            (stopped, ctrl.axis("X").active_moves.load(Ordering::SeqCst))
+        });
+        moved.unwrap();
+        assert!(stopped.0.is_ok());
+        assert_eq!(stopped.1, 1);
+
+        // A move that isn't waited for keeps the permit until it ends.
+        let (mut cmd, rx) = move_cmd("ctrl", "X", 4.0);
+        if let Command::Move { wait, .. } = &mut cmd {
+            *wait = false;
+        }
+        let started = tokio::time::Instant::now();
+        manager.send_command(cmd).await.unwrap();
+        rx.await.unwrap().unwrap();
+        move_with(&manager, "Y", 5.0, None).await.unwrap();
+        assert!(started.elapsed() >= delay * 2);
+        assert_eq!(ctrl.axis("Y").max_active_moves.load(Ordering::SeqCst), 1);
+
+        // Pause isn't urgent, but it isn't held up by the permit either.
+        let (mut cmd, rx) = move_cmd("ctrl", "X", 6.0);
+        if let Command::Move { wait, .. } = &mut cmd {
+            *wait = false;
+        }
+        let started = tokio::time::Instant::now();
+        manager.send_command(cmd).await.unwrap();
+        rx.await.unwrap().unwrap();
+        request(&manager, |resp| Command::Pause {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert!(started.elapsed() < delay);
+    }
+
+    #[tokio::test]
+    async fn test_describe_reports_failing_controller_alongside_healthy_ones() {
+        let (manager, _) = manager_with(test_config(), TestController::new("good", &["X"])).await;
+        let offline = Arc::new(TestController::new("offline", &["A"]));
+        offline
+            .axis("A")
+            .fail_capabilities
+            .store(true, Ordering::SeqCst);
+        manager
+            .register_controller("offline".to_string(), offline)
+            .await
+            .unwrap();
+
+        let response = request(&manager, |resp| Command::Describe { resp })
+            .await
+            .unwrap();
+        let controllers = response["controllers"].as_array().unwrap();
+        assert_eq!(controllers.len(), 2);
+
+        assert_eq!(controllers[0]["controller"], "good");
+        assert_eq!(controllers[0]["axes"][0]["name"], "X");
+        assert!(controllers[0]["axes"][0]["available_params"].is_array());
+
+        assert_eq!(controllers[1]["controller"], "offline");
+        assert_eq!(controllers[1]["error"], "Axis A is offline");
+        assert!(controllers[1].get("axes").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_aborts_in_flight_move() {
+        let (manager, ctrl) = manager_with(
+            test_config(),
+            TestController::with_move_delay("ctrl", &["X"], Duration::from_secs(60)),
+        )
+        .await;
+        let cancel = || {
+            request(&manager, |resp| Command::Cancel {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                resp,
+            })
+        };
+        let move_x = |wait| {
+            request(&manager, move |resp| Command::Move {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                target: 5.0,
+                target_kind: TargetKind::Absolute,
+                params: None,
+                dry_run: false,
+                wait,
+                force: false,
+                override_distance: false,
+                owner: None,
+                resp,
+            })
+        };
+
+        // A background move is cancelled by axis and frees the axis lock.
+        let response = move_x(false).await.unwrap();
+        assert_eq!(response["wait"], false);
+        assert_eq!(cancel().await.unwrap()["cancelled"], true);
+        assert_eq!(cancel().await.unwrap()["cancelled"], false);
+
+        // A waited move reports the cancellation to its caller.
+        let (moved, cancelled) = tokio::join!(move_x(true), async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            cancel().await
+        });
+        assert_eq!(error_code(&moved), Some("CANCELLED"));
+        assert_eq!(cancelled.unwrap()["cancelled"], true);
+        assert_eq!(*ctrl.axis("X").position.read().await, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_fresh_position_read_bypasses_and_refreshes_cache() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        *ctrl.axis("X").position.write().await = 7.0;
+        manager
+            .cache()
+            .insert("ctrl::X::position".to_string(), json!(1.0))
+            .await;
+
+        assert_eq!(
+            get_pos(&manager, "ctrl", "X").await.unwrap()["position"],
+            1.0
+        );
+
+        let fresh = request(&manager, |resp| Command::GetPos {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            fresh: true,
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(fresh["position"], 7.0);
+        assert_eq!(
+            get_pos(&manager, "ctrl", "X").await.unwrap()["position"],
+            7.0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stop_preempts_queued_moves() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+
+        // Queue the moves without yielding, so the command loop sees them
+        // all pending alongside the stop.
+        let mut moves = Vec::new();
+        for _ in 0..5 {
+            let (resp, rx) = oneshot::channel();
+            let cmd = Command::MoveToLimit {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                direction: LimitDirection::Upper,
+                params: None,
+                zero_position: false,
+                owner: None,
+                resp,
+            };
+            manager.send_command(cmd).await.unwrap();
+            moves.push(rx);
+        }
+        request(&manager, |resp| Command::Stop {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            mode: StopMode::Immediate,
+            resp,
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(ctrl.axis("X").calls.lock().unwrap().first(), Some(&"stop"));
+        for rx in moves {
+            rx.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_progress_reported_by_get_state_and_poller() {
+        let config = ManagerConfig {
+            poll_interval: Some(Duration::from_millis(10)),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        let get_state = |fresh| {
+            request(&manager, move |resp| Command::GetState {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                fresh,
+                resp,
+            })
+        };
+        *ctrl.axis("X").progress.lock().unwrap() = Some(0.1);
+        // Only moving axes are asked for progress.
+        assert_eq!(get_state(false).await.unwrap()["progress"], Value::Null);
+
+        ctrl.axis("X").set_state(AxisState::Moving).await;
+        assert_eq!(get_state(true).await.unwrap()["progress"], 0.1);
+        // Progress isn't cached along with the status.
+        *ctrl.axis("X").progress.lock().unwrap() = Some(0.25);
+        assert_eq!(get_state(false).await.unwrap()["progress"], 0.25);
+
+        let mut events = manager.subscribe_events();
+
+        let progress = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if let AxisEventKind::Progress { fraction } = event.kind {
+                    return fraction;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(progress, 0.25);
+
+        // Controllers that track progress themselves are asked directly.
+        ctrl.attribute_overrides
+            .lock()
+            .unwrap()
+            .insert("progress".to_string(), 0.75);
+        assert_eq!(get_state(false).await.unwrap()["progress"], 0.75);
+    }
+
+    #[tokio::test]
+    async fn test_handler_panic_leaves_manager_running() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X").panic_on_stop.store(true, Ordering::SeqCst);
+
+        let stopped = request(&manager, |resp| Command::Stop {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            mode: StopMode::Immediate,
+            resp,
+        })
+        .await;
+        assert!(stopped.is_err());
+
+        assert!(manager.is_running());
+        assert_eq!(
+            get_pos(&manager, "ctrl", "X").await.unwrap()["position"],
+            0.0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_commands_after_shutdown_fail_with_manager_down() {
+        let (manager, _ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        manager.shutdown_all().await;
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while manager.is_running() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let result = get_pos(&manager, "ctrl", "X").await;
+        assert_eq!(error_code(&result), Some("MANAGER_DOWN"));
+    }
+
+    #[tokio::test]
+    async fn test_stop_joins_command_loop() {
+        let config = ManagerConfig {
+            poll_interval: Some(Duration::from_millis(10)),
+            ..test_config()
+        };
+        let (manager, _ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        assert!(get_pos(&manager, "ctrl", "X").await.is_ok());
+
+        tokio::time::timeout(Duration::from_secs(1), manager.stop())
+            .await
+            .unwrap();
+        assert!(!manager.is_running());
+        let result = get_pos(&manager, "ctrl", "X").await;
+        assert_eq!(error_code(&result), Some("MANAGER_DOWN"));
+
+        // Stopping twice is harmless.
+        manager.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_moves_merge_axis_defaults() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        manager
+            .set_axis_defaults(
+                "ctrl",
+                "X",
+                MovementParams::new()
+                    .with_velocity(10.0)
+                    .with_acceleration(100.0),
+            )
+            .await
+            .unwrap();
+
+        move_with(&manager, "X", 1.0, None).await.unwrap();
+        let used = ctrl.axis("X").last_params.lock().unwrap().clone().unwrap();
+        assert_eq!(
+            (used.velocity, used.acceleration),
+            (Some(10.0), Some(100.0))
+        );
+
+        let partial = MovementParams::new().with_velocity(20.0);
+        move_with(&manager, "X", 2.0, Some(partial)).await.unwrap();
+        let used = ctrl.axis("X").last_params.lock().unwrap().clone().unwrap();
+        assert_eq!(
+            (used.velocity, used.acceleration),
+            (Some(20.0), Some(100.0))
+        );
+
+        manager
+            .set_axis_defaults("ctrl", "X", MovementParams::new())
+            .await
+            .unwrap();
+        move_with(&manager, "X", 3.0, None).await.unwrap();
+        assert!(ctrl.axis("X").last_params.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_params_applies_defaults_overrides_and_clamps() {
+        let config = ManagerConfig {
+            max_velocity: Some(50.0),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        manager
+            .set_axis_defaults(
+                "ctrl",
+                "X",
+                MovementParams::new()
+                    .with_velocity(10.0)
+                    .with_acceleration(100.0)
+                    .with_deceleration(80.0),
+            )
+            .await
+            .unwrap();
+        let resolve = |params: Option<MovementParams>| {
+            request(&manager, move |resp| Command::ResolveParams {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                params,
+                resp,
+            })
+        };
+
+        let resolved = resolve(None).await.unwrap();
+        assert_eq!(resolved["params"]["velocity"], 10.0);
+        assert_eq!(resolved["params"]["acceleration"], 100.0);
+        assert!(resolved.get("clamped").is_none());
+
+        // The command's own values win over the defaults, and the safety
+        // limit over both.
+        let params = MovementParams::new()
+            .with_velocity(80.0)
+            .with_acceleration(150.0);
+        let resolved = resolve(Some(params)).await.unwrap();
+        assert_eq!(
+            resolved["params"],
+            json!({"velocity": 50.0, "acceleration": 150.0, "deceleration": 80.0, "custom": {}})
+        );
+        assert_eq!(resolved["clamped"], json!({"velocity": 50.0}));
+
+        let unsupported = MovementParams::new().with_custom_param("jerk".to_string(), 1.0);
+        let result = resolve(Some(unsupported)).await;
+        assert_eq!(error_code(&result), Some("UNKNOWN_CUSTOM_PARAM"));
+        assert!(ctrl.axis("X").calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reset_fault_requires_faulted_axis() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let reset = || {
+            request(&manager, |resp| Command::ResetFault {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                resp,
+            })
+        };
+        let state = || {
+            request(&manager, |resp| Command::GetState {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                fresh: false,
+                resp,
+            })
+        };
+
+        assert_eq!(error_code(&reset().await), Some("NOT_FAULTED"));
+
+        ctrl.axis("X").set_state(AxisState::Fault).await;
+        assert_eq!(state().await.unwrap()["status"]["state"], "fault");
+
+        reset().await.unwrap();
+        assert_eq!(state().await.unwrap()["status"]["state"], "on");
+    }
+
+    #[tokio::test]
+    async fn test_set_reference_redefines_position_without_moving() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X").set_units(2.0, 0.0);
+        move_with(&manager, "X", 10.0, None).await.unwrap();
+        assert_eq!(
+            get_pos(&manager, "ctrl", "X").await.unwrap()["position"],
+            10.0
+        );
+        let starts = ctrl.axis("X").calls.lock().unwrap().len();
+
+        let result = request(&manager, |resp| Command::SetReference {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            value: 1.5,
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(result["position"], 1.5);
+
+        assert_eq!(
+            get_pos(&manager, "ctrl", "X").await.unwrap()["position"],
+            1.5
+        );
+        assert_eq!(*ctrl.axis("X").position.read().await, 3.0);
+        assert_eq!(ctrl.axis("X").calls.lock().unwrap().len(), starts);
+    }
+
+    #[tokio::test]
+    async fn test_set_attribute_verify_catches_ignored_writes() {
+        let ctrl = TestController::new("drive", &["X"]);
+        *ctrl.axis("X").gain.lock().unwrap() = Some(1.0);
+        let (manager, ctrl) = manager_with(test_config(), ctrl).await;
+        let set = |value: f64, verify: bool| {
+            request(&manager, move |resp| Command::SetAttr {
+                controller: "drive".to_string(),
+                axis: "X".to_string(),
+                attr: "gain".to_string(),
+                value,
+                verify,
+                tolerance: None,
+                resp,
+            })
+        };
+        let get = || {
+            request(&manager, |resp| Command::GetAttr {
+                controller: "drive".to_string(),
+                axis: "X".to_string(),
+                attr: "gain".to_string(),
+                resp,
+            })
+        };
+
+        // A plain write drops the cached value.
+        assert_eq!(get().await.unwrap()["value"], 1.0);
+        set(2.0, false).await.unwrap();
+        assert_eq!(get().await.unwrap()["value"], 2.0);
+
+        let result = set(2.5, true).await.unwrap();
+        assert_eq!(result["verified"], true);
+        assert_eq!(result["read_back"], 2.5);
+
+        ctrl.axis("X").ignore_writes.store(true, Ordering::SeqCst);
+        let result = set(4.0, true).await;
+        assert_eq!(error_code(&result), Some("VERIFY_FAILED"));
+        assert_eq!(get().await.unwrap()["value"], 2.5);
+        // Without verify the ignored write goes unnoticed.
+        assert_eq!(set(4.0, false).await.unwrap()["value"], 4.0);
+
+        let result = request(&manager, |resp| Command::SetAttr {
+            controller: "drive".to_string(),
+            axis: "X".to_string(),
+            attr: "gain".to_string(),
+            value: 2.5,
+            verify: true,
+            tolerance: Some(-1.0),
+            resp,
+        })
+        .await;
+        assert_eq!(error_code(&result), Some("INVALID_PARAMS"));
+    }
+
+    #[tokio::test]
+    async fn test_set_attribute_verify_reads_through_the_controller() {
+        let ctrl = TestController::new("drive", &["X"]);
+        *ctrl.axis("X").gain.lock().unwrap() = Some(1.0);
+        // The controller serves gain itself and disagrees with the axis.
+        ctrl.attribute_overrides
+            .lock()
+            .unwrap()
+            .insert("gain".to_string(), 2.5);
+        let (manager, _ctrl) = manager_with(test_config(), ctrl).await;
+
+        let result = request(&manager, |resp| Command::SetAttr {
+            controller: "drive".to_string(),
+            axis: "X".to_string(),
+            attr: "gain".to_string(),
+            value: 2.0,
+            verify: true,
+            tolerance: None,
+            resp,
+        })
+        .await;
+        assert_eq!(error_code(&result), Some("VERIFY_FAILED"));
+    }
+
+    #[tokio::test]
+    async fn test_percent_target_resolves_against_soft_limits() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let move_percent = |controller: &str, target: f64| {
+            let controller = controller.to_string();
+            request(&manager, move |resp| Command::Move {
+                controller,
+                axis: "X".to_string(),
+                target,
+                target_kind: TargetKind::Percent,
+                params: None,
+                dry_run: false,
+                wait: true,
+                force: false,
+                override_distance: false,
+                owner: None,
+                resp,
+            })
+        };
+
+        // X travels from -100 to 100.
+        let result = move_percent("ctrl", 75.0).await.unwrap();
+        assert_eq!(result["target"], 50.0);
+        assert_eq!(result["percent"], 75.0);
+        assert_eq!(*ctrl.axis("X").position.read().await, 50.0);
+
+        let result = move_percent("ctrl", 120.0).await;
+        assert_eq!(error_code(&result), Some("INVALID_PARAMS"));
+
+        let unlimited = TestController::new("drive", &["X"]);
+        unlimited.axis("X").no_limits.store(true, Ordering::SeqCst);
+        manager
+            .register_controller("drive".to_string(), Arc::new(unlimited))
+            .await
+            .unwrap();
+        let result = move_percent("drive", 50.0).await;
+        assert_eq!(error_code(&result), Some("NO_LIMITS"));
+    }
+
+    #[tokio::test]
+    async fn test_get_positions_reports_per_axis_errors() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X", "Y"])).await;
+        *ctrl.axis("Y").position.write().await = 4.0;
+
+        let result = request(&manager, |resp| Command::GetPositions {
+            controller: "ctrl".to_string(),
+            axes: vec!["X".to_string(), "Y".to_string(), "Q".to_string()],
+            resp,
+        })
+        .await
+        .unwrap();
+
+        let positions = &result["positions"];
+        assert_eq!(positions["X"], json!({"position": 0.0}));
+        assert_eq!(positions["Y"], json!({"position": 4.0}));
+        assert_eq!(positions["Q"]["position"], Value::Null);
+        assert!(positions["Q"]["error"]
+            .as_str()
+            .unwrap()
+            .contains("Axis not found"));
+
+        let result = request(&manager, |resp| Command::GetPositions {
+            controller: "missing".to_string(),
+            axes: vec!["X".to_string()],
+            resp,
+        })
+        .await;
+        assert!(result.is_err());
+    }
+
+    // Built on an idle runtime as in the test below, so nothing is dequeued
+    // until the backend is driven.
+    #[test]
+    fn test_queue_depth_counts_waiting_commands() {
+        let backend = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let manager = backend.block_on(async { ControllerManager::new(test_config()) });
+        assert_eq!(manager.queue_depth(), 0);
+
+        let replies: Vec<_> = (0..7)
+            .map(|_| {
+                let (tx, rx) = oneshot::channel();
+                manager
+                    .try_send_command(Command::ListControllers { resp: tx })
+                    .unwrap();
+                rx
+            })
+            .collect();
+        let (tx, stop) = oneshot::channel();
+        manager
+            .try_send_command(Command::Stop {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                mode: StopMode::Immediate,
+                resp: tx,
+            })
+            .unwrap();
+        assert_eq!(manager.queue_depth(), 8);
+
+        backend.block_on(async {
+            for rx in replies {
+                rx.await.unwrap().unwrap();
+            }
+            let _ = stop.await.unwrap();
+        });
+        assert_eq!(manager.queue_depth(), 0);
+    }
+
+    #[test]
+    fn test_queued_commands_are_rejected_at_shutdown() {
+        let backend = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let manager = backend.block_on(async { ControllerManager::new(test_config()) });
+
+        // The runtime is idle, so the loop picks none of these up.
+        let mut replies: Vec<_> = (0..3)
+            .map(|_| {
+                let (tx, rx) = oneshot::channel();
+                manager
+                    .try_send_command(Command::ListControllers { resp: tx })
+                    .unwrap();
+                rx
+            })
+            .collect();
+        let (tx, stop) = oneshot::channel();
+        manager
+            .try_send_command(Command::Stop {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                mode: StopMode::Immediate,
+                resp: tx,
+            })
+            .unwrap();
+        replies.push(stop);
+
+        backend.block_on(async {
+            manager.shutdown_all().await;
+            for rx in replies {
+                let result = rx.await.expect("reply dropped without an answer");
+                assert_eq!(error_code(&result), Some("SERVER_SHUTTING_DOWN"));
+            }
+        });
+        assert!(!manager.is_running());
+        assert_eq!(manager.queue_depth(), 0);
+    }
+
+    // The manager is built on a runtime that is then left idle, standing in
+    // for a stuck command loop, until the test drives it to drain the queue.
+    #[test]
+    fn test_saturated_queue_fails_instead_of_blocking() {
+        let runtime = || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+        };
+        let (backend, client) = (runtime(), runtime());
+        let manager = backend.block_on(async { ControllerManager::new(test_config()) });
+
+        let (replies, stop) = client.block_on(async {
+            let mut replies = Vec::new();
+            loop {
+                let (tx, rx) = oneshot::channel();
+                match manager.try_send_command(Command::ListControllers { resp: tx }) {
+                    Ok(()) => replies.push(rx),
+                    Err(e) => {
+                        assert_eq!(error_code(&Err(e)), Some("QUEUE_FULL"));
+                        break;
+                    }
+                }
+            }
+            assert_eq!(replies.len(), 100);
+
+            let (tx, _rx) = oneshot::channel();
+            let result = manager
+                .send_command_timeout(
+                    Command::ListControllers { resp: tx },
+                    Duration::from_millis(20),
+                )
+                .await;
+            assert_eq!(error_code(&result.map(|_| Value::Null)), Some("QUEUE_FULL"));
+
+            // Urgent commands have a queue of their own.
+            let (tx, stop) = oneshot::channel();
+            manager
+                .try_send_command(Command::Stop {
+                    controller: "ctrl".to_string(),
+                    axis: "X".to_string(),
+                    mode: StopMode::Immediate,
+                    resp: tx,
+                })
+                .unwrap();
+            (replies, stop)
+        });
+
+        backend.block_on(async {
+            for rx in replies {
+                rx.await.unwrap().unwrap();
+            }
+            assert!(stop.await.unwrap().is_err());
+            let (tx, rx) = oneshot::channel();
+            manager
+                .send_command_timeout(
+                    Command::ListControllers { resp: tx },
+                    Duration::from_secs(1),
+                )
+                .await
+                .unwrap();
+            rx.await.unwrap().unwrap();
+        });
+    }
+
+    #[tokio::test]
+    async fn test_attribute_ttls_expire_independently() {
+        let config = ManagerConfig {
+            attribute_ttls: HashMap::from([("position".to_string(), Duration::from_millis(50))]),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        let read = |attr: &'static str| {
+            request(&manager, move |resp| Command::GetAttr {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                attr: attr.to_string(),
+                resp,
+            })
+        };
+        let reads = || ctrl.axis("X").attribute_reads.load(Ordering::SeqCst);
+
+        read("position").await.unwrap();
+        read("max_position").await.unwrap();
+        assert_eq!(reads(), 2);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        read("max_position").await.unwrap();
+        assert_eq!(reads(), 2);
+        read("position").await.unwrap();
+        assert_eq!(reads(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_handle_methods() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let handle = manager.handle();
+
+        let params = MovementParams::new().with_velocity(5.0);
+        handle
+            .move_to("ctrl", "X", 4.0, Some(params))
+            .await
+            .unwrap();
+        assert_eq!(*ctrl.axis("X").position.read().await, 4.0);
+        assert_eq!(handle.get_position("ctrl", "X").await.unwrap(), 4.0);
+        assert_eq!(
+            handle
+                .get_attribute("ctrl", "X", "max_position")
+                .await
+                .unwrap(),
+            100.0
+        );
+        assert!(handle.get_available_params("ctrl", "X").await.is_ok());
+        let supported = handle
+            .get_supported_movement_params("ctrl", "X")
+            .await
+            .unwrap();
+        assert!(supported.to_string().contains("velocity"));
+
+        handle.disable("ctrl", "X").await.unwrap();
+        assert_eq!(
+            handle.get_state("ctrl", "X").await.unwrap()["state"],
+            "disabled"
+        );
+        handle.enable("ctrl", "X").await.unwrap();
+        handle.stop("ctrl", "X").await.unwrap();
+        assert!(ctrl.axis("X").calls.lock().unwrap().contains(&"stop"));
+        assert!(handle.get_position("ctrl", "Q").await.is_err());
+
+        manager.shutdown_all().await;
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while handle.is_running() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        let result = handle.get_state("ctrl", "X").await;
+        assert_eq!(error_code(&result), Some("MANAGER_DOWN"));
+    }
+
+    #[tokio::test]
+    async fn test_controller_attribute_is_cached() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let read = |attr: &str| {
+            let attr = attr.to_string();
+            request(&manager, move |resp| Command::GetControllerAttr {
+                controller: "ctrl".to_string(),
+                attr,
+                resp,
+            })
+        };
+
+        for _ in 0..2 {
+            let result = read("firmware_version").await.unwrap();
+            assert_eq!(result["value"], "1.2.3");
+            assert!(result.get("axis").is_none());
+        }
+        assert_eq!(ctrl.controller_attribute_reads.load(Ordering::SeqCst), 1);
+        assert!(read("bus_voltage").await.is_err());
+
+        request(&manager, |resp| Command::Invalidate {
+            controller: "ctrl".to_string(),
+            axis: None,
+            resp,
+        })
+        .await
+        .unwrap();
+        read("firmware_version").await.unwrap();
+        assert_eq!(ctrl.controller_attribute_reads.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_system_health_reports_every_controller() {
+        let config = ManagerConfig {
+            health_timeout: Duration::from_millis(50),
+            ..test_config()
+        };
+        let (manager, healthy) = manager_with(config, TestController::new("a", &["X"])).await;
+        let faulted = Arc::new(TestController::new("b", &["X", "Y"]));
+        let stuck = Arc::new(TestController::new("c", &["X"]));
+        for ctrl in [&faulted, &stuck] {
+            manager
+                .register_controller(ctrl.name.clone(), ctrl.clone())
+                .await
+                .unwrap();
+        }
+        faulted.axis("Y").set_state(AxisState::Fault).await;
+        stuck.axis("X").hang_on_state.store(true, Ordering::SeqCst);
+
+        let health = request(&manager, |resp| Command::SystemHealth { resp })
+            .await
+            .unwrap();
+        assert_eq!(health["any_faulted"], true);
+        assert_eq!(health["any_moving"], false);
+        assert_eq!(health["any_unreadable"], true);
+
+        let controllers = health["controllers"].as_array().unwrap();
+        assert_eq!(controllers.len(), 3);
+        assert_eq!(controllers[0]["controller"], healthy.name);
+        assert_eq!(controllers[0]["any_faulted"], false);
+        assert_eq!(controllers[0]["axes"]["X"]["ready"], true);
+        assert_eq!(controllers[1]["controller"], "b");
+        assert_eq!(controllers[1]["any_faulted"], true);
+        assert_eq!(controllers[1]["axes"]["Y"]["state"], "fault");
+        assert_eq!(controllers[2]["controller"], "c");
+        assert!(controllers[2]["error"]
+            .as_str()
+            .unwrap()
+            .contains("No health report"));
+    }
+
+    #[tokio::test]
+    async fn test_controller_health_flags_faulted_axis() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X", "Y"])).await;
+        ctrl.axis("Y").set_state(AxisState::Fault).await;
+
+        let health = request(&manager, |resp| Command::ControllerHealth {
+            controller: "ctrl".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(health["any_faulted"], true);
+        assert_eq!(health["any_moving"], false);
+        assert_eq!(health["any_limit_active"], false);
+        assert_eq!(health["any_unreadable"], false);
+        assert_eq!(health["axes"]["X"]["ready"], true);
+        assert_eq!(health["axes"]["Y"]["state"], "fault");
+        assert_eq!(health["axes"]["Y"]["faulted"], true);
+        assert_eq!(health["axes"]["Y"]["ready"], false);
+    }
+
+    #[tokio::test]
+    async fn test_get_attribute_info_returns_metadata() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        let info = |attr: &str| {
+            let attr = attr.to_string();
+            request(&manager, move |resp| Command::GetAttrInfo {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                attr,
+                resp,
+            })
+        };
+
+        let result = info("position").await.unwrap();
+        assert_eq!(
+            result["info"],
+            json!({"name": "position", "unit": "mm", "min": -100.0, "max": 100.0, "writable": false})
+        );
+        let result = info("firmware").await.unwrap();
+        assert_eq!(result["info"]["unit"], Value::Null);
+        // Filled in by the controller rather than the axis.
+        let result = info("min_position").await.unwrap();
+        assert_eq!(result["info"]["unit"], "counts");
+
+        *ctrl.axis("X").tolerance.lock().unwrap() = Some(0.01);
+        let result = info("position").await.unwrap();
+        assert_eq!(result["info"]["tolerance"], 0.01);
+        let result = info("firmware").await.unwrap();
+        assert!(result["info"].get("tolerance").is_none());
+        assert!(info("torque").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_move_past_limit_reports_tripped_switch() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        *ctrl.axis("X").switch_position.lock().unwrap() = Some(100.0);
+        let get_state = || {
+            request(&manager, |resp| Command::GetState {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                fresh: false,
+                resp,
+            })
+        };
+        // Warm the status cache so the check below relies on the refresh.
+        get_state().await.unwrap();
+
+        let result = move_with(&manager, "X", 101.0, None).await;
+        assert_eq!(error_code(&result), Some("LIMIT_TRIPPED"));
+        assert!(result.unwrap_err().to_string().contains("upper"));
+
+        let state = get_state().await.unwrap();
+        assert_eq!(state["status"]["limit_switches"]["upper"], true);
+        assert_eq!(*ctrl.axis("X").position.read().await, 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_safety_limits_clamp_or_reject() {
+        let config = ManagerConfig {
+            max_velocity: Some(50.0),
+            max_acceleration: Some(200.0),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        let params = || {
+            Some(
+                MovementParams::new()
+                    .with_velocity(80.0)
+                    .with_acceleration(100.0),
+            )
+        };
+
+        let response = move_with(&manager, "X", 1.0, params()).await.unwrap();
+        assert_eq!(response["clamped"], json!({"velocity": 50.0}));
+        let used = ctrl.axis("X").last_params.lock().unwrap().clone().unwrap();
+        assert_eq!(
+            (used.velocity, used.acceleration),
+            (Some(50.0), Some(100.0))
+        );
+
+        let response = move_with(&manager, "X", 2.0, None).await.unwrap();
+        assert!(response.get("clamped").is_none());
+
+        let config = ManagerConfig {
+            max_velocity: Some(50.0),
+            safety_limit_mode: SafetyLimitMode::Reject,
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        let result = move_with(&manager, "X", 1.0, params()).await;
+        assert_eq!(error_code(&result), Some("SAFETY_LIMIT"));
+        assert_eq!(*ctrl.axis("X").position.read().await, 0.0);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("Z", "Z"));
+        assert!(!glob_match("Z", "Z1"));
+        assert!(glob_match("Z*", "Z1"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("?_axis", "X_axis"));
+        assert!(glob_match("a*b*c", "aXbYbc"));
+        assert!(!glob_match("a*b*c", "aXbY"));
+    }
+
+    #[tokio::test]
+    async fn test_stop_controller_stops_only_its_axes() {
+        let (manager, first) =
+            manager_with(test_config(), TestController::new("a", &["X", "Y"])).await;
+        let second = Arc::new(TestController::new("b", &["X"]));
+        manager
+            .register_controller("b".to_string(), second.clone())
+            .await
+            .unwrap();
+        first.axis("Y").set_state(AxisState::Moving).await;
+        second.axis("X").set_state(AxisState::Moving).await;
+
+        let response = request(&manager, |resp| Command::StopController {
+            controller: "a".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            response["results"],
+            json!([
+                {"controller": "a", "axis": "X", "status": "ok"},
+                {"controller": "a", "axis": "Y", "status": "ok"},
+            ])
+        );
+        assert_eq!(*first.axis("X").calls.lock().unwrap(), ["stop"]);
+        assert_eq!(*first.axis("Y").calls.lock().unwrap(), ["stop"]);
+        assert_eq!(*first.axis("Y").state.read().await, AxisState::On);
+        assert!(second.axis("X").calls.lock().unwrap().is_empty());
+        assert_eq!(*second.axis("X").state.read().await, AxisState::Moving);
+
+        let missing = request(&manager, |resp| Command::StopController {
+            controller: "c".to_string(),
+            resp,
+        })
+        .await;
+        assert!(missing
+            .unwrap_err()
+            .to_string()
+            .contains("Controller not found"));
+    }
+
+    #[tokio::test]
+    async fn test_fault_message_reaches_state_and_refused_moves() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X").fault("encoder signal lost").await;
+
+        let state = request(&manager, |resp| Command::GetState {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            fresh: true,
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(state["status"]["state"], "fault");
+        assert_eq!(state["status"]["message"], "encoder signal lost");
+
+        let (cmd, rx) = move_cmd("ctrl", "X", 5.0);
+        manager.send_command(cmd).await.unwrap();
+        let refused = rx.await.unwrap();
+        assert_eq!(error_code(&refused), Some("AXIS_NOT_READY"));
+        let err = refused.unwrap_err();
+        let err = err.downcast_ref::<CommandError>().unwrap();
+        assert!(err.message.contains("encoder signal lost"));
+        assert_eq!(
+            err.details,
+            Some(json!({"axis_message": "encoder signal lost"}))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_controller_vetoes_moves() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X", "Z"])).await;
+        ctrl.z_interlock.store(true, Ordering::SeqCst);
+
+        let (cmd, rx) = move_cmd("ctrl", "Z", 5.0);
+        manager.send_command(cmd).await.unwrap();
+        let vetoed = rx.await.unwrap();
+        assert_eq!(error_code(&vetoed), Some("MOVE_VETOED"));
+        assert!(vetoed
+            .unwrap_err()
+            .to_string()
+            .contains("enclosure door is open"));
+        assert!(ctrl.axis("Z").calls.lock().unwrap().is_empty());
+
+        let (cmd, rx) = move_cmd("ctrl", "X", 5.0);
+        manager.send_command(cmd).await.unwrap();
+        rx.await.unwrap().unwrap();
+
+        // Every other way of moving the axis is vetoed the same way.
+        let limit = request(&manager, |resp| Command::MoveToLimit {
+            controller: "ctrl".to_string(),
+            axis: "Z".to_string(),
+            direction: LimitDirection::Upper,
+            params: None,
+            zero_position: false,
+            owner: None,
+            resp,
+        })
+        .await;
+        assert_eq!(error_code(&limit), Some("MOVE_VETOED"));
+        let all = request(&manager, |resp| Command::MoveAll {
+            axis_pattern: "ctrl::Z".to_string(),
+            target: 5.0,
+            params: None,
+            confirm: false,
+            owner: None,
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(all["results"][0]["code"], "MOVE_VETOED");
+        assert!(ctrl.axis("Z").calls.lock().unwrap().is_empty());
+        // A move to a limit is checked against the soft limit it heads for.
+        assert!(ctrl
+            .checked_targets
+            .lock()
+            .unwrap()
+            .contains(&("Z".to_string(), 100.0)));
+
+        ctrl.z_interlock.store(false, Ordering::SeqCst);
+        let (cmd, rx) = move_cmd("ctrl", "Z", 5.0);
+        manager.send_command(cmd).await.unwrap();
+        rx.await.unwrap().unwrap();
+        assert_eq!(*ctrl.axis("Z").position.read().await, 5.0);
+    }
+
+    #[tokio::test]
+    async fn test_max_move_distance_refuses_runaway_moves() {
+        let config = ManagerConfig {
+            max_move_distance: Some(10.0),
+            axis_max_move_distance: HashMap::from([(("ctrl".to_string(), "Z".to_string()), 2.0)]),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X", "Z"])).await;
+
+        let (cmd, rx) = move_cmd("ctrl", "X", 15.0);
+        manager.send_command(cmd).await.unwrap();
+        let refused = rx.await.unwrap();
+        assert_eq!(error_code(&refused), Some("MOVE_TOO_LARGE"));
+        let err = refused.unwrap_err();
+        let details = err.downcast_ref::<CommandError>().unwrap().details.as_ref();
+        assert_eq!(details.unwrap()["distance"], 15.0);
+        assert!(ctrl.axis("X").calls.lock().unwrap().is_empty());
+
+        let (cmd, rx) = move_cmd("ctrl", "X", 8.0);
+        manager.send_command(cmd).await.unwrap();
+        rx.await.unwrap().unwrap();
+
+        let (cmd, rx) = move_cmd("ctrl", "Z", 3.0);
+        manager.send_command(cmd).await.unwrap();
+        assert_eq!(error_code(&rx.await.unwrap()), Some("MOVE_TOO_LARGE"));
+    }
+
+    #[tokio::test]
+    async fn test_max_move_distance_checks_queued_moves_from_where_they_start() {
+        let config = ManagerConfig {
+            max_move_distance: Some(100.0),
+            ..test_config()
+        };
+        let (manager, _) = manager_with(
+            config,
+            TestController::with_move_delay("ctrl", &["X"], Duration::from_millis(20)),
+        )
+        .await;
+
+        let started = request(&manager, |resp| Command::Move {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            target: -100.0,
+            target_kind: TargetKind::Absolute,
+            params: None,
+            dry_run: false,
+            wait: false,
+            force: false,
+            override_distance: false,
+            owner: None,
+            resp,
+        })
+        .await;
+        assert!(started.is_ok());
+        // 40 away from where the axis is now, but 140 from where it stops.
+        let queued = move_with(&manager, "X", 40.0, None).await;
+        assert_eq!(error_code(&queued), Some("MOVE_TOO_LARGE"));
+    }
+
+    #[tokio::test]
+    async fn test_max_move_distance_refuses_unreadable_positions() {
+        let config = ManagerConfig {
+            max_move_distance: Some(10.0),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        *ctrl.axis("X").position.write().await = f64::NAN;
+
+        let refused = move_with(&manager, "X", 1.0, None).await;
+        assert_eq!(error_code(&refused), Some("INVALID_READING"));
+        assert!(ctrl.axis("X").calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_override_skips_max_move_distance() {
+        let config = ManagerConfig {
+            max_move_distance: Some(10.0),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+
+        let moved = request(&manager, |resp| Command::Move {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            target: 25.0,
+            target_kind: TargetKind::Absolute,
+            params: None,
+            dry_run: false,
+            wait: true,
+            force: false,
+            override_distance: true,
+            owner: None,
+            resp,
+        })
+        .await;
+        assert!(moved.is_ok());
+        assert_eq!(*ctrl.axis("X").position.read().await, 25.0);
+    }
+
+    #[tokio::test]
+    async fn test_move_all_moves_matching_axes() {
+        let (manager, first) =
+            manager_with(test_config(), TestController::new("a", &["X", "Z"])).await;
+        let second = Arc::new(TestController::new("b", &["Z", "Z2"]));
+        manager
+            .register_controller("b".to_string(), second.clone())
+            .await
+            .unwrap();
+        *second.axis("Z2").state.write().await = AxisState::Disabled;
+        let move_all = |pattern: &str, confirm: bool| {
+            let pattern = pattern.to_string();
+            request(&manager, move |resp| Command::MoveAll {
+                axis_pattern: pattern,
+                target: 3.0,
+                params: None,
+                confirm,
+                owner: None,
+                resp,
+            })
+        };
+
+        let response = move_all("Z*", false).await.unwrap();
+        assert_eq!(
+            response["results"],
+            json!([
+                {"controller": "a", "axis": "Z", "status": "ok"},
+                {"controller": "b", "axis": "Z", "status": "ok"},
+                {"controller": "b", "axis": "Z2", "status": "error",
+                 "error": response["results"][2]["error"], "code": "AXIS_DISABLED"},
+            ])
+        );
+        assert_eq!(*first.axis("Z").position.read().await, 3.0);
+        assert_eq!(*second.axis("Z").position.read().await, 3.0);
+        assert_eq!(*first.axis("X").position.read().await, 0.0);
+
+        let result = move_all("b::Z", false).await;
+        assert!(result.is_ok());
+        assert_eq!(error_code(&move_all("Q", false).await), Some("NO_MATCH"));
+        assert_eq!(
+            error_code(&move_all("a::*", false).await),
+            Some("CONFIRMATION_REQUIRED")
+        );
+        assert!(move_all("a::*", true).await.is_ok());
+        assert_eq!(*first.axis("X").position.read().await, 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_move_group_moves_every_member() {
+        let (manager, first) =
+            manager_with(test_config(), TestController::new("a", &["X", "Y"])).await;
+        let second = Arc::new(TestController::new("b", &["Z"]));
+        manager
+            .register_controller("b".to_string(), second.clone())
+            .await
+            .unwrap();
+        let member = |controller: &str, axis: &str| (controller.to_string(), axis.to_string());
+        manager
+            .define_group(
+                "linear".to_string(),
+                vec![member("a", "X"), member("b", "Z")],
+            )
+            .unwrap();
+        let move_group = |group: &str| {
+            let group = group.to_string();
+            request(&manager, move |resp| Command::MoveGroup {
+                group,
+                target: 7.0,
+                params: None,
+                owner: None,
+                resp,
+            })
+        };
+
+        let response = move_group("linear").await.unwrap();
+        assert_eq!(
+            response["results"],
+            json!([
+                {"controller": "a", "axis": "X", "status": "ok"},
+                {"controller": "b", "axis": "Z", "status": "ok"},
+            ])
+        );
+        assert_eq!(*first.axis("X").position.read().await, 7.0);
+        assert_eq!(*second.axis("Z").position.read().await, 7.0);
+        assert_eq!(*first.axis("Y").position.read().await, 0.0);
+
+        assert_eq!(
+            error_code(&move_group("rotary").await),
+            Some("GROUP_NOT_FOUND")
+        );
+        let stopped = request(&manager, |resp| Command::StopGroup {
+            group: "linear".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(stopped["results"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_self_test_reports_checks() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X", "Y"])).await;
+        let self_test = || {
+            request(&manager, |resp| Command::SelfTest {
+                controller: "ctrl".to_string(),
+                resp,
+            })
+        };
+
+        let report = self_test().await.unwrap();
+        assert_eq!(report["passed"], true);
+        assert_eq!(report["checks"].as_array().unwrap().len(), 2);
+
+        ctrl.axis("Y").set_state(AxisState::Fault).await;
+        let report = self_test().await.unwrap();
+        assert_eq!(report["passed"], false);
+        assert_eq!(
+            report["checks"][1],
+            json!({"name": "Y", "passed": false, "detail": "faulted"})
+        );
+    }
+
+    #[test]
+    fn test_cache_keys_escape_separators() {
+        assert_eq!(cache_key("ctrl", "X", "position"), "ctrl::X::position");
+        assert_ne!(
+            cache_key("a::b", "c", "position"),
+            cache_key("a", "b::c", "position")
+        );
+        assert_ne!(cache_key("a\\", ":b", "f"), cache_key("a\\:", "b", "f"));
+    }
+
+    #[tokio::test]
+    async fn test_names_with_colons_do_not_share_cache_entries() {
+        // Unescaped, both keys would be `a:::b::position`.
+        let (manager, first) = manager_with(test_config(), TestController::new("a:", &["b"])).await;
+        let second = Arc::new(TestController::new("a", &[":b"]));
+        manager
+            .register_controller("a".to_string(), second.clone())
+            .await
+            .unwrap();
+        *first.axis("b").position.write().await = 1.0;
+        *second.axis(":b").position.write().await = 2.0;
+
+        assert_eq!(get_pos(&manager, "a:", "b").await.unwrap()["position"], 1.0);
+        assert_eq!(get_pos(&manager, "a", ":b").await.unwrap()["position"], 2.0);
+
+        request(&manager, |resp| Command::Invalidate {
+            controller: "a".to_string(),
+            axis: None,
+            resp,
+        })
+        .await
+        .unwrap();
+        *first.axis("b").position.write().await = 5.0;
+        assert_eq!(get_pos(&manager, "a:", "b").await.unwrap()["position"], 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_no_op_cache_sends_every_read_to_the_axis() {
+        let manager = ControllerManager::with_cache(test_config(), Arc::new(cache::NoOpCache));
+        let ctrl = Arc::new(TestController::new("ctrl", &["X"]));
+        manager
+            .register_controller("ctrl".to_string(), ctrl.clone())
+            .await
+            .unwrap();
+
+        for position in [1.0, 2.0, 3.0] {
+            *ctrl.axis("X").position.write().await = position;
+            assert_eq!(
+                get_pos(&manager, "ctrl", "X").await.unwrap()["position"],
+                position
+            );
+        }
+        assert_eq!(manager.metrics().snapshot().cache_hits, 0);
+    }
+
+    #[tokio::test]
+    async fn test_non_finite_readings_are_tagged() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X", "Y"])).await;
+        *ctrl.axis("X").position.write().await = f64::NAN;
+        *ctrl.axis("Y").position.write().await = f64::NEG_INFINITY;
+
+        let response = get_pos(&manager, "ctrl", "X").await.unwrap();
+        assert_eq!(response["position"], json!({"special": "nan"}));
+        // The cached copy keeps the tag.
+        let response = get_pos(&manager, "ctrl", "X").await.unwrap();
+        assert_eq!(response["position"], json!({"special": "nan"}));
+
+        let response = request(&manager, |resp| Command::GetAttr {
+            controller: "ctrl".to_string(),
+            axis: "Y".to_string(),
+            attr: "position".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(response["value"], json!({"special": "-inf"}));
+
+        assert_eq!(number_value(f64::INFINITY), json!({"special": "inf"}));
+        assert_eq!(number_value(1.5), json!(1.5));
+    }
+
+    #[tokio::test]
+    async fn test_position_decimals_round_readings() {
+        let config = ManagerConfig {
+            position_decimals: Some(3),
+            axis_position_decimals: HashMap::from([(("ctrl".to_string(), "Y".to_string()), 1)]),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X", "Y"])).await;
+        *ctrl.axis("X").position.write().await = 12.2 + 0.1;
+        *ctrl.axis("Y").position.write().await = 4.26;
+
+        let response = get_pos(&manager, "ctrl", "X").await.unwrap();
+        assert_eq!(response["position"], json!(12.3));
+        let response = request(&manager, |resp| Command::GetAttr {
+            controller: "ctrl".to_string(),
+            axis: "Y".to_string(),
+            attr: "position".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(response["value"], json!(4.3));
+
+        *ctrl.axis("X").position.write().await = 1.23456;
+        let response = request(&manager, |resp| Command::GetPos {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            fresh: true,
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(response["position"], json!(1.235));
+        assert!(round_to(f64::NAN, 3).is_nan());
+    }
+
+    #[tokio::test]
+    async fn test_history_keeps_latest_moves_newest_first() {
+        let config = ManagerConfig {
+            move_history_size: 3,
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        for target in [1.0, 2.0, 3.0] {
+            move_with(&manager, "X", target, None).await.unwrap();
+        }
+        ctrl.axis("X").set_state(AxisState::Disabled).await;
+        assert!(move_with(&manager, "X", 4.0, None).await.is_err());
+
+        let history = |limit| {
+            request(&manager, move |resp| Command::GetHistory {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                limit,
+                resp,
+            })
+        };
+        let moves = history(None).await.unwrap()["moves"].take();
+        let targets: Vec<f64> = moves
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["target"].as_f64().unwrap())
+            .collect();
+        assert_eq!(targets, [4.0, 3.0, 2.0]);
+        assert!(moves[0]["error"].as_str().unwrap().contains("disabled"));
+        assert_eq!(moves[1]["error"], Value::Null);
+
+        let moves = history(Some(1)).await.unwrap()["moves"].take();
+        assert_eq!(moves.as_array().unwrap().len(), 1);
+        assert_eq!(moves[0]["target"], 4.0);
+    }
+
+    #[tokio::test]
+    async fn test_background_move_failure_lands_in_history() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        *ctrl.axis("X").switch_position.lock().unwrap() = Some(5.0);
+
+        let (mut cmd, rx) = move_cmd("ctrl", "X", 10.0);
+        if let Command::Move { wait, .. } = &mut cmd {
+            *wait = false;
+        }
+        manager.send_command(cmd).await.unwrap();
+        rx.await.unwrap().unwrap();
+
+        let mut moves = Value::Null;
+        for _ in 0..100 {
+            moves = request(&manager, |resp| Command::GetHistory {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                limit: None,
+                resp,
+            })
+            .await
+            .unwrap()["moves"]
+                .take();
+            if !moves[0]["error"].is_null() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(moves[0]["target"], 10.0);
+        assert!(moves[0]["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_cold_reads_share_one_hardware_read() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X").set_read_delay(Duration::from_millis(50));
+
+        let reads = (0..20).map(|_| {
+            request(&manager, |resp| Command::GetPos {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                fresh: false,
+                resp,
+            })
+        });
+        for result in join_all(reads).await {
+            assert_eq!(result.unwrap()["position"], 0.0);
+        }
+        assert_eq!(ctrl.axis("X").attribute_reads.load(Ordering::SeqCst), 1);
+        assert!(manager.state.reads.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_reads_share_the_result_not_the_cache() {
+        let manager = ControllerManager::new(test_config());
+        let reads = AtomicUsize::new(0);
+        let read = || async {
+            reads.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(json!(1.5))
+        };
+
+        // The reads never fill the cache, so waiters can only get the value
+        // from the read in flight.
+        let results = join_all((0..10).map(|_| manager.state.read_coalesced("key", read()))).await;
+        for result in results {
+            assert_eq!(result.unwrap(), 1.5);
+        }
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+        assert!(manager.state.reads.lock().unwrap().is_empty());
+
+        // A failed read leaves the waiters to read for themselves.
+        let failing = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Err(anyhow::anyhow!("offline"))
+        };
+        let (failed, retried) = tokio::join!(
+            manager.state.read_coalesced("key", failing),
+            manager.state.read_coalesced("key", read()),
+        );
+        assert!(failed.is_err());
+        assert_eq!(retried.unwrap(), 1.5);
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_flushes_cached_positions_to_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("positions.json");
+        let store = || Box::new(position_store::FilePositionStore::new(&path));
+
+        let manager = ControllerManager::with_position_store(test_config(), store())
+            .await
+            .unwrap();
+        manager
+            .register_controller(
+                "ctrl".to_string(),
+                Arc::new(TestController::new("ctrl", &["X", "Y"])),
+            )
+            .await
+            .unwrap();
+        move_with(&manager, "X", 7.0, None).await.unwrap();
+        // Values cached without going through the store, e.g. by the poller.
+        for (axis, position) in [("X", 9.5), ("Y", -3.0)] {
+            manager
+                .state
+                .cache_insert("ctrl", axis, "position", "position", json!(position))
+                .await;
+        }
+        manager.shutdown_all().await;
+
+        let saved = store().load().await.unwrap();
+        assert_eq!(saved["ctrl"]["X"], 9.5);
+        assert_eq!(saved["ctrl"]["Y"], -3.0);
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_moves_persist_where_the_axis_stopped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("positions.json");
+        let store = || Box::new(position_store::FilePositionStore::new(&path));
+        let saved = |axis: &'static str, expected: f64| {
+            let store = store();
+            async move {
+                for _ in 0..100 {
+                    let saved = store.load().await.unwrap();
+                    if saved.get("ctrl").and_then(|axes| axes.get(axis)) == Some(&expected) {
+                        return true;
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                false
+            }
+        };
+
+        let manager = ControllerManager::with_position_store(test_config(), store())
+            .await
+            .unwrap();
+        let ctrl = Arc::new(TestController::with_move_delay(
+            "ctrl",
+            &["X", "Y"],
+            Duration::from_secs(60),
+        ));
+        manager
+            .register_controller("ctrl".to_string(), ctrl.clone())
+            .await
+            .unwrap();
+
+        // A move that trips a limit stops at the switch, not the target.
+        *ctrl.axis("X").switch_position.lock().unwrap() = Some(5.0);
+        let result = move_with(&manager, "X", 7.0, None).await;
+        assert_eq!(error_code(&result), Some("LIMIT_TRIPPED"));
+        assert!(saved("X", 5.0).await);
+
+        // A cancelled move persists wherever the axis got to.
+        let (cmd, rx) = move_cmd("ctrl", "Y", 9.0);
+        manager.send_command(cmd).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        *ctrl.axis("Y").position.write().await = 2.5;
+        request(&manager, |resp| Command::Cancel {
+            controller: "ctrl".to_string(),
+            axis: "Y".to_string(),
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(error_code(&rx.await.unwrap()), Some("CANCELLED"));
+        assert!(saved("Y", 2.5).await);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_state_resolves_on_transition_or_times_out() {
+        let config = ManagerConfig {
+            poll_interval: Some(Duration::from_millis(10)),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        let wait = |state, timeout_ms| {
+            request(&manager, move |resp| Command::WaitForState {
+                controller: "ctrl".to_string(),
+                axis: "X".to_string(),
+                state,
+                timeout: Duration::from_millis(timeout_ms),
+                resp,
+            })
+        };
+
+        ctrl.axis("X").set_state(AxisState::Moving).await;
+        move_with(&manager, "X", 5.0, None).await.unwrap();
+        let axis = ctrl.axis("X");
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            axis.set_state(AxisState::On).await;
+        });
+        let response = wait(AxisState::On, 2000).await.unwrap();
+        assert_eq!(response["reached"], true);
+        assert_eq!(response["state"], "on");
+
+        let err = wait(AxisState::Fault, 50).await.unwrap_err();
+        let err = err.downcast_ref::<CommandError>().unwrap();
+        assert_eq!(err.code, "WAIT_TIMEOUT");
+    }
+
+    #[tokio::test]
+    async fn test_missing_attribute_policy() {
+        async fn get_acceleration(manager: &ControllerManager) -> Result<Value> {
+            request(manager, |resp| Command::GetAttr {
+                controller: "ctrl".to_string(),
+                axis: "Z".to_string(),
+                attr: "acceleration".to_string(),
+                resp,
+            })
+            .await
+        }
+
+        let (manager, _) =
+            manager_with(test_config(), TestController::new("ctrl", &["X", "Z"])).await;
+        let err = get_acceleration(&manager).await.unwrap_err();
+        assert!(err.to_string().contains("not supported"));
+
+        let config = ManagerConfig {
+            missing_attribute_policy: MissingAttributePolicy::Null,
+            ..test_config()
+        };
+        let (manager, _) = manager_with(config, TestController::new("ctrl", &["X", "Z"])).await;
+        for _ in 0..2 {
+            let response = get_acceleration(&manager).await.unwrap();
+            assert_eq!(response["value"], Value::Null);
+            assert_eq!(response["supported"], false);
+        }
+        assert_eq!(manager.metrics().snapshot().cache_hits, 0);
+    }
+
+    #[tokio::test]
+    async fn test_register_rejects_ambiguous_names() {
+        let manager = ControllerManager::new(test_config());
+        let register = |name: &str, axes: &[&str]| {
+            let controller = Arc::new(TestController::new(name, axes));
+            manager.register_controller(name.to_string(), controller)
+        };
+        let code = |result: Result<()>| {
+            result
+                .unwrap_err()
+                .downcast_ref::<CommandError>()
+                .map(|e| e.code)
+        };
+
+        assert_eq!(code(register("", &["X"]).await), Some("INVALID_NAME"));
+        assert_eq!(code(register("a::b", &["X"]).await), Some("INVALID_NAME"));
+        assert_eq!(code(register("ctrl", &[""]).await), Some("INVALID_NAME"));
+        assert_eq!(
+            code(register("ctrl", &["X::1"]).await),
+            Some("INVALID_NAME")
+        );
+        assert_eq!(
+            code(register("ctrl", &["X", "Y", "X"]).await),
+            Some("INVALID_NAME")
+        );
+        assert_eq!(
+            code(register("ctrl", &[CONTROLLER_SCOPE]).await),
+            Some("INVALID_NAME")
+        );
+        assert!(manager.state.controllers.read().await.is_empty());
+
+        register("a:b", &["X", "Y"]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_case_insensitive_axes_match_any_case() {
+        let config = ManagerConfig {
+            case_insensitive_axes: true,
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X", "Y"])).await;
+
+        let (cmd, rx) = move_cmd("ctrl", "x", 4.0);
+        manager.send_command(cmd).await.unwrap();
+        rx.await.unwrap().unwrap();
+        assert_eq!(*ctrl.axis("X").position.read().await, 4.0);
+        let response = get_pos(&manager, "ctrl", "x").await.unwrap();
+        assert_eq!(response["axis"], "X");
+        assert_eq!(response["position"], 4.0);
+        let positions = request(&manager, |resp| Command::GetPositions {
+            controller: "ctrl".to_string(),
+            axes: vec!["x".to_string(), "y".to_string()],
+            resp,
+        })
+        .await
+        .unwrap();
+        assert_eq!(positions["positions"]["X"]["position"], 4.0);
+        assert!(get_pos(&manager, "ctrl", "z").await.is_err());
+
+        // Axes that only case tells apart can't be registered.
+        let ambiguous = Arc::new(TestController::new("other", &["X", "x"]));
+        let err = manager
+            .register_controller("other".to_string(), ambiguous)
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(&Err(err)), Some("INVALID_NAME"));
+    }
+
+    #[tokio::test]
+    async fn test_axis_names_match_exactly_by_default() {
+        let (manager, _) =
+            manager_with(test_config(), TestController::new("ctrl", &["X", "x"])).await;
+        let err = get_pos(&manager, "ctrl", "y").await.unwrap_err();
+        assert!(err.to_string().contains("Axis not found"));
+
+        let (cmd, rx) = move_cmd("ctrl", "x", 2.0);
+        manager.send_command(cmd).await.unwrap();
+        rx.await.unwrap().unwrap();
+        assert_eq!(
+            get_pos(&manager, "ctrl", "X").await.unwrap()["position"],
+            0.0
+        );
+        assert_eq!(
+            get_pos(&manager, "ctrl", "x").await.unwrap()["position"],
+            2.0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_alias_resolves_to_real_controller() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("site-a-ctrl", &["X"])).await;
+        manager
+            .register_alias("stage".to_string(), "site-a-ctrl".to_string())
+            .await
+            .unwrap();
+
+        let (cmd, rx) = move_cmd("stage", "X", 4.0);
+        manager.send_command(cmd).await.unwrap();
+        rx.await.unwrap().unwrap();
+        assert_eq!(*ctrl.axis("X").position.read().await, 4.0);
+        let response = get_pos(&manager, "stage", "X").await.unwrap();
+        assert_eq!(response["controller"], "site-a-ctrl");
+        assert_eq!(response["position"], 4.0);
+        // Same cache entry whichever name is used.
+        get_pos(&manager, "site-a-ctrl", "X").await.unwrap();
+        assert_eq!(manager.metrics().snapshot().cache_hits, 1);
+
+        let aliases = request(&manager, |resp| Command::ListAliases { resp })
+            .await
+            .unwrap();
+        assert_eq!(aliases["aliases"], json!({"stage": "site-a-ctrl"}));
+
+        let err = manager
+            .register_alias("site-a-ctrl".to_string(), "site-a-ctrl".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(&Err(err)), Some("INVALID_NAME"));
+        let other = Arc::new(TestController::new("stage", &["X"]));
+        assert!(manager
+            .register_controller("stage".to_string(), other)
+            .await
+            .is_err());
+        assert!(manager
+            .register_alias("nowhere".to_string(), "missing".to_string())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tripped_limit_publishes_one_limit_switch_event() {
+        let config = ManagerConfig {
+            poll_interval: Some(Duration::from_millis(10)),
+            ..test_config()
+        };
+        let (manager, ctrl) = manager_with(config, TestController::new("ctrl", &["X"])).await;
+        let mut events = manager.subscribe_events();
+        *ctrl.axis("X").switch_position.lock().unwrap() = Some(5.0);
+
+        let result = move_with(&manager, "X", 10.0, None).await;
+        assert_eq!(error_code(&result), Some("LIMIT_TRIPPED"));
+        // Let the poller see the engaged switch a few more times.
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        let mut changes = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let AxisEventKind::LimitSwitchChanged { new, .. } = event.kind {
+                changes.push(new);
+            }
+        }
+        assert_eq!(changes, [LimitSwitches::Upper]);
+    }
+
+    #[tokio::test]
+    async fn test_faulted_axis_rejects_moves_unless_forced() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X").set_state(AxisState::Fault).await;
+
+        let result = move_with(&manager, "X", 3.0, None).await;
+        assert_eq!(error_code(&result), Some("AXIS_NOT_READY"));
+        assert_eq!(*ctrl.axis("X").position.read().await, 0.0);
+
+        let forced = request(&manager, |resp| Command::Move {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            target: 3.0,
+            target_kind: TargetKind::Absolute,
+            params: None,
+            dry_run: false,
+            wait: true,
+            force: true,
+            override_distance: false,
+            owner: None,
+            resp,
+        })
+        .await;
+        assert!(forced.is_ok());
+        assert_eq!(*ctrl.axis("X").position.read().await, 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_move_readiness_uses_cached_state() {
+        let (manager, ctrl) =
+            manager_with(test_config(), TestController::new("ctrl", &["X"])).await;
+        ctrl.axis("X").set_state(AxisState::Alarm).await;
+        request(&manager, |resp| Command::GetState {
+            controller: "ctrl".to_string(),
+            axis: "X".to_string(),
+            fresh: true,
+            resp,
+        })
+        .await
+        .unwrap();
+        let hits = manager.metrics().snapshot().cache_hits;
+
+        let result = move_with(&manager, "X", 3.0, None).await;
+        assert_eq!(error_code(&result), Some("AXIS_NOT_READY"));
+        assert_eq!(manager.metrics().snapshot().cache_hits, hits + 1);
     }
 }
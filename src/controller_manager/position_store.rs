@@ -0,0 +1,109 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::Result;
+use tokio::sync::Mutex;
+
+/// Last-known positions, keyed by controller and then axis.
+pub type Positions = HashMap<String, HashMap<String, f64>>;
+
+/// Durable record of the last position seen for each axis, used to warm the
+/// cache when the manager starts. Only suitable for hardware that keeps its
+/// absolute position across restarts.
+#[async_trait::async_trait]
+pub trait PositionStore: Send + Sync {
+    async fn load(&self) -> Result<Positions>;
+    async fn save(&self, controller: &str, axis: &str, position: f64) -> Result<()>;
+
+    /// Saves many positions at once, as the manager does on shutdown with
+    /// everything it has cached. The default saves them one by one.
+    async fn flush(&self, positions: &Positions) -> Result<()> {
+        for (controller, axes) in positions {
+            for (axis, position) in axes {
+                self.save(controller, axis, *position).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keeps all positions in a single JSON file, rewritten on every save.
+/// Saves start from what `load` read, so load before saving to keep entries
+/// from a previous run.
+pub struct FilePositionStore {
+    path: PathBuf,
+    positions: Mutex<Positions>,
+}
+
+impl FilePositionStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            positions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl PositionStore for FilePositionStore {
+    async fn load(&self) -> Result<Positions> {
+        let loaded: Positions = match tokio::fs::read(&self.path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        *self.positions.lock().await = loaded.clone();
+        Ok(loaded)
+    }
+
+    async fn save(&self, controller: &str, axis: &str, position: f64) -> Result<()> {
+        // The lock is held across the write so concurrent saves can't leave
+        // an older snapshot on disk.
+        let mut positions = self.positions.lock().await;
+        positions
+            .entry(controller.to_string())
+            .or_default()
+            .insert(axis.to_string(), position);
+        self.write(&positions).await
+    }
+
+    /// Merges everything and rewrites the file once.
+    async fn flush(&self, flushed: &Positions) -> Result<()> {
+        let mut positions = self.positions.lock().await;
+        for (controller, axes) in flushed {
+            positions.entry(controller.clone()).or_default().extend(
+                axes.iter()
+                    .map(|(axis, position)| (axis.clone(), *position)),
+            );
+        }
+        self.write(&positions).await
+    }
+}
+
+impl FilePositionStore {
+    async fn write(&self, positions: &Positions) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec(positions)?).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("positions.json");
+
+        let store = FilePositionStore::new(&path);
+        assert!(store.load().await.unwrap().is_empty());
+        store.save("ctrl", "X", 1.5).await.unwrap();
+        store.save("ctrl", "Y", -2.0).await.unwrap();
+
+        let reloaded = FilePositionStore::new(&path).load().await.unwrap();
+        assert_eq!(reloaded["ctrl"]["X"], 1.5);
+        assert_eq!(reloaded["ctrl"]["Y"], -2.0);
+    }
+}
@@ -1,5 +1,11 @@
 pub mod axis;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod controller_manager;
 pub mod motor_controller;
 pub mod protocol;
 pub mod socket_server;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+/// One check of a controller self-test.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticCheck {
+    pub name: String,
+    pub passed: bool,
+    /// What was measured, or why the check failed.
+    pub detail: Option<String>,
+}
+
+/// Outcome of `MotorController::self_test`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticsReport {
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+impl DiagnosticsReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pass(&mut self, name: impl Into<String>, detail: Option<String>) {
+        self.checks.push(DiagnosticCheck {
+            name: name.into(),
+            passed: true,
+            detail,
+        });
+    }
+
+    pub fn fail(&mut self, name: impl Into<String>, detail: impl Into<String>) {
+        self.checks.push(DiagnosticCheck {
+            name: name.into(),
+            passed: false,
+            detail: Some(detail.into()),
+        });
+    }
+
+    /// True when every check passed, including when there were none.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+}
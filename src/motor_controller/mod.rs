@@ -1,6 +1,19 @@
-use std::sync::Arc;
+pub mod diagnostics;
 
-use crate::axis::{movement_parameters::MovementParams, state_info::AxisStateInfo, Axis};
+use std::{borrow::Cow, sync::Arc};
+
+use crate::axis::{
+    attribute_info::AttributeInfo,
+    attribute_value::AttributeValue,
+    capabilities::AxisCapabilities,
+    limit_switches::LimitSwitches,
+    limits::{AxisLimits, LimitDirection},
+    movement_parameters::MovementParams,
+    state_info::AxisStateInfo,
+    stop_mode::StopMode,
+    Axis,
+};
+use diagnostics::DiagnosticsReport;
 
 #[async_trait::async_trait]
 pub trait MotorController: Send + Sync {
@@ -17,13 +30,61 @@ pub trait MotorController: Send + Sync {
             .cloned()
     }
 
+    /// How many commands the hardware can service at once, e.g. `Some(1)`
+    /// for a serial bus. `None` defers to the manager's configuration.
+    fn max_concurrent_commands(&self) -> Option<usize> {
+        None
+    }
+
+    /// Called once before the controller is registered with a manager.
+    async fn initialize(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Runs the controller's built-in diagnostics. A check that fails
+    /// belongs in the report; an `Err` means the self-test itself couldn't
+    /// run. Unsupported unless the controller overrides it.
+    async fn self_test(&self) -> anyhow::Result<DiagnosticsReport> {
+        Err(anyhow::anyhow!(
+            "Self-test is not implemented by controller {}",
+            self.name()
+        ))
+    }
+
+    /// Reads an attribute of the controller itself rather than of an axis,
+    /// such as its firmware version or bus voltage. None are supported
+    /// unless the controller overrides it.
+    async fn get_controller_attribute(&self, name: &str) -> anyhow::Result<AttributeValue> {
+        Err(anyhow::anyhow!(
+            "Controller attribute not supported by {}: {}",
+            self.name(),
+            name
+        ))
+    }
+
     async fn shutdown(&self) -> anyhow::Result<()> {
         for axis in self.axes() {
-            axis.stop().await?;
+            axis.stop_with(StopMode::Immediate).await?;
         }
         Ok(())
     }
 
+    /// Called before every move to a target, with the target and
+    /// parameters `start` would receive, so the controller can refuse moves
+    /// its interlocks forbid. Before a `move_to_limit` the target is the
+    /// soft limit in that direction, or an infinity of its sign when the
+    /// axis has none. The reason of an `Err` is reported to the client as
+    /// `MOVE_VETOED`. Allows everything unless overridden.
+    async fn check_move_allowed(
+        &self,
+        axis: &str,
+        target: f64,
+        params: Option<&MovementParams>,
+    ) -> Result<(), String> {
+        let _ = (axis, target, params);
+        Ok(())
+    }
+
     async fn start(
         &self,
         axis: &str,
@@ -39,28 +100,142 @@ pub trait MotorController: Send + Sync {
         ax.stop().await
     }
 
+    async fn stop_with(&self, axis: &str, mode: StopMode) -> anyhow::Result<()> {
+        let ax = self.get_axis(axis)?;
+        ax.stop_with(mode).await
+    }
+
+    async fn move_to_limit(
+        &self,
+        axis: &str,
+        direction: LimitDirection,
+        params: Option<MovementParams>,
+        zero_position: bool,
+    ) -> anyhow::Result<LimitSwitches> {
+        let ax = self.get_axis(axis)?;
+        ax.move_to_limit(direction, params, zero_position).await
+    }
+
+    async fn pause(&self, axis: &str) -> anyhow::Result<()> {
+        let ax = self.get_axis(axis)?;
+        ax.pause().await
+    }
+
+    async fn resume(&self, axis: &str) -> anyhow::Result<()> {
+        let ax = self.get_axis(axis)?;
+        ax.resume().await
+    }
+
+    async fn enable(&self, axis: &str) -> anyhow::Result<()> {
+        let ax = self.get_axis(axis)?;
+        ax.enable().await
+    }
+
+    async fn disable(&self, axis: &str) -> anyhow::Result<()> {
+        let ax = self.get_axis(axis)?;
+        ax.disable().await
+    }
+
+    async fn reset_fault(&self, axis: &str) -> anyhow::Result<()> {
+        let ax = self.get_axis(axis)?;
+        ax.reset_fault().await
+    }
+
+    async fn set_position_reference(&self, axis: &str, value: f64) -> anyhow::Result<()> {
+        let ax = self.get_axis(axis)?;
+        ax.set_position_reference(value).await
+    }
+
     async fn state(&self, axis: &str) -> anyhow::Result<AxisStateInfo> {
         let ax = self.get_axis(axis)?;
         ax.get_state().await
     }
 
-    async fn get_attribute(&self, axis: &str, attribute: &str) -> anyhow::Result<f64> {
-        let supported_attributes = self.get_available_attributes(axis).await?;
-        if !supported_attributes.contains(&attribute.to_string()) {
-            return Err(anyhow::anyhow!("Attribute not supported: {}", attribute));
-        }
+    async fn get_position(&self, axis: &str) -> anyhow::Result<f64> {
+        let ax = self.get_axis(axis)?;
+        ax.get_position().await
+    }
 
+    /// How far the axis's current move has got, as `get_state` and the
+    /// poller's progress events report it; see `Axis::get_progress`.
+    async fn get_progress(&self, axis: &str) -> anyhow::Result<Option<f64>> {
+        let ax = self.get_axis(axis)?;
+        Ok(ax.get_progress().await)
+    }
+
+    /// Attribute reads and writes. A manager checks `attribute` against
+    /// the axis's `available_attributes`, which it caches, before calling
+    /// these, so overrides needn't query the list again.
+    async fn get_attribute(&self, axis: &str, attribute: &str) -> anyhow::Result<f64> {
         let ax = self.get_axis(axis)?;
         ax.get_attribute(attribute).await
     }
 
-    async fn get_available_attributes(&self, axis: &str) -> anyhow::Result<Vec<String>> {
+    async fn set_attribute(&self, axis: &str, attribute: &str, value: f64) -> anyhow::Result<()> {
         let ax = self.get_axis(axis)?;
-        ax.get_available_params().await
+        ax.set_attribute(attribute, value).await
     }
 
+    /// What a manager's `get_attribute` command reads, so a controller
+    /// that serves attribute reads itself overrides this one.
+    async fn get_attribute_value(
+        &self,
+        axis: &str,
+        attribute: &str,
+    ) -> anyhow::Result<AttributeValue> {
+        let ax = self.get_axis(axis)?;
+        ax.get_attribute_value(attribute).await
+    }
+
+    async fn get_attribute_array(&self, axis: &str, attribute: &str) -> anyhow::Result<Vec<f64>> {
+        let ax = self.get_axis(axis)?;
+        ax.get_attribute_array(attribute).await
+    }
+
+    async fn attribute_info(&self, axis: &str, attribute: &str) -> anyhow::Result<AttributeInfo> {
+        let ax = self.get_axis(axis)?;
+        ax.attribute_info(attribute).await
+    }
+
+    /// Owned copy of `available_attributes`, which is what the manager
+    /// reads, so the two always agree.
+    async fn get_available_attributes(&self, axis: &str) -> anyhow::Result<Vec<String>> {
+        let params = self.available_attributes(axis).await?;
+        Ok(params.into_iter().map(Cow::into_owned).collect())
+    }
+
+    /// Owned copy of `supported_movement_params`; see
+    /// `get_available_attributes`.
     async fn get_supported_movement_params(&self, axis: &str) -> anyhow::Result<Vec<String>> {
+        let params = self.supported_movement_params(axis).await?;
+        Ok(params.into_iter().map(Cow::into_owned).collect())
+    }
+
+    async fn available_attributes(&self, axis: &str) -> anyhow::Result<Vec<Cow<'static, str>>> {
+        let ax = self.get_axis(axis)?;
+        ax.available_params().await
+    }
+
+    async fn supported_movement_params(
+        &self,
+        axis: &str,
+    ) -> anyhow::Result<Vec<Cow<'static, str>>> {
+        let ax = self.get_axis(axis)?;
+        ax.supported_movement_params().await
+    }
+
+    async fn capabilities(&self, axis: &str) -> anyhow::Result<AxisCapabilities> {
+        let ax = self.get_axis(axis)?;
+        ax.capabilities().await
+    }
+
+    async fn get_limits(&self, axis: &str) -> anyhow::Result<AxisLimits> {
+        let ax = self.get_axis(axis)?;
+        ax.get_limits().await
+    }
+
+    async fn get_limit_switches(&self, axis: &str) -> anyhow::Result<LimitSwitches> {
         let ax = self.get_axis(axis)?;
-        ax.get_supported_movement_params().await
+        ax.get_limit_switches().await
     }
 }
@@ -1,94 +1,894 @@
-use crate::axis::movement_parameters::MovementParams;
+use crate::axis::{
+    limits::LimitDirection, movement_parameters::MovementParams, state::AxisState,
+    stop_mode::StopMode, target_kind::TargetKind,
+};
 
 use serde::{Deserialize, Serialize};
 
+/// A command as sent by a client. Commands addressing a controller may
+/// leave `controller` out once the connection has picked a default with
+/// `use_controller`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ClientCommand {
     #[serde(rename = "move")]
     Move {
-        controller: String,
+        #[serde(default)]
+        controller: Option<String>,
         axis: String,
         target: f64,
+        /// `percent` to give `target` as a percentage of the axis's travel.
+        #[serde(default)]
+        target_kind: TargetKind,
+        #[serde(default)]
+        params: Option<MovementParams>,
+        #[serde(default)]
+        dry_run: bool,
+        /// Reply only once the move has finished (the default). With `false`
+        /// the reply comes as soon as the move starts.
+        #[serde(default = "default_wait")]
+        wait: bool,
+        /// Move even if the axis is faulted, to recover it.
+        #[serde(default)]
+        force: bool,
+        /// Move even if the target is further from the current position
+        /// than the server's `max_move_distance`.
+        #[serde(default, rename = "override")]
+        override_distance: bool,
+        /// Send the axis's progress events, tagged with this command's `id`,
+        /// while the move runs and before its reply.
+        #[serde(default)]
+        stream_progress: bool,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "move_to_limit")]
+    MoveToLimit {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        direction: LimitDirection,
+        #[serde(default)]
+        params: Option<MovementParams>,
+        #[serde(default)]
+        zero_position: bool,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Moves every axis whose name matches `axis_pattern`, on any
+    /// controller, or `controller::axis` to match both names.
+    #[serde(rename = "move_all")]
+    MoveAll {
+        axis_pattern: String,
+        target: f64,
         #[serde(default)]
         params: Option<MovementParams>,
         #[serde(default)]
+        confirm: bool,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Moves every member of a group defined with `define_group` or in the
+    /// manager's configuration.
+    #[serde(rename = "move_group")]
+    MoveGroup {
+        group: String,
+        target: f64,
+        #[serde(default)]
+        params: Option<MovementParams>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "stop_group")]
+    StopGroup {
+        group: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Names a set of axes for `move_group` and `stop_group`, replacing any
+    /// earlier group of that name.
+    #[serde(rename = "define_group")]
+    DefineGroup {
+        group: String,
+        members: Vec<GroupMember>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "cancel")]
+    Cancel {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        #[serde(default)]
         id: Option<String>,
     },
     #[serde(rename = "stop")]
     Stop {
-        controller: String,
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        /// `"immediate"` (the default) or `{"decelerate": {"rate": ...}}`.
+        #[serde(default)]
+        mode: Option<StopMode>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Stops every axis of one controller.
+    #[serde(rename = "stop_controller")]
+    StopController {
+        #[serde(default)]
+        controller: Option<String>,
+        /// From a prior `prepare`, when the server requires confirmation.
+        #[serde(default)]
+        confirm_token: Option<String>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "pause")]
+    Pause {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "resume")]
+    Resume {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "enable")]
+    Enable {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "disable")]
+    Disable {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "reset_fault")]
+    ResetFault {
+        #[serde(default)]
+        controller: Option<String>,
         axis: String,
+        /// From a prior `prepare`, when the server requires confirmation.
+        #[serde(default)]
+        confirm_token: Option<String>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "set_reference")]
+    SetReference {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        value: f64,
         #[serde(default)]
         id: Option<String>,
     },
     #[serde(rename = "get_state")]
     GetState {
-        controller: String,
+        #[serde(default)]
+        controller: Option<String>,
         axis: String,
+        /// Bypass the cache and read the hardware directly.
+        #[serde(default)]
+        fresh: bool,
         #[serde(default)]
         id: Option<String>,
     },
     #[serde(rename = "get_position")]
     GetPosition {
-        controller: String,
+        #[serde(default)]
+        controller: Option<String>,
         axis: String,
         #[serde(default)]
+        fresh: bool,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "get_positions")]
+    GetPositions {
+        #[serde(default)]
+        controller: Option<String>,
+        axes: Vec<String>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "get_controller_attribute")]
+    GetControllerAttribute {
+        #[serde(default)]
+        controller: Option<String>,
+        attribute: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "controller_health")]
+    ControllerHealth {
+        #[serde(default)]
+        controller: Option<String>,
+        #[serde(default)]
         id: Option<String>,
     },
     #[serde(rename = "get_attribute")]
     GetAttribute {
-        controller: String,
+        #[serde(default)]
+        controller: Option<String>,
         axis: String,
         attribute: String,
         #[serde(default)]
         id: Option<String>,
     },
+    /// Reads an attribute holding several numbers, such as per-phase
+    /// currents, as a JSON array.
+    #[serde(rename = "get_attribute_array")]
+    GetAttributeArray {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        attribute: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "set_attribute")]
+    SetAttribute {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        attribute: String,
+        value: f64,
+        /// Read the attribute back after writing it and fail with
+        /// `VERIFY_FAILED` unless it is within `tolerance` of `value`.
+        #[serde(default)]
+        verify: bool,
+        #[serde(default)]
+        tolerance: Option<f64>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "get_attribute_info")]
+    GetAttributeInfo {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        attribute: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Replies once the axis is in `target_state`, or with `WAIT_TIMEOUT`
+    /// after `timeout_ms`.
+    #[serde(rename = "wait_for_state")]
+    WaitForState {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        target_state: AxisState,
+        timeout_ms: u64,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "get_history")]
+    GetHistory {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        #[serde(default)]
+        limit: Option<usize>,
+        #[serde(default)]
+        id: Option<String>,
+    },
     #[serde(rename = "get_available_params")]
     GetAvailableParams {
-        controller: String,
+        #[serde(default)]
+        controller: Option<String>,
         axis: String,
         #[serde(default)]
         id: Option<String>,
     },
     #[serde(rename = "get_supported_movement_params")]
     GetSupportedMovementParams {
-        controller: String,
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "get_capabilities")]
+    GetCapabilities {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "get_limits")]
+    GetLimits {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// The `min` and `max` positions the axis could reach within `time_ms`
+    /// at its velocity, clamped to its soft limits.
+    #[serde(rename = "reachable_range")]
+    ReachableRange {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        time_ms: u64,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Only the `upper` and `lower` limit switches, cheaper to poll than
+    /// `get_state`.
+    #[serde(rename = "get_limit_switches")]
+    GetLimitSwitches {
+        #[serde(default)]
+        controller: Option<String>,
         axis: String,
         #[serde(default)]
         id: Option<String>,
     },
+    #[serde(rename = "set_defaults")]
+    SetDefaults {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        params: MovementParams,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// The parameters a move would use, after axis defaults and safety
+    /// limits, without moving.
+    #[serde(rename = "resolve_params")]
+    ResolveParams {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        #[serde(default)]
+        params: Option<MovementParams>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "self_test")]
+    SelfTest {
+        #[serde(default)]
+        controller: Option<String>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "invalidate_cache")]
+    InvalidateCache {
+        #[serde(default)]
+        controller: Option<String>,
+        #[serde(default)]
+        axis: Option<String>,
+        #[serde(default)]
+        id: Option<String>,
+    },
     #[serde(rename = "list_controllers")]
     ListControllers {
         #[serde(default)]
         id: Option<String>,
     },
+    #[serde(rename = "list_aliases")]
+    ListAliases {
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "describe")]
+    Describe {
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "system_health")]
+    SystemHealth {
+        #[serde(default)]
+        id: Option<String>,
+    },
     #[serde(rename = "list_axes")]
     ListAxes {
+        #[serde(default)]
+        controller: Option<String>,
+        #[serde(default)]
+        detailed: bool,
+        #[serde(default)]
+        filter: Option<String>,
+        #[serde(default)]
+        offset: usize,
+        #[serde(default)]
+        limit: Option<usize>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Streams the events of one axis to this connection until it
+    /// unsubscribes or disconnects.
+    #[serde(rename = "subscribe")]
+    Subscribe {
+        #[serde(default)]
+        controller: Option<String>,
+        axis: String,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Switches the connection to binary position frames for `axes`,
+    /// sampled every `interval_ms`; see `socket_server::binary_stream`.
+    #[serde(rename = "subscribe_binary")]
+    SubscribeBinary {
+        #[serde(default)]
+        controller: Option<String>,
+        axes: Vec<String>,
+        #[serde(default = "default_interval_ms")]
+        interval_ms: u64,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Negotiates optional protocol features for this connection: the
+    /// `compression` algorithms the client can decode, in order of
+    /// preference. See `socket_server::compression`.
+    #[serde(rename = "hello")]
+    Hello {
+        #[serde(default)]
+        compression: Vec<String>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Asks for a `confirm_token` to send a destructive `command` with, on
+    /// servers that require confirmation; see `is_destructive`.
+    #[serde(rename = "prepare")]
+    Prepare {
+        command: Box<ClientCommand>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Makes `controller` the default for the rest of the connection.
+    #[serde(rename = "use_controller")]
+    UseController {
         controller: String,
         #[serde(default)]
         id: Option<String>,
     },
+    #[serde(rename = "unsubscribe")]
+    Unsubscribe {
+        subscription: u64,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Version, transport, enabled features and limits of the server.
+    #[serde(rename = "server_info")]
+    ServerInfo {
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// The rate, connection and subscription limits a client should stay
+    /// under. Not to be confused with `get_limits`, an axis's travel range.
+    #[serde(rename = "limits")]
+    Limits {
+        #[serde(default)]
+        id: Option<String>,
+    },
     #[serde(rename = "ping")]
     Ping {
         #[serde(default)]
         id: Option<String>,
     },
+    /// Carries `token` in plain-token mode, or `hmac` answering the
+    /// connection's challenge in challenge mode.
+    #[serde(rename = "auth")]
+    Auth {
+        #[serde(default)]
+        token: Option<String>,
+        #[serde(default)]
+        hmac: Option<String>,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// The server's live connections. Admin only.
+    #[serde(rename = "list_connections")]
+    ListConnections {
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// How many socket subscriptions each axis has, and from which
+    /// connections. Admin only.
+    #[serde(rename = "list_subscriptions")]
+    ListSubscriptions {
+        #[serde(default)]
+        id: Option<String>,
+    },
+    /// Closes another connection, by the id `list_connections` reports.
+    /// Admin only.
+    #[serde(rename = "disconnect")]
+    Disconnect {
+        connection_id: u64,
+        #[serde(default)]
+        id: Option<String>,
+    },
+    #[serde(rename = "metrics")]
+    Metrics {
+        #[serde(default)]
+        id: Option<String>,
+    },
+}
+
+/// One axis of a group, as `define_group` takes it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMember {
+    pub controller: String,
+    pub axis: String,
+}
+
+fn default_wait() -> bool {
+    true
+}
+
+fn default_interval_ms() -> u64 {
+    10
 }
 
 impl ClientCommand {
     pub fn id(&self) -> Option<&String> {
         match self {
             ClientCommand::Move { id, .. } => id.as_ref(),
+            ClientCommand::MoveToLimit { id, .. } => id.as_ref(),
+            ClientCommand::MoveAll { id, .. } => id.as_ref(),
+            ClientCommand::MoveGroup { id, .. } => id.as_ref(),
+            ClientCommand::StopGroup { id, .. } => id.as_ref(),
+            ClientCommand::DefineGroup { id, .. } => id.as_ref(),
+            ClientCommand::Cancel { id, .. } => id.as_ref(),
             ClientCommand::Stop { id, .. } => id.as_ref(),
+            ClientCommand::StopController { id, .. } => id.as_ref(),
+            ClientCommand::Pause { id, .. } => id.as_ref(),
+            ClientCommand::Resume { id, .. } => id.as_ref(),
+            ClientCommand::Enable { id, .. } => id.as_ref(),
+            ClientCommand::Disable { id, .. } => id.as_ref(),
+            ClientCommand::ResetFault { id, .. } => id.as_ref(),
+            ClientCommand::SetReference { id, .. } => id.as_ref(),
+            ClientCommand::SetAttribute { id, .. } => id.as_ref(),
             ClientCommand::GetState { id, .. } => id.as_ref(),
             ClientCommand::GetPosition { id, .. } => id.as_ref(),
+            ClientCommand::GetPositions { id, .. } => id.as_ref(),
+            ClientCommand::ControllerHealth { id, .. } => id.as_ref(),
             ClientCommand::GetAttribute { id, .. } => id.as_ref(),
+            ClientCommand::GetAttributeArray { id, .. } => id.as_ref(),
+            ClientCommand::GetControllerAttribute { id, .. } => id.as_ref(),
+            ClientCommand::GetAttributeInfo { id, .. } => id.as_ref(),
+            ClientCommand::WaitForState { id, .. } => id.as_ref(),
+            ClientCommand::GetHistory { id, .. } => id.as_ref(),
             ClientCommand::GetAvailableParams { id, .. } => id.as_ref(),
             ClientCommand::GetSupportedMovementParams { id, .. } => id.as_ref(),
+            ClientCommand::GetCapabilities { id, .. } => id.as_ref(),
+            ClientCommand::GetLimits { id, .. } => id.as_ref(),
+            ClientCommand::ReachableRange { id, .. } => id.as_ref(),
+            ClientCommand::GetLimitSwitches { id, .. } => id.as_ref(),
+            ClientCommand::SetDefaults { id, .. } => id.as_ref(),
+            ClientCommand::ResolveParams { id, .. } => id.as_ref(),
+            ClientCommand::SelfTest { id, .. } => id.as_ref(),
+            ClientCommand::InvalidateCache { id, .. } => id.as_ref(),
             ClientCommand::ListControllers { id, .. } => id.as_ref(),
+            ClientCommand::ListAliases { id, .. } => id.as_ref(),
+            ClientCommand::Describe { id, .. } => id.as_ref(),
+            ClientCommand::SystemHealth { id, .. } => id.as_ref(),
             ClientCommand::ListAxes { id, .. } => id.as_ref(),
+            ClientCommand::Subscribe { id, .. } => id.as_ref(),
+            ClientCommand::SubscribeBinary { id, .. } => id.as_ref(),
+            ClientCommand::Hello { id, .. } => id.as_ref(),
+            ClientCommand::Prepare { id, .. } => id.as_ref(),
+            ClientCommand::UseController { id, .. } => id.as_ref(),
+            ClientCommand::Unsubscribe { id, .. } => id.as_ref(),
+            ClientCommand::ServerInfo { id, .. } => id.as_ref(),
+            ClientCommand::Limits { id, .. } => id.as_ref(),
             ClientCommand::Ping { id, .. } => id.as_ref(),
+            ClientCommand::Auth { id, .. } => id.as_ref(),
+            ClientCommand::ListConnections { id, .. } => id.as_ref(),
+            ClientCommand::ListSubscriptions { id, .. } => id.as_ref(),
+            ClientCommand::Disconnect { id, .. } => id.as_ref(),
+            ClientCommand::Metrics { id, .. } => id.as_ref(),
+        }
+    }
+
+    /// The wire name of the command, as used in its `type` field.
+    pub fn wire_name(&self) -> &'static str {
+        match self {
+            ClientCommand::Move { .. } => "move",
+            ClientCommand::MoveToLimit { .. } => "move_to_limit",
+            ClientCommand::MoveAll { .. } => "move_all",
+            ClientCommand::MoveGroup { .. } => "move_group",
+            ClientCommand::StopGroup { .. } => "stop_group",
+            ClientCommand::DefineGroup { .. } => "define_group",
+            ClientCommand::Cancel { .. } => "cancel",
+            ClientCommand::Stop { .. } => "stop",
+            ClientCommand::StopController { .. } => "stop_controller",
+            ClientCommand::Pause { .. } => "pause",
+            ClientCommand::Resume { .. } => "resume",
+            ClientCommand::Enable { .. } => "enable",
+            ClientCommand::Disable { .. } => "disable",
+            ClientCommand::ResetFault { .. } => "reset_fault",
+            ClientCommand::SetReference { .. } => "set_reference",
+            ClientCommand::SetAttribute { .. } => "set_attribute",
+            ClientCommand::GetState { .. } => "get_state",
+            ClientCommand::GetPosition { .. } => "get_position",
+            ClientCommand::GetPositions { .. } => "get_positions",
+            ClientCommand::ControllerHealth { .. } => "controller_health",
+            ClientCommand::GetAttribute { .. } => "get_attribute",
+            ClientCommand::GetAttributeArray { .. } => "get_attribute_array",
+            ClientCommand::GetControllerAttribute { .. } => "get_controller_attribute",
+            ClientCommand::GetAttributeInfo { .. } => "get_attribute_info",
+            ClientCommand::WaitForState { .. } => "wait_for_state",
+            ClientCommand::GetHistory { .. } => "get_history",
+            ClientCommand::GetAvailableParams { .. } => "get_available_params",
+            ClientCommand::GetSupportedMovementParams { .. } => "get_supported_movement_params",
+            ClientCommand::GetCapabilities { .. } => "get_capabilities",
+            ClientCommand::GetLimits { .. } => "get_limits",
+            ClientCommand::ReachableRange { .. } => "reachable_range",
+            ClientCommand::GetLimitSwitches { .. } => "get_limit_switches",
+            ClientCommand::SetDefaults { .. } => "set_defaults",
+            ClientCommand::ResolveParams { .. } => "resolve_params",
+            ClientCommand::SelfTest { .. } => "self_test",
+            ClientCommand::InvalidateCache { .. } => "invalidate_cache",
+            ClientCommand::ListControllers { .. } => "list_controllers",
+            ClientCommand::ListAliases { .. } => "list_aliases",
+            ClientCommand::Describe { .. } => "describe",
+            ClientCommand::SystemHealth { .. } => "system_health",
+            ClientCommand::ListAxes { .. } => "list_axes",
+            ClientCommand::Subscribe { .. } => "subscribe",
+            ClientCommand::SubscribeBinary { .. } => "subscribe_binary",
+            ClientCommand::Hello { .. } => "hello",
+            ClientCommand::Prepare { .. } => "prepare",
+            ClientCommand::UseController { .. } => "use_controller",
+            ClientCommand::Unsubscribe { .. } => "unsubscribe",
+            ClientCommand::ServerInfo { .. } => "server_info",
+            ClientCommand::Limits { .. } => "limits",
+            ClientCommand::Ping { .. } => "ping",
+            ClientCommand::Auth { .. } => "auth",
+            ClientCommand::ListConnections { .. } => "list_connections",
+            ClientCommand::ListSubscriptions { .. } => "list_subscriptions",
+            ClientCommand::Disconnect { .. } => "disconnect",
+            ClientCommand::Metrics { .. } => "metrics",
+        }
+    }
+
+    /// The controller the command addresses, if any.
+    pub fn controller(&self) -> Option<&str> {
+        match self {
+            ClientCommand::Move { controller, .. }
+            | ClientCommand::MoveToLimit { controller, .. }
+            | ClientCommand::Cancel { controller, .. }
+            | ClientCommand::Stop { controller, .. }
+            | ClientCommand::StopController { controller, .. }
+            | ClientCommand::Pause { controller, .. }
+            | ClientCommand::Resume { controller, .. }
+            | ClientCommand::Enable { controller, .. }
+            | ClientCommand::Disable { controller, .. }
+            | ClientCommand::ResetFault { controller, .. }
+            | ClientCommand::SetReference { controller, .. }
+            | ClientCommand::SetAttribute { controller, .. }
+            | ClientCommand::GetState { controller, .. }
+            | ClientCommand::GetPosition { controller, .. }
+            | ClientCommand::GetPositions { controller, .. }
+            | ClientCommand::ControllerHealth { controller, .. }
+            | ClientCommand::GetAttribute { controller, .. }
+            | ClientCommand::GetAttributeArray { controller, .. }
+            | ClientCommand::GetControllerAttribute { controller, .. }
+            | ClientCommand::GetAttributeInfo { controller, .. }
+            | ClientCommand::WaitForState { controller, .. }
+            | ClientCommand::GetHistory { controller, .. }
+            | ClientCommand::GetAvailableParams { controller, .. }
+            | ClientCommand::GetSupportedMovementParams { controller, .. }
+            | ClientCommand::GetCapabilities { controller, .. }
+            | ClientCommand::GetLimits { controller, .. }
+            | ClientCommand::ReachableRange { controller, .. }
+            | ClientCommand::GetLimitSwitches { controller, .. }
+            | ClientCommand::SetDefaults { controller, .. }
+            | ClientCommand::ResolveParams { controller, .. }
+            | ClientCommand::SelfTest { controller, .. }
+            | ClientCommand::InvalidateCache { controller, .. }
+            | ClientCommand::ListAxes { controller, .. }
+            | ClientCommand::Subscribe { controller, .. }
+            | ClientCommand::SubscribeBinary { controller, .. } => controller.as_deref(),
+            ClientCommand::UseController { controller, .. } => Some(controller),
+            ClientCommand::MoveAll { .. }
+            | ClientCommand::MoveGroup { .. }
+            | ClientCommand::StopGroup { .. }
+            | ClientCommand::DefineGroup { .. }
+            | ClientCommand::ListControllers { .. }
+            | ClientCommand::ListAliases { .. }
+            | ClientCommand::Describe { .. }
+            | ClientCommand::SystemHealth { .. }
+            | ClientCommand::Hello { .. }
+            | ClientCommand::Prepare { .. }
+            | ClientCommand::Unsubscribe { .. }
+            | ClientCommand::ServerInfo { .. }
+            | ClientCommand::Limits { .. }
+            | ClientCommand::Ping { .. }
+            | ClientCommand::Auth { .. }
+            | ClientCommand::ListConnections { .. }
+            | ClientCommand::ListSubscriptions { .. }
+            | ClientCommand::Disconnect { .. }
+            | ClientCommand::Metrics { .. } => None,
+        }
+    }
+
+    /// The `controller` field of the commands that may omit it, to fill in
+    /// from the connection's default.
+    pub fn controller_mut(&mut self) -> Option<&mut Option<String>> {
+        match self {
+            ClientCommand::Move { controller, .. }
+            | ClientCommand::MoveToLimit { controller, .. }
+            | ClientCommand::Cancel { controller, .. }
+            | ClientCommand::Stop { controller, .. }
+            | ClientCommand::StopController { controller, .. }
+            | ClientCommand::Pause { controller, .. }
+            | ClientCommand::Resume { controller, .. }
+            | ClientCommand::Enable { controller, .. }
+            | ClientCommand::Disable { controller, .. }
+            | ClientCommand::ResetFault { controller, .. }
+            | ClientCommand::SetReference { controller, .. }
+            | ClientCommand::SetAttribute { controller, .. }
+            | ClientCommand::GetState { controller, .. }
+            | ClientCommand::GetPosition { controller, .. }
+            | ClientCommand::GetPositions { controller, .. }
+            | ClientCommand::ControllerHealth { controller, .. }
+            | ClientCommand::GetAttribute { controller, .. }
+            | ClientCommand::GetAttributeArray { controller, .. }
+            | ClientCommand::GetControllerAttribute { controller, .. }
+            | ClientCommand::GetAttributeInfo { controller, .. }
+            | ClientCommand::WaitForState { controller, .. }
+            | ClientCommand::GetHistory { controller, .. }
+            | ClientCommand::GetAvailableParams { controller, .. }
+            | ClientCommand::GetSupportedMovementParams { controller, .. }
+            | ClientCommand::GetCapabilities { controller, .. }
+            | ClientCommand::GetLimits { controller, .. }
+            | ClientCommand::ReachableRange { controller, .. }
+            | ClientCommand::GetLimitSwitches { controller, .. }
+            | ClientCommand::SetDefaults { controller, .. }
+            | ClientCommand::ResolveParams { controller, .. }
+            | ClientCommand::SelfTest { controller, .. }
+            | ClientCommand::InvalidateCache { controller, .. }
+            | ClientCommand::ListAxes { controller, .. }
+            | ClientCommand::Subscribe { controller, .. }
+            | ClientCommand::SubscribeBinary { controller, .. } => Some(controller),
+            ClientCommand::MoveAll { .. }
+            | ClientCommand::MoveGroup { .. }
+            | ClientCommand::StopGroup { .. }
+            | ClientCommand::DefineGroup { .. }
+            | ClientCommand::ListControllers { .. }
+            | ClientCommand::ListAliases { .. }
+            | ClientCommand::Describe { .. }
+            | ClientCommand::SystemHealth { .. }
+            | ClientCommand::Hello { .. }
+            | ClientCommand::Prepare { .. }
+            | ClientCommand::UseController { .. }
+            | ClientCommand::Unsubscribe { .. }
+            | ClientCommand::ServerInfo { .. }
+            | ClientCommand::Limits { .. }
+            | ClientCommand::Ping { .. }
+            | ClientCommand::Auth { .. }
+            | ClientCommand::ListConnections { .. }
+            | ClientCommand::ListSubscriptions { .. }
+            | ClientCommand::Disconnect { .. }
+            | ClientCommand::Metrics { .. } => None,
         }
     }
+
+    /// The axis the command addresses, if any.
+    pub fn axis(&self) -> Option<&str> {
+        match self {
+            ClientCommand::Move { axis, .. }
+            | ClientCommand::MoveToLimit { axis, .. }
+            | ClientCommand::Cancel { axis, .. }
+            | ClientCommand::Stop { axis, .. }
+            | ClientCommand::Pause { axis, .. }
+            | ClientCommand::Resume { axis, .. }
+            | ClientCommand::Enable { axis, .. }
+            | ClientCommand::Disable { axis, .. }
+            | ClientCommand::ResetFault { axis, .. }
+            | ClientCommand::SetReference { axis, .. }
+            | ClientCommand::SetAttribute { axis, .. }
+            | ClientCommand::GetState { axis, .. }
+            | ClientCommand::GetPosition { axis, .. }
+            | ClientCommand::GetAttribute { axis, .. }
+            | ClientCommand::GetAttributeArray { axis, .. }
+            | ClientCommand::GetAttributeInfo { axis, .. }
+            | ClientCommand::WaitForState { axis, .. }
+            | ClientCommand::GetHistory { axis, .. }
+            | ClientCommand::GetAvailableParams { axis, .. }
+            | ClientCommand::GetSupportedMovementParams { axis, .. }
+            | ClientCommand::GetCapabilities { axis, .. }
+            | ClientCommand::GetLimits { axis, .. }
+            | ClientCommand::ReachableRange { axis, .. }
+            | ClientCommand::GetLimitSwitches { axis, .. }
+            | ClientCommand::SetDefaults { axis, .. }
+            | ClientCommand::ResolveParams { axis, .. }
+            | ClientCommand::Subscribe { axis, .. } => Some(axis),
+            ClientCommand::InvalidateCache { axis, .. } => axis.as_deref(),
+            ClientCommand::MoveAll { .. }
+            | ClientCommand::MoveGroup { .. }
+            | ClientCommand::StopGroup { .. }
+            | ClientCommand::DefineGroup { .. }
+            | ClientCommand::StopController { .. }
+            | ClientCommand::ListControllers { .. }
+            | ClientCommand::ListAliases { .. }
+            | ClientCommand::Describe { .. }
+            | ClientCommand::SystemHealth { .. }
+            | ClientCommand::ListAxes { .. }
+            | ClientCommand::GetPositions { .. }
+            | ClientCommand::ControllerHealth { .. }
+            | ClientCommand::GetControllerAttribute { .. }
+            | ClientCommand::SelfTest { .. }
+            | ClientCommand::SubscribeBinary { .. }
+            | ClientCommand::Hello { .. }
+            | ClientCommand::Prepare { .. }
+            | ClientCommand::UseController { .. }
+            | ClientCommand::Unsubscribe { .. }
+            | ClientCommand::ServerInfo { .. }
+            | ClientCommand::Limits { .. }
+            | ClientCommand::Ping { .. }
+            | ClientCommand::Auth { .. }
+            | ClientCommand::ListConnections { .. }
+            | ClientCommand::ListSubscriptions { .. }
+            | ClientCommand::Disconnect { .. }
+            | ClientCommand::Metrics { .. } => None,
+        }
+    }
+
+    /// The axes listed by a command addressing several at once.
+    pub fn axes(&self) -> Option<&[String]> {
+        match self {
+            ClientCommand::GetPositions { axes, .. }
+            | ClientCommand::SubscribeBinary { axes, .. } => Some(axes),
+            _ => None,
+        }
+    }
+
+    /// Whether the command is disruptive enough that a server configured
+    /// with `confirm_token_ttl` only runs it with a `confirm_token`.
+    pub fn is_destructive(&self) -> bool {
+        matches!(
+            self,
+            ClientCommand::StopController { .. } | ClientCommand::ResetFault { .. }
+        )
+    }
+
+    pub fn confirm_token(&self) -> Option<&str> {
+        match self {
+            ClientCommand::StopController { confirm_token, .. }
+            | ClientCommand::ResetFault { confirm_token, .. } => confirm_token.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Whether the command can put hardware in motion. Such commands are
+    /// subject to per-connection rate limiting; reads and pings are not.
+    pub fn is_motion(&self) -> bool {
+        matches!(
+            self,
+            ClientCommand::Move { .. }
+                | ClientCommand::MoveToLimit { .. }
+                | ClientCommand::MoveAll { .. }
+                | ClientCommand::MoveGroup { .. }
+                | ClientCommand::StopGroup { .. }
+                | ClientCommand::Cancel { .. }
+                | ClientCommand::Stop { .. }
+                | ClientCommand::StopController { .. }
+                | ClientCommand::Pause { .. }
+                | ClientCommand::Resume { .. }
+                | ClientCommand::Enable { .. }
+                | ClientCommand::Disable { .. }
+                | ClientCommand::ResetFault { .. }
+                | ClientCommand::SetReference { .. }
+                | ClientCommand::SetAttribute { .. }
+        )
+    }
 }
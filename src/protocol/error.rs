@@ -1,17 +1,56 @@
 #[derive(Debug)]
 pub enum ProtocolError {
-    InvalidJson(String),
+    /// Where the error was found is known for syntax errors, not for
+    /// well-formed JSON that doesn't fit the command.
+    InvalidJson {
+        message: String,
+        line: Option<usize>,
+        column: Option<usize>,
+    },
     UnsupportedCommand(String),
     MissingField(String),
+    /// A field the command doesn't have, refused in strict parsing.
+    UnknownField(String),
     IoError(std::io::Error),
 }
 
+impl ProtocolError {
+    /// Machine-readable code sent to the client alongside the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ProtocolError::InvalidJson { .. } => "PARSE_ERROR",
+            ProtocolError::UnsupportedCommand(_) => "UNSUPPORTED_COMMAND",
+            ProtocolError::MissingField(_) => "MISSING_FIELD",
+            ProtocolError::UnknownField(_) => "UNKNOWN_FIELD",
+            ProtocolError::IoError(_) => "IO_ERROR",
+        }
+    }
+
+    /// What the client needs to find the problem, sent alongside the code:
+    /// the `line` and `column` of a syntax error, or the missing or unknown
+    /// `field`.
+    pub fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            ProtocolError::InvalidJson {
+                line: Some(line),
+                column,
+                ..
+            } => Some(serde_json::json!({"line": line, "column": column})),
+            ProtocolError::MissingField(field) | ProtocolError::UnknownField(field) => {
+                Some(serde_json::json!({"field": field}))
+            }
+            _ => None,
+        }
+    }
+}
+
 impl std::fmt::Display for ProtocolError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ProtocolError::InvalidJson(msg) => write!(f, "Invalid JSON: {}", msg),
+            ProtocolError::InvalidJson { message, .. } => write!(f, "Invalid JSON: {}", message),
             ProtocolError::UnsupportedCommand(cmd) => write!(f, "Unsupported command: {}", cmd),
             ProtocolError::MissingField(field) => write!(f, "Missing required field: {}", field),
+            ProtocolError::UnknownField(field) => write!(f, "Unknown field: {}", field),
             ProtocolError::IoError(err) => write!(f, "IO error: {}", err),
         }
     }
@@ -21,7 +60,13 @@ impl std::error::Error for ProtocolError {}
 
 impl From<serde_json::Error> for ProtocolError {
     fn from(err: serde_json::Error) -> Self {
-        ProtocolError::InvalidJson(err.to_string())
+        // serde_json reports line 0 when the error has no position.
+        let position = (err.line() > 0).then(|| (err.line(), err.column()));
+        ProtocolError::InvalidJson {
+            message: err.to_string(),
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+        }
     }
 }
 
@@ -30,3 +75,37 @@ impl From<std::io::Error> for ProtocolError {
         ProtocolError::IoError(err)
     }
 }
+
+/// An error carrying a machine-readable code for the client, e.g.
+/// `INVALID_PARAMS`. Handlers return it inside `anyhow::Error`; the socket
+/// layer recovers the code with `downcast_ref`.
+#[derive(Debug, Clone)]
+pub struct CommandError {
+    pub code: &'static str,
+    pub message: String,
+    /// Sent to the client as the response's `details`.
+    pub details: Option<serde_json::Value>,
+}
+
+impl CommandError {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CommandError {}
@@ -8,8 +8,73 @@ use server_response::ServerResponse;
 
 use anyhow::Result;
 
+/// Version of the wire protocol, reported by `server_info`. Bumped when a
+/// change would break existing clients.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Parses a command line. Syntax errors, unknown command types and missing
+/// fields are reported as distinct `ProtocolError` variants.
 pub fn parse_command(json_str: &str) -> Result<ClientCommand, ProtocolError> {
-    serde_json::from_str(json_str).map_err(ProtocolError::from)
+    command_from_value(serde_json::from_str(json_str)?)
+}
+
+/// Like `parse_command`, also returning the fields of `json_str` that the
+/// command has no use for, such as a misspelt `velocity`, as dotted paths.
+/// serde drops those silently, so they are found by comparing the input
+/// with the parsed command serialized back.
+pub fn parse_command_checked(
+    json_str: &str,
+) -> Result<(ClientCommand, Vec<String>), ProtocolError> {
+    let value: serde_json::Value = serde_json::from_str(json_str)?;
+    let command = command_from_value(value.clone())?;
+    let mut unknown = Vec::new();
+    collect_unknown(&value, &serde_json::to_value(&command)?, "", &mut unknown);
+    Ok((command, unknown))
+}
+
+fn collect_unknown(
+    sent: &serde_json::Value,
+    parsed: &serde_json::Value,
+    prefix: &str,
+    unknown: &mut Vec<String>,
+) {
+    let (serde_json::Value::Object(sent), serde_json::Value::Object(parsed)) = (sent, parsed)
+    else {
+        return;
+    };
+    for (key, value) in sent {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match parsed.get(key) {
+            Some(parsed) => collect_unknown(value, parsed, &path, unknown),
+            None => unknown.push(path),
+        }
+    }
+}
+
+fn command_from_value(value: serde_json::Value) -> Result<ClientCommand, ProtocolError> {
+    let command_type = value.get("type").and_then(|t| t.as_str()).map(String::from);
+
+    serde_json::from_value(value).map_err(|err| {
+        // serde only exposes these cases through its messages. An unknown
+        // variant is only the command's if it is the `type` itself, rather
+        // than a bad value in an enum field such as `direction`.
+        let msg = err.to_string();
+        let command_type = command_type.unwrap_or_default();
+        if msg.starts_with(&format!("unknown variant `{}`", command_type)) {
+            ProtocolError::UnsupportedCommand(command_type)
+        } else if let Some(field) = msg
+            .strip_prefix("missing field `")
+            .and_then(|rest| rest.split('`').next())
+        {
+            ProtocolError::MissingField(field.to_string())
+        } else {
+            ProtocolError::from(err)
+        }
+    })
 }
 
 pub fn serialize_response(response: &ServerResponse) -> Result<String, ProtocolError> {
@@ -19,6 +84,7 @@ pub fn serialize_response(response: &ServerResponse) -> Result<String, ProtocolE
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::axis::stop_mode::StopMode;
 
     #[test]
     fn test_parse_move_command() {
@@ -32,7 +98,7 @@ mod tests {
                 target,
                 ..
             } => {
-                assert_eq!(controller, "test");
+                assert_eq!(controller.as_deref(), Some("test"));
                 assert_eq!(axis, "X");
                 assert_eq!(target, 100.0);
             }
@@ -63,4 +129,268 @@ mod tests {
         assert!(json.contains("error"));
         assert!(json.contains("Something went wrong"));
     }
+
+    #[test]
+    fn test_parse_errors_are_classified() {
+        let err = parse_command(r#"{"type": "move", "#).unwrap_err();
+        assert!(matches!(err, ProtocolError::InvalidJson { .. }));
+
+        let err = parse_command(r#"{"type": "teleport"}"#).unwrap_err();
+        assert!(matches!(err, ProtocolError::UnsupportedCommand(ref t) if t == "teleport"));
+
+        let err = parse_command(r#"{"type": "stop", "controller": "c"}"#).unwrap_err();
+        assert!(matches!(err, ProtocolError::MissingField(ref f) if f == "axis"));
+
+        // Left to the connection's default controller.
+        let cmd = parse_command(r#"{"type": "stop", "axis": "X"}"#).unwrap();
+        assert_eq!(cmd.controller(), None);
+
+        let err = parse_command(r#"{"controller": "c"}"#).unwrap_err();
+        assert!(matches!(err, ProtocolError::MissingField(ref f) if f == "type"));
+    }
+
+    #[test]
+    fn test_parse_errors_locate_the_problem() {
+        let err = parse_command(r#"{"type": "move", "controller": "c", "axis": "X"}"#).unwrap_err();
+        assert!(matches!(err, ProtocolError::MissingField(ref f) if f == "target"));
+        assert_eq!(err.details(), Some(serde_json::json!({"field": "target"})));
+
+        let err = parse_command("{\"type\": \"move\",\n \"target\": 1.0,,}").unwrap_err();
+        assert_eq!(
+            err.details(),
+            Some(serde_json::json!({"line": 2, "column": 16}))
+        );
+
+        let err =
+            parse_command(r#"{"type": "move", "controller": "c", "axis": "X", "target": "far"}"#)
+                .unwrap_err();
+        assert!(matches!(err, ProtocolError::InvalidJson { line: None, .. }));
+        assert_eq!(err.details(), None);
+    }
+
+    #[test]
+    fn test_parse_stop_modes() {
+        let mode = |line: &str| match parse_command(line).unwrap() {
+            ClientCommand::Stop { mode, .. } => mode,
+            _ => panic!("Expected Stop command"),
+        };
+        assert_eq!(
+            mode(r#"{"type": "stop", "controller": "c", "axis": "X"}"#),
+            None
+        );
+        assert_eq!(
+            mode(r#"{"type": "stop", "controller": "c", "axis": "X", "mode": "immediate"}"#),
+            Some(StopMode::Immediate)
+        );
+        assert_eq!(
+            mode(
+                r#"{"type": "stop", "controller": "c", "axis": "X", "mode": {"decelerate": {"rate": 50.0}}}"#
+            ),
+            Some(StopMode::Decelerate { rate: Some(50.0) })
+        );
+    }
+
+    #[test]
+    fn test_wire_name_matches_type() {
+        let lines = [
+            r#"{"type": "move", "controller": "c", "axis": "X", "target": 1.0}"#,
+            r#"{"type": "get_attribute", "controller": "c", "axis": "X", "attribute": "a"}"#,
+            r#"{"type": "invalidate_cache", "controller": "c"}"#,
+            r#"{"type": "ping"}"#,
+            r#"{"type": "server_info"}"#,
+        ];
+        for line in lines {
+            let cmd = parse_command(line).unwrap();
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(cmd.wire_name(), value["type"]);
+            assert_eq!(cmd.controller(), value["controller"].as_str());
+            assert_eq!(cmd.axis(), value["axis"].as_str());
+        }
+    }
+
+    /// Which variant `command` is. The match is exhaustive, so a new variant
+    /// won't compile until it has been given a sample below.
+    fn variant_index(command: &ClientCommand) -> usize {
+        match command {
+            ClientCommand::Move { .. } => 0,
+            ClientCommand::MoveToLimit { .. } => 1,
+            ClientCommand::MoveAll { .. } => 2,
+            ClientCommand::Cancel { .. } => 3,
+            ClientCommand::Stop { .. } => 4,
+            ClientCommand::Pause { .. } => 5,
+            ClientCommand::Resume { .. } => 6,
+            ClientCommand::Enable { .. } => 7,
+            ClientCommand::Disable { .. } => 8,
+            ClientCommand::ResetFault { .. } => 9,
+            ClientCommand::SetReference { .. } => 10,
+            ClientCommand::GetState { .. } => 11,
+            ClientCommand::GetPosition { .. } => 12,
+            ClientCommand::GetPositions { .. } => 13,
+            ClientCommand::ControllerHealth { .. } => 14,
+            ClientCommand::GetAttribute { .. } => 15,
+            ClientCommand::GetControllerAttribute { .. } => 16,
+            ClientCommand::GetAttributeInfo { .. } => 17,
+            ClientCommand::WaitForState { .. } => 18,
+            ClientCommand::GetHistory { .. } => 19,
+            ClientCommand::GetAvailableParams { .. } => 20,
+            ClientCommand::GetSupportedMovementParams { .. } => 21,
+            ClientCommand::GetCapabilities { .. } => 22,
+            ClientCommand::GetLimits { .. } => 23,
+            ClientCommand::SetDefaults { .. } => 24,
+            ClientCommand::ResolveParams { .. } => 25,
+            ClientCommand::SelfTest { .. } => 26,
+            ClientCommand::InvalidateCache { .. } => 27,
+            ClientCommand::ListControllers { .. } => 28,
+            ClientCommand::ListAliases { .. } => 29,
+            ClientCommand::Describe { .. } => 30,
+            ClientCommand::ListAxes { .. } => 31,
+            ClientCommand::Subscribe { .. } => 32,
+            ClientCommand::SubscribeBinary { .. } => 33,
+            ClientCommand::Unsubscribe { .. } => 34,
+            ClientCommand::ServerInfo { .. } => 35,
+            ClientCommand::Ping { .. } => 36,
+            ClientCommand::Auth { .. } => 37,
+            ClientCommand::ListConnections { .. } => 38,
+            ClientCommand::Disconnect { .. } => 39,
+            ClientCommand::Metrics { .. } => 40,
+            ClientCommand::SetAttribute { .. } => 41,
+            ClientCommand::UseController { .. } => 42,
+            ClientCommand::StopController { .. } => 43,
+            ClientCommand::Hello { .. } => 44,
+            ClientCommand::GetLimitSwitches { .. } => 45,
+            ClientCommand::SystemHealth { .. } => 46,
+            ClientCommand::GetAttributeArray { .. } => 47,
+            ClientCommand::Prepare { .. } => 48,
+            ClientCommand::ListSubscriptions { .. } => 49,
+            ClientCommand::Limits { .. } => 50,
+            ClientCommand::MoveGroup { .. } => 51,
+            ClientCommand::StopGroup { .. } => 52,
+            ClientCommand::DefineGroup { .. } => 53,
+            ClientCommand::ReachableRange { .. } => 54,
+        }
+    }
+
+    #[test]
+    fn test_every_command_round_trips() {
+        let samples = [
+            r#"{"type": "move", "controller": "c", "axis": "X", "target": 75.0, "target_kind": "percent"}"#,
+            r#"{"type": "move_to_limit", "controller": "c", "axis": "X", "direction": "upper"}"#,
+            r#"{"type": "move_all", "axis_pattern": "*", "target": 0.0}"#,
+            r#"{"type": "move_group", "group": "g", "target": 1.0, "params": {"velocity": 10.0}}"#,
+            r#"{"type": "stop_group", "group": "g"}"#,
+            r#"{"type": "define_group", "group": "g", "members": [{"controller": "c", "axis": "X"}]}"#,
+            r#"{"type": "cancel", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "stop", "controller": "c", "axis": "X", "mode": {"decelerate": {"rate": 5.0}}}"#,
+            r#"{"type": "stop_controller", "controller": "c"}"#,
+            r#"{"type": "pause", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "resume", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "enable", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "disable", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "reset_fault", "controller": "c", "axis": "X", "confirm_token": "t"}"#,
+            r#"{"type": "set_reference", "controller": "c", "axis": "X", "value": 0.0}"#,
+            r#"{"type": "get_state", "controller": "c", "axis": "X", "fresh": true}"#,
+            r#"{"type": "get_position", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "get_positions", "controller": "c", "axes": ["X", "Y"]}"#,
+            r#"{"type": "controller_health", "controller": "c"}"#,
+            r#"{"type": "get_attribute", "controller": "c", "axis": "X", "attribute": "a"}"#,
+            r#"{"type": "get_attribute_array", "controller": "c", "axis": "X", "attribute": "a"}"#,
+            r#"{"type": "get_controller_attribute", "controller": "c", "attribute": "a"}"#,
+            r#"{"type": "set_attribute", "controller": "c", "axis": "X", "attribute": "a", "value": 2.0, "verify": true}"#,
+            r#"{"type": "get_attribute_info", "controller": "c", "axis": "X", "attribute": "a"}"#,
+            r#"{"type": "wait_for_state", "controller": "c", "axis": "X", "target_state": "on", "timeout_ms": 100}"#,
+            r#"{"type": "get_history", "controller": "c", "axis": "X", "limit": 5}"#,
+            r#"{"type": "get_available_params", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "get_supported_movement_params", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "get_capabilities", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "get_limits", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "reachable_range", "controller": "c", "axis": "X", "time_ms": 500}"#,
+            r#"{"type": "get_limit_switches", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "set_defaults", "controller": "c", "axis": "X", "params": {"velocity": 10.0}}"#,
+            r#"{"type": "resolve_params", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "self_test", "controller": "c"}"#,
+            r#"{"type": "invalidate_cache", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "list_controllers"}"#,
+            r#"{"type": "list_aliases"}"#,
+            r#"{"type": "describe"}"#,
+            r#"{"type": "system_health"}"#,
+            r#"{"type": "list_axes", "controller": "c", "detailed": true}"#,
+            r#"{"type": "subscribe", "controller": "c", "axis": "X"}"#,
+            r#"{"type": "subscribe_binary", "controller": "c", "axes": ["X"]}"#,
+            r#"{"type": "hello", "compression": ["gzip", "deflate"]}"#,
+            r#"{"type": "prepare", "command": {"type": "reset_fault", "controller": "c", "axis": "X"}}"#,
+            r#"{"type": "use_controller", "controller": "c"}"#,
+            r#"{"type": "unsubscribe", "subscription": 1}"#,
+            r#"{"type": "server_info"}"#,
+            r#"{"type": "ping", "id": "7"}"#,
+            r#"{"type": "auth", "token": "secret"}"#,
+            r#"{"type": "list_connections"}"#,
+            r#"{"type": "list_subscriptions"}"#,
+            r#"{"type": "limits"}"#,
+            r#"{"type": "disconnect", "connection_id": 2}"#,
+            r#"{"type": "metrics"}"#,
+        ];
+        let mut covered = [false; 55];
+        for line in samples {
+            let command = parse_command(line).unwrap();
+            let sent: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(command.wire_name(), sent["type"], "{}", line);
+
+            let serialized = serde_json::to_string(&command).unwrap();
+            let reparsed = parse_command(&serialized).unwrap();
+            assert_eq!(reparsed.wire_name(), command.wire_name(), "{}", serialized);
+            assert_eq!(variant_index(&reparsed), variant_index(&command));
+            assert_eq!(reparsed.id(), command.id());
+            let (_, unknown) = parse_command_checked(line).unwrap();
+            assert!(unknown.is_empty(), "{}: {:?}", line, unknown);
+            covered[variant_index(&command)] = true;
+        }
+        assert!(covered.iter().all(|c| *c), "a variant has no sample");
+    }
+
+    #[test]
+    fn test_checked_parse_finds_unknown_fields() {
+        let (command, unknown) = parse_command_checked(
+            r#"{"type": "move", "controller": "c", "axis": "X", "target": 1.0, "velocty": 10.0,
+                "params": {"velocity": 5.0, "acelleration": 2.0, "custom": {"dwell": 1.0}}}"#,
+        )
+        .unwrap();
+        assert_eq!(command.wire_name(), "move");
+        assert_eq!(unknown, ["params.acelleration", "velocty"]);
+
+        for line in [
+            r#"{"type": "move", "controller": "c", "axis": "X", "target": 1.0, "override": true}"#,
+            r#"{"type": "prepare", "command": {"type": "reset_fault", "controller": "c", "axis": "X"}}"#,
+        ] {
+            assert!(
+                parse_command_checked(line).unwrap().1.is_empty(),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_type_is_unsupported_command() {
+        for line in [
+            r#"{"type": "teleport"}"#,
+            r#"{"type": "Move", "controller": "c", "axis": "X", "target": 1.0}"#,
+            r#"{"type": "move_to", "controller": "c", "axis": "X", "target": 1.0}"#,
+        ] {
+            let err = parse_command(line).unwrap_err();
+            assert!(
+                matches!(err, ProtocolError::UnsupportedCommand(_)),
+                "{}: {:?}",
+                line,
+                err
+            );
+            assert_eq!(err.code(), "UNSUPPORTED_COMMAND");
+        }
+
+        // A known command with a bad enum value is malformed, not unknown.
+        let err = parse_command(
+            r#"{"type": "move_to_limit", "controller": "c", "axis": "X", "direction": "up"}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ProtocolError::InvalidJson { .. }));
+    }
 }
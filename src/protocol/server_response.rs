@@ -1,5 +1,8 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::error::CommandError;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "status")]
 pub enum ServerResponse {
@@ -8,6 +11,9 @@ pub enum ServerResponse {
         #[serde(skip_serializing_if = "Option::is_none")]
         id: Option<String>,
         data: serde_json::Value,
+        /// When the server sent the frame; see `with_timestamp`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ts: Option<DateTime<Utc>>,
     },
     #[serde(rename = "error")]
     Error {
@@ -16,12 +22,18 @@ pub enum ServerResponse {
         message: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         code: Option<String>,
+        /// Machine-readable specifics of the error, such as where a parse
+        /// error was found.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        details: Option<serde_json::Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ts: Option<DateTime<Utc>>,
     },
 }
 
 impl ServerResponse {
     pub fn success(id: Option<String>, data: serde_json::Value) -> Self {
-        Self::Success { id, data }
+        Self::Success { id, data, ts: None }
     }
 
     pub fn error(id: Option<String>, message: String) -> Self {
@@ -29,6 +41,8 @@ impl ServerResponse {
             id,
             message,
             code: None,
+            details: None,
+            ts: None,
         }
     }
 
@@ -37,6 +51,45 @@ impl ServerResponse {
             id,
             message,
             code: Some(code),
+            details: None,
+            ts: None,
+        }
+    }
+
+    pub fn error_with_details(
+        id: Option<String>,
+        message: String,
+        code: String,
+        details: Option<serde_json::Value>,
+    ) -> Self {
+        Self::Error {
+            id,
+            message,
+            code: Some(code),
+            details,
+            ts: None,
+        }
+    }
+
+    /// Stamps the frame with the time it was sent, serialized as RFC 3339.
+    pub fn with_timestamp(mut self, at: DateTime<Utc>) -> Self {
+        match &mut self {
+            Self::Success { ts, .. } | Self::Error { ts, .. } => *ts = Some(at),
+        }
+        self
+    }
+
+    /// Builds an error response, keeping the code when the error is a
+    /// `CommandError`.
+    pub fn from_error(id: Option<String>, err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<CommandError>() {
+            Some(cmd_err) => Self::error_with_details(
+                id,
+                cmd_err.message.clone(),
+                cmd_err.code.to_string(),
+                cmd_err.details.clone(),
+            ),
+            None => Self::error(id, err.to_string()),
         }
     }
 }
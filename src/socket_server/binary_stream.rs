@@ -0,0 +1,229 @@
+//! Binary position telemetry, for clients sampling faster than JSON frames
+//! can keep up with.
+//!
+//! After a successful `subscribe_binary` reply the connection carries
+//! nothing but fixed-size frames, one per axis per sampling interval:
+//!
+//! | offset | size | field       | encoding                                   |
+//! |--------|------|-------------|--------------------------------------------|
+//! | 0      | 2    | `axis_id`   | `u16` LE, index into the reply's `axes`     |
+//! | 2      | 8    | `timestamp` | `u64` LE, microseconds since the Unix epoch |
+//! | 10     | 8    | `position`  | `f64` LE, NaN if the read failed            |
+//! | 18     | 1    | `state`     | `u8`, see `state_code`                      |
+//!
+//! Frames are not length-prefixed or delimited, whatever the configured
+//! framing. Anything the client sends afterwards is ignored; closing the
+//! connection ends the stream.
+//!
+//! Samples come from the manager's cache, so a stream sampling faster than
+//! the position and status TTLs repeats values rather than adding hardware
+//! reads.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use futures::future::join_all;
+use serde_json::Value;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::{broadcast, oneshot, OwnedSemaphorePermit},
+};
+use tracing::debug;
+
+use crate::{
+    axis::state::AxisState,
+    controller_manager::{command::Command, ControllerManager},
+};
+
+pub const FRAME_SIZE: usize = 19;
+
+/// The shortest `interval_ms` a binary stream may be sampled at.
+pub const MIN_INTERVAL_MS: u64 = 1;
+
+/// What a `subscribe_binary` handshake asked for, kept on the connection
+/// until the reply has gone out and the stream can take over.
+#[derive(Debug)]
+pub struct BinaryStream {
+    pub controller: String,
+    pub axes: Vec<String>,
+    pub interval: Duration,
+    /// The `max_subscriptions` slot the stream holds while it runs.
+    pub slot: Option<OwnedSemaphorePermit>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionFrame {
+    pub axis_id: u16,
+    pub timestamp_us: u64,
+    pub position: f64,
+    pub state: AxisState,
+}
+
+impl PositionFrame {
+    pub fn encode(&self) -> [u8; FRAME_SIZE] {
+        let mut frame = [0; FRAME_SIZE];
+        frame[0..2].copy_from_slice(&self.axis_id.to_le_bytes());
+        frame[2..10].copy_from_slice(&self.timestamp_us.to_le_bytes());
+        frame[10..18].copy_from_slice(&self.position.to_le_bytes());
+        frame[18] = state_code(self.state);
+        frame
+    }
+
+    /// Decodes one frame from the start of `bytes`, or `None` if fewer than
+    /// `FRAME_SIZE` bytes are available.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let frame: &[u8; FRAME_SIZE] = bytes.get(..FRAME_SIZE)?.try_into().ok()?;
+        Some(Self {
+            axis_id: u16::from_le_bytes([frame[0], frame[1]]),
+            timestamp_us: u64::from_le_bytes(frame[2..10].try_into().ok()?),
+            position: f64::from_le_bytes(frame[10..18].try_into().ok()?),
+            state: state_from_code(frame[18]),
+        })
+    }
+}
+
+/// Wire value of each state. Codes are fixed once assigned; new states get
+/// new codes.
+pub fn state_code(state: AxisState) -> u8 {
+    match state {
+        AxisState::On => 0,
+        AxisState::Moving => 1,
+        AxisState::Settling => 2,
+        AxisState::Paused => 3,
+        AxisState::Disabled => 4,
+        AxisState::Alarm => 5,
+        AxisState::Fault => 6,
+        AxisState::Unknown => 255,
+    }
+}
+
+/// Inverse of `state_code`; codes this version doesn't know decode as
+/// `Unknown`.
+pub fn state_from_code(code: u8) -> AxisState {
+    match code {
+        0 => AxisState::On,
+        1 => AxisState::Moving,
+        2 => AxisState::Settling,
+        3 => AxisState::Paused,
+        4 => AxisState::Disabled,
+        5 => AxisState::Alarm,
+        6 => AxisState::Fault,
+        _ => AxisState::Unknown,
+    }
+}
+
+/// Samples every axis of `spec` on its interval and writes the frames
+/// until the client disconnects or the server shuts down.
+pub async fn run<S>(
+    mut stream: S,
+    manager: &ControllerManager,
+    spec: BinaryStream,
+    shutdown_rx: &mut broadcast::Receiver<()>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut ticks = tokio::time::interval(spec.interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut discard = [0; 256];
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                let samples = sample(manager, &spec.controller, &spec.axes).await;
+                let timestamp_us = Utc::now().timestamp_micros() as u64;
+                let mut frames = Vec::with_capacity(samples.len() * FRAME_SIZE);
+                for (axis_id, (position, state)) in samples.into_iter().enumerate() {
+                    let frame = PositionFrame {
+                        axis_id: axis_id as u16,
+                        timestamp_us,
+                        position,
+                        state,
+                    };
+                    frames.extend_from_slice(&frame.encode());
+                }
+                if let Err(e) = stream.write_all(&frames).await {
+                    debug!("Binary stream closed: {}", e);
+                    break;
+                }
+            }
+            read = stream.read(&mut discard) => {
+                if matches!(read, Ok(0) | Err(_)) {
+                    debug!("Binary stream client disconnected");
+                    break;
+                }
+            }
+            _ = shutdown_rx.recv() => break,
+        }
+    }
+    Ok(())
+}
+
+/// Cached positions of every axis, read with one `get_positions`, and
+/// their cached states; a failed read shows up as NaN or `Unknown` in the
+/// frame.
+async fn sample(
+    manager: &ControllerManager,
+    controller: &str,
+    axes: &[String],
+) -> Vec<(f64, AxisState)> {
+    let positions = query(manager, |resp| Command::GetPositions {
+        controller: controller.to_string(),
+        axes: axes.to_vec(),
+        resp,
+    })
+    .await
+    .unwrap_or_default();
+    let states = join_all(axes.iter().map(|axis| {
+        query(manager, |resp| Command::GetState {
+            controller: controller.to_string(),
+            axis: axis.to_string(),
+            fresh: false,
+            resp,
+        })
+    }))
+    .await;
+    axes.iter()
+        .zip(states)
+        .map(|(axis, state)| {
+            let position = positions["positions"][axis]["position"]
+                .as_f64()
+                .unwrap_or(f64::NAN);
+            let state = state
+                .and_then(|mut value| serde_json::from_value(value["status"]["state"].take()).ok())
+                .unwrap_or(AxisState::Unknown);
+            (position, state)
+        })
+        .collect()
+}
+
+async fn query(
+    manager: &ControllerManager,
+    command: impl FnOnce(oneshot::Sender<Result<Value>>) -> Command,
+) -> Option<Value> {
+    let (tx, rx) = oneshot::channel();
+    manager.send_command(command(tx)).await.ok()?;
+    rx.await.ok()?.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_round_trip() {
+        let frame = PositionFrame {
+            axis_id: 3,
+            timestamp_us: 1_700_000_000_123_456,
+            position: -12.5,
+            state: AxisState::Settling,
+        };
+        let bytes = frame.encode();
+        assert_eq!(&bytes[0..2], &[3, 0]);
+        assert_eq!(bytes[18], 2);
+        assert_eq!(PositionFrame::decode(&bytes), Some(frame));
+        assert_eq!(PositionFrame::decode(&bytes[..FRAME_SIZE - 1]), None);
+        assert_eq!(state_from_code(200), AxisState::Unknown);
+    }
+}
@@ -0,0 +1,90 @@
+use std::io;
+
+use tokio_util::{
+    bytes::{Bytes, BytesMut},
+    codec::{
+        length_delimited::LengthDelimitedCodecError, Decoder, Encoder, LengthDelimitedCodec,
+        LinesCodec, LinesCodecError,
+    },
+};
+
+use super::config::Framing;
+
+/// Frames JSON messages according to the configured `Framing`, so the
+/// connection handler can work with whole message strings either way.
+/// Incoming messages longer than `max_length` bytes fail to decode; see
+/// `is_too_long`.
+pub enum MessageCodec {
+    Lines(LinesCodec),
+    LengthDelimited(LengthDelimitedCodec),
+}
+
+impl MessageCodec {
+    pub fn new(framing: Framing, max_length: usize) -> Self {
+        match framing {
+            Framing::Lines => MessageCodec::Lines(LinesCodec::new_with_max_length(max_length)),
+            Framing::LengthDelimited => MessageCodec::LengthDelimited(
+                LengthDelimitedCodec::builder()
+                    .max_frame_length(max_length)
+                    .new_codec(),
+            ),
+        }
+    }
+}
+
+/// Whether a decode error is an incoming message over the length limit.
+pub fn is_too_long(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| {
+        inner.is::<LengthDelimitedCodecError>()
+            || matches!(
+                inner.downcast_ref::<LinesCodecError>(),
+                Some(LinesCodecError::MaxLineLengthExceeded)
+            )
+    })
+}
+
+impl Decoder for MessageCodec {
+    type Item = String;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<String>, io::Error> {
+        match self {
+            MessageCodec::Lines(codec) => codec.decode(src).map_err(invalid_data),
+            MessageCodec::LengthDelimited(codec) => codec
+                .decode(src)?
+                .map(|frame| String::from_utf8(frame.to_vec()).map_err(invalid_data))
+                .transpose(),
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<String>, io::Error> {
+        match self {
+            MessageCodec::Lines(codec) => codec.decode_eof(src).map_err(invalid_data),
+            MessageCodec::LengthDelimited(codec) => codec
+                .decode_eof(src)?
+                .map(|frame| String::from_utf8(frame.to_vec()).map_err(invalid_data))
+                .transpose(),
+        }
+    }
+}
+
+impl Encoder<String> for MessageCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: String, dst: &mut BytesMut) -> Result<(), io::Error> {
+        match self {
+            MessageCodec::Lines(codec) => codec.encode(item, dst).map_err(invalid_data),
+            // The limit is on what clients send; replies may be longer.
+            MessageCodec::LengthDelimited(_) => {
+                LengthDelimitedCodec::new().encode(Bytes::from(item), dst)
+            }
+        }
+    }
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,114 @@
+//! Compression of large responses, for connections that asked for it with
+//! `hello`.
+//!
+//! A compressed response replaces the plain one with a frame of the form
+//!
+//! ```json
+//! {"compressed": "gzip", "data": "H4sIAAAA..."}
+//! ```
+//!
+//! where `data` is the standard base64 of the response JSON compressed
+//! with the `compressed` algorithm, `gzip` or raw `deflate`. Plain frames
+//! never carry a `compressed` field, so a client tells the two apart by
+//! its presence and undoes it with `decode`. Only command replies are
+//! compressed; heartbeats and subscription events are sent as they are.
+
+use std::io::{Read, Write};
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{
+    read::{DeflateDecoder, GzDecoder},
+    write::{DeflateEncoder, GzEncoder},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    Gzip,
+    Deflate,
+}
+
+impl Compression {
+    /// The first of the client's `offered` algorithms that the server
+    /// supports, so the client's order is its preference.
+    pub fn negotiate(offered: &[String]) -> Option<Self> {
+        offered
+            .iter()
+            .find_map(|name| serde_json::from_value(Value::String(name.clone())).ok())
+    }
+}
+
+/// The frame to send for the serialized `response`: compressed if it is at
+/// least `threshold` bytes and compressing makes it smaller.
+pub fn encode(response: String, compression: Compression, threshold: usize) -> Result<String> {
+    if response.len() < threshold {
+        return Ok(response);
+    }
+    let level = flate2::Compression::default();
+    let compressed = match compression {
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), level);
+            encoder.write_all(response.as_bytes())?;
+            encoder.finish()?
+        }
+        Compression::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), level);
+            encoder.write_all(response.as_bytes())?;
+            encoder.finish()?
+        }
+    };
+    let frame = json!({"compressed": compression, "data": STANDARD.encode(compressed)}).to_string();
+    Ok(if frame.len() < response.len() {
+        frame
+    } else {
+        response
+    })
+}
+
+/// The response a received `frame` carries, decompressing it if it was
+/// sent compressed.
+pub fn decode(frame: Value) -> Result<Value> {
+    let Some(compressed) = frame.get("compressed") else {
+        return Ok(frame);
+    };
+    let compression: Compression = serde_json::from_value(compressed.clone())?;
+    let data = frame["data"]
+        .as_str()
+        .context("Compressed frame has no data")?;
+    let data = STANDARD.decode(data)?;
+    let mut response = String::new();
+    match compression {
+        Compression::Gzip => GzDecoder::new(data.as_slice()).read_to_string(&mut response)?,
+        Compression::Deflate => {
+            DeflateDecoder::new(data.as_slice()).read_to_string(&mut response)?
+        }
+    };
+    Ok(serde_json::from_str(&response)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_takes_first_supported() {
+        let offered = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            Compression::negotiate(&offered(&["zstd", "deflate", "gzip"])),
+            Some(Compression::Deflate)
+        );
+        assert_eq!(Compression::negotiate(&offered(&["br"])), None);
+    }
+
+    #[test]
+    fn test_small_responses_stay_plain() {
+        let response = r#"{"status":"success","data":{"message":"pong"}}"#.to_string();
+        let frame = encode(response.clone(), Compression::Gzip, 1024).unwrap();
+        assert_eq!(frame, response);
+        let decoded = decode(serde_json::from_str(&frame).unwrap()).unwrap();
+        assert_eq!(decoded["data"]["message"], "pong");
+    }
+}
@@ -1,7 +1,144 @@
+use std::net::SocketAddr;
+#[cfg(feature = "tls")]
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How messages are delimited on the wire.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// One JSON document per line.
+    #[default]
+    Lines,
+    /// Each JSON document is preceded by its length as a 4-byte big-endian
+    /// integer, so documents may contain raw newlines.
+    LengthDelimited,
+}
+
+/// PEM files for serving TCP clients over TLS.
+#[cfg(feature = "tls")]
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// The server certificate, followed by any intermediates.
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
 pub struct SocketServerConfig {
     pub socket_path: String,
+    /// Connections served at once, across the Unix and TCP transports.
+    /// Once reached, new clients wait unanswered until a connection closes.
+    /// Capped at `tokio::sync::Semaphore::MAX_PERMITS`.
     pub max_connections: usize,
     pub buffer_size: usize,
+    /// Shared secret clients must present with an `auth` command before any
+    /// other command is accepted. Authentication is disabled when `None`.
+    pub auth_token: Option<String>,
+    /// Authenticate with a challenge instead of sending the token: each
+    /// connection is greeted with a `{"challenge": nonce}` frame and the
+    /// client answers with `auth` carrying `hmac`, the hex HMAC-SHA256 of
+    /// the nonce keyed with `auth_token`. Nonces are single-use, so a
+    /// captured answer can't be replayed. Ignored without `auth_token`.
+    pub auth_challenge: bool,
+    /// Failed `auth` attempts a connection may make; the connection is
+    /// closed after replying to the last one, so a client can't keep
+    /// guessing the token on one connection.
+    pub max_auth_failures: u32,
+    /// Per-connection limit on motion commands (see
+    /// `ClientCommand::is_motion`). Unlimited when `None`.
+    pub max_commands_per_sec: Option<u32>,
+    /// Reject commands reusing an id seen recently on the same connection.
+    pub reject_duplicate_ids: bool,
+    /// How many of the most recently used ids per connection are
+    /// remembered for duplicate detection.
+    pub duplicate_id_window: usize,
+    /// Send a `{"heartbeat": ts}` success frame on this interval so a dead
+    /// peer is noticed by a failing write rather than leaving the handler
+    /// blocked on a read. Heartbeats are timed independently of any other
+    /// server-initiated frames, such as subscription events, and are
+    /// disabled when `None`.
+    pub heartbeat_interval: Option<Duration>,
+    /// How long a command may wait for room in the manager's queue before
+    /// failing with `QUEUE_FULL`, so a stuck backend doesn't stall the
+    /// connection. Waits as long as it takes when `None`.
+    pub command_queue_timeout: Option<Duration>,
+    pub framing: Framing,
+    /// Stamp every frame sent to clients with the server's UTC time, as an
+    /// RFC 3339 `ts` field, for latency measurement and ordering.
+    pub response_timestamps: bool,
+    /// Longest message, in bytes, a client may send. A longer one is
+    /// answered with `MESSAGE_TOO_LONG` and the connection is closed, as
+    /// the rest of the stream can't be trusted to be framed. This also
+    /// bounds what a single command can make the server allocate.
+    pub max_message_length: usize,
+    /// Most axes a single command may list, e.g. in `get_positions`. More
+    /// fail the command with `TOO_MANY_ITEMS`.
+    pub max_multi_axes: usize,
+    /// Permission bits applied to the socket file after it is created, e.g.
+    /// `0o660` to admit only the owning user and group. Left to the umask
+    /// when `None`.
+    pub socket_mode: Option<u32>,
+    /// Only processes running as one of these uids may connect. Any user
+    /// that can open the socket may connect when `None`.
+    pub allowed_uids: Option<Vec<u32>>,
+    /// Processes running as one of these uids may use the admin commands,
+    /// `list_connections` and `disconnect`. No one may when `None`, nor
+    /// ever over TCP, where there is no uid to check.
+    pub admin_uids: Option<Vec<u32>>,
+    /// When a connection closes, stop the axes of every move it started
+    /// that is still in flight. Off by default, for setups where moves
+    /// should outlive the client that sent them.
+    pub stop_on_disconnect: bool,
+    /// How long `SocketServer::shutdown` lets commands that are already
+    /// running finish before it closes the connections. Meanwhile new
+    /// commands are answered with `SERVER_SHUTTING_DOWN`.
+    pub shutdown_grace: Duration,
+    /// JSON Lines file of commands replayed in order by `start` before the
+    /// socket is opened.
+    pub startup_script: Option<String>,
+    /// Abort `start` on the first failing script command instead of logging
+    /// it and carrying on.
+    pub startup_script_strict: bool,
+    /// Limit on active subscriptions per connection. The manager's
+    /// `max_subscriptions` additionally caps them across all connections.
+    pub max_subscriptions_per_connection: usize,
+    /// At-most-once motion commands: a move or other motion command whose
+    /// `id` was already handled on the connection within this long gets
+    /// the earlier reply again instead of running twice, so clients can
+    /// safely retry after a timeout. Disabled when `None`.
+    pub idempotency_ttl: Option<Duration>,
+    /// Most replies kept per connection for `idempotency_ttl`.
+    pub idempotency_capacity: u64,
+    /// Refuse commands carrying fields they don't have, such as a misspelt
+    /// parameter, with `UNKNOWN_FIELD` naming the first. Otherwise such
+    /// fields are ignored with a warning in the log.
+    pub strict_parse: bool,
+    /// Two-phase confirmation of destructive commands such as
+    /// `stop_controller` and `reset_fault`: when set, each must carry a
+    /// `confirm_token` obtained with `prepare` on the same connection no
+    /// longer ago than this. Disabled when `None`.
+    pub confirm_token_ttl: Option<Duration>,
+    /// Also accept clients over TCP on this address, with the same protocol
+    /// and framing as the Unix socket. Port 0 picks a free port; see
+    /// `SocketServer::tcp_addr`. TCP peers have no credentials, so they are
+    /// refused when `allowed_uids` is set.
+    pub tcp_addr: Option<SocketAddr>,
+    /// Require TLS on the TCP transport. `start` fails if the certificate
+    /// or key can't be loaded.
+    #[cfg(feature = "tls")]
+    pub tls: Option<TlsConfig>,
+    /// How long a TCP client may take to complete the TLS handshake before
+    /// its connection is dropped.
+    #[cfg(feature = "tls")]
+    pub tls_handshake_timeout: Duration,
+    /// Serve the manager's metrics in the Prometheus text format over HTTP
+    /// on this address. Port 0 picks a free port; see
+    /// `SocketServer::metrics_addr`. Disabled when `None`.
+    #[cfg(feature = "metrics-prometheus")]
+    pub metrics_addr: Option<SocketAddr>,
+    /// Replies of at least this many bytes are compressed on connections
+    /// that negotiated compression with `hello`.
+    #[cfg(feature = "compression")]
+    pub compression_threshold: usize,
 }
 
 impl Default for SocketServerConfig {
@@ -10,6 +147,39 @@ impl Default for SocketServerConfig {
             socket_path: "/tmp/motarem.sock".to_string(),
             max_connections: 100,
             buffer_size: 8192,
+            auth_token: None,
+            auth_challenge: false,
+            max_auth_failures: 3,
+            max_commands_per_sec: None,
+            reject_duplicate_ids: false,
+            duplicate_id_window: 1024,
+            heartbeat_interval: None,
+            command_queue_timeout: Some(Duration::from_secs(5)),
+            framing: Framing::default(),
+            response_timestamps: true,
+            max_message_length: 64 * 1024,
+            max_multi_axes: 64,
+            socket_mode: None,
+            allowed_uids: None,
+            admin_uids: None,
+            stop_on_disconnect: false,
+            shutdown_grace: Duration::from_secs(5),
+            startup_script: None,
+            startup_script_strict: false,
+            max_subscriptions_per_connection: 16,
+            idempotency_ttl: None,
+            idempotency_capacity: 1024,
+            strict_parse: false,
+            confirm_token_ttl: None,
+            tcp_addr: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "tls")]
+            tls_handshake_timeout: Duration::from_secs(10),
+            #[cfg(feature = "metrics-prometheus")]
+            metrics_addr: None,
+            #[cfg(feature = "compression")]
+            compression_threshold: 8 * 1024,
         }
     }
 }
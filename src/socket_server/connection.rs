@@ -0,0 +1,411 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::{net::UnixStream, sync::mpsc};
+
+use super::{
+    binary_stream::BinaryStream, config::SocketServerConfig, rate_limit::TokenBucket,
+    registry::ConnectionRegistry, subscription::Subscriptions,
+};
+use crate::protocol::{
+    client_command::ClientCommand, error::CommandError, server_response::ServerResponse,
+};
+
+/// State kept by `handle_client` for the lifetime of one client connection.
+pub struct ConnectionState {
+    pub authenticated: bool,
+    /// The nonce this connection must answer in challenge mode, until an
+    /// `auth` attempt uses it up.
+    pub challenge: Option<String>,
+    /// Failed `auth` attempts so far; see `max_auth_failures`.
+    pub auth_failures: u32,
+    /// Set by a command whose reply must be the connection's last;
+    /// `handle_client` closes the connection once it has been sent.
+    pub close_after_reply: bool,
+    pub rate_limiter: Option<TokenBucket>,
+    pub recent_ids: Option<RecentIds>,
+    pub subscriptions: Subscriptions,
+    /// Set by a successful `subscribe_binary`; the connection switches to
+    /// binary frames after replying.
+    pub binary_stream: Option<BinaryStream>,
+    /// This connection's id in the server's registry.
+    pub id: u64,
+    /// May use the admin commands; see `admin_uids`.
+    pub admin: bool,
+    /// The server's live connections, for the admin commands. Absent when
+    /// the connection isn't served by a running server.
+    pub registry: Option<Arc<ConnectionRegistry>>,
+    /// Set by `use_controller`; fills in commands that leave out
+    /// `controller`.
+    pub default_controller: Option<String>,
+    /// Frames a command sends ahead of its reply, such as the progress of a
+    /// `stream_progress` move, which `handle_client` writes out while the
+    /// command runs. Absent when nothing is draining them.
+    pub progress: Option<mpsc::Sender<ServerResponse>>,
+    /// Replies to motion commands by id, replayed when a command with the
+    /// same id is retried; see `idempotency_ttl`. Each is kept with the
+    /// `fingerprint` of the command it answered.
+    pub idempotency: Option<moka::future::Cache<String, (u64, ServerResponse)>>,
+    /// Tokens handed out by `prepare`, when `confirm_token_ttl` is set.
+    pub confirmations: Option<Confirmations>,
+    /// How the client reached the server, as `server_info` reports it.
+    pub transport: Transport,
+    /// Negotiated with `hello`; replies go out uncompressed when `None`.
+    #[cfg(feature = "compression")]
+    pub compression: Option<super::compression::Compression>,
+}
+
+impl ConnectionState {
+    pub fn new(config: &SocketServerConfig) -> Self {
+        Self {
+            authenticated: config.auth_token.is_none(),
+            challenge: (config.auth_challenge && config.auth_token.is_some()).then(new_nonce),
+            auth_failures: 0,
+            close_after_reply: false,
+            rate_limiter: config.max_commands_per_sec.map(TokenBucket::new),
+            recent_ids: config
+                .reject_duplicate_ids
+                .then(|| RecentIds::new(config.duplicate_id_window)),
+            subscriptions: Subscriptions::new(config.max_subscriptions_per_connection),
+            binary_stream: None,
+            id: 0,
+            admin: false,
+            registry: None,
+            default_controller: None,
+            progress: None,
+            idempotency: config.idempotency_ttl.map(|ttl| {
+                moka::future::Cache::builder()
+                    .max_capacity(config.idempotency_capacity)
+                    .time_to_live(ttl)
+                    .build()
+            }),
+            confirmations: config.confirm_token_ttl.map(Confirmations::new),
+            transport: Transport::Unix,
+            #[cfg(feature = "compression")]
+            compression: None,
+        }
+    }
+}
+
+/// Credentials of the process on the other end of a Unix socket, as
+/// reported by the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerIdentity {
+    pub pid: Option<i32>,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl PeerIdentity {
+    pub fn of(stream: &UnixStream) -> std::io::Result<Self> {
+        let cred = stream.peer_cred()?;
+        Ok(Self {
+            pid: cred.pid(),
+            uid: cred.uid(),
+            gid: cred.gid(),
+        })
+    }
+}
+
+impl fmt::Display for PeerIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pid {
+            Some(pid) => write!(f, "pid={} uid={} gid={}", pid, self.uid, self.gid),
+            None => write!(f, "uid={} gid={}", self.uid, self.gid),
+        }
+    }
+}
+
+/// Who is on the other end of a new connection.
+#[derive(Debug, Clone, Copy)]
+pub enum Peer {
+    /// A Unix socket peer, with its credentials if they could be read.
+    Unix(Option<PeerIdentity>),
+    Tcp(SocketAddr),
+}
+
+impl Peer {
+    /// Whether the peer may use the admin commands under `admin_uids`. TCP
+    /// peers have no uid to check, so they never may.
+    pub fn is_admin(&self, config: &SocketServerConfig) -> bool {
+        match (self, &config.admin_uids) {
+            (Peer::Unix(Some(peer)), Some(uids)) => uids.contains(&peer.uid),
+            _ => false,
+        }
+    }
+
+    /// The span a connection from this peer is served in.
+    pub fn span(&self) -> tracing::Span {
+        match self {
+            Peer::Unix(peer) => tracing::debug_span!(
+                "connection",
+                pid = peer.and_then(|p| p.pid),
+                uid = peer.map(|p| p.uid),
+            ),
+            Peer::Tcp(addr) => tracing::debug_span!("connection", peer = %addr),
+        }
+    }
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Peer::Unix(Some(peer)) => write!(f, "{}", peer),
+            Peer::Unix(None) => write!(f, "unidentified"),
+            Peer::Tcp(addr) => write!(f, "tcp://{}", addr),
+        }
+    }
+}
+
+/// The transport a connection came in on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Unix,
+    /// TCP, wrapped in TLS when the server is configured with `tls`.
+    Tcp {
+        tls: bool,
+    },
+}
+
+impl Transport {
+    pub fn of(peer: &Peer, config: &SocketServerConfig) -> Self {
+        match peer {
+            Peer::Unix(_) => Transport::Unix,
+            #[cfg(feature = "tls")]
+            Peer::Tcp(_) => Transport::Tcp {
+                tls: config.tls.is_some(),
+            },
+            #[cfg(not(feature = "tls"))]
+            Peer::Tcp(_) => {
+                let _ = config;
+                Transport::Tcp { tls: false }
+            }
+        }
+    }
+}
+
+/// Whether a peer may connect under `allowed_uids`. A peer whose
+/// credentials couldn't be read is only allowed when there is no list.
+pub fn peer_allowed(config: &SocketServerConfig, peer: Option<&PeerIdentity>) -> bool {
+    match (&config.allowed_uids, peer) {
+        (None, _) => true,
+        (Some(uids), Some(peer)) => uids.contains(&peer.uid),
+        (Some(_), None) => false,
+    }
+}
+
+/// The `capacity` most recently used command ids on a connection, least
+/// recently used evicted first, so long-lived connections don't grow
+/// without bound. A repeated id counts as a use, so an id a client keeps
+/// retrying stays rejected.
+pub struct RecentIds {
+    capacity: usize,
+    /// Bumped on every use, giving each id its place in `order`.
+    uses: u64,
+    order: BTreeMap<u64, String>,
+    seen: HashMap<String, u64>,
+}
+
+impl RecentIds {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            uses: 0,
+            order: BTreeMap::new(),
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Records `id`, returning `false` if it is already in the window.
+    pub fn insert(&mut self, id: &str) -> bool {
+        self.uses += 1;
+        if let Some(last_use) = self.seen.get_mut(id) {
+            self.order.remove(last_use);
+            *last_use = self.uses;
+            self.order.insert(self.uses, id.to_string());
+            return false;
+        }
+
+        if self.seen.len() == self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.order.insert(self.uses, id.to_string());
+        self.seen.insert(id.to_string(), self.uses);
+        true
+    }
+}
+
+/// The `confirm_token`s issued on a connection, each good for one
+/// destructive command matching the one it was prepared for, until it
+/// expires.
+pub struct Confirmations {
+    ttl: Duration,
+    /// Token to the command it confirms and when it expires.
+    pending: HashMap<String, (String, Instant)>,
+}
+
+impl Confirmations {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// A new token for `command`.
+    pub fn issue(&mut self, command: &ClientCommand) -> String {
+        self.expire();
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        self.pending.insert(
+            token.clone(),
+            (confirm_key(command), Instant::now() + self.ttl),
+        );
+        token
+    }
+
+    /// Uses up the token `command` carries, failing with
+    /// `CONFIRMATION_REQUIRED` if it has none and `CONFIRM_TOKEN_INVALID`
+    /// if it is unknown, expired or was prepared for another command.
+    pub fn redeem(&mut self, command: &ClientCommand) -> Result<(), CommandError> {
+        self.expire();
+        let Some(token) = command.confirm_token() else {
+            return Err(CommandError::new(
+                "CONFIRMATION_REQUIRED",
+                format!(
+                    "{} must be confirmed: send it to prepare first and repeat it with the confirm_token",
+                    command.wire_name()
+                ),
+            ));
+        };
+        match self.pending.remove(token) {
+            Some((key, _)) if key == confirm_key(command) => Ok(()),
+            Some(_) => Err(CommandError::new(
+                "CONFIRM_TOKEN_INVALID",
+                "Confirmation token was prepared for a different command",
+            )),
+            None => Err(CommandError::new(
+                "CONFIRM_TOKEN_INVALID",
+                "Confirmation token is unknown or has expired",
+            )),
+        }
+    }
+
+    fn expire(&mut self) {
+        let now = Instant::now();
+        self.pending.retain(|_, (_, expires)| *expires > now);
+    }
+}
+
+/// What a token is bound to: the command and what it addresses.
+fn confirm_key(command: &ClientCommand) -> String {
+    format!(
+        "{}:{}:{}",
+        command.wire_name(),
+        command.controller().unwrap_or_default(),
+        command.axis().unwrap_or_default()
+    )
+}
+
+/// Compares two tokens without short-circuiting on the first mismatching
+/// byte, so response timing does not leak how much of the token matched.
+pub fn tokens_match(expected: &str, provided: &str) -> bool {
+    let (expected, provided) = (expected.as_bytes(), provided.as_bytes());
+    if expected.len() != provided.len() {
+        return false;
+    }
+    expected
+        .iter()
+        .zip(provided)
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+/// 256 random bits from the OS, hex-encoded.
+fn new_nonce() -> String {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).expect("OS random source unavailable");
+    hex::encode(bytes)
+}
+
+/// The answer to a challenge: the hex HMAC-SHA256 of `nonce` keyed with
+/// `token`.
+pub fn challenge_response(token: &str, nonce: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(token.as_bytes()).expect("HMAC takes any key");
+    mac.update(nonce.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Checks an answer in constant time.
+pub fn verify_challenge(token: &str, nonce: &str, answer: &str) -> bool {
+    let Ok(answer) = hex::decode(answer) else {
+        return false;
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(token.as_bytes()).expect("HMAC takes any key");
+    mac.update(nonce.as_bytes());
+    mac.verify_slice(&answer).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_ids_rejects_repeats_within_window() {
+        let mut ids = RecentIds::new(2);
+        assert!(ids.insert("a"));
+        assert!(!ids.insert("a"));
+        assert!(ids.insert("b"));
+        assert!(ids.insert("c"));
+        // "a" has been evicted by now
+        assert!(ids.insert("a"));
+        assert!(!ids.insert("c"));
+    }
+
+    #[test]
+    fn test_recent_ids_evicts_least_recently_used() {
+        let mut ids = RecentIds::new(2);
+        assert!(ids.insert("a"));
+        assert!(ids.insert("b"));
+        // The retry keeps "a" fresh, so "b" goes instead
+        assert!(!ids.insert("a"));
+        assert!(ids.insert("c"));
+        assert!(!ids.insert("a"));
+        assert!(ids.insert("b"));
+    }
+
+    #[tokio::test]
+    async fn test_peer_allow_list() {
+        let (stream, _other) = UnixStream::pair().unwrap();
+        let peer = PeerIdentity::of(&stream).unwrap();
+
+        let open = SocketServerConfig::default();
+        assert!(peer_allowed(&open, Some(&peer)));
+        assert!(peer_allowed(&open, None));
+
+        let restricted = SocketServerConfig {
+            allowed_uids: Some(vec![peer.uid]),
+            ..Default::default()
+        };
+        assert!(peer_allowed(&restricted, Some(&peer)));
+        assert!(!peer_allowed(&restricted, None));
+
+        let other_user = SocketServerConfig {
+            allowed_uids: Some(vec![peer.uid.wrapping_add(1)]),
+            ..Default::default()
+        };
+        assert!(!peer_allowed(&other_user, Some(&peer)));
+    }
+}
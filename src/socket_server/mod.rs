@@ -1,115 +1,501 @@
+pub mod binary_stream;
+pub mod codec;
+#[cfg(feature = "compression")]
+pub mod compression;
 pub mod config;
+pub mod connection;
+#[cfg(feature = "metrics-prometheus")]
+pub mod prometheus;
+pub mod rate_limit;
+pub mod registry;
+pub mod subscription;
+#[cfg(feature = "tls")]
+pub mod tls;
 
 use anyhow::Result;
+use chrono::Utc;
 use futures::{SinkExt, StreamExt};
 use serde_json::json;
 use std::{
+    collections::VecDeque,
+    future::Future,
+    os::{fd::OwnedFd, unix::fs::PermissionsExt},
     path::Path,
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, Ordering},
         Arc,
     },
 };
 use tokio::{
-    net::{UnixListener, UnixStream},
-    sync::oneshot,
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, UnixListener},
+    sync::{broadcast, mpsc, oneshot, OwnedSemaphorePermit},
+    time::{Instant, Interval},
 };
-use tokio_util::codec::{Framed, LinesCodec};
-use tracing::{debug, error, info, warn};
+use tokio_util::codec::Framed;
+use tracing::{debug, error, info, warn, Instrument};
 
 use crate::{
-    controller_manager::{command::Command, ControllerManager},
+    controller_manager::{
+        command::Command,
+        event::{AxisEventType, EventFilter},
+        ControllerManager,
+    },
     protocol::{
-        client_command::ClientCommand, parse_command, serialize_response,
+        client_command::ClientCommand,
+        error::{CommandError, ProtocolError},
+        parse_command, parse_command_checked, serialize_response,
         server_response::ServerResponse,
+        PROTOCOL_VERSION,
     },
 };
-use config::SocketServerConfig;
+use binary_stream::BinaryStream;
+use codec::MessageCodec;
+use config::{Framing, SocketServerConfig};
+use connection::{
+    peer_allowed, tokens_match, verify_challenge, ConnectionState, Peer, PeerIdentity, Transport,
+};
+use registry::ConnectionRegistry;
+
+/// How many frames a command may queue ahead of its reply before it waits
+/// for the connection to write them out.
+const PROGRESS_BUFFER: usize = 16;
+
+/// Lines a connection reads ahead while watching for a hang-up during a
+/// command; past this it stops reading until the command is done.
+const READ_AHEAD_LIMIT: usize = 16;
 
 pub struct SocketServer {
-    config: SocketServerConfig,
+    config: Arc<SocketServerConfig>,
     manager: Arc<ControllerManager>,
     shutdown_tx: Option<tokio::sync::broadcast::Sender<()>>,
+    /// The connections being served, once started.
+    registry: Option<Arc<ConnectionRegistry>>,
+    /// A listener handed in by whoever owns the socket file (e.g. systemd
+    /// socket activation), taken by `start`.
+    listener: Option<UnixListener>,
+    /// False when serving a handed-in listener: the socket file isn't ours
+    /// to create, chmod or remove.
+    owns_socket: bool,
+    /// Set once `shutdown` or `drop` has removed the socket file, so the
+    /// other doesn't remove it again, possibly from a server started since.
+    socket_removed: AtomicBool,
+    /// Where the TCP transport ended up listening, once started.
+    tcp_addr: Option<std::net::SocketAddr>,
+    /// Where the Prometheus endpoint ended up listening, once started.
+    #[cfg(feature = "metrics-prometheus")]
+    metrics_addr: Option<std::net::SocketAddr>,
 }
 
 impl SocketServer {
     pub fn new(config: SocketServerConfig, manager: Arc<ControllerManager>) -> Self {
         Self {
-            config,
+            config: Arc::new(config),
             manager,
             shutdown_tx: None,
+            registry: None,
+            listener: None,
+            owns_socket: true,
+            socket_removed: AtomicBool::new(false),
+            tcp_addr: None,
+            #[cfg(feature = "metrics-prometheus")]
+            metrics_addr: None,
         }
     }
 
+    /// Serves an already-bound listener instead of binding
+    /// `config.socket_path`. `socket_mode` is not applied and the socket
+    /// file is never removed; `socket_path` is only reported.
+    pub fn from_listener(
+        listener: UnixListener,
+        config: SocketServerConfig,
+        manager: Arc<ControllerManager>,
+    ) -> Self {
+        let mut server = Self::new(config, manager);
+        server.listener = Some(listener);
+        server.owns_socket = false;
+        server
+    }
+
+    /// Like `from_listener`, for a listening socket passed as a file
+    /// descriptor, as with systemd's `LISTEN_FDS`. Must be called within a
+    /// tokio runtime.
+    pub fn from_fd(
+        fd: OwnedFd,
+        config: SocketServerConfig,
+        manager: Arc<ControllerManager>,
+    ) -> Result<Self> {
+        let listener = std::os::unix::net::UnixListener::from(fd);
+        listener.set_nonblocking(true)?;
+        let listener = UnixListener::from_std(listener)?;
+        Ok(Self::from_listener(listener, config, manager))
+    }
+
     pub async fn start(&mut self) -> Result<()> {
-        if Path::new(&self.config.socket_path).exists() {
-            tokio::fs::remove_file(&self.config.socket_path).await?;
+        if let Some(script) = &self.config.startup_script {
+            Self::replay_script(
+                Path::new(script),
+                &self.manager,
+                self.config.startup_script_strict,
+            )
+            .await?;
         }
 
-        let listener = UnixListener::bind(&self.config.socket_path)?;
+        // Certificates are loaded and the TCP ports bound before the socket
+        // file is created, so a failure in either leaves nothing behind.
+        #[cfg(feature = "tls")]
+        let tls = match &self.config.tls {
+            Some(tls) => Some(tls::acceptor(tls)?),
+            None => None,
+        };
+        let tcp_listener = match self.config.tcp_addr {
+            Some(addr) => Some(TcpListener::bind(addr).await?),
+            None => None,
+        };
+        #[cfg(feature = "metrics-prometheus")]
+        let metrics_listener = match self.config.metrics_addr {
+            Some(addr) => Some(TcpListener::bind(addr).await?),
+            None => None,
+        };
+
+        let listener = match self.listener.take() {
+            Some(listener) => listener,
+            None => self.bind().await?,
+        };
         info!("Socket server listening on: {}", self.config.socket_path);
 
-        let (shutdown_tx, mut shutdown_rx) = tokio::sync::broadcast::channel(1);
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
         self.shutdown_tx = Some(shutdown_tx);
+        let registry = Arc::new(ConnectionRegistry::new(self.config.max_connections));
+        self.registry = Some(registry.clone());
 
-        let manager = self.manager.clone();
-        let max_connections = self.config.max_connections;
+        if let Some(tcp_listener) = tcp_listener {
+            let addr = tcp_listener.local_addr()?;
+            info!("Socket server listening on tcp://{}", addr);
+            self.tcp_addr = Some(addr);
+            tokio::spawn(Self::accept_tcp(
+                tcp_listener,
+                #[cfg(feature = "tls")]
+                tls,
+                self.manager.clone(),
+                self.config.clone(),
+                shutdown_rx.resubscribe(),
+                registry.clone(),
+            ));
+        }
 
-        tokio::spawn(async move {
-            let active_connections = Arc::new(AtomicUsize::new(0));
+        #[cfg(feature = "metrics-prometheus")]
+        if let Some(metrics_listener) = metrics_listener {
+            let addr = metrics_listener.local_addr()?;
+            info!("Prometheus metrics on http://{}/metrics", addr);
+            self.metrics_addr = Some(addr);
+            tokio::spawn(prometheus::serve(
+                metrics_listener,
+                self.manager.clone(),
+                shutdown_rx.resubscribe(),
+            ));
+        }
 
-            loop {
-                tokio::select! {
-                    accept_result = listener.accept() => {
-                        match accept_result {
-                            Ok((stream, _addr)) => {
-                                let current_connections = active_connections.load(Ordering::Relaxed);
-                                if current_connections >= max_connections {
-                                    warn!("Maximum connections reached ({}), rejecting new connection", current_connections);
-                                    continue;
-                                }
+        tokio::spawn(Self::accept_unix(
+            listener,
+            self.manager.clone(),
+            self.config.clone(),
+            shutdown_rx,
+            registry,
+        ));
 
-                                active_connections.fetch_add(1, Ordering::Relaxed);
-                                let new_count = active_connections.load(Ordering::Relaxed);
-                                debug!("New client connected. Active connections: {}", new_count);
+        Ok(())
+    }
 
-                                let manager_clone = manager.clone();
-                                let mut shutdown_rx_clone = shutdown_rx.resubscribe();
-                                let active_connections_clone = active_connections.clone();
+    async fn accept_unix(
+        listener: UnixListener,
+        manager: Arc<ControllerManager>,
+        config: Arc<SocketServerConfig>,
+        mut shutdown_rx: broadcast::Receiver<()>,
+        registry: Arc<ConnectionRegistry>,
+    ) {
+        loop {
+            tokio::select! {
+                (slot, accept_result) = Self::admit(&registry, &config, listener.accept()) => {
+                    match accept_result {
+                        Ok((stream, _addr)) => {
+                            let peer = match PeerIdentity::of(&stream) {
+                                Ok(peer) => Some(peer),
+                                Err(e) => {
+                                    warn!("Could not read peer credentials: {}", e);
+                                    None
+                                }
+                            };
+                            if !peer_allowed(&config, peer.as_ref()) {
+                                match &peer {
+                                    Some(peer) => warn!("Denied connection from {}: uid not allowed", peer),
+                                    None => warn!("Denied connection from unidentified peer"),
+                                }
+                                continue;
+                            }
+                            if let Some(peer) = &peer {
+                                info!("Accepted connection from {}", peer);
+                            }
 
-                                tokio::spawn(async move {
-                                    let result = Self::handle_client(stream, manager_clone, &mut shutdown_rx_clone).await;
-                                    if let Err(e) = result {
-                                        error!("Client handler error: {}", e);
-                                    }
+                            Self::spawn_client(
+                                async { Ok(stream) },
+                                Peer::Unix(peer),
+                                slot,
+                                manager.clone(),
+                                config.clone(),
+                                shutdown_rx.resubscribe(),
+                                registry.clone(),
+                            );
+                        }
+                        Err(e) => {
+                            error!("Failed to accept connection: {}", e);
+                        }
+                    }
+                }
+                _ = registry.shutdown_begun() => {
+                    info!("Socket server shutting down");
+                    break;
+                }
+                _ = shutdown_rx.recv() => {
+                    info!("Socket server shutting down");
+                    break;
+                }
+            }
+        }
+    }
 
-                                    let remaining = active_connections_clone.fetch_sub(1, Ordering::Relaxed) - 1;
-                                    debug!("Client disconnected. Active connections: {}", remaining);
-                                });
+    async fn accept_tcp(
+        listener: TcpListener,
+        #[cfg(feature = "tls")] tls: Option<tokio_rustls::TlsAcceptor>,
+        manager: Arc<ControllerManager>,
+        config: Arc<SocketServerConfig>,
+        mut shutdown_rx: broadcast::Receiver<()>,
+        registry: Arc<ConnectionRegistry>,
+    ) {
+        loop {
+            tokio::select! {
+                (slot, accept_result) = Self::admit(&registry, &config, listener.accept()) => {
+                    match accept_result {
+                        Ok((stream, addr)) => {
+                            if !peer_allowed(&config, None) {
+                                warn!("Denied TCP connection from {}: allowed_uids can't be checked over TCP", addr);
+                                continue;
                             }
-                            Err(e) => {
-                                error!("Failed to accept connection: {}", e);
+                            info!("Accepted TCP connection from {}", addr);
+
+                            let peer = Peer::Tcp(addr);
+                            let (manager, config) = (manager.clone(), config.clone());
+                            let (shutdown_rx, registry) = (shutdown_rx.resubscribe(), registry.clone());
+                            #[cfg(feature = "tls")]
+                            if let Some(tls) = &tls {
+                                let handshake = tls::handshake(tls, stream, config.tls_handshake_timeout);
+                                Self::spawn_client(handshake, peer, slot, manager, config, shutdown_rx, registry);
+                                continue;
                             }
+                            Self::spawn_client(async { Ok(stream) }, peer, slot, manager, config, shutdown_rx, registry);
+                        }
+                        Err(e) => {
+                            error!("Failed to accept TCP connection: {}", e);
                         }
                     }
-                    _ = shutdown_rx.recv() => {
-                        info!("Socket server shutting down");
-                        break;
+                }
+                _ = registry.shutdown_begun() => break,
+                _ = shutdown_rx.recv() => break,
+            }
+        }
+    }
+
+    /// Runs `accept`, then waits for a free slot for what it returned. The
+    /// slot is only claimed once a client has actually arrived, so a listener
+    /// sitting idle doesn't hold one the other transport could use. At
+    /// `max_connections` the accepted client waits without a reply until a
+    /// connection closes, and the loop doesn't accept again until it's in.
+    async fn admit<T>(
+        registry: &ConnectionRegistry,
+        config: &SocketServerConfig,
+        accept: impl Future<Output = T>,
+    ) -> (OwnedSemaphorePermit, T) {
+        let accepted = accept.await;
+        let slot = match registry.reserve() {
+            Some(slot) => slot,
+            None => {
+                warn!(
+                    "Maximum connections reached ({}), waiting for one to close before serving more",
+                    config.max_connections
+                );
+                registry.acquire().await
+            }
+        };
+        (slot, accepted)
+    }
+
+    /// Serves one client on its own task once `stream` resolves, which for
+    /// TLS is after the handshake, so a slow handshake holds up no one else.
+    /// The connection is listed in `registry` until it closes, and an admin
+    /// `disconnect` drops it whatever it is doing.
+    fn spawn_client<S>(
+        stream: impl Future<Output = std::io::Result<S>> + Send + 'static,
+        peer: Peer,
+        slot: OwnedSemaphorePermit,
+        manager: Arc<ControllerManager>,
+        config: Arc<SocketServerConfig>,
+        mut shutdown_rx: broadcast::Receiver<()>,
+        registry: Arc<ConnectionRegistry>,
+    ) where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (id, disconnected) = registry.register(peer.to_string(), slot);
+        manager.metrics().connection_opened();
+        debug!(
+            "New client connected as connection {}. Active connections: {}",
+            id,
+            registry.len()
+        );
+
+        let span = peer.span();
+        tokio::spawn(
+            async move {
+                // Declared first so it drops last, after the connection's
+                // subscriptions, however the task ends.
+                let _guard = ClientGuard {
+                    id,
+                    registry: registry.clone(),
+                    manager: manager.clone(),
+                    stop_moves: config.stop_on_disconnect,
+                };
+                let mut connection = ConnectionState::new(&config);
+                connection.id = id;
+                connection.admin = peer.is_admin(&config);
+                connection.transport = Transport::of(&peer, &config);
+                connection.registry = Some(registry.clone());
+
+                let serve = async {
+                    match stream.await {
+                        Ok(stream) => {
+                            Self::handle_client(
+                                stream,
+                                manager.clone(),
+                                config,
+                                connection,
+                                &mut shutdown_rx,
+                            )
+                            .await
+                        }
+                        Err(e) => Err(anyhow::anyhow!("Connection handshake failed: {}", e)),
+                    }
+                };
+                let result = tokio::select! {
+                    result = serve => result,
+                    _ = disconnected => {
+                        info!("Connection {} closed by an admin", id);
+                        Ok(())
                     }
+                };
+                if let Err(e) = result {
+                    error!("Client handler error: {}", e);
                 }
             }
-        });
+            .instrument(span),
+        );
+    }
 
-        Ok(())
+    /// The address of the TCP transport, once `start` has bound it.
+    pub fn tcp_addr(&self) -> Option<std::net::SocketAddr> {
+        self.tcp_addr
+    }
+
+    /// The address of the Prometheus endpoint, once `start` has bound it.
+    #[cfg(feature = "metrics-prometheus")]
+    pub fn metrics_addr(&self) -> Option<std::net::SocketAddr> {
+        self.metrics_addr
+    }
+
+    /// Binds `config.socket_path`, replacing any stale socket file, and
+    /// applies `socket_mode`.
+    async fn bind(&self) -> Result<UnixListener> {
+        if Path::new(&self.config.socket_path).exists() {
+            tokio::fs::remove_file(&self.config.socket_path).await?;
+        }
+
+        let listener = UnixListener::bind(&self.config.socket_path)?;
+        if let Some(mode) = self.config.socket_mode {
+            let permissions = std::fs::Permissions::from_mode(mode);
+            if let Err(e) = std::fs::set_permissions(&self.config.socket_path, permissions) {
+                // Don't leave a socket behind with the wrong permissions.
+                let _ = std::fs::remove_file(&self.config.socket_path);
+                anyhow::bail!(
+                    "Failed to set mode {:o} on {}: {}",
+                    mode,
+                    self.config.socket_path,
+                    e
+                );
+            }
+        }
+        Ok(listener)
+    }
+
+    /// Executes each command in a JSON Lines file in order, logging every
+    /// result; blank lines are skipped. Returns how many commands succeeded.
+    /// In strict mode the first failure is returned as an error.
+    pub async fn replay_script(
+        path: &Path,
+        manager: &ControllerManager,
+        strict: bool,
+    ) -> Result<usize> {
+        let script = tokio::fs::read_to_string(path).await?;
+        info!("Replaying startup script {}", path.display());
+
+        let mut succeeded = 0;
+        for (index, line) in script.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line_no = index + 1;
+
+            let result = match parse_command(line) {
+                Ok(command) => Self::execute_command(command, manager, None, None).await,
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Ok(data) => {
+                    info!("Script line {}: {}", line_no, data);
+                    succeeded += 1;
+                }
+                Err(e) if strict => {
+                    anyhow::bail!("Startup script line {} failed: {}", line_no, e);
+                }
+                Err(e) => warn!("Script line {} failed: {}", line_no, e),
+            }
+        }
+        Ok(succeeded)
     }
 
+    /// Stops accepting, then gives commands that are still running up to
+    /// `shutdown_grace` to finish before closing every connection. Commands
+    /// arriving in the meantime are answered with `SERVER_SHUTTING_DOWN`.
     pub async fn shutdown(&self) -> Result<()> {
+        if let Some(registry) = &self.registry {
+            registry.begin_shutdown();
+            let grace = self.config.shutdown_grace;
+            if tokio::time::timeout(grace, registry.drained())
+                .await
+                .is_err()
+            {
+                warn!(
+                    "Commands still running after {:?}, closing their connections anyway",
+                    grace
+                );
+            }
+        }
         if let Some(shutdown_tx) = &self.shutdown_tx {
             let _ = shutdown_tx.send(());
         }
 
         // Remove socket file
-        if Path::new(&self.config.socket_path).exists() {
+        if self.owns_socket
+            && !self.socket_removed.swap(true, Ordering::SeqCst)
+            && Path::new(&self.config.socket_path).exists()
+        {
             tokio::fs::remove_file(&self.config.socket_path).await?;
         }
 
@@ -117,27 +503,116 @@ impl SocketServer {
         Ok(())
     }
 
-    async fn handle_client(
-        stream: UnixStream,
+    pub(crate) async fn handle_client<S>(
+        stream: S,
         manager: Arc<ControllerManager>,
-        shutdown_rx: &mut tokio::sync::broadcast::Receiver<()>,
-    ) -> Result<()> {
-        let mut framed = Framed::new(stream, LinesCodec::new());
+        config: Arc<SocketServerConfig>,
+        mut connection: ConnectionState,
+        shutdown_rx: &mut broadcast::Receiver<()>,
+    ) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let mut framed = Framed::new(
+            stream,
+            MessageCodec::new(config.framing, config.max_message_length),
+        );
+        if let Some(nonce) = &connection.challenge {
+            let frame = ServerResponse::success(None, json!({"challenge": nonce}));
+            framed
+                .send(serialize_response(&stamp(frame, &config))?)
+                .await?;
+        }
+        let mut heartbeat = config
+            .heartbeat_interval
+            .map(|period| tokio::time::interval_at(Instant::now() + period, period));
+        let (progress_tx, mut progress_rx) = mpsc::channel(PROGRESS_BUFFER);
+        connection.progress = Some(progress_tx);
+        // Under `stop_on_disconnect` the connection keeps reading while a
+        // command runs, so a client hanging up mid-move is seen at once.
+        // Lines read that way wait here for their turn.
+        let watch_hangup = config.stop_on_disconnect;
+        let mut read_ahead = VecDeque::new();
 
         loop {
             tokio::select! {
-                line_result = framed.next() => {
+                line_result = next_line(&mut read_ahead, &mut framed) => {
                     match line_result {
                         Some(Ok(line)) => {
                             debug!("Received command: {}", line);
 
-                            let response = Self::process_command(&line, &manager).await;
+                            let response = {
+                                let _running = connection.registry.as_ref().map(|registry| registry.command_started());
+                                let process = Self::process_command(&line, &manager, &config, &mut connection);
+                                tokio::pin!(process);
+                                loop {
+                                    tokio::select! {
+                                        biased;
+                                        Some(frame) = progress_rx.recv() => {
+                                            if let Err(e) = framed.send(serialize_response(&stamp(frame, &config))?).await {
+                                                warn!("Failed to send progress frame, closing connection: {}", e);
+                                                return Ok(());
+                                            }
+                                        }
+                                        read = framed.next(), if watch_hangup
+                                            && read_ahead.len() < READ_AHEAD_LIMIT
+                                            && read_ahead.back().is_none_or(Result::is_ok) => {
+                                            match read {
+                                                Some(read) => read_ahead.push_back(read),
+                                                None => {
+                                                    debug!("Client hung up during a command");
+                                                    return Ok(());
+                                                }
+                                            }
+                                        }
+                                        _ = shutdown_rx.recv() => {
+                                            debug!("Shutdown grace period over, closing connection mid-command");
+                                            return Ok(());
+                                        }
+                                        response = &mut process => break response,
+                                    }
+                                }
+                            };
+                            // Frames queued just before the command finished.
+                            while let Ok(frame) = progress_rx.try_recv() {
+                                framed.send(serialize_response(&stamp(frame, &config))?).await?;
+                            }
                             let response_json = serialize_response(&response)?;
+                            #[cfg(feature = "compression")]
+                            let response_json = match connection.compression {
+                                Some(algorithm) => compression::encode(
+                                    response_json,
+                                    algorithm,
+                                    config.compression_threshold,
+                                )?,
+                                None => response_json,
+                            };
 
                             if let Err(e) = framed.send(response_json).await {
                                 error!("Failed to send response: {}", e);
                                 break;
                             }
+                            if connection.close_after_reply {
+                                break;
+                            }
+
+                            if let Some(spec) = connection.binary_stream.take() {
+                                debug!("Switching connection to binary position stream");
+                                return binary_stream::run(framed.into_inner(), &manager, spec, shutdown_rx).await;
+                            }
+                        }
+                        Some(Err(e)) if codec::is_too_long(&e) => {
+                            warn!("Closing connection after an oversized message");
+                            let response = ServerResponse::error_with_code(
+                                None,
+                                format!(
+                                    "Message exceeds {} bytes",
+                                    config.max_message_length
+                                ),
+                                "MESSAGE_TOO_LONG".to_string(),
+                            );
+                            let _ = framed.send(serialize_response(&stamp(response, &config))?).await;
+                            break;
                         }
                         Some(Err(e)) => {
                             error!("Error reading from client: {}", e);
@@ -149,6 +624,22 @@ impl SocketServer {
                         }
                     }
                 }
+                frame = connection.subscriptions.next_frame() => {
+                    if let Err(e) = framed.send(serialize_response(&stamp(frame, &config))?).await {
+                        warn!("Failed to send subscription event, closing connection: {}", e);
+                        break;
+                    }
+                }
+                _ = next_heartbeat(&mut heartbeat) => {
+                    let frame = ServerResponse::success(
+                        None,
+                        json!({"heartbeat": Utc::now().timestamp_millis()}),
+                    );
+                    if let Err(e) = framed.send(serialize_response(&stamp(frame, &config))?).await {
+                        warn!("Failed to send heartbeat, closing connection: {}", e);
+                        break;
+                    }
+                }
                 _ = shutdown_rx.recv() => {
                     debug!("Shutdown signal received, closing client connection");
                     break;
@@ -159,34 +650,689 @@ impl SocketServer {
         Ok(())
     }
 
-    async fn process_command(line: &str, manager: &ControllerManager) -> ServerResponse {
-        let command = match parse_command(line) {
-            Ok(cmd) => cmd,
-            Err(e) => {
-                return ServerResponse::error(None, format!("Failed to parse command: {}", e));
-            }
+    async fn process_command(
+        line: &str,
+        manager: &ControllerManager,
+        config: &SocketServerConfig,
+        connection: &mut ConnectionState,
+    ) -> ServerResponse {
+        let response = Self::respond(line, manager, config, connection).await;
+        stamp(response, config)
+    }
+
+    async fn respond(
+        line: &str,
+        manager: &ControllerManager,
+        config: &SocketServerConfig,
+        connection: &mut ConnectionState,
+    ) -> ServerResponse {
+        let parse_failed = |id, e: ProtocolError| {
+            ServerResponse::error_with_details(
+                id,
+                format!("Failed to parse command: {}", e),
+                e.code().to_string(),
+                e.details(),
+            )
+        };
+        let (mut command, unknown_fields) = match parse_command_checked(line) {
+            Ok(parsed) => parsed,
+            Err(e) => return parse_failed(None, e),
         };
 
         let command_id = command.id().cloned();
 
-        let result = Self::execute_command(command, manager).await;
+        if connection
+            .registry
+            .as_ref()
+            .is_some_and(|registry| registry.is_shutting_down())
+        {
+            return ServerResponse::error_with_code(
+                command_id,
+                "Server is shutting down".to_string(),
+                "SERVER_SHUTTING_DOWN".to_string(),
+            );
+        }
+
+        if let Some(field) = unknown_fields.first() {
+            if config.strict_parse {
+                return parse_failed(command_id, ProtocolError::UnknownField(field.clone()));
+            }
+            warn!(
+                command_type = command.wire_name(),
+                fields = ?unknown_fields,
+                "Ignoring unknown fields"
+            );
+        }
+
+        if let ClientCommand::Auth { token, hmac, .. } = &command {
+            return Self::authenticate(
+                command_id,
+                token.as_deref(),
+                hmac.as_deref(),
+                config,
+                connection,
+            );
+        }
+
+        // Answered before authentication, so a client can find out whether
+        // it needs to authenticate at all.
+        if let ClientCommand::ServerInfo { .. } = &command {
+            return ServerResponse::success(
+                command_id,
+                Self::server_info(manager, config, connection.transport),
+            );
+        }
+
+        if !connection.authenticated {
+            return ServerResponse::error_with_code(
+                command_id,
+                "Authentication required".to_string(),
+                "UNAUTHENTICATED".to_string(),
+            );
+        }
+
+        if let Some(axes) = command.axes() {
+            if axes.len() > config.max_multi_axes {
+                return ServerResponse::error_with_details(
+                    command_id,
+                    format!(
+                        "{} axes given, at most {} allowed",
+                        axes.len(),
+                        config.max_multi_axes
+                    ),
+                    "TOO_MANY_ITEMS".to_string(),
+                    Some(json!({"field": "axes", "limit": config.max_multi_axes})),
+                );
+            }
+        }
+
+        if let Some(controller @ None) = command.controller_mut() {
+            match &connection.default_controller {
+                Some(default) => *controller = Some(default.clone()),
+                None => {
+                    return ServerResponse::error_with_details(
+                        command_id,
+                        no_controller().message,
+                        "MISSING_FIELD".to_string(),
+                        Some(json!({"field": "controller"})),
+                    );
+                }
+            }
+        }
+
+        // A retried motion command gets the reply of the first attempt
+        // rather than moving again, and isn't a duplicate. Reusing the id for
+        // a different command is refused rather than answered with the
+        // reply of the first.
+        let idempotency = match (&command_id, &connection.idempotency) {
+            (Some(id), Some(cache)) if command.is_motion() => {
+                let fingerprint = fingerprint(&command);
+                if let Some((prior_fingerprint, prior)) = cache.get(id).await {
+                    if prior_fingerprint != fingerprint {
+                        return ServerResponse::error_with_code(
+                            Some(id.clone()),
+                            format!("Command id {} was already used for a different command", id),
+                            "IDEMPOTENCY_MISMATCH".to_string(),
+                        );
+                    }
+                    debug!(id = %id, "Replaying the reply to a retried command");
+                    return prior;
+                }
+                Some((id.clone(), fingerprint, cache.clone()))
+            }
+            _ => None,
+        };
+
+        if let (Some(id), Some(recent_ids)) = (&command_id, connection.recent_ids.as_mut()) {
+            if !recent_ids.insert(id) {
+                return ServerResponse::error_with_code(
+                    command_id.clone(),
+                    format!("Duplicate command id: {}", id),
+                    "DUPLICATE_ID".to_string(),
+                );
+            }
+        }
+
+        if command.is_motion() {
+            if let Some(limiter) = connection.rate_limiter.as_mut() {
+                if let Err(wait) = limiter.try_acquire() {
+                    return ServerResponse::error_with_code(
+                        command_id,
+                        format!(
+                            "Rate limit exceeded, retry after {} ms",
+                            wait.as_millis().max(1)
+                        ),
+                        "RATE_LIMITED".to_string(),
+                    );
+                }
+            }
+        }
+
+        if command.is_destructive() {
+            if let Some(confirmations) = connection.confirmations.as_mut() {
+                if let Err(e) = confirmations.redeem(&command) {
+                    return ServerResponse::from_error(command_id, &e.into());
+                }
+            }
+        }
+
+        let kind = command.wire_name();
+        let controller = command.controller().map(String::from);
+        let axis = command.axis().map(String::from);
+        let started = Instant::now();
+
+        let result = match command {
+            ClientCommand::Subscribe {
+                controller: Some(controller),
+                axis,
+                ..
+            } => Self::subscribe(controller, axis, manager, config, connection).await,
+            ClientCommand::SubscribeBinary {
+                controller: Some(controller),
+                axes,
+                interval_ms,
+                ..
+            } => {
+                Self::subscribe_binary(controller, axes, interval_ms, manager, config, connection)
+                    .await
+            }
+            ClientCommand::Hello { compression, .. } => {
+                Ok(Self::hello(&compression, config, connection))
+            }
+            ClientCommand::Prepare { command, .. } => Self::prepare(*command, connection),
+            ClientCommand::UseController { controller, .. } => {
+                connection.default_controller = Some(controller.clone());
+                Ok(json!({"controller": controller}))
+            }
+            ClientCommand::Unsubscribe { subscription, .. } => {
+                let unsubscribed = connection.subscriptions.remove(subscription);
+                if let Some(registry) = &connection.registry {
+                    registry.unsubscribed(connection.id, subscription);
+                }
+                Ok(json!({"subscription": subscription, "unsubscribed": unsubscribed}))
+            }
+            ClientCommand::Limits { .. } => Ok(Self::limits(manager, config)),
+            ClientCommand::ListConnections { .. } => Self::list_connections(connection),
+            ClientCommand::ListSubscriptions { .. } => Self::list_subscriptions(connection),
+            ClientCommand::Disconnect { connection_id, .. } => {
+                Self::disconnect(connection_id, connection)
+            }
+            command @ ClientCommand::Move {
+                stream_progress: true,
+                wait: true,
+                dry_run: false,
+                ..
+            } if connection.progress.is_some() => {
+                Self::move_streaming(command, manager, config, connection).await
+            }
+            command => {
+                let owner = config.stop_on_disconnect.then_some(connection.id);
+                Self::execute_command(command, manager, config.command_queue_timeout, owner)
+                    .await
+                    .map_err(handler_failed)
+            }
+        };
+
+        debug!(
+            command_type = kind,
+            controller = controller.as_deref().unwrap_or_default(),
+            axis = axis.as_deref().unwrap_or_default(),
+            duration_ms = started.elapsed().as_secs_f64() * 1000.0,
+            success = result.is_ok(),
+            "Command completed"
+        );
 
-        match result {
+        let response = match result {
             Ok(data) => ServerResponse::success(command_id, data),
-            Err(e) => ServerResponse::error(command_id, e.to_string()),
+            Err(e) => ServerResponse::from_error(command_id, &e),
+        };
+        if let Some((id, fingerprint, cache)) = idempotency {
+            if !is_unattempted(&response) {
+                cache.insert(id, (fingerprint, response.clone())).await;
+            }
+        }
+        response
+    }
+
+    /// Issues a `confirm_token` for a destructive `command`, which is
+    /// addressed the way it would be if sent on this connection.
+    fn prepare(
+        mut command: ClientCommand,
+        connection: &mut ConnectionState,
+    ) -> Result<serde_json::Value> {
+        if !command.is_destructive() {
+            return Err(CommandError::new(
+                "INVALID_PARAMS",
+                format!("{} needs no confirmation", command.wire_name()),
+            )
+            .into());
+        }
+        if let Some(controller @ None) = command.controller_mut() {
+            *controller = connection.default_controller.clone();
+        }
+        let confirmations = connection.confirmations.as_mut().ok_or_else(|| {
+            CommandError::new(
+                "UNSUPPORTED_COMMAND",
+                "This server doesn't require confirmation",
+            )
+        })?;
+        let token = confirmations.issue(&command);
+        Ok(json!({
+            "command": command.wire_name(),
+            "confirm_token": token,
+            "expires_in_ms": confirmations.ttl().as_millis() as u64,
+        }))
+    }
+
+    /// The server's connections, if this connection may administer them.
+    fn admin_registry(connection: &ConnectionState) -> Result<&ConnectionRegistry> {
+        if !connection.admin {
+            return Err(CommandError::new(
+                "FORBIDDEN",
+                "Admin commands are not allowed on this connection",
+            )
+            .into());
+        }
+        connection.registry.as_deref().ok_or_else(|| {
+            CommandError::new(
+                "UNSUPPORTED_COMMAND",
+                "Connection admin is only available on a client connection",
+            )
+            .into()
+        })
+    }
+
+    /// `current` is the id of the asking connection, so it can tell itself
+    /// apart.
+    fn list_connections(connection: &ConnectionState) -> Result<serde_json::Value> {
+        let connections = Self::admin_registry(connection)?.list();
+        Ok(json!({
+            "count": connections.len(),
+            "current": connection.id,
+            "connections": connections,
+        }))
+    }
+
+    fn list_subscriptions(connection: &ConnectionState) -> Result<serde_json::Value> {
+        let axes = Self::admin_registry(connection)?.subscriptions();
+        Ok(json!({
+            "total": axes.iter().map(|axis| axis.count).sum::<usize>(),
+            "axes": axes,
+        }))
+    }
+
+    fn disconnect(connection_id: u64, connection: &ConnectionState) -> Result<serde_json::Value> {
+        if !Self::admin_registry(connection)?.disconnect(connection_id) {
+            return Err(CommandError::new(
+                "CONNECTION_NOT_FOUND",
+                format!("No open connection with id {}", connection_id),
+            )
+            .into());
+        }
+        Ok(json!({"connection_id": connection_id, "disconnected": true}))
+    }
+
+    /// Runs a `stream_progress` move, queueing each progress event of its
+    /// axis as a frame for `handle_client` until the move has finished. The
+    /// event feed holds one of the `max_subscriptions` slots meanwhile.
+    async fn move_streaming(
+        command: ClientCommand,
+        manager: &ControllerManager,
+        config: &SocketServerConfig,
+        connection: &ConnectionState,
+    ) -> Result<serde_json::Value> {
+        let id = command.id().cloned();
+        let (controller, axis) = Self::resolve_axis(
+            command.controller().unwrap_or_default(),
+            command.axis().unwrap_or_default(),
+            manager,
+            config.command_queue_timeout,
+        )
+        .await?;
+        let slot = manager.reserve_subscription()?;
+        let mut events = Box::pin(
+            manager.subscribe_filtered(
+                EventFilter::new()
+                    .controller(controller)
+                    .axis(axis)
+                    .event_type(AxisEventType::Progress),
+            ),
+        );
+        let frames = connection.progress.clone();
+        let forward = tokio::spawn(async move {
+            let _slot = slot;
+            let Some(frames) = frames else { return };
+            while let Some(event) = events.next().await {
+                let data = serde_json::to_value(&event).unwrap_or_else(|_| json!({}));
+                if frames
+                    .send(ServerResponse::success(id.clone(), data))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let owner = config.stop_on_disconnect.then_some(connection.id);
+        let result = Self::execute_command(command, manager, config.command_queue_timeout, owner)
+            .await
+            .map_err(handler_failed);
+        // Awaited so that no progress frame can follow the reply.
+        forward.abort();
+        let _ = forward.await;
+        result
+    }
+
+    /// The real names of `controller`, which may be an alias, and of
+    /// `axis`, which may differ in case under `case_insensitive_axes`, as
+    /// events carry them.
+    async fn resolve_axis(
+        controller: &str,
+        axis: &str,
+        manager: &ControllerManager,
+        queue_timeout: Option<std::time::Duration>,
+    ) -> Result<(String, String)> {
+        let (controller, names) = Self::list_axes(controller, manager, queue_timeout).await?;
+        let axis = Self::match_axis(&names, axis, &controller, manager)?;
+        Ok((controller, axis))
+    }
+
+    /// The real name of `controller`, which may be an alias, and its axes.
+    async fn list_axes(
+        controller: &str,
+        manager: &ControllerManager,
+        queue_timeout: Option<std::time::Duration>,
+    ) -> Result<(String, Vec<String>)> {
+        let (tx, rx) = oneshot::channel();
+        let list = Command::ListAxes {
+            controller: controller.to_string(),
+            detailed: false,
+            filter: None,
+            offset: 0,
+            limit: None,
+            resp: tx,
+        };
+        Self::send(manager, list, queue_timeout).await?;
+        let listing = rx.await??;
+        // The listing names the real controller if `controller` is an alias,
+        // and events carry the real name.
+        let controller = listing["controller"]
+            .as_str()
+            .unwrap_or(controller)
+            .to_string();
+        let names = listing["axes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|name| name.as_str().map(String::from))
+            .collect();
+        Ok((controller, names))
+    }
+
+    /// The one of `names` that `axis` refers to: an exact match or, under
+    /// `case_insensitive_axes`, one differing only in case.
+    fn match_axis(
+        names: &[String],
+        axis: &str,
+        controller: &str,
+        manager: &ControllerManager,
+    ) -> Result<String> {
+        let folded = axis.to_lowercase();
+        let real = names.iter().find(|name| *name == axis).or_else(|| {
+            let ignore_case = manager.config().case_insensitive_axes;
+            names
+                .iter()
+                .find(|name| ignore_case && name.to_lowercase() == folded)
+        });
+        match real {
+            Some(real) => Ok(real.clone()),
+            None => anyhow::bail!("Axis not found: {} in controller {}", axis, controller),
+        }
+    }
+
+    /// Checks the per-connection limit before taking a global slot, so a
+    /// connection at its own limit never holds one up.
+    async fn subscribe(
+        controller: String,
+        axis: String,
+        manager: &ControllerManager,
+        config: &SocketServerConfig,
+        connection: &mut ConnectionState,
+    ) -> Result<serde_json::Value> {
+        connection.subscriptions.check_capacity()?;
+        let (controller, axis) =
+            Self::resolve_axis(&controller, &axis, manager, config.command_queue_timeout).await?;
+
+        let slot = manager.reserve_subscription()?;
+        let events = manager.subscribe_events();
+        let id = connection
+            .subscriptions
+            .add(controller.clone(), axis.clone(), events, slot)?;
+        if let Some(registry) = &connection.registry {
+            registry.subscribed(connection.id, id, &controller, &axis);
+        }
+        Ok(json!({"subscription": id, "controller": controller, "axis": axis}))
+    }
+
+    /// Validates the request and leaves it on the connection; `handle_client`
+    /// switches to the binary stream once this reply has been sent.
+    async fn subscribe_binary(
+        controller: String,
+        axes: Vec<String>,
+        interval_ms: u64,
+        manager: &ControllerManager,
+        config: &SocketServerConfig,
+        connection: &mut ConnectionState,
+    ) -> Result<serde_json::Value> {
+        if axes.is_empty() || axes.len() > u16::MAX as usize + 1 {
+            return Err(CommandError::new(
+                "INVALID_PARAMS",
+                format!("Expected 1 to {} axes", u16::MAX as usize + 1),
+            )
+            .into());
+        }
+        if interval_ms < binary_stream::MIN_INTERVAL_MS {
+            return Err(CommandError::new(
+                "INVALID_PARAMS",
+                format!(
+                    "interval_ms must be at least {}",
+                    binary_stream::MIN_INTERVAL_MS
+                ),
+            )
+            .into());
+        }
+
+        let (controller, names) =
+            Self::list_axes(&controller, manager, config.command_queue_timeout).await?;
+        let axes = axes
+            .iter()
+            .map(|axis| Self::match_axis(&names, axis, &controller, manager))
+            .collect::<Result<Vec<_>>>()?;
+
+        let slot = manager.reserve_subscription()?;
+        let reply = json!({
+            "stream": "binary",
+            "frame_size": binary_stream::FRAME_SIZE,
+            "controller": controller,
+            "axes": axes,
+            "interval_ms": interval_ms,
+        });
+        connection.binary_stream = Some(BinaryStream {
+            controller,
+            axes,
+            interval: std::time::Duration::from_millis(interval_ms),
+            slot,
+        });
+        Ok(reply)
+    }
+
+    /// `transport` describes the one the asking client came in on.
+    fn server_info(
+        manager: &ControllerManager,
+        config: &SocketServerConfig,
+        transport: Transport,
+    ) -> serde_json::Value {
+        let framing = match config.framing {
+            Framing::Lines => "lines",
+            Framing::LengthDelimited => "length_delimited",
+        };
+        let transport = match transport {
+            Transport::Unix => {
+                json!({"type": "unix", "path": config.socket_path, "framing": framing})
+            }
+            Transport::Tcp { tls } => json!({
+                "type": "tcp",
+                "address": config.tcp_addr,
+                "tls": tls,
+                "framing": framing,
+            }),
+        };
+        json!({
+            "server": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "protocol_version": PROTOCOL_VERSION,
+            "transport": transport,
+            "auth_required": config.auth_token.is_some(),
+            "auth_mode": match (&config.auth_token, config.auth_challenge) {
+                (None, _) => "none",
+                (Some(_), false) => "token",
+                (Some(_), true) => "challenge",
+            },
+            "features": {
+                "duplicate_id_rejection": config.reject_duplicate_ids,
+                "heartbeat_interval_ms": config.heartbeat_interval.map(|i| i.as_millis() as u64),
+                "compression": cfg!(feature = "compression"),
+            },
+            "limits": Self::limits(manager, config),
+        })
+    }
+
+    /// The limits a client should stay under, as `limits` reports them.
+    /// Connections are never closed for being idle, so `idle_timeout_ms`
+    /// is always null.
+    fn limits(manager: &ControllerManager, config: &SocketServerConfig) -> serde_json::Value {
+        let millis = |d: Option<std::time::Duration>| d.map(|d| d.as_millis() as u64);
+        json!({
+            "max_connections": config.max_connections,
+            "max_commands_per_sec": config.max_commands_per_sec,
+            "idle_timeout_ms": null,
+            "heartbeat_interval_ms": millis(config.heartbeat_interval),
+            "command_queue_timeout_ms": millis(config.command_queue_timeout),
+            "min_subscribe_interval_ms": binary_stream::MIN_INTERVAL_MS,
+            "poll_interval_ms": millis(manager.config().poll_interval),
+            "max_subscriptions_per_connection": config.max_subscriptions_per_connection,
+            "max_subscriptions": manager.config().max_subscriptions,
+            "max_message_length": config.max_message_length,
+            "max_multi_axes": config.max_multi_axes,
+        })
+    }
+
+    /// Settles the optional protocol features the client offered. Only
+    /// compression is negotiated so far.
+    fn hello(
+        offered_compression: &[String],
+        config: &SocketServerConfig,
+        connection: &mut ConnectionState,
+    ) -> serde_json::Value {
+        #[cfg(feature = "compression")]
+        {
+            connection.compression = compression::Compression::negotiate(offered_compression);
+            json!({
+                "protocol_version": PROTOCOL_VERSION,
+                "compression": connection.compression,
+                "compression_threshold": connection
+                    .compression
+                    .map(|_| config.compression_threshold),
+            })
+        }
+        #[cfg(not(feature = "compression"))]
+        {
+            let _ = (offered_compression, config, connection);
+            json!({
+                "protocol_version": PROTOCOL_VERSION,
+                "compression": null,
+                "compression_threshold": null,
+            })
+        }
+    }
+
+    /// In challenge mode any attempt uses up the nonce, right or wrong, so
+    /// each nonce can be answered once; a client that fails reconnects for
+    /// a new one.
+    fn authenticate(
+        id: Option<String>,
+        token: Option<&str>,
+        hmac: Option<&str>,
+        config: &SocketServerConfig,
+        connection: &mut ConnectionState,
+    ) -> ServerResponse {
+        let accepted = match &config.auth_token {
+            Some(expected) if config.auth_challenge => match (connection.challenge.take(), hmac) {
+                (Some(nonce), Some(hmac)) => verify_challenge(expected, &nonce, hmac),
+                _ => false,
+            },
+            Some(expected) => token.is_some_and(|token| tokens_match(expected, token)),
+            None => true,
+        };
+
+        if !accepted {
+            connection.auth_failures += 1;
+            if connection.auth_failures >= config.max_auth_failures {
+                warn!(
+                    "Client failed to authenticate {} times, closing connection",
+                    connection.auth_failures
+                );
+                connection.close_after_reply = true;
+            } else {
+                warn!("Client failed to authenticate");
+            }
+            return ServerResponse::error_with_code(
+                id,
+                "Invalid auth token".to_string(),
+                "UNAUTHENTICATED".to_string(),
+            );
+        }
+
+        connection.authenticated = true;
+        ServerResponse::success(id, json!({"authenticated": true}))
+    }
+
+    /// Queues `cmd` with the manager. With a `queue_timeout`, a manager too
+    /// backed up to queue it within that time fails the command with
+    /// `QUEUE_FULL`; without one, it waits for room.
+    async fn send(
+        manager: &ControllerManager,
+        cmd: Command,
+        queue_timeout: Option<std::time::Duration>,
+    ) -> Result<()> {
+        match queue_timeout {
+            Some(timeout) => manager.send_command_timeout(cmd, timeout).await,
+            None => manager.send_command(cmd).await,
         }
     }
 
+    /// Hands `command` to the manager through `send`.
+    /// Moves are tagged with `owner`, the connection to stop them for on
+    /// disconnect, if any.
     async fn execute_command(
         command: ClientCommand,
         manager: &ControllerManager,
+        queue_timeout: Option<std::time::Duration>,
+        owner: Option<u64>,
     ) -> Result<serde_json::Value> {
+        let send = |cmd| Self::send(manager, cmd, queue_timeout);
         match command {
             ClientCommand::Move {
-                controller,
+                controller: Some(controller),
                 axis,
                 target,
+                target_kind,
                 params,
+                dry_run,
+                wait,
+                force,
+                override_distance,
                 ..
             } => {
                 let (tx, rx) = oneshot::channel();
@@ -194,50 +1340,303 @@ impl SocketServer {
                     controller,
                     axis,
                     target,
+                    target_kind,
                     params,
+                    dry_run,
+                    wait,
+                    force,
+                    override_distance,
+                    owner,
                     resp: tx,
                 };
-                manager.send_command(cmd).await?;
+                send(cmd).await?;
                 rx.await?
             }
-            ClientCommand::Stop {
-                controller, axis, ..
+            ClientCommand::MoveToLimit {
+                controller: Some(controller),
+                axis,
+                direction,
+                params,
+                zero_position,
+                ..
             } => {
                 let (tx, rx) = oneshot::channel();
-                let cmd = Command::Stop {
+                let cmd = Command::MoveToLimit {
                     controller,
                     axis,
+                    direction,
+                    params,
+                    zero_position,
+                    owner,
                     resp: tx,
                 };
-                manager.send_command(cmd).await?;
+                send(cmd).await?;
                 rx.await?
             }
-            ClientCommand::GetState {
-                controller, axis, ..
-            } => {
+            ClientCommand::MoveAll {
+                axis_pattern,
+                target,
+                params,
+                confirm,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::MoveAll {
+                    axis_pattern,
+                    target,
+                    params,
+                    confirm,
+                    owner,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::MoveGroup {
+                group,
+                target,
+                params,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::MoveGroup {
+                    group,
+                    target,
+                    params,
+                    owner,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::StopGroup { group, .. } => {
+                let (tx, rx) = oneshot::channel();
+                send(Command::StopGroup { group, resp: tx }).await?;
+                rx.await?
+            }
+            ClientCommand::DefineGroup { group, members, .. } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::DefineGroup {
+                    group,
+                    members: members
+                        .into_iter()
+                        .map(|member| (member.controller, member.axis))
+                        .collect(),
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::Cancel {
+                controller: Some(controller),
+                axis,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::Cancel {
+                    controller,
+                    axis,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::Stop {
+                controller: Some(controller),
+                axis,
+                mode,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::Stop {
+                    controller,
+                    axis,
+                    mode: mode.unwrap_or_default(),
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::StopController {
+                controller: Some(controller),
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::StopController {
+                    controller,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::Pause {
+                controller: Some(controller),
+                axis,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::Pause {
+                    controller,
+                    axis,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::Resume {
+                controller: Some(controller),
+                axis,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::Resume {
+                    controller,
+                    axis,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::Enable {
+                controller: Some(controller),
+                axis,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::Enable {
+                    controller,
+                    axis,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::Disable {
+                controller: Some(controller),
+                axis,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::Disable {
+                    controller,
+                    axis,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::ResetFault {
+                controller: Some(controller),
+                axis,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::ResetFault {
+                    controller,
+                    axis,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::SetReference {
+                controller: Some(controller),
+                axis,
+                value,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::SetReference {
+                    controller,
+                    axis,
+                    value,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::SetAttribute {
+                controller: Some(controller),
+                axis,
+                attribute,
+                value,
+                verify,
+                tolerance,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::SetAttr {
+                    controller,
+                    axis,
+                    attr: attribute,
+                    value,
+                    verify,
+                    tolerance,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::GetState {
+                controller: Some(controller),
+                axis,
+                fresh,
+                ..
+            } => {
                 let (tx, rx) = oneshot::channel();
                 let cmd = Command::GetState {
                     controller,
                     axis,
+                    fresh,
                     resp: tx,
                 };
-                manager.send_command(cmd).await?;
+                send(cmd).await?;
                 rx.await?
             }
             ClientCommand::GetPosition {
-                controller, axis, ..
+                controller: Some(controller),
+                axis,
+                fresh,
+                ..
             } => {
                 let (tx, rx) = oneshot::channel();
                 let cmd = Command::GetPos {
                     controller,
                     axis,
+                    fresh,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::GetPositions {
+                controller: Some(controller),
+                axes,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::GetPositions {
+                    controller,
+                    axes,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::ControllerHealth {
+                controller: Some(controller),
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::ControllerHealth {
+                    controller,
                     resp: tx,
                 };
-                manager.send_command(cmd).await?;
+                send(cmd).await?;
                 rx.await?
             }
             ClientCommand::GetAttribute {
-                controller,
+                controller: Some(controller),
                 axis,
                 attribute,
                 ..
@@ -249,11 +1648,93 @@ impl SocketServer {
                     attr: attribute,
                     resp: tx,
                 };
-                manager.send_command(cmd).await?;
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::GetAttributeArray {
+                controller: Some(controller),
+                axis,
+                attribute,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::GetAttrArray {
+                    controller,
+                    axis,
+                    attr: attribute,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::GetControllerAttribute {
+                controller: Some(controller),
+                attribute,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::GetControllerAttr {
+                    controller,
+                    attr: attribute,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::GetAttributeInfo {
+                controller: Some(controller),
+                axis,
+                attribute,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::GetAttrInfo {
+                    controller,
+                    axis,
+                    attr: attribute,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::WaitForState {
+                controller: Some(controller),
+                axis,
+                target_state,
+                timeout_ms,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::WaitForState {
+                    controller,
+                    axis,
+                    state: target_state,
+                    timeout: std::time::Duration::from_millis(timeout_ms),
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::GetHistory {
+                controller: Some(controller),
+                axis,
+                limit,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::GetHistory {
+                    controller,
+                    axis,
+                    limit,
+                    resp: tx,
+                };
+                send(cmd).await?;
                 rx.await?
             }
             ClientCommand::GetAvailableParams {
-                controller, axis, ..
+                controller: Some(controller),
+                axis,
+                ..
             } => {
                 let (tx, rx) = oneshot::channel();
                 let cmd = Command::GetAvailableParams {
@@ -261,11 +1742,13 @@ impl SocketServer {
                     axis,
                     resp: tx,
                 };
-                manager.send_command(cmd).await?;
+                send(cmd).await?;
                 rx.await?
             }
             ClientCommand::GetSupportedMovementParams {
-                controller, axis, ..
+                controller: Some(controller),
+                axis,
+                ..
             } => {
                 let (tx, rx) = oneshot::channel();
                 let cmd = Command::GetSupportedMovementParams {
@@ -273,31 +1756,2283 @@ impl SocketServer {
                     axis,
                     resp: tx,
                 };
-                manager.send_command(cmd).await?;
+                send(cmd).await?;
                 rx.await?
             }
-            ClientCommand::ListControllers { .. } => {
+            ClientCommand::GetCapabilities {
+                controller: Some(controller),
+                axis,
+                ..
+            } => {
                 let (tx, rx) = oneshot::channel();
-                let cmd = Command::ListControllers { resp: tx };
-                manager.send_command(cmd).await?;
+                let cmd = Command::GetCapabilities {
+                    controller,
+                    axis,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::GetLimitSwitches {
+                controller: Some(controller),
+                axis,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::GetLimitSwitches {
+                    controller,
+                    axis,
+                    resp: tx,
+                };
+                send(cmd).await?;
                 rx.await?
             }
-            ClientCommand::ListAxes { controller, .. } => {
+            ClientCommand::GetLimits {
+                controller: Some(controller),
+                axis,
+                ..
+            } => {
                 let (tx, rx) = oneshot::channel();
-                let cmd = Command::ListAxes {
+                let cmd = Command::GetLimits {
                     controller,
+                    axis,
                     resp: tx,
                 };
-                manager.send_command(cmd).await?;
+                send(cmd).await?;
                 rx.await?
             }
-            ClientCommand::Ping { .. } => Ok(json!({
-                "message": "pong",
-                "timestamp": chrono::Utc::now().to_rfc3339()
-            })),
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {}
+            ClientCommand::ReachableRange {
+                controller: Some(controller),
+                axis,
+                time_ms,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::ReachableRange {
+                    controller,
+                    axis,
+                    time_ms,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::SetDefaults {
+                controller: Some(controller),
+                axis,
+                params,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::SetDefaults {
+                    controller,
+                    axis,
+                    params,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::ResolveParams {
+                controller: Some(controller),
+                axis,
+                params,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::ResolveParams {
+                    controller,
+                    axis,
+                    params,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::SelfTest {
+                controller: Some(controller),
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                send(Command::SelfTest {
+                    controller,
+                    resp: tx,
+                })
+                .await?;
+                rx.await?
+            }
+            ClientCommand::InvalidateCache {
+                controller: Some(controller),
+                axis,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::Invalidate {
+                    controller,
+                    axis,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::ListControllers { .. } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::ListControllers { resp: tx };
+                send(cmd).await?;
+                rx.await?
+            }
+            ClientCommand::ListAliases { .. } => {
+                let (tx, rx) = oneshot::channel();
+                send(Command::ListAliases { resp: tx }).await?;
+                rx.await?
+            }
+            ClientCommand::Describe { .. } => {
+                let (tx, rx) = oneshot::channel();
+                send(Command::Describe { resp: tx }).await?;
+                rx.await?
+            }
+            ClientCommand::SystemHealth { .. } => {
+                let (tx, rx) = oneshot::channel();
+                send(Command::SystemHealth { resp: tx }).await?;
+                rx.await?
+            }
+            ClientCommand::ListAxes {
+                controller: Some(controller),
+                detailed,
+                filter,
+                offset,
+                limit,
+                ..
+            } => {
+                let (tx, rx) = oneshot::channel();
+                let cmd = Command::ListAxes {
+                    controller,
+                    detailed,
+                    filter,
+                    offset,
+                    limit,
+                    resp: tx,
+                };
+                send(cmd).await?;
+                rx.await?
+            }
+            // Answered here without reaching the manager, so counted here.
+            ClientCommand::Ping { .. } => {
+                manager.metrics().record_command("ping");
+                Ok(json!({
+                    "message": "pong",
+                    "timestamp": chrono::Utc::now().to_rfc3339()
+                }))
+            }
+            ClientCommand::Auth { .. } => Ok(json!({"authenticated": true})),
+            ClientCommand::ServerInfo { .. } | ClientCommand::Limits { .. } => {
+                Err(CommandError::new(
+                    "UNSUPPORTED_COMMAND",
+                    "Server info is only available on a client connection",
+                )
+                .into())
+            }
+            ClientCommand::Subscribe { .. }
+            | ClientCommand::SubscribeBinary { .. }
+            | ClientCommand::Unsubscribe { .. } => Err(CommandError::new(
+                "UNSUPPORTED_COMMAND",
+                "Subscriptions are only available on a client connection",
+            )
+            .into()),
+            ClientCommand::ListConnections { .. }
+            | ClientCommand::ListSubscriptions { .. }
+            | ClientCommand::Disconnect { .. } => Err(CommandError::new(
+                "UNSUPPORTED_COMMAND",
+                "Connection admin is only available on a client connection",
+            )
+            .into()),
+            ClientCommand::UseController { .. } => Err(CommandError::new(
+                "UNSUPPORTED_COMMAND",
+                "A default controller is only available on a client connection",
+            )
+            .into()),
+            ClientCommand::Prepare { .. } => Err(CommandError::new(
+                "UNSUPPORTED_COMMAND",
+                "Confirmation is only available on a client connection",
+            )
+            .into()),
+            ClientCommand::Hello { .. } => Err(CommandError::new(
+                "UNSUPPORTED_COMMAND",
+                "Negotiation is only available on a client connection",
+            )
+            .into()),
+            ClientCommand::Metrics { .. } => {
+                let mut metrics = serde_json::to_value(manager.metrics().snapshot())?;
+                metrics["cache"] = serde_json::to_value(manager.cache_stats().await)?;
+                metrics["queue_depth"] = manager.queue_depth().into();
+                Ok(metrics)
+            }
+            ClientCommand::Move {
+                controller: None, ..
+            }
+            | ClientCommand::MoveToLimit {
+                controller: None, ..
+            }
+            | ClientCommand::Cancel {
+                controller: None, ..
+            }
+            | ClientCommand::Stop {
+                controller: None, ..
+            }
+            | ClientCommand::StopController {
+                controller: None, ..
+            }
+            | ClientCommand::Pause {
+                controller: None, ..
+            }
+            | ClientCommand::Resume {
+                controller: None, ..
+            }
+            | ClientCommand::Enable {
+                controller: None, ..
+            }
+            | ClientCommand::Disable {
+                controller: None, ..
+            }
+            | ClientCommand::ResetFault {
+                controller: None, ..
+            }
+            | ClientCommand::SetReference {
+                controller: None, ..
+            }
+            | ClientCommand::SetAttribute {
+                controller: None, ..
+            }
+            | ClientCommand::GetState {
+                controller: None, ..
+            }
+            | ClientCommand::GetPosition {
+                controller: None, ..
+            }
+            | ClientCommand::GetPositions {
+                controller: None, ..
+            }
+            | ClientCommand::ControllerHealth {
+                controller: None, ..
+            }
+            | ClientCommand::GetAttribute {
+                controller: None, ..
+            }
+            | ClientCommand::GetAttributeArray {
+                controller: None, ..
+            }
+            | ClientCommand::GetControllerAttribute {
+                controller: None, ..
+            }
+            | ClientCommand::GetAttributeInfo {
+                controller: None, ..
+            }
+            | ClientCommand::WaitForState {
+                controller: None, ..
+            }
+            | ClientCommand::GetHistory {
+                controller: None, ..
+            }
+            | ClientCommand::GetAvailableParams {
+                controller: None, ..
+            }
+            | ClientCommand::GetSupportedMovementParams {
+                controller: None, ..
+            }
+            | ClientCommand::GetCapabilities {
+                controller: None, ..
+            }
+            | ClientCommand::GetLimits {
+                controller: None, ..
+            }
+            | ClientCommand::ReachableRange {
+                controller: None, ..
+            }
+            | ClientCommand::GetLimitSwitches {
+                controller: None, ..
+            }
+            | ClientCommand::SetDefaults {
+                controller: None, ..
+            }
+            | ClientCommand::ResolveParams {
+                controller: None, ..
+            }
+            | ClientCommand::SelfTest {
+                controller: None, ..
+            }
+            | ClientCommand::InvalidateCache {
+                controller: None, ..
+            }
+            | ClientCommand::ListAxes {
+                controller: None, ..
+            } => Err(no_controller().into()),
+        }
+    }
+}
+
+/// Releases a connection's place in the registry, and with it its
+/// `max_connections` slot, when its task ends, whether the handler
+/// returned, failed, panicked or was dropped at shutdown.
+struct ClientGuard {
+    id: u64,
+    registry: Arc<ConnectionRegistry>,
+    manager: Arc<ControllerManager>,
+    /// Stop the connection's in-flight moves; see `stop_on_disconnect`.
+    stop_moves: bool,
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        let remaining = self.registry.remove(self.id);
+        self.manager.metrics().connection_closed();
+        debug!("Client disconnected. Active connections: {}", remaining);
+
+        if self.stop_moves {
+            let (handle, owner) = (self.manager.handle(), self.id);
+            // Drop can't wait for the stops; failures of single axes are
+            // logged by the manager.
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                runtime.spawn(async move {
+                    let stopped = handle
+                        .request(|resp| Command::StopOwnedMoves { owner, resp })
+                        .await;
+                    if let Err(e) = stopped {
+                        warn!("Could not stop the moves of connection {}: {}", owner, e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Best-effort cleanup for a server dropped without `shutdown`: signals the
+/// accept loop and connections to stop and removes the socket file. `Drop`
+/// can't await, so the removal is synchronous and its errors are ignored,
+/// and connection tasks finish on their own after the signal rather than
+/// before `drop` returns. A server that was never started, that serves a
+/// handed-in listener or whose `shutdown` already removed the socket file
+/// leaves the path alone.
+impl Drop for SocketServer {
+    fn drop(&mut self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(());
+            if self.owns_socket && !self.socket_removed.swap(true, Ordering::SeqCst) {
+                let _ = std::fs::remove_file(&self.config.socket_path);
+            }
+        }
+    }
+}
+
+/// Adds the send time to `frame` unless `response_timestamps` is off.
+fn stamp(frame: ServerResponse, config: &SocketServerConfig) -> ServerResponse {
+    if config.response_timestamps {
+        frame.with_timestamp(Utc::now())
+    } else {
+        frame
+    }
+}
+
+/// The client's next line, taking any read ahead during the last command
+/// first.
+async fn next_line<S>(
+    read_ahead: &mut VecDeque<std::io::Result<String>>,
+    framed: &mut Framed<S, MessageCodec>,
+) -> Option<std::io::Result<String>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match read_ahead.pop_front() {
+        Some(line) => Some(line),
+        None => framed.next().await,
+    }
+}
+
+/// Errors of commands that never reached the hardware, so a retry should
+/// run them rather than get the error again. `TIMEOUT` isn't one: the
+/// handler had already called the controller when it gave up.
+const UNATTEMPTED_CODES: [&str; 3] = ["QUEUE_FULL", "MANAGER_DOWN", "SERVER_SHUTTING_DOWN"];
+
+fn is_unattempted(response: &ServerResponse) -> bool {
+    matches!(
+        response,
+        ServerResponse::Error { code: Some(code), .. } if UNATTEMPTED_CODES.contains(&code.as_str())
+    )
+}
+
+/// Tells commands apart for the idempotency cache: the same command sent
+/// again, id included, has the same fingerprint. It goes through a `Value`,
+/// whose maps are sorted, since the order of a deserialized `HashMap` such
+/// as the custom movement parameters differs from one parse to the next.
+fn fingerprint(command: &ClientCommand) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    serde_json::to_value(command)
+        .map(|value| value.to_string())
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Resolves on the next heartbeat tick, or never when heartbeats are off.
+async fn next_heartbeat(heartbeat: &mut Option<Interval>) {
+    match heartbeat {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// For a command that names no controller on a connection without a
+/// default.
+fn no_controller() -> CommandError {
+    CommandError::new(
+        "MISSING_FIELD",
+        "Missing required field: controller (no default set with use_controller)",
+    )
+}
+
+/// A handler that panics drops its reply channel; report that as an
+/// internal error rather than a bare "channel closed".
+fn handler_failed(err: anyhow::Error) -> anyhow::Error {
+    if err.is::<oneshot::error::RecvError>() {
+        CommandError::new("INTERNAL", "The command failed unexpectedly").into()
+    } else {
+        err
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        axis::{
+            movement_parameters::MovementParams, state::AxisState, state_info::AxisStateInfo, Axis,
+        },
+        controller_manager::config::ManagerConfig,
+        motor_controller::MotorController,
+        test_support::{pair::PairedClient, ScriptedController},
+    };
+    use tokio::net::UnixStream;
+
+    fn error_code(response: &ServerResponse) -> Option<&str> {
+        match response {
+            ServerResponse::Error { code, .. } => code.as_deref(),
+            ServerResponse::Success { .. } => None,
+        }
+    }
+
+    async fn run(
+        line: &str,
+        manager: &ControllerManager,
+        config: &SocketServerConfig,
+        connection: &mut ConnectionState,
+    ) -> ServerResponse {
+        SocketServer::process_command(line, manager, config, connection).await
+    }
+
+    #[tokio::test]
+    async fn test_auth_required_when_token_configured() {
+        let manager = ControllerManager::new(ManagerConfig::default());
+        let config = SocketServerConfig {
+            auth_token: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let mut connection = ConnectionState::new(&config);
+        let ping = r#"{"type": "ping"}"#;
+
+        let response = run(ping, &manager, &config, &mut connection).await;
+        assert_eq!(error_code(&response), Some("UNAUTHENTICATED"));
+
+        let bad_auth = r#"{"type": "auth", "token": "wrong"}"#;
+        let response = run(bad_auth, &manager, &config, &mut connection).await;
+        assert_eq!(error_code(&response), Some("UNAUTHENTICATED"));
+
+        let auth = r#"{"type": "auth", "token": "secret"}"#;
+        let response = run(auth, &manager, &config, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+
+        let response = run(ping, &manager, &config, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+    }
+
+    fn challenge_config() -> SocketServerConfig {
+        SocketServerConfig {
+            auth_token: Some("secret".to_string()),
+            auth_challenge: true,
+            ..Default::default()
+        }
+    }
+
+    fn hmac_auth(answer: &str) -> String {
+        format!(r#"{{"type": "auth", "hmac": "{}"}}"#, answer)
+    }
+
+    #[tokio::test]
+    async fn test_challenge_auth_accepts_correct_hmac() {
+        let manager = ControllerManager::new(ManagerConfig::default());
+        let config = challenge_config();
+        let mut connection = ConnectionState::new(&config);
+        let nonce = connection.challenge.clone().unwrap();
+        let ping = r#"{"type": "ping"}"#;
+
+        let response = run(ping, &manager, &config, &mut connection).await;
+        assert_eq!(error_code(&response), Some("UNAUTHENTICATED"));
+
+        let answer = connection::challenge_response("secret", &nonce);
+        let response = run(&hmac_auth(&answer), &manager, &config, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+        let response = run(ping, &manager, &config, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_challenge_auth_rejects_wrong_hmac_and_plain_token() {
+        let manager = ControllerManager::new(ManagerConfig::default());
+        let config = challenge_config();
+
+        let mut connection = ConnectionState::new(&config);
+        let nonce = connection.challenge.clone().unwrap();
+        let wrong = connection::challenge_response("guess", &nonce);
+        let response = run(&hmac_auth(&wrong), &manager, &config, &mut connection).await;
+        assert_eq!(error_code(&response), Some("UNAUTHENTICATED"));
+        // The failed attempt used up the nonce.
+        let right = connection::challenge_response("secret", &nonce);
+        let response = run(&hmac_auth(&right), &manager, &config, &mut connection).await;
+        assert_eq!(error_code(&response), Some("UNAUTHENTICATED"));
+
+        let mut connection = ConnectionState::new(&config);
+        let plain = r#"{"type": "auth", "token": "secret"}"#;
+        let response = run(plain, &manager, &config, &mut connection).await;
+        assert_eq!(error_code(&response), Some("UNAUTHENTICATED"));
+    }
+
+    #[tokio::test]
+    async fn test_challenge_auth_rejects_replayed_answer() {
+        let manager = ControllerManager::new(ManagerConfig::default());
+        let config = challenge_config();
+
+        let mut first = ConnectionState::new(&config);
+        let answer = connection::challenge_response("secret", first.challenge.as_ref().unwrap());
+        let response = run(&hmac_auth(&answer), &manager, &config, &mut first).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+
+        let mut second = ConnectionState::new(&config);
+        assert_ne!(first.challenge, second.challenge);
+        let response = run(&hmac_auth(&answer), &manager, &config, &mut second).await;
+        assert_eq!(error_code(&response), Some("UNAUTHENTICATED"));
+    }
+
+    #[tokio::test]
+    async fn test_challenge_sent_on_connect() {
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut client = PairedClient::connect(manager, challenge_config()).unwrap();
+
+        let frame = client.recv().await.unwrap().unwrap();
+        let nonce = frame["data"]["challenge"].as_str().unwrap();
+        assert_eq!(nonce.len(), 64);
+
+        let answer = connection::challenge_response("secret", nonce);
+        let reply = client.send_line(&hmac_auth(&answer)).await.unwrap();
+        assert_eq!(reply["data"]["authenticated"], true);
+
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_repeated_auth_failures_close_the_connection() {
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let config = SocketServerConfig {
+            auth_token: Some("secret".to_string()),
+            max_auth_failures: 2,
+            ..Default::default()
+        };
+        let mut client = PairedClient::connect(manager, config).unwrap();
+
+        for guess in ["first", "second"] {
+            let reply = client
+                .send(json!({"type": "auth", "token": guess}))
+                .await
+                .unwrap();
+            assert_eq!(reply["code"], "UNAUTHENTICATED");
+        }
+        assert!(client.recv().await.unwrap().is_none());
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_auth_disabled_by_default() {
+        let manager = ControllerManager::new(ManagerConfig::default());
+        let config = SocketServerConfig::default();
+        let mut connection = ConnectionState::new(&config);
+
+        let response = run(r#"{"type": "ping"}"#, &manager, &config, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_server_info_reports_configuration() {
+        let manager = ControllerManager::new(ManagerConfig::default());
+        let config = SocketServerConfig {
+            socket_path: "/tmp/info.sock".to_string(),
+            auth_token: Some("secret".to_string()),
+            framing: Framing::LengthDelimited,
+            max_commands_per_sec: Some(5),
+            ..Default::default()
+        };
+        let mut connection = ConnectionState::new(&config);
+
+        let line = r#"{"type": "server_info", "id": "i"}"#;
+        let response = run(line, &manager, &config, &mut connection).await;
+        let ServerResponse::Success { data, .. } = response else {
+            panic!("server_info failed before auth: {:?}", response);
+        };
+        assert_eq!(
+            data["transport"],
+            json!({"type": "unix", "path": "/tmp/info.sock", "framing": "length_delimited"})
+        );
+        assert_eq!(data["auth_required"], true);
+        assert_eq!(data["protocol_version"], PROTOCOL_VERSION);
+        assert_eq!(data["limits"]["max_commands_per_sec"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_limits_match_configuration() {
+        let manager = ControllerManager::new(ManagerConfig {
+            max_subscriptions: Some(12),
+            ..Default::default()
+        });
+        let config = SocketServerConfig {
+            max_connections: 3,
+            max_commands_per_sec: Some(20),
+            heartbeat_interval: Some(std::time::Duration::from_secs(2)),
+            max_subscriptions_per_connection: 4,
+            ..Default::default()
+        };
+        let mut connection = ConnectionState::new(&config);
+
+        let response = run(r#"{"type": "limits"}"#, &manager, &config, &mut connection).await;
+        let ServerResponse::Success { data, .. } = response else {
+            panic!("limits failed: {:?}", response);
+        };
+        assert_eq!(data["max_connections"], 3);
+        assert_eq!(data["max_commands_per_sec"], 20);
+        assert!(data["idle_timeout_ms"].is_null());
+        assert_eq!(data["heartbeat_interval_ms"], 2000);
+        assert_eq!(data["min_subscribe_interval_ms"], 1);
+        assert_eq!(data["max_subscriptions_per_connection"], 4);
+        assert_eq!(data["max_subscriptions"], 12);
+        assert_eq!(data["max_multi_axes"], config.max_multi_axes);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_applies_only_to_motion_commands() {
+        let manager = ControllerManager::new(ManagerConfig::default());
+        let config = SocketServerConfig {
+            max_commands_per_sec: Some(1),
+            ..Default::default()
+        };
+        let mut connection = ConnectionState::new(&config);
+        let stop = r#"{"type": "stop", "controller": "c", "axis": "X"}"#;
+
+        let response = run(stop, &manager, &config, &mut connection).await;
+        assert_ne!(error_code(&response), Some("RATE_LIMITED"));
+
+        let response = run(stop, &manager, &config, &mut connection).await;
+        assert_eq!(error_code(&response), Some("RATE_LIMITED"));
+
+        let response = run(r#"{"type": "ping"}"#, &manager, &config, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_ids_rejected_when_enabled() {
+        let manager = ControllerManager::new(ManagerConfig::default());
+        let config = SocketServerConfig {
+            reject_duplicate_ids: true,
+            ..Default::default()
+        };
+        let mut connection = ConnectionState::new(&config);
+        let ping = r#"{"type": "ping", "id": "1"}"#;
+
+        let response = run(ping, &manager, &config, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+
+        let response = run(ping, &manager, &config, &mut connection).await;
+        assert_eq!(error_code(&response), Some("DUPLICATE_ID"));
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_sent_on_idle_connection() {
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let config = SocketServerConfig {
+            heartbeat_interval: Some(std::time::Duration::from_millis(20)),
+            ..Default::default()
+        };
+        let mut client = PairedClient::connect(manager, config).unwrap();
+
+        let frame = client.recv().await.unwrap().unwrap();
+        assert_eq!(frame["status"], "success");
+        assert!(frame["data"]["heartbeat"].is_i64());
+
+        client.close().await.unwrap();
+    }
+
+    // See the manager's saturation test: an idle runtime stands in for a
+    // stuck command loop.
+    #[test]
+    fn test_full_manager_queue_fails_command() {
+        let runtime = || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+        };
+        let (backend, client) = (runtime(), runtime());
+        let manager = backend.block_on(async { ControllerManager::new(ManagerConfig::default()) });
+        let config = SocketServerConfig {
+            command_queue_timeout: Some(std::time::Duration::from_millis(20)),
+            ..Default::default()
+        };
+        let mut connection = ConnectionState::new(&config);
+
+        client.block_on(async {
+            let mut queued = Vec::new();
+            loop {
+                let (tx, rx) = oneshot::channel();
+                if manager
+                    .try_send_command(Command::ListControllers { resp: tx })
+                    .is_err()
+                {
+                    break;
+                }
+                queued.push(rx);
+            }
+
+            for line in [
+                r#"{"type": "list_controllers"}"#,
+                r#"{"type": "describe"}"#,
+                r#"{"type": "system_health"}"#,
+                r#"{"type": "subscribe", "controller": "ctrl", "axis": "X"}"#,
+            ] {
+                let response = run(line, &manager, &config, &mut connection).await;
+                assert_eq!(error_code(&response), Some("QUEUE_FULL"), "{}", line);
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn test_responses_carry_rfc3339_timestamp() {
+        let manager = ControllerManager::new(ManagerConfig::default());
+        let config = SocketServerConfig::default();
+        let mut connection = ConnectionState::new(&config);
+
+        let before = Utc::now();
+        for line in [r#"{"type": "ping"}"#, r#"{"type": "teleport"}"#] {
+            let response = run(line, &manager, &config, &mut connection).await;
+            let frame = serde_json::to_value(&response).unwrap();
+            let ts = chrono::DateTime::parse_from_rfc3339(frame["ts"].as_str().unwrap()).unwrap();
+            assert!(ts >= before && ts <= Utc::now(), "{}", line);
+        }
+
+        let config = SocketServerConfig {
+            response_timestamps: false,
+            ..Default::default()
+        };
+        let response = run(r#"{"type": "ping"}"#, &manager, &config, &mut connection).await;
+        let frame = serde_json::to_value(&response).unwrap();
+        assert!(frame.get("ts").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_parse_failures_carry_distinct_codes() {
+        let manager = ControllerManager::new(ManagerConfig::default());
+        let config = SocketServerConfig::default();
+        let mut connection = ConnectionState::new(&config);
+
+        let cases = [
+            (r#"{"type": "ping""#, "PARSE_ERROR"),
+            (r#"{"type": "teleport"}"#, "UNSUPPORTED_COMMAND"),
+            (r#"{"type": "get_state", "axis": "X"}"#, "MISSING_FIELD"),
+        ];
+        for (line, code) in cases {
+            let response = run(line, &manager, &config, &mut connection).await;
+            assert_eq!(error_code(&response), Some(code), "{}", line);
+        }
+
+        let line = r#"{"type": "move", "controller": "c", "axis": "X"}"#;
+        let response = run(line, &manager, &config, &mut connection).await;
+        let frame = serde_json::to_value(&response).unwrap();
+        assert_eq!(frame["code"], "MISSING_FIELD");
+        assert_eq!(frame["details"], json!({"field": "target"}));
+    }
+
+    #[tokio::test]
+    async fn test_axis_lists_capped_at_max_multi_axes() {
+        let manager = script_manager().await;
+        let config = SocketServerConfig {
+            max_multi_axes: 2,
+            ..Default::default()
+        };
+        let mut connection = ConnectionState::new(&config);
+
+        let line = r#"{"type": "get_positions", "controller": "ctrl", "axes": ["X", "Y"]}"#;
+        let response = run(line, &manager, &config, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+
+        let line = r#"{"type": "get_positions", "controller": "ctrl", "axes": ["X", "Y", "X"]}"#;
+        let response = run(line, &manager, &config, &mut connection).await;
+        let frame = serde_json::to_value(&response).unwrap();
+        assert_eq!(frame["code"], "TOO_MANY_ITEMS");
+        assert_eq!(frame["details"], json!({"field": "axes", "limit": 2}));
+    }
+
+    #[tokio::test]
+    async fn test_strict_parse_refuses_unknown_fields() {
+        let manager = script_manager().await;
+        let line = r#"{"type": "move", "controller": "ctrl", "axis": "X", "target": 2.0, "velocty": 10.0, "id": "m1"}"#;
+
+        let lenient = SocketServerConfig::default();
+        let mut connection = ConnectionState::new(&lenient);
+        let response = run(line, &manager, &lenient, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+        assert_eq!(position(&manager, "X").await, 2.0);
+
+        let strict = SocketServerConfig {
+            strict_parse: true,
+            ..Default::default()
+        };
+        let mut connection = ConnectionState::new(&strict);
+        let response = run(line, &manager, &strict, &mut connection).await;
+        let frame = serde_json::to_value(&response).unwrap();
+        assert_eq!(frame["code"], "UNKNOWN_FIELD");
+        assert_eq!(frame["id"], "m1");
+        assert_eq!(frame["details"], json!({"field": "velocty"}));
+
+        let line =
+            r#"{"type": "move", "controller": "ctrl", "axis": "X", "target": 3.0, "id": "m2"}"#;
+        let response = run(line, &manager, &strict, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+        assert_eq!(position(&manager, "X").await, 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_message_closes_connection() {
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let config = SocketServerConfig {
+            max_message_length: 64,
+            ..Default::default()
+        };
+        let mut client = PairedClient::connect(manager, config).unwrap();
+        // A ping padded out to `len` bytes through its id.
+        let ping = |len: usize| {
+            let padding = "x".repeat(len - r#"{"type": "ping", "id": ""}"#.len());
+            format!(r#"{{"type": "ping", "id": "{}"}}"#, padding)
+        };
+
+        let at_limit = ping(64);
+        assert_eq!(at_limit.len(), 64);
+        let response = client.send_line(&at_limit).await.unwrap();
+        assert_eq!(response["status"], "success");
+
+        let response = client.send_line(&ping(65)).await.unwrap();
+        assert_eq!(response["code"], "MESSAGE_TOO_LONG");
+        assert!(client.recv().await.unwrap().is_none());
+        client.close().await.unwrap();
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn test_large_reply_compressed_after_hello() {
+        let manager = ControllerManager::new(ManagerConfig::default());
+        let names: Vec<String> = (0..1000).map(|i| format!("axis-{:04}", i)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        manager
+            .register_controller(
+                "ctrl".to_string(),
+                Arc::new(ScriptedController::new("ctrl", &names)),
+            )
+            .await
+            .unwrap();
+        let manager = Arc::new(manager);
+        let config = Arc::new(SocketServerConfig::default());
+
+        let connect = || {
+            let (server, client) = UnixStream::pair().unwrap();
+            let (manager, config) = (manager.clone(), config.clone());
+            tokio::spawn(async move {
+                let (_shutdown_tx, mut shutdown_rx) = tokio::sync::broadcast::channel(1);
+                let connection = ConnectionState::new(&config);
+                SocketServer::handle_client(server, manager, config, connection, &mut shutdown_rx)
+                    .await
+            });
+            Framed::new(client, tokio_util::codec::LinesCodec::new())
+        };
+        let list_axes = r#"{"type": "list_axes", "controller": "ctrl"}"#;
+
+        let mut plain = connect();
+        plain.send(list_axes).await.unwrap();
+        let plain_frame = plain.next().await.unwrap().unwrap();
+
+        let mut compressed = connect();
+        let hello = exchange(
+            &mut compressed,
+            r#"{"type": "hello", "compression": ["gzip"]}"#,
+        )
+        .await;
+        assert_eq!(hello["data"]["compression"], "gzip");
+        compressed.send(list_axes).await.unwrap();
+        let frame = compressed.next().await.unwrap().unwrap();
+        assert!(frame.len() < plain_frame.len() / 2, "{} bytes", frame.len());
+
+        let frame: serde_json::Value = serde_json::from_str(&frame).unwrap();
+        assert_eq!(frame["compressed"], "gzip");
+        let plain_frame: serde_json::Value = serde_json::from_str(&plain_frame).unwrap();
+        assert_eq!(
+            compression::decode(frame).unwrap()["data"],
+            plain_frame["data"]
+        );
+
+        // Small replies are left alone.
+        let pong = exchange(&mut compressed, r#"{"type": "ping"}"#).await;
+        assert_eq!(pong["data"]["message"], "pong");
+    }
+
+    #[tokio::test]
+    async fn test_length_delimited_round_trip_with_newlines() {
+        use tokio_util::{bytes::Bytes, codec::LengthDelimitedCodec};
+
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let config = Arc::new(SocketServerConfig {
+            framing: config::Framing::LengthDelimited,
+            ..Default::default()
+        });
+        let (server, client) = UnixStream::pair().unwrap();
+        let (shutdown_tx, mut shutdown_rx) = tokio::sync::broadcast::channel(1);
+        let handler = tokio::spawn(async move {
+            let connection = ConnectionState::new(&config);
+            SocketServer::handle_client(server, manager, config, connection, &mut shutdown_rx).await
+        });
+
+        let mut client = Framed::new(client, LengthDelimitedCodec::new());
+        let request = "{\n  \"type\": \"ping\",\n  \"id\": \"multi-line\"\n}";
+        client.send(Bytes::from(request)).await.unwrap();
+
+        let frame = client.next().await.unwrap().unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&frame).unwrap();
+        assert_eq!(response["status"], "success");
+        assert_eq!(response["id"], "multi-line");
+        assert_eq!(response["data"]["message"], "pong");
+
+        shutdown_tx.send(()).unwrap();
+        handler.await.unwrap().unwrap();
+    }
+
+    async fn script_manager() -> ControllerManager {
+        script_manager_with(ManagerConfig::default()).await
+    }
+
+    async fn script_manager_with(config: ManagerConfig) -> ControllerManager {
+        slow_script_manager(config, std::time::Duration::ZERO).await
+    }
+
+    /// Like `script_manager_with`, with moves taking `move_delay`.
+    async fn slow_script_manager(
+        config: ManagerConfig,
+        move_delay: std::time::Duration,
+    ) -> ControllerManager {
+        let manager = ControllerManager::new(config);
+        let stage = ScriptedController::new("ctrl", &["X", "Y"]);
+        let control = stage.control();
+        for axis in ["X", "Y"] {
+            control.set_move_delay(axis, move_delay);
+        }
+        manager
+            .register_controller("ctrl".to_string(), Arc::new(stage))
+            .await
+            .unwrap();
+        manager
+    }
+
+    async fn position(manager: &ControllerManager, axis: &str) -> f64 {
+        let (tx, rx) = oneshot::channel();
+        manager
+            .send_command(Command::GetPos {
+                controller: "ctrl".to_string(),
+                axis: axis.to_string(),
+                fresh: false,
+                resp: tx,
+            })
+            .await
+            .unwrap();
+        rx.await.unwrap().unwrap()["position"].as_f64().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_replay_script_runs_commands_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("startup.jsonl");
+        let script = [
+            r#"{"type": "move", "controller": "ctrl", "axis": "X", "target": 3.0}"#,
+            "",
+            r#"{"type": "move", "controller": "ctrl", "axis": "X", "target": 5.0}"#,
+            r#"{"type": "move", "controller": "missing", "axis": "X", "target": 1.0}"#,
+            r#"{"type": "move", "controller": "ctrl", "axis": "Y", "target": -2.0}"#,
+        ];
+        std::fs::write(&path, script.join("\n")).unwrap();
+
+        let manager = script_manager().await;
+        let succeeded = SocketServer::replay_script(&path, &manager, false)
+            .await
+            .unwrap();
+        assert_eq!(succeeded, 3);
+        assert_eq!(position(&manager, "X").await, 5.0);
+        assert_eq!(position(&manager, "Y").await, -2.0);
+
+        let manager = script_manager().await;
+        let err = SocketServer::replay_script(&path, &manager, true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("line 4"));
+        assert_eq!(position(&manager, "Y").await, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_use_controller_sets_connection_default() {
+        let manager = script_manager().await;
+        let config = SocketServerConfig::default();
+        let mut connection = ConnectionState::new(&config);
+        let move_x = r#"{"type": "move", "axis": "X", "target": 4.0}"#;
+
+        let response = run(move_x, &manager, &config, &mut connection).await;
+        assert_eq!(error_code(&response), Some("MISSING_FIELD"));
+
+        let use_ctrl = r#"{"type": "use_controller", "controller": "ctrl"}"#;
+        let response = run(use_ctrl, &manager, &config, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+
+        let response = run(move_x, &manager, &config, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+        assert_eq!(position(&manager, "X").await, 4.0);
+
+        let get_pos = r#"{"type": "get_position", "axis": "X"}"#;
+        match run(get_pos, &manager, &config, &mut connection).await {
+            ServerResponse::Success { data, .. } => assert_eq!(data["position"], 4.0),
+            response => panic!("unexpected response: {:?}", response),
+        }
+
+        // The default belongs to the connection that set it.
+        let mut other = ConnectionState::new(&config);
+        let response = run(get_pos, &manager, &config, &mut other).await;
+        assert_eq!(error_code(&response), Some("MISSING_FIELD"));
+    }
+
+    fn subscribe_line(axis: &str) -> String {
+        format!(
+            r#"{{"type": "subscribe", "controller": "ctrl", "axis": "{}"}}"#,
+            axis
+        )
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_limited_per_connection() {
+        let manager = script_manager().await;
+        let config = SocketServerConfig {
+            max_subscriptions_per_connection: 2,
+            ..Default::default()
+        };
+        let mut connection = ConnectionState::new(&config);
+
+        for axis in ["X", "Y"] {
+            let response = run(&subscribe_line(axis), &manager, &config, &mut connection).await;
+            assert!(matches!(response, ServerResponse::Success { .. }));
+        }
+        let response = run(&subscribe_line("X"), &manager, &config, &mut connection).await;
+        assert_eq!(error_code(&response), Some("SUBSCRIPTION_LIMIT"));
+
+        let unsubscribe = r#"{"type": "unsubscribe", "subscription": 1}"#;
+        run(unsubscribe, &manager, &config, &mut connection).await;
+        assert_eq!(connection.subscriptions.len(), 1);
+        let response = run(&subscribe_line("X"), &manager, &config, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+
+        let response = run(&subscribe_line("Q"), &manager, &config, &mut connection).await;
+        assert!(matches!(response, ServerResponse::Error { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_limited_across_connections() {
+        let manager = script_manager_with(ManagerConfig {
+            max_subscriptions: Some(1),
+            ..Default::default()
+        })
+        .await;
+        let config = SocketServerConfig::default();
+        let mut first = ConnectionState::new(&config);
+        let mut second = ConnectionState::new(&config);
+
+        let response = run(&subscribe_line("X"), &manager, &config, &mut first).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+        let response = run(&subscribe_line("Y"), &manager, &config, &mut second).await;
+        assert_eq!(error_code(&response), Some("SUBSCRIPTION_LIMIT"));
+
+        // Disconnecting releases the slot once the subscription task ends.
+        drop(first);
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            loop {
+                let response = run(&subscribe_line("Y"), &manager, &config, &mut second).await;
+                if matches!(response, ServerResponse::Success { .. }) {
+                    break;
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_subscription_streams_axis_events() {
+        let manager = Arc::new(script_manager().await);
+        let mut client = PairedClient::connect(manager, SocketServerConfig::default()).unwrap();
+
+        let response = client.send_line(&subscribe_line("X")).await.unwrap();
+        assert_eq!(response["data"]["subscription"], 1);
+
+        let moves = r#"{"type": "move", "controller": "ctrl", "axis": "X", "target": 1.0}"#;
+        let mut frame = client.send_line(moves).await.unwrap();
+        let event = loop {
+            if frame["data"]["event"].is_string() {
+                break frame["data"].clone();
+            }
+            frame = client.recv().await.unwrap().unwrap();
+        };
+        assert_eq!(event["subscription"], 1);
+        assert_eq!(event["axis"], "X");
+        assert_eq!(event["event"], "state_changed");
+        assert_eq!(event["new"], "on");
+
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_binary_stream_takes_a_subscription_slot() {
+        let manager = script_manager_with(ManagerConfig {
+            max_subscriptions: Some(1),
+            ..Default::default()
+        })
+        .await;
+        let config = SocketServerConfig::default();
+        let mut streaming = ConnectionState::new(&config);
+        let mut other = ConnectionState::new(&config);
+
+        let line = r#"{"type": "subscribe_binary", "controller": "ctrl", "axes": ["X"]}"#;
+        let response = run(line, &manager, &config, &mut streaming).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+        let response = run(&subscribe_line("Y"), &manager, &config, &mut other).await;
+        assert_eq!(error_code(&response), Some("SUBSCRIPTION_LIMIT"));
+
+        drop(streaming);
+        let response = run(&subscribe_line("Y"), &manager, &config, &mut other).await;
+        assert!(matches!(response, ServerResponse::Success { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_binary_switches_to_frames() {
+        let manager = Arc::new(script_manager().await);
+        let config = Arc::new(SocketServerConfig::default());
+        let (server, client) = UnixStream::pair().unwrap();
+        let (_shutdown_tx, mut shutdown_rx) = tokio::sync::broadcast::channel(1);
+        let handler = tokio::spawn(async move {
+            let connection = ConnectionState::new(&config);
+            SocketServer::handle_client(server, manager, config, connection, &mut shutdown_rx).await
+        });
+
+        let mut client = Framed::new(client, tokio_util::codec::LinesCodec::new());
+        let line = r#"{"type": "subscribe_binary", "controller": "ctrl", "axes": ["Y", "X"], "interval_ms": 5}"#;
+        client.send(line.to_string()).await.unwrap();
+        let reply: serde_json::Value =
+            serde_json::from_str(&client.next().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["data"]["axes"], json!(["Y", "X"]));
+
+        let mut client = client.into_inner();
+        let mut bytes = [0; 2 * binary_stream::FRAME_SIZE];
+        tokio::io::AsyncReadExt::read_exact(&mut client, &mut bytes)
+            .await
+            .unwrap();
+        let ids: Vec<u16> = bytes
+            .chunks(binary_stream::FRAME_SIZE)
+            .map(|chunk| {
+                let frame = binary_stream::PositionFrame::decode(chunk).unwrap();
+                assert_eq!(frame.position, 0.0);
+                assert_eq!(frame.state, AxisState::On);
+                frame.axis_id
+            })
+            .collect();
+        assert_eq!(ids, [0, 1]);
+
+        drop(client);
+        handler.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_binary_resolves_axes_like_other_commands() {
+        let manager = script_manager_with(ManagerConfig {
+            case_insensitive_axes: true,
+            ..Default::default()
+        })
+        .await;
+        let config = SocketServerConfig::default();
+        let mut connection = ConnectionState::new(&config);
+
+        let line = r#"{"type": "subscribe_binary", "controller": "ctrl", "axes": ["y", "X"]}"#;
+        let response = run(line, &manager, &config, &mut connection).await;
+        let ServerResponse::Success { data, .. } = response else {
+            panic!("expected success, got {:?}", response);
+        };
+        assert_eq!(data["axes"], json!(["Y", "X"]));
+        assert_eq!(connection.binary_stream.unwrap().axes, ["Y", "X"]);
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_read_follows_move() {
+        let manager = slow_script_manager(
+            ManagerConfig::default(),
+            std::time::Duration::from_millis(50),
+        )
+        .await;
+        let mut client =
+            PairedClient::connect(Arc::new(manager), SocketServerConfig::default()).unwrap();
+
+        for target in [3.0, -4.5, 8.0] {
+            let replies = client
+                .pipeline(&[
+                    json!({"type": "move", "controller": "ctrl", "axis": "X", "target": target}),
+                    json!({"type": "get_position", "controller": "ctrl", "axis": "X"}),
+                ])
+                .await
+                .unwrap();
+            assert_eq!(replies[0]["status"], "success");
+            assert_eq!(replies[1]["data"]["position"], target);
+        }
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_socket_mode_applied_to_socket_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            socket_mode: Some(0o640),
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager);
+        server.start().await.unwrap();
+
+        let mode = std::fs::metadata(&socket_path)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o640);
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drop_removes_socket_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager);
+        server.start().await.unwrap();
+        assert!(socket_path.exists());
+
+        drop(server);
+        assert!(!socket_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_drop_after_shutdown_leaves_successor_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = || SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut old = SocketServer::new(config(), manager.clone());
+        old.start().await.unwrap();
+        old.shutdown().await.unwrap();
+
+        let mut successor = SocketServer::new(config(), manager);
+        successor.start().await.unwrap();
+        drop(old);
+        assert!(socket_path.exists());
+        UnixStream::connect(&socket_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_from_listener_serves_without_owning_socket_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("activated.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::from_listener(listener, config, manager);
+        server.start().await.unwrap();
+
+        let client = UnixStream::connect(&socket_path).await.unwrap();
+        let mut client = Framed::new(client, tokio_util::codec::LinesCodec::new());
+        client.send(r#"{"type": "ping"}"#).await.unwrap();
+        let line = client.next().await.unwrap().unwrap();
+        let frame: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(frame["status"], "success");
+
+        server.shutdown().await.unwrap();
+        drop(server);
+        assert!(socket_path.exists());
+    }
+
+    #[cfg(feature = "metrics-prometheus")]
+    #[tokio::test]
+    async fn test_prometheus_endpoint_counts_pings() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            metrics_addr: Some("127.0.0.1:0".parse().unwrap()),
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager);
+        server.start().await.unwrap();
+        let addr = server.metrics_addr().unwrap();
+
+        let scrape = || async move {
+            let mut http = tokio::net::TcpStream::connect(addr).await.unwrap();
+            http.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .await
+                .unwrap();
+            let mut response = String::new();
+            http.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = scrape().await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        for name in [
+            "motarem_commands_total",
+            "motarem_errors_total",
+            "motarem_cache_hits_total",
+            "motarem_cache_misses_total",
+            "motarem_active_connections",
+        ] {
+            assert!(response.contains(&format!("# TYPE {} ", name)), "{}", name);
+        }
+        assert!(!response.contains(r#"motarem_commands_total{kind="ping"}"#));
+
+        let client = UnixStream::connect(&socket_path).await.unwrap();
+        let mut client = Framed::new(client, tokio_util::codec::LinesCodec::new());
+        client.send(r#"{"type": "ping"}"#).await.unwrap();
+        client.next().await.unwrap().unwrap();
+
+        let response = scrape().await;
+        assert!(response.contains(r#"motarem_commands_total{kind="ping"} 1"#));
+        assert!(response.contains("motarem_active_connections 1"));
+
+        server.shutdown().await.unwrap();
+    }
+
+    /// Sends one line and parses the reply.
+    async fn exchange<S>(
+        client: &mut Framed<S, tokio_util::codec::LinesCodec>,
+        line: &str,
+    ) -> serde_json::Value
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        client.send(line).await.unwrap();
+        serde_json::from_str(&client.next().await.unwrap().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_admin_disconnects_another_connection() {
+        let (probe, _other) = UnixStream::pair().unwrap();
+        let uid = PeerIdentity::of(&probe).unwrap().uid;
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            admin_uids: Some(vec![uid]),
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager.clone());
+        server.start().await.unwrap();
+
+        let connect = || async {
+            let client = UnixStream::connect(&socket_path).await.unwrap();
+            Framed::new(client, tokio_util::codec::LinesCodec::new())
+        };
+        let mut admin = connect().await;
+        let mut victim = connect().await;
+        // Make sure both connections are being served before listing them.
+        for client in [&mut admin, &mut victim] {
+            client.send(r#"{"type": "ping"}"#).await.unwrap();
+            client.next().await.unwrap().unwrap();
+        }
+
+        let listing = exchange(&mut admin, r#"{"type": "list_connections"}"#).await;
+        assert_eq!(listing["data"]["count"], 2);
+        let current = listing["data"]["current"].as_u64().unwrap();
+        let victim_id = listing["data"]["connections"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|info| info["id"].as_u64().unwrap())
+            .find(|id| *id != current)
+            .unwrap();
+
+        let line = format!(
+            r#"{{"type": "disconnect", "connection_id": {}}}"#,
+            victim_id
+        );
+        let reply = exchange(&mut admin, &line).await;
+        assert_eq!(reply["data"]["disconnected"], true);
+        let closed = tokio::time::timeout(std::time::Duration::from_secs(1), victim.next()).await;
+        assert!(matches!(closed, Ok(None)));
+
+        let reply = exchange(&mut admin, &line).await;
+        assert_eq!(reply["code"], "CONNECTION_NOT_FOUND");
+
+        // Connections are only admins by uid.
+        let config = SocketServerConfig::default();
+        let mut connection = ConnectionState::new(&config);
+        let response = run(
+            r#"{"type": "list_connections"}"#,
+            &manager,
+            &config,
+            &mut connection,
+        )
+        .await;
+        assert_eq!(error_code(&response), Some("FORBIDDEN"));
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unattempted_motion_commands_are_not_remembered_for_retries() {
+        let manager = script_manager().await;
+        let config = SocketServerConfig {
+            idempotency_ttl: Some(std::time::Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let mut connection = ConnectionState::new(&config);
+        let line =
+            r#"{"type": "move", "controller": "ctrl", "axis": "X", "target": 4.0, "id": "m"}"#;
+
+        manager.stop().await;
+        let response = run(line, &manager, &config, &mut connection).await;
+        assert_eq!(error_code(&response), Some("MANAGER_DOWN"));
+        let cache = connection.idempotency.as_ref().unwrap();
+        assert!(cache.get("m").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retried_move_with_custom_params_is_replayed() {
+        let manager = script_manager().await;
+        let config = SocketServerConfig {
+            idempotency_ttl: Some(std::time::Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let mut connection = ConnectionState::new(&config);
+        let custom: serde_json::Map<String, serde_json::Value> =
+            (0..8).map(|n| (format!("p{}", n), json!(n))).collect();
+        let line = json!({
+            "type": "move",
+            "controller": "ctrl",
+            "axis": "X",
+            "target": 4.0,
+            "params": {"custom": custom},
+            "id": "m",
+        })
+        .to_string();
+
+        // Each parse seeds its `HashMap` afresh, so the keys come out in a
+        // different order most times.
+        let first = run(&line, &manager, &config, &mut connection).await;
+        for _ in 0..4 {
+            let retried = run(&line, &manager, &config, &mut connection).await;
+            assert_eq!(error_code(&retried), error_code(&first));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_subscriptions_counts_per_axis() {
+        let manager = script_manager().await;
+        let config = SocketServerConfig::default();
+        let registry = Arc::new(ConnectionRegistry::new(4));
+        let connect = || {
+            let (id, _) = registry.register("test".to_string(), registry.reserve().unwrap());
+            let mut connection = ConnectionState::new(&config);
+            connection.id = id;
+            connection.admin = true;
+            connection.registry = Some(registry.clone());
+            connection
+        };
+        let (mut first, mut second) = (connect(), connect());
+        let subscribe = |axis: &str| {
+            format!(
+                r#"{{"type": "subscribe", "controller": "ctrl", "axis": "{}"}}"#,
+                axis
+            )
+        };
+
+        for line in [subscribe("X"), subscribe("X"), subscribe("Y")] {
+            run(&line, &manager, &config, &mut first).await;
+        }
+        let response = run(&subscribe("X"), &manager, &config, &mut second).await;
+        let ServerResponse::Success { data, .. } = response else {
+            panic!("subscribe failed");
+        };
+        let line = format!(
+            r#"{{"type": "unsubscribe", "subscription": {}}}"#,
+            data["subscription"]
+        );
+        run(&line, &manager, &config, &mut second).await;
+        run(&subscribe("Y"), &manager, &config, &mut second).await;
+
+        let listing = run(
+            r#"{"type": "list_subscriptions"}"#,
+            &manager,
+            &config,
+            &mut first,
+        )
+        .await;
+        let listing = serde_json::to_value(&listing).unwrap();
+        assert_eq!(listing["data"]["total"], 4);
+        assert_eq!(
+            listing["data"]["axes"],
+            json!([
+                {"controller": "ctrl", "axis": "X", "count": 2, "connections": [first.id]},
+                {"controller": "ctrl", "axis": "Y", "count": 2, "connections": [first.id, second.id]},
+            ])
+        );
+
+        let mut outsider = ConnectionState::new(&config);
+        let response = run(
+            r#"{"type": "list_subscriptions"}"#,
+            &manager,
+            &config,
+            &mut outsider,
+        )
+        .await;
+        assert_eq!(error_code(&response), Some("FORBIDDEN"));
+    }
+
+    /// Whether the server answers a ping from `client` within `wait`.
+    async fn admitted<S>(
+        mut client: Framed<S, tokio_util::codec::LinesCodec>,
+        wait: std::time::Duration,
+    ) -> (bool, Framed<S, tokio_util::codec::LinesCodec>)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        client.send(r#"{"type": "ping"}"#).await.ok();
+        let reply = tokio::time::timeout(wait, client.next()).await;
+        (matches!(reply, Ok(Some(Ok(_)))), client)
+    }
+
+    #[tokio::test]
+    async fn test_unbounded_max_connections_is_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            max_connections: usize::MAX,
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager);
+        server.start().await.unwrap();
+
+        let client = UnixStream::connect(&socket_path).await.unwrap();
+        let mut client = Framed::new(client, tokio_util::codec::LinesCodec::new());
+        client
+            .send(r#"{"type": "ping"}"#.to_string())
+            .await
+            .unwrap();
+        assert!(client.next().await.unwrap().is_ok());
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_max_connections_holds_across_concurrent_accepts() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            tcp_addr: Some("127.0.0.1:0".parse().unwrap()),
+            max_connections: 3,
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager);
+        server.start().await.unwrap();
+        let tcp_addr = server.tcp_addr().unwrap();
+
+        // Both transports accept on their own tasks, so these race for the
+        // last slots. Served clients answer at once; the rest are left
+        // waiting, so the window only has to outlast a ping.
+        let wait = std::time::Duration::from_millis(500);
+        let unix = (0..16).map(|_| async {
+            let client = UnixStream::connect(&socket_path).await.unwrap();
+            admitted(
+                Framed::new(client, tokio_util::codec::LinesCodec::new()),
+                wait,
+            )
+            .await
+        });
+        let tcp = (0..16).map(|_| async move {
+            let client = tokio::net::TcpStream::connect(tcp_addr).await.unwrap();
+            admitted(
+                Framed::new(client, tokio_util::codec::LinesCodec::new()),
+                wait,
+            )
+            .await
+        });
+        let (unix, tcp) = tokio::join!(
+            futures::future::join_all(unix),
+            futures::future::join_all(tcp)
+        );
+        let served = unix.iter().filter(|(served, _)| *served).count()
+            + tcp.iter().filter(|(served, _)| *served).count();
+        assert_eq!(served, 3);
+
+        // Closing the clients gives their slots back.
+        drop((unix, tcp));
+        let mut served = false;
+        for _ in 0..50 {
+            let client = UnixStream::connect(&socket_path).await.unwrap();
+            let (ok, _client) = admitted(
+                Framed::new(client, tokio_util::codec::LinesCodec::new()),
+                wait,
+            )
+            .await;
+            if ok {
+                served = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert!(served);
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_idle_listener_leaves_slots_to_the_other_transport() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            tcp_addr: Some("127.0.0.1:0".parse().unwrap()),
+            max_connections: 2,
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager);
+        server.start().await.unwrap();
+        let tcp_addr = server.tcp_addr().unwrap();
+
+        // With one Unix client in, the Unix listener is waiting on its next
+        // accept; that must not tie up the slot left for TCP.
+        let unix = UnixStream::connect(&socket_path).await.unwrap();
+        let mut unix = Framed::new(unix, tokio_util::codec::LinesCodec::new());
+        exchange(&mut unix, r#"{"type": "ping"}"#).await;
+
+        let tcp = tokio::net::TcpStream::connect(tcp_addr).await.unwrap();
+        let (served, _tcp) = admitted(
+            Framed::new(tcp, tokio_util::codec::LinesCodec::new()),
+            std::time::Duration::from_secs(5),
+        )
+        .await;
+        assert!(served);
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_saturated_server_stops_accepting() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            max_connections: 1,
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager);
+        server.start().await.unwrap();
+
+        let first = UnixStream::connect(&socket_path).await.unwrap();
+        let mut first = Framed::new(first, tokio_util::codec::LinesCodec::new());
+        exchange(&mut first, r#"{"type": "ping"}"#).await;
+
+        // Clients past the cap are left waiting rather than accepted and
+        // closed over and over, so none of them sees its connection end.
+        let mut waiting = Vec::new();
+        for _ in 0..8 {
+            let client = UnixStream::connect(&socket_path).await.unwrap();
+            let mut client = Framed::new(client, tokio_util::codec::LinesCodec::new());
+            client.send(r#"{"type": "ping"}"#).await.unwrap();
+            waiting.push(client);
+        }
+        for client in &mut waiting {
+            let frame =
+                tokio::time::timeout(std::time::Duration::from_millis(50), client.next()).await;
+            assert!(frame.is_err(), "queued client got {:?}", frame);
+        }
+
+        // Freeing the slot serves the queue in order.
+        drop(first);
+        let reply = tokio::time::timeout(std::time::Duration::from_secs(5), waiting[0].next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["data"]["message"], "pong");
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_closing_mid_response_releases_its_slot() {
+        use tokio::io::AsyncWriteExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            max_connections: 1,
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager.clone());
+        server.start().await.unwrap();
+
+        let client = UnixStream::connect(&socket_path).await.unwrap();
+        let mut client = Framed::new(client, tokio_util::codec::LinesCodec::new());
+        exchange(&mut client, r#"{"type": "ping"}"#).await;
+        assert_eq!(manager.metrics().snapshot().active_connections, 1);
+
+        // Queue far more replies than the socket buffers, then hang up
+        // without reading any, so the server's writes fail part way.
+        let mut client = client.into_inner();
+        let burst = r#"{"type": "server_info"}"#.to_string() + "\n";
+        client
+            .write_all(burst.repeat(2000).as_bytes())
+            .await
+            .unwrap();
+        drop(client);
+
+        let mut closed = false;
+        for _ in 0..100 {
+            if manager.metrics().snapshot().active_connections == 0 {
+                closed = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert!(closed);
+
+        // The only slot is free again.
+        let client = UnixStream::connect(&socket_path).await.unwrap();
+        let mut client = Framed::new(client, tokio_util::codec::LinesCodec::new());
+        let reply = exchange(&mut client, r#"{"type": "ping"}"#).await;
+        assert_eq!(reply["status"], "success");
+
+        server.shutdown().await.unwrap();
+    }
+
+    /// An axis whose moves run until it is stopped, or for `move_time` when
+    /// set.
+    struct EndlessAxis {
+        name: String,
+        stops: std::sync::atomic::AtomicUsize,
+        move_time: Option<std::time::Duration>,
+    }
+
+    impl EndlessAxis {
+        fn new(name: &str) -> Self {
+            Self {
+                name: name.to_string(),
+                stops: Default::default(),
+                move_time: None,
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Axis for EndlessAxis {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        async fn start(&self, _target: f64, _params: Option<MovementParams>) -> Result<()> {
+            match self.move_time {
+                Some(move_time) => tokio::time::sleep(move_time).await,
+                None => std::future::pending().await,
+            }
+            Ok(())
+        }
+
+        async fn move_to_limit(
+            &self,
+            _direction: crate::axis::limits::LimitDirection,
+            _params: Option<MovementParams>,
+            _zero_position: bool,
+        ) -> Result<crate::axis::limit_switches::LimitSwitches> {
+            std::future::pending().await
+        }
+
+        async fn stop(&self) -> Result<()> {
+            self.stops.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn get_state(&self) -> Result<AxisStateInfo> {
+            Ok(AxisStateInfo::new(AxisState::On))
+        }
+
+        async fn get_attribute(&self, _name: &str) -> Result<f64> {
+            Ok(0.0)
+        }
+    }
+
+    struct EndlessController {
+        axes: Vec<Arc<EndlessAxis>>,
+    }
+
+    impl MotorController for EndlessController {
+        fn name(&self) -> &str {
+            "ctrl"
+        }
+
+        fn axes(&self) -> Vec<Arc<dyn Axis>> {
+            self.axes
+                .iter()
+                .map(|axis| axis.clone() as Arc<dyn Axis>)
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_on_disconnect_stops_only_that_clients_moves() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            stop_on_disconnect: true,
+            ..Default::default()
+        };
+        let axes: Vec<Arc<EndlessAxis>> = ["X", "Y"]
+            .into_iter()
+            .map(|name| Arc::new(EndlessAxis::new(name)))
+            .collect();
+        let server = endless_server(config, &axes).await;
+
+        let connect = || async {
+            let client = UnixStream::connect(&socket_path).await.unwrap();
+            Framed::new(client, tokio_util::codec::LinesCodec::new())
+        };
+        let (mut leaving, mut staying) = (connect().await, connect().await);
+        for (client, axis) in [(&mut leaving, "X"), (&mut staying, "Y")] {
+            let line = format!(
+                r#"{{"type": "move", "controller": "ctrl", "axis": "{}", "target": 1.0, "wait": false}}"#,
+                axis
+            );
+            assert_eq!(exchange(client, &line).await["status"], "success");
+        }
+
+        drop(leaving);
+        let stops = |axis: &EndlessAxis| axis.stops.load(std::sync::atomic::Ordering::SeqCst);
+        for _ in 0..100 {
+            if stops(&axes[0]) > 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(stops(&axes[0]), 1);
+        assert_eq!(stops(&axes[1]), 0);
+        let reply = exchange(&mut staying, r#"{"type": "ping"}"#).await;
+        assert_eq!(reply["status"], "success");
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stop_on_disconnect_covers_waited_moves_of_every_kind() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            stop_on_disconnect: true,
+            ..Default::default()
+        };
+        let axes: Vec<Arc<EndlessAxis>> = ["X", "Y", "Z"]
+            .into_iter()
+            .map(|name| Arc::new(EndlessAxis::new(name)))
+            .collect();
+        let server = endless_server(config, &axes).await;
+
+        let connect = || async {
+            let client = UnixStream::connect(&socket_path).await.unwrap();
+            Framed::new(client, tokio_util::codec::LinesCodec::new())
+        };
+        let (mut all, mut limit, mut staying) = (connect().await, connect().await, connect().await);
+        all.send(r#"{"type": "move_all", "axis_pattern": "ctrl::X", "target": 1.0}"#)
+            .await
+            .unwrap();
+        limit
+            .send(r#"{"type": "move_to_limit", "controller": "ctrl", "axis": "Y", "direction": "upper"}"#)
+            .await
+            .unwrap();
+        // A command pipelined behind a waited move still gets its turn.
+        staying
+            .send(r#"{"type": "move_all", "axis_pattern": "ctrl::Z", "target": 1.0}"#)
+            .await
+            .unwrap();
+        staying
+            .send(r#"{"type": "ping", "id": "after"}"#)
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // Neither move ever finishes, so only seeing the hang-up while they
+        // run can stop them.
+        drop((all, limit));
+        let stops = |axis: &EndlessAxis| axis.stops.load(std::sync::atomic::Ordering::SeqCst);
+        for _ in 0..100 {
+            if stops(&axes[0]) > 0 && stops(&axes[1]) > 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(stops(&axes[0]), 1);
+        assert_eq!(stops(&axes[1]), 1);
+        assert_eq!(stops(&axes[2]), 0);
+
+        let mut other = connect().await;
+        let reply = exchange(
+            &mut other,
+            r#"{"type": "cancel", "controller": "ctrl", "axis": "Z"}"#,
+        )
+        .await;
+        assert_eq!(reply["data"]["cancelled"], true);
+        let moved: serde_json::Value =
+            serde_json::from_str(&staying.next().await.unwrap().unwrap()).unwrap();
+        assert_eq!(moved["data"]["results"][0]["code"], "CANCELLED");
+        let pong: serde_json::Value =
+            serde_json::from_str(&staying.next().await.unwrap().unwrap()).unwrap();
+        assert_eq!(pong["id"], "after");
+
+        server.shutdown().await.unwrap();
+    }
+
+    /// Starts a server with `config` for a controller `ctrl` of `axes`.
+    async fn endless_server(config: SocketServerConfig, axes: &[Arc<EndlessAxis>]) -> SocketServer {
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        manager
+            .register_controller(
+                "ctrl".to_string(),
+                Arc::new(EndlessController {
+                    axes: axes.to_vec(),
+                }),
+            )
+            .await
+            .unwrap();
+        let mut server = SocketServer::new(config, manager);
+        server.start().await.unwrap();
+        server
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_lets_running_commands_finish() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            shutdown_grace: std::time::Duration::from_secs(10),
+            ..Default::default()
+        };
+        let axis = Arc::new(EndlessAxis {
+            move_time: Some(std::time::Duration::from_millis(300)),
+            ..EndlessAxis::new("X")
+        });
+        let server = endless_server(config, &[axis]).await;
+
+        let connect = || async {
+            let client = UnixStream::connect(&socket_path).await.unwrap();
+            Framed::new(client, tokio_util::codec::LinesCodec::new())
+        };
+        let (mut moving, mut idle) = (connect().await, connect().await);
+        exchange(&mut idle, r#"{"type": "ping"}"#).await;
+        moving
+            .send(
+                r#"{"type": "move", "controller": "ctrl", "axis": "X", "target": 1.0, "id": "m"}"#,
+            )
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let started = Instant::now();
+        let shutdown = tokio::spawn(async move { server.shutdown().await });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // New commands are refused on the connections still open...
+        let refused = exchange(&mut idle, r#"{"type": "ping", "id": "p"}"#).await;
+        assert_eq!(refused["code"], "SERVER_SHUTTING_DOWN");
+        assert_eq!(refused["id"], "p");
+        // ...and new connections aren't served.
+        assert!(tokio::time::timeout(
+            std::time::Duration::from_millis(100),
+            UnixStream::connect(&socket_path)
+        )
+        .await
+        .map_or(true, |client| client.is_err()));
+
+        // The running move gets its reply before its connection closes.
+        let reply: serde_json::Value =
+            serde_json::from_str(&moving.next().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["status"], "success");
+        assert_eq!(reply["id"], "m");
+        shutdown.await.unwrap().unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert!(moving.next().await.is_none());
+        assert!(idle.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_connections_after_the_grace_period() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            shutdown_grace: std::time::Duration::from_millis(100),
+            ..Default::default()
+        };
+        let server = endless_server(config, &[Arc::new(EndlessAxis::new("X"))]).await;
+
+        let client = UnixStream::connect(&socket_path).await.unwrap();
+        let mut client = Framed::new(client, tokio_util::codec::LinesCodec::new());
+        client
+            .send(r#"{"type": "move", "controller": "ctrl", "axis": "X", "target": 1.0}"#)
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), server.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+        let closed = tokio::time::timeout(std::time::Duration::from_secs(5), client.next())
+            .await
+            .unwrap();
+        assert!(closed.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tcp_transport_serves_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SocketServerConfig {
+            socket_path: dir
+                .path()
+                .join("motarem.sock")
+                .to_str()
+                .unwrap()
+                .to_string(),
+            tcp_addr: Some("127.0.0.1:0".parse().unwrap()),
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager);
+        server.start().await.unwrap();
+
+        let client = tokio::net::TcpStream::connect(server.tcp_addr().unwrap())
+            .await
+            .unwrap();
+        let mut client = Framed::new(client, tokio_util::codec::LinesCodec::new());
+        client.send(r#"{"type": "ping"}"#).await.unwrap();
+        let line = client.next().await.unwrap().unwrap();
+        let frame: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(frame["status"], "success");
+
+        client.send(r#"{"type": "server_info"}"#).await.unwrap();
+        let line = client.next().await.unwrap().unwrap();
+        let frame: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(frame["data"]["transport"]["type"], "tcp");
+        assert_eq!(frame["data"]["transport"]["tls"], false);
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_tcp_bind_leaves_no_socket_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let taken = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            tcp_addr: Some(taken.local_addr().unwrap()),
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager);
+
+        assert!(server.start().await.is_err());
+        assert!(!socket_path.exists());
+    }
+
+    // The fixtures are a throwaway CA and a certificate it issued for
+    // `localhost`, both valid for a century.
+    #[cfg(feature = "tls")]
+    fn testdata(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/socket_server/testdata")
+            .join(name)
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_tls_round_trip() {
+        use tokio_rustls::{
+            rustls::{pki_types::ServerName, ClientConfig, RootCertStore},
+            TlsConnector,
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = SocketServerConfig {
+            socket_path: dir
+                .path()
+                .join("motarem.sock")
+                .to_str()
+                .unwrap()
+                .to_string(),
+            tcp_addr: Some("127.0.0.1:0".parse().unwrap()),
+            tls: Some(config::TlsConfig {
+                cert_path: testdata("server.pem"),
+                key_path: testdata("server.key"),
+            }),
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager);
+        server.start().await.unwrap();
+
+        let mut roots = RootCertStore::empty();
+        let ca = std::fs::File::open(testdata("ca.pem")).unwrap();
+        for cert in rustls_pemfile::certs(&mut std::io::BufReader::new(ca)) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let connector = TlsConnector::from(Arc::new(
+            ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        ));
+        let tcp = tokio::net::TcpStream::connect(server.tcp_addr().unwrap())
+            .await
+            .unwrap();
+        let tls = connector
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+            .unwrap();
+
+        let mut client = Framed::new(tls, tokio_util::codec::LinesCodec::new());
+        client.send(r#"{"type": "ping"}"#).await.unwrap();
+        let line = client.next().await.unwrap().unwrap();
+        let frame: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(frame["status"], "success");
+
+        client.send(r#"{"type": "server_info"}"#).await.unwrap();
+        let line = client.next().await.unwrap().unwrap();
+        let frame: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(frame["data"]["transport"]["tls"], true);
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_stalled_tls_handshake_is_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SocketServerConfig {
+            socket_path: dir
+                .path()
+                .join("motarem.sock")
+                .to_str()
+                .unwrap()
+                .to_string(),
+            tcp_addr: Some("127.0.0.1:0".parse().unwrap()),
+            tls: Some(config::TlsConfig {
+                cert_path: testdata("server.pem"),
+                key_path: testdata("server.key"),
+            }),
+            tls_handshake_timeout: std::time::Duration::from_millis(100),
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager);
+        server.start().await.unwrap();
+
+        // Connects but never starts the handshake.
+        let mut stalled = tokio::net::TcpStream::connect(server.tcp_addr().unwrap())
+            .await
+            .unwrap();
+        let mut buf = [0; 1];
+        let read = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            tokio::io::AsyncReadExt::read(&mut stalled, &mut buf),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(read, Ok(0) | Err(_)));
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_start_fails_on_missing_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            tcp_addr: Some("127.0.0.1:0".parse().unwrap()),
+            tls: Some(config::TlsConfig {
+                cert_path: dir.path().join("missing.pem"),
+                key_path: testdata("server.key"),
+            }),
+            ..Default::default()
+        };
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        let mut server = SocketServer::new(config, manager);
+
+        let err = server.start().await.unwrap_err().to_string();
+        assert!(err.contains("missing.pem"), "{}", err);
+        assert!(!socket_path.exists());
+    }
+}
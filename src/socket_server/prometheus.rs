@@ -0,0 +1,117 @@
+//! Minimal HTTP endpoint exposing the manager's metrics in the Prometheus
+//! text format. It answers `GET /metrics` and nothing else, one request per
+//! connection, so no HTTP stack is needed.
+
+use std::{fmt::Write as _, sync::Arc};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+use tracing::{debug, error};
+
+use crate::controller_manager::{metrics::MetricsSnapshot, ControllerManager};
+
+/// Largest request head read before giving up on the request.
+const MAX_REQUEST: usize = 8192;
+
+/// Accepts scrapes until the server shuts down.
+pub async fn serve(
+    listener: TcpListener,
+    manager: Arc<ControllerManager>,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) {
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    let manager = manager.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = respond(stream, &manager).await {
+                            debug!("Metrics request failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("Failed to accept metrics connection: {}", e),
+            },
+            _ = shutdown_rx.recv() => break,
+        }
+    }
+}
+
+async fn respond(mut stream: TcpStream, manager: &ControllerManager) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request_line = request.split(|&b| b == b'\r').next().unwrap_or_default();
+    let response = if request_line.starts_with(b"GET /metrics ") {
+        let body = render(&manager.metrics().snapshot());
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Formats a snapshot in the Prometheus text exposition format.
+pub fn render(snapshot: &MetricsSnapshot) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, u64)>| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "{}{} {}", name, labels, value);
+        }
+    };
+    let by_kind = |counts: &std::collections::BTreeMap<String, u64>| {
+        counts
+            .iter()
+            .map(|(kind, count)| (format!("{{kind=\"{}\"}}", kind), *count))
+            .collect()
+    };
+
+    metric(
+        "motarem_commands_total",
+        "counter",
+        "Commands handled, by kind.",
+        by_kind(&snapshot.commands_by_kind),
+    );
+    metric(
+        "motarem_errors_total",
+        "counter",
+        "Commands that failed, by kind.",
+        by_kind(&snapshot.errors_by_kind),
+    );
+    metric(
+        "motarem_cache_hits_total",
+        "counter",
+        "Reads answered from the cache.",
+        vec![(String::new(), snapshot.cache_hits)],
+    );
+    metric(
+        "motarem_cache_misses_total",
+        "counter",
+        "Reads that went to the hardware.",
+        vec![(String::new(), snapshot.cache_misses)],
+    );
+    metric(
+        "motarem_active_connections",
+        "gauge",
+        "Socket clients currently connected.",
+        vec![(String::new(), snapshot.active_connections)],
+    );
+    out
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::{oneshot, watch, OwnedSemaphorePermit, Semaphore};
+
+/// A live client connection, as listed by `list_connections`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    /// The peer's credentials on the Unix socket, or its address over TCP.
+    pub peer: String,
+    pub connected_at: DateTime<Utc>,
+}
+
+/// The socket subscriptions to one axis, as listed by
+/// `list_subscriptions`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AxisSubscriptions {
+    pub controller: String,
+    pub axis: String,
+    pub count: usize,
+    /// The subscribed connections, each listed once however many
+    /// subscriptions it holds.
+    pub connections: Vec<u64>,
+}
+
+/// Every connection the server is serving, shared by all transports so
+/// that `max_connections` caps the total. Ids are handed out in accept
+/// order and never reused.
+pub struct ConnectionRegistry {
+    next_id: AtomicU64,
+    connections: Mutex<HashMap<u64, Entry>>,
+    /// One permit per connection the server may serve at once. A connection
+    /// holds its permit until it is removed, so the cap can't be overshot
+    /// by accepts racing on different transports.
+    slots: Arc<Semaphore>,
+    /// Set once the server has begun shutting down; see `begin_shutdown`.
+    shutting_down: watch::Sender<bool>,
+    /// Commands being handled across all connections; see
+    /// `command_started`.
+    running: Arc<watch::Sender<usize>>,
+}
+
+/// Counts a command as running until dropped.
+pub struct RunningCommand {
+    running: Arc<watch::Sender<usize>>,
+}
+
+impl Drop for RunningCommand {
+    fn drop(&mut self) {
+        self.running.send_modify(|running| *running -= 1);
+    }
+}
+
+struct Entry {
+    info: ConnectionInfo,
+    /// Fires the connection's disconnect; taken by the first `disconnect`.
+    disconnect: Option<oneshot::Sender<()>>,
+    /// The connection's subscriptions, by id, with their controller and
+    /// axis.
+    subscriptions: HashMap<u64, (String, String)>,
+    _slot: OwnedSemaphorePermit,
+}
+
+impl ConnectionRegistry {
+    /// `max_connections` above `Semaphore::MAX_PERMITS`, which would make
+    /// the semaphore panic, is lowered to it; that many is unlimited in
+    /// practice anyway.
+    pub fn new(max_connections: usize) -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            connections: Mutex::new(HashMap::new()),
+            slots: Arc::new(Semaphore::new(max_connections.min(Semaphore::MAX_PERMITS))),
+            shutting_down: watch::Sender::new(false),
+            running: Arc::new(watch::Sender::new(0)),
+        }
+    }
+
+    /// Marks the server as shutting down, after which the listeners stop
+    /// accepting and connections refuse new commands with
+    /// `SERVER_SHUTTING_DOWN`. Commands already running are left to finish.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.send_replace(true);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutting_down.borrow()
+    }
+
+    /// Resolves once `begin_shutdown` has been called.
+    pub async fn shutdown_begun(&self) {
+        let mut shutting_down = self.shutting_down.subscribe();
+        // The sender lives as long as `self`, so this can't fail.
+        let _ = shutting_down.wait_for(|shutting_down| *shutting_down).await;
+    }
+
+    /// Counts a command as running until the returned guard is dropped, so
+    /// that shutdown can wait for it.
+    pub fn command_started(&self) -> RunningCommand {
+        self.running.send_modify(|running| *running += 1);
+        RunningCommand {
+            running: self.running.clone(),
+        }
+    }
+
+    /// Resolves once no command is running on any connection.
+    pub async fn drained(&self) {
+        let mut running = self.running.subscribe();
+        let _ = running.wait_for(|running| *running == 0).await;
+    }
+
+    /// Claims room for one more connection, or `None` when the server is
+    /// full. Dropping the slot without registering gives it back.
+    pub fn reserve(&self) -> Option<OwnedSemaphorePermit> {
+        self.slots.clone().try_acquire_owned().ok()
+    }
+
+    /// Like `reserve`, waiting for a connection to close if the server is
+    /// full.
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.slots
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("connection slots are never closed")
+    }
+
+    /// Adds a connection in the `slot` reserved for it, which is released
+    /// when the connection is removed. The receiver resolves once it is
+    /// `disconnect`ed.
+    pub fn register(
+        &self,
+        peer: String,
+        slot: OwnedSemaphorePermit,
+    ) -> (u64, oneshot::Receiver<()>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let (tx, rx) = oneshot::channel();
+        let info = ConnectionInfo {
+            id,
+            peer,
+            connected_at: Utc::now(),
+        };
+        self.connections.lock().unwrap().insert(
+            id,
+            Entry {
+                info,
+                disconnect: Some(tx),
+                subscriptions: HashMap::new(),
+                _slot: slot,
+            },
+        );
+        (id, rx)
+    }
+
+    /// Drops a connection that has closed, returning how many remain.
+    pub fn remove(&self, id: u64) -> usize {
+        let mut connections = self.connections.lock().unwrap();
+        connections.remove(&id);
+        connections.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.connections.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The live connections, oldest first.
+    pub fn list(&self) -> Vec<ConnectionInfo> {
+        let mut list: Vec<ConnectionInfo> = self
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.info.clone())
+            .collect();
+        list.sort_by_key(|info| info.id);
+        list
+    }
+
+    /// Records that connection `id` subscribed to an axis, so that
+    /// `subscriptions` can list it until `unsubscribed` or the connection
+    /// is removed.
+    pub fn subscribed(&self, id: u64, subscription: u64, controller: &str, axis: &str) {
+        if let Some(entry) = self.connections.lock().unwrap().get_mut(&id) {
+            entry
+                .subscriptions
+                .insert(subscription, (controller.to_string(), axis.to_string()));
+        }
+    }
+
+    pub fn unsubscribed(&self, id: u64, subscription: u64) {
+        if let Some(entry) = self.connections.lock().unwrap().get_mut(&id) {
+            entry.subscriptions.remove(&subscription);
+        }
+    }
+
+    /// Every subscribed axis across all connections, by controller and
+    /// axis name.
+    pub fn subscriptions(&self) -> Vec<AxisSubscriptions> {
+        let mut by_axis: BTreeMap<(String, String), (usize, Vec<u64>)> = BTreeMap::new();
+        for (id, entry) in self.connections.lock().unwrap().iter() {
+            for key in entry.subscriptions.values() {
+                let (count, connections) = by_axis.entry(key.clone()).or_default();
+                *count += 1;
+                if !connections.contains(id) {
+                    connections.push(*id);
+                }
+            }
+        }
+        by_axis
+            .into_iter()
+            .map(|((controller, axis), (count, mut connections))| {
+                connections.sort_unstable();
+                AxisSubscriptions {
+                    controller,
+                    axis,
+                    count,
+                    connections,
+                }
+            })
+            .collect()
+    }
+
+    /// Closes connection `id`, returning false if there is no such
+    /// connection or it is already being closed. The connection stays
+    /// listed until its handler has finished.
+    pub fn disconnect(&self, id: u64) -> bool {
+        let disconnect = self
+            .connections
+            .lock()
+            .unwrap()
+            .get_mut(&id)
+            .and_then(|entry| entry.disconnect.take());
+        disconnect.is_some_and(|tx| tx.send(()).is_ok())
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use tokio::{
+    sync::{broadcast, mpsc, OwnedSemaphorePermit},
+    task::AbortHandle,
+};
+
+use crate::{
+    controller_manager::event::AxisEvent,
+    protocol::{error::CommandError, server_response::ServerResponse},
+};
+
+/// How many event frames may queue up for a connection before the
+/// subscription tasks wait for the client to catch up.
+const FRAME_BUFFER: usize = 64;
+
+struct Subscription {
+    task: AbortHandle,
+}
+
+/// The subscriptions of one connection. Each one is a task forwarding the
+/// matching axis events into a queue that `handle_client` drains onto the
+/// socket. Dropping this ends every task, which also releases their slots
+/// in the manager's global limit.
+pub struct Subscriptions {
+    limit: usize,
+    next_id: u64,
+    active: HashMap<u64, Subscription>,
+    frames_tx: mpsc::Sender<ServerResponse>,
+    frames_rx: mpsc::Receiver<ServerResponse>,
+}
+
+impl Subscriptions {
+    pub fn new(limit: usize) -> Self {
+        let (frames_tx, frames_rx) = mpsc::channel(FRAME_BUFFER);
+        Self {
+            limit,
+            next_id: 1,
+            active: HashMap::new(),
+            frames_tx,
+            frames_rx,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.active.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+
+    /// Fails with `SUBSCRIPTION_LIMIT` once the connection holds its
+    /// `max_subscriptions_per_connection`.
+    pub fn check_capacity(&self) -> Result<(), CommandError> {
+        if self.active.len() >= self.limit {
+            return Err(CommandError::new(
+                "SUBSCRIPTION_LIMIT",
+                format!("Connection subscription limit of {} reached", self.limit),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Starts forwarding events for one axis and returns the subscription
+    /// id. `slot` is held for as long as the subscription lives.
+    pub fn add(
+        &mut self,
+        controller: String,
+        axis: String,
+        mut events: broadcast::Receiver<AxisEvent>,
+        slot: Option<OwnedSemaphorePermit>,
+    ) -> Result<u64, CommandError> {
+        self.check_capacity()?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let frames = self.frames_tx.clone();
+        let task = tokio::spawn(async move {
+            let _slot = slot;
+            loop {
+                let data = match events.recv().await {
+                    Ok(event) if event.controller == controller && event.axis == axis => {
+                        event_frame(id, &event)
+                    }
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        json!({"subscription": id, "lagged": missed})
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if frames
+                    .send(ServerResponse::success(None, data))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        self.active.insert(
+            id,
+            Subscription {
+                task: task.abort_handle(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Ends a subscription, returning whether it existed.
+    pub fn remove(&mut self, id: u64) -> bool {
+        match self.active.remove(&id) {
+            Some(subscription) => {
+                subscription.task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The next event frame to send to the client. Never resolves while the
+    /// connection has no subscriptions.
+    pub async fn next_frame(&mut self) -> ServerResponse {
+        match self.frames_rx.recv().await {
+            Some(frame) => frame,
+            // Unreachable: `frames_tx` is held here, so the queue never closes.
+            None => std::future::pending().await,
+        }
+    }
+}
+
+impl Drop for Subscriptions {
+    fn drop(&mut self) {
+        for subscription in self.active.values() {
+            subscription.task.abort();
+        }
+    }
+}
+
+fn event_frame(id: u64, event: &AxisEvent) -> Value {
+    let mut data = serde_json::to_value(event).unwrap_or_else(|_| json!({}));
+    data["subscription"] = json!(id);
+    data
+}
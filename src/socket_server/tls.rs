@@ -0,0 +1,86 @@
+use std::{
+    fs::File,
+    future::Future,
+    io::{self, BufReader},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::{
+    rustls::{
+        pki_types::{CertificateDer, PrivateKeyDer},
+        ServerConfig,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+use super::config::TlsConfig;
+
+/// Loads the certificate chain and key, failing with an error naming the
+/// file at fault.
+pub fn acceptor(config: &TlsConfig) -> Result<TlsAcceptor> {
+    let certs = load_certs(&config.cert_path)?;
+    let key = load_key(&config.key_path)?;
+    let server_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "TLS certificate {} doesn't match key {}: {}",
+                config.cert_path.display(),
+                config.key_path.display(),
+                e
+            )
+        })?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Runs the server side of the handshake on `stream`, giving up with
+/// `TimedOut` after `timeout` so a client that connects and says nothing
+/// doesn't keep its connection slot.
+pub fn handshake<S>(
+    acceptor: &TlsAcceptor,
+    stream: S,
+    timeout: Duration,
+) -> impl Future<Output = io::Result<TlsStream<S>>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let accept = acceptor.accept(stream);
+    async move {
+        tokio::time::timeout(timeout, accept)
+            .await
+            .unwrap_or_else(|_| {
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "TLS handshake timed out",
+                ))
+            })
+    }
+}
+
+fn open(path: &Path, what: &str) -> Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| anyhow::anyhow!("Failed to open TLS {} {}: {}", what, path.display(), e))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut open(path, "certificate")?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("Invalid TLS certificate {}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificate found in {}", path.display());
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    rustls_pemfile::private_key(&mut open(path, "key")?)
+        .map_err(|e| anyhow::anyhow!("Invalid TLS key {}: {}", path.display(), e))?
+        .ok_or_else(|| anyhow::anyhow!("No private key found in {}", path.display()))
+}
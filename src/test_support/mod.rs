@@ -0,0 +1,501 @@
+//! A controller whose behavior is scripted by the test driving it, for
+//! deterministic tests of the server and of integrations built on it.
+//!
+//! Moves finish instantly unless a delay is set, attribute values are
+//! injected, and faults are raised on demand, all through the
+//! `MockControl` handle of a `ScriptedController`:
+//!
+//! ```
+//! use std::{sync::Arc, time::Duration};
+//!
+//! use motarem::{
+//!     controller_manager::{config::ManagerConfig, ControllerManager},
+//!     test_support::ScriptedController,
+//! };
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> anyhow::Result<()> {
+//! let stage = ScriptedController::new("stage", &["X", "Y"]);
+//! let control = stage.control();
+//! control.set_move_delay("Y", Duration::from_millis(50));
+//! control.set_attribute("X", "temperature", 41.5);
+//!
+//! let manager = ControllerManager::new(ManagerConfig::default());
+//! manager.register_controller("stage".to_string(), Arc::new(stage)).await?;
+//! manager.handle().move_to("stage", "X", 3.0, None).await?;
+//! assert_eq!(control.position("X"), 3.0);
+//!
+//! control.fail_next_move("X", "following error");
+//! assert!(manager.handle().move_to("stage", "X", 4.0, None).await.is_err());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `pair::PairedClient` serves a connection over a socket pair, for tests
+//! of the protocol that shouldn't depend on a socket file.
+
+pub mod pair;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use tokio::sync::Notify;
+
+use crate::{
+    axis::{
+        movement_parameters::MovementParams, state::AxisState, state_info::AxisStateInfo, Axis,
+    },
+    motor_controller::MotorController,
+};
+
+/// A controller of `ScriptedAxis`es. Everything about it can be changed
+/// while it is registered, through `control`.
+pub struct ScriptedController {
+    name: String,
+    axes: Vec<Arc<ScriptedAxis>>,
+}
+
+impl ScriptedController {
+    pub fn new(name: &str, axes: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            axes: axes
+                .iter()
+                .map(|axis| Arc::new(ScriptedAxis::new(axis)))
+                .collect(),
+        }
+    }
+
+    /// A handle for scripting the axes, which stays valid after the
+    /// controller has been handed to a manager.
+    pub fn control(&self) -> MockControl {
+        MockControl {
+            axes: Arc::new(
+                self.axes
+                    .iter()
+                    .map(|axis| (axis.name.clone(), axis.clone()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl MotorController for ScriptedController {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn axes(&self) -> Vec<Arc<dyn Axis>> {
+        self.axes
+            .iter()
+            .map(|axis| axis.clone() as Arc<dyn Axis>)
+            .collect()
+    }
+}
+
+/// Scripts the axes of a `ScriptedController`. Cheap to clone; every
+/// method panics on an axis the controller doesn't have.
+#[derive(Clone)]
+pub struct MockControl {
+    axes: Arc<HashMap<String, Arc<ScriptedAxis>>>,
+}
+
+impl MockControl {
+    fn axis(&self, name: &str) -> &ScriptedAxis {
+        self.axes
+            .get(name)
+            .unwrap_or_else(|| panic!("No scripted axis named {}", name))
+    }
+
+    /// How long each move of the axis takes; zero, the default, finishes
+    /// moves instantly. A stop ends a delayed move where it is.
+    pub fn set_move_delay(&self, axis: &str, delay: Duration) {
+        self.axis(axis).script().move_delay = delay;
+    }
+
+    /// Injects an attribute value, adding the attribute to those the axis
+    /// lists if it is new.
+    pub fn set_attribute(&self, axis: &str, name: &str, value: f64) {
+        self.axis(axis)
+            .script()
+            .attributes
+            .insert(name.to_string(), value);
+    }
+
+    /// Injects an array-valued attribute, read with `get_attribute_array`,
+    /// adding it to those the axis lists if it is new.
+    pub fn set_attribute_array(&self, axis: &str, name: &str, values: &[f64]) {
+        self.axis(axis)
+            .script()
+            .arrays
+            .insert(name.to_string(), values.to_vec());
+    }
+
+    /// Faults the axis now, as if the drive had tripped.
+    pub fn fault(&self, axis: &str, message: &str) {
+        let axis = self.axis(axis);
+        let mut script = axis.script();
+        script.state = AxisState::Fault;
+        script.fault = Some(message.to_string());
+        drop(script);
+        axis.interrupted.notify_waiters();
+    }
+
+    /// Makes the next move of the axis fault instead of moving.
+    pub fn fail_next_move(&self, axis: &str, message: &str) {
+        self.axis(axis).script().fail_next_move = Some(message.to_string());
+    }
+
+    pub fn set_state(&self, axis: &str, state: AxisState) {
+        self.axis(axis).script().state = state;
+    }
+
+    pub fn state(&self, axis: &str) -> AxisState {
+        self.axis(axis).script().state
+    }
+
+    pub fn position(&self, axis: &str) -> f64 {
+        self.axis(axis).script().position
+    }
+
+    /// Targets of every move the axis has started, oldest first.
+    pub fn moves(&self, axis: &str) -> Vec<f64> {
+        self.axis(axis).script().moves.clone()
+    }
+}
+
+/// An axis that does what its `MockControl` says. It supports enabling,
+/// disabling, fault resets and position references, besides moving.
+pub struct ScriptedAxis {
+    name: String,
+    script: std::sync::Mutex<Script>,
+    /// Wakes a delayed move when the axis is stopped or faulted.
+    interrupted: Notify,
+}
+
+struct Script {
+    position: f64,
+    state: AxisState,
+    fault: Option<String>,
+    move_delay: Duration,
+    fail_next_move: Option<String>,
+    attributes: HashMap<String, f64>,
+    arrays: HashMap<String, Vec<f64>>,
+    moves: Vec<f64>,
+    /// When the move in progress started, for its progress.
+    move_started: Option<std::time::Instant>,
+}
+
+impl ScriptedAxis {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            script: std::sync::Mutex::new(Script {
+                position: 0.0,
+                state: AxisState::On,
+                fault: None,
+                move_delay: Duration::ZERO,
+                fail_next_move: None,
+                attributes: HashMap::new(),
+                arrays: HashMap::new(),
+                moves: Vec::new(),
+                move_started: None,
+            }),
+            interrupted: Notify::new(),
+        }
+    }
+
+    fn script(&self) -> std::sync::MutexGuard<'_, Script> {
+        self.script.lock().unwrap()
+    }
+}
+
+#[async_trait::async_trait]
+impl Axis for ScriptedAxis {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn start(&self, target: f64, _params: Option<MovementParams>) -> Result<()> {
+        let mut interrupted = std::pin::pin!(self.interrupted.notified());
+        let delay = {
+            let mut script = self.script();
+            script.moves.push(target);
+            match script.state {
+                AxisState::Disabled => anyhow::bail!("Axis {} is disabled", self.name),
+                AxisState::Fault | AxisState::Alarm => {
+                    anyhow::bail!("Axis {} is faulted", self.name)
+                }
+                _ => {}
+            }
+            if let Some(message) = script.fail_next_move.take() {
+                script.state = AxisState::Fault;
+                script.fault = Some(message.clone());
+                anyhow::bail!("Axis {} faulted: {}", self.name, message);
+            }
+            script.state = AxisState::Moving;
+            script.move_started = Some(std::time::Instant::now());
+            // Registered before the lock is released, so a stop that comes
+            // in right after can't be missed.
+            interrupted.as_mut().enable();
+            script.move_delay
+        };
+
+        if !delay.is_zero() {
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = interrupted => return Ok(()),
+            }
+        }
+
+        let mut script = self.script();
+        if script.state == AxisState::Moving {
+            script.position = target;
+            script.state = AxisState::On;
+        }
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        let mut script = self.script();
+        if script.state == AxisState::Moving {
+            script.state = AxisState::On;
+        }
+        drop(script);
+        self.interrupted.notify_waiters();
+        Ok(())
+    }
+
+    async fn enable(&self) -> Result<()> {
+        let mut script = self.script();
+        if script.state == AxisState::Disabled {
+            script.state = AxisState::On;
+        }
+        Ok(())
+    }
+
+    async fn disable(&self) -> Result<()> {
+        self.script().state = AxisState::Disabled;
+        self.interrupted.notify_waiters();
+        Ok(())
+    }
+
+    async fn reset_fault(&self) -> Result<()> {
+        let mut script = self.script();
+        if !matches!(script.state, AxisState::Fault | AxisState::Alarm) {
+            anyhow::bail!("Axis {} is not faulted", self.name);
+        }
+        script.state = AxisState::On;
+        script.fault = None;
+        Ok(())
+    }
+
+    async fn set_position_reference(&self, value: f64) -> Result<()> {
+        self.script().position = value;
+        Ok(())
+    }
+
+    async fn get_state(&self) -> Result<AxisStateInfo> {
+        let script = self.script();
+        let info = AxisStateInfo::new(script.state);
+        Ok(match &script.fault {
+            Some(message) => info.with_message(message.clone()),
+            None => info,
+        })
+    }
+
+    /// How much of the move delay has passed, while a delayed move runs.
+    async fn get_progress(&self) -> Option<f64> {
+        let script = self.script();
+        let started = script.move_started?;
+        if script.state != AxisState::Moving || script.move_delay.is_zero() {
+            return None;
+        }
+        Some((started.elapsed().as_secs_f64() / script.move_delay.as_secs_f64()).min(1.0))
+    }
+
+    async fn get_attribute(&self, name: &str) -> Result<f64> {
+        let script = self.script();
+        if name == "position" {
+            return Ok(script.position);
+        }
+        script
+            .attributes
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Unknown attribute {} on axis {}", name, self.name))
+    }
+
+    async fn get_attribute_array(&self, name: &str) -> Result<Vec<f64>> {
+        self.script().arrays.get(name).cloned().ok_or_else(|| {
+            anyhow::anyhow!("Unknown array attribute {} on axis {}", name, self.name)
+        })
+    }
+
+    /// Writes any attribute but `position`, as `MockControl::set_attribute`
+    /// would.
+    async fn set_attribute(&self, name: &str, value: f64) -> Result<()> {
+        anyhow::ensure!(name != "position", "Attribute position is read-only");
+        self.script().attributes.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    async fn get_available_params(&self) -> Result<Vec<String>> {
+        let script = self.script();
+        let mut names: Vec<String> = script
+            .attributes
+            .keys()
+            .chain(script.arrays.keys())
+            .cloned()
+            .collect();
+        names.sort();
+        names.insert(0, "position".to_string());
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        controller_manager::{config::ManagerConfig, ControllerManager},
+        socket_server::{config::SocketServerConfig, SocketServer},
+    };
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{json, Value};
+    use tokio::net::UnixStream;
+    use tokio_util::codec::{Framed, LinesCodec};
+
+    /// A running server over a scripted controller named "stage", and a
+    /// client connected to it.
+    async fn serve(
+        axes: &[&str],
+    ) -> (
+        SocketServer,
+        MockControl,
+        Framed<UnixStream, LinesCodec>,
+        tempfile::TempDir,
+    ) {
+        let stage = ScriptedController::new("stage", axes);
+        let control = stage.control();
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        manager
+            .register_controller("stage".to_string(), Arc::new(stage))
+            .await
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("motarem.sock");
+        let config = SocketServerConfig {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let mut server = SocketServer::new(config, manager);
+        server.start().await.unwrap();
+        let client = Framed::new(
+            UnixStream::connect(&socket_path).await.unwrap(),
+            LinesCodec::new(),
+        );
+        (server, control, client, dir)
+    }
+
+    async fn send(client: &mut Framed<UnixStream, LinesCodec>, command: Value) -> Value {
+        client.send(command.to_string()).await.unwrap();
+        serde_json::from_str(&client.next().await.unwrap().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_scripted_faults_and_attributes_over_the_socket() {
+        let (server, control, mut client, _dir) = serve(&["X"]).await;
+        control.set_attribute("X", "temperature", 41.5);
+
+        let moved = send(
+            &mut client,
+            json!({"type": "move", "controller": "stage", "axis": "X", "target": 5.0}),
+        )
+        .await;
+        assert_eq!(moved["status"], "success");
+        assert_eq!(control.position("X"), 5.0);
+
+        let temperature = send(
+            &mut client,
+            json!({"type": "get_attribute", "controller": "stage", "axis": "X", "attribute": "temperature"}),
+        )
+        .await;
+        assert_eq!(temperature["data"]["value"], 41.5);
+
+        control.fault("X", "overcurrent");
+        let state = send(
+            &mut client,
+            json!({"type": "get_state", "controller": "stage", "axis": "X", "fresh": true}),
+        )
+        .await;
+        assert_eq!(state["data"]["status"]["state"], "fault");
+        assert_eq!(state["data"]["status"]["message"], "overcurrent");
+        let refused = send(
+            &mut client,
+            json!({"type": "move", "controller": "stage", "axis": "X", "target": 6.0}),
+        )
+        .await;
+        assert_eq!(refused["code"], "AXIS_NOT_READY");
+
+        let reset = send(
+            &mut client,
+            json!({"type": "reset_fault", "controller": "stage", "axis": "X"}),
+        )
+        .await;
+        assert_eq!(reset["status"], "success");
+        assert_eq!(control.moves("X"), [5.0]);
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scripted_delay_lets_a_stop_interrupt_the_move() {
+        let (server, control, mut client, _dir) = serve(&["X"]).await;
+        control.set_move_delay("X", Duration::from_secs(60));
+
+        let started = send(
+            &mut client,
+            json!({"type": "move", "controller": "stage", "axis": "X", "target": 5.0, "wait": false}),
+        )
+        .await;
+        assert_eq!(started["status"], "success");
+        assert_eq!(control.state("X"), AxisState::Moving);
+
+        let stopped = send(
+            &mut client,
+            json!({"type": "stop", "controller": "stage", "axis": "X"}),
+        )
+        .await;
+        assert_eq!(stopped["data"]["was_moving"], true);
+        assert_eq!(control.state("X"), AxisState::On);
+        assert_eq!(control.position("X"), 0.0);
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_stop_right_after_start_ends_the_move() {
+        let stage = ScriptedController::new("stage", &["X"]);
+        let control = stage.control();
+        control.set_move_delay("X", Duration::from_secs(60));
+        let axis = stage.axes().remove(0);
+
+        for _ in 0..100 {
+            let moving = tokio::spawn({
+                let axis = axis.clone();
+                async move { axis.start(5.0, None).await }
+            });
+            while control.state("X") != AxisState::Moving {
+                tokio::task::yield_now().await;
+            }
+            axis.stop().await.unwrap();
+            tokio::time::timeout(Duration::from_secs(1), moving)
+                .await
+                .expect("stop was missed")
+                .unwrap()
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,486 @@
+//! A server connection served in-process over a socket pair, so tests can
+//! exercise the whole protocol without binding a socket file:
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use motarem::{
+//!     controller_manager::{config::ManagerConfig, ControllerManager},
+//!     socket_server::config::SocketServerConfig,
+//!     test_support::pair::PairedClient,
+//! };
+//! use serde_json::json;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> anyhow::Result<()> {
+//! let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+//! let mut client = PairedClient::connect(manager, SocketServerConfig::default())?;
+//!
+//! let pong = client.send(json!({"type": "ping"})).await?;
+//! assert_eq!(pong["data"]["message"], "pong");
+//! client.close().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use futures::{SinkExt, StreamExt};
+use serde_json::Value;
+use tokio::{net::UnixStream, sync::broadcast, task::JoinHandle};
+use tokio_util::codec::{Framed, LinesCodec};
+
+use crate::{
+    controller_manager::ControllerManager,
+    socket_server::{config::SocketServerConfig, connection::ConnectionState, SocketServer},
+};
+
+/// The client end of a socket pair whose other end is handled exactly as
+/// the server handles an accepted connection. Speaks JSON lines, so
+/// `config` should keep the default framing; tests of other framings or of
+/// binary frames drive `SocketServer::handle_client` themselves.
+pub struct PairedClient {
+    framed: Framed<UnixStream, LinesCodec>,
+    shutdown: broadcast::Sender<()>,
+    handler: JoinHandle<Result<()>>,
+}
+
+impl PairedClient {
+    /// Starts serving a new connection to `manager`. Needs a tokio runtime.
+    pub fn connect(manager: Arc<ControllerManager>, config: SocketServerConfig) -> Result<Self> {
+        let (server, client) = UnixStream::pair()?;
+        let (shutdown, mut shutdown_rx) = broadcast::channel(1);
+        let config = Arc::new(config);
+        let handler = tokio::spawn(async move {
+            let connection = ConnectionState::new(&config);
+            SocketServer::handle_client(server, manager, config, connection, &mut shutdown_rx).await
+        });
+        Ok(Self {
+            framed: Framed::new(client, LinesCodec::new()),
+            shutdown,
+            handler,
+        })
+    }
+
+    /// Sends `command` and returns the next frame, its reply unless the
+    /// connection has other frames queued, such as subscription events.
+    pub async fn send(&mut self, command: Value) -> Result<Value> {
+        self.send_line(&command.to_string()).await
+    }
+
+    /// Like `send`, for a line that needn't be valid JSON.
+    pub async fn send_line(&mut self, line: &str) -> Result<Value> {
+        self.framed.send(line).await?;
+        self.recv()
+            .await?
+            .context("Connection closed before replying")
+    }
+
+    /// Writes all of `commands` before reading any reply, then returns the
+    /// next frame for each, in order.
+    pub async fn pipeline(&mut self, commands: &[Value]) -> Result<Vec<Value>> {
+        for command in commands {
+            self.framed.feed(command.to_string()).await?;
+        }
+        SinkExt::<String>::flush(&mut self.framed).await?;
+        let mut replies = Vec::with_capacity(commands.len());
+        for _ in commands {
+            replies.push(
+                self.recv()
+                    .await?
+                    .context("Connection closed before replying")?,
+            );
+        }
+        Ok(replies)
+    }
+
+    /// The next frame the server sends, or `None` once it has closed the
+    /// connection.
+    pub async fn recv(&mut self) -> Result<Option<Value>> {
+        match self.framed.next().await {
+            Some(line) => Ok(Some(serde_json::from_str(&line?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Shuts the connection down as a stopping server would, returning how
+    /// its handler finished.
+    pub async fn close(self) -> Result<()> {
+        let _ = self.shutdown.send(());
+        self.handler.await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        axis::state::AxisState,
+        controller_manager::config::ManagerConfig,
+        test_support::{MockControl, ScriptedController},
+    };
+    use serde_json::json;
+
+    async fn stage_client() -> (PairedClient, MockControl) {
+        let stage = ScriptedController::new("stage", &["X"]);
+        let control = stage.control();
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        manager
+            .register_controller("stage".to_string(), Arc::new(stage))
+            .await
+            .unwrap();
+        let client = PairedClient::connect(manager, SocketServerConfig::default()).unwrap();
+        (client, control)
+    }
+
+    #[tokio::test]
+    async fn test_paired_ping() {
+        let (mut client, _) = stage_client().await;
+        let pong = client
+            .send(json!({"type": "ping", "id": "1"}))
+            .await
+            .unwrap();
+        assert_eq!(pong["status"], "success");
+        assert_eq!(pong["id"], "1");
+        assert_eq!(pong["data"]["message"], "pong");
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_paired_move() {
+        let (mut client, control) = stage_client().await;
+        let moved = client
+            .send(json!({"type": "move", "controller": "stage", "axis": "X", "target": 7.5}))
+            .await
+            .unwrap();
+        assert_eq!(moved["status"], "success");
+        assert_eq!(control.moves("X"), [7.5]);
+
+        let position = client
+            .send(json!({"type": "get_position", "controller": "stage", "axis": "X"}))
+            .await
+            .unwrap();
+        assert_eq!(position["data"]["position"], 7.5);
+        client.close().await.unwrap();
+    }
+
+    /// The ordering contract documented in `controller_manager::command`: a
+    /// read pipelined behind a move on the same axis sees the finished move.
+    #[tokio::test]
+    async fn test_streamed_move_reports_progress_then_one_reply() {
+        let stage = ScriptedController::new("stage", &["X"]);
+        stage
+            .control()
+            .set_move_delay("X", std::time::Duration::from_millis(200));
+        let manager = Arc::new(ControllerManager::new(ManagerConfig {
+            poll_interval: Some(std::time::Duration::from_millis(10)),
+            ..Default::default()
+        }));
+        manager
+            .register_controller("stage".to_string(), Arc::new(stage))
+            .await
+            .unwrap();
+        let mut client = PairedClient::connect(manager, SocketServerConfig::default()).unwrap();
+
+        let mut frame = client
+            .send(json!({
+                "type": "move", "controller": "stage", "axis": "X", "target": 2.0,
+                "stream_progress": true, "id": "m1",
+            }))
+            .await
+            .unwrap();
+        let mut fractions = Vec::new();
+        while frame["data"]["event"] == "progress" {
+            assert_eq!(frame["id"], "m1");
+            fractions.push(frame["data"]["fraction"].as_f64().unwrap());
+            frame = client.recv().await.unwrap().unwrap();
+        }
+        assert!(!fractions.is_empty());
+        assert_eq!(frame["id"], "m1");
+        assert_eq!(frame["status"], "success");
+        assert_eq!(frame["data"]["action"], "move");
+
+        // The reply ended the stream: the next frame answers the next command.
+        let pong = client.send(json!({"type": "ping"})).await.unwrap();
+        assert_eq!(pong["data"]["message"], "pong");
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_streamed_move_takes_a_subscription_slot() {
+        let stage = ScriptedController::new("stage", &["X"]);
+        let manager = Arc::new(ControllerManager::new(ManagerConfig {
+            max_subscriptions: Some(1),
+            ..Default::default()
+        }));
+        manager
+            .register_controller("stage".to_string(), Arc::new(stage))
+            .await
+            .unwrap();
+        let mut watcher =
+            PairedClient::connect(manager.clone(), SocketServerConfig::default()).unwrap();
+        let mut mover = PairedClient::connect(manager, SocketServerConfig::default()).unwrap();
+
+        let subscribed = watcher
+            .send(json!({"type": "subscribe", "controller": "stage", "axis": "X"}))
+            .await
+            .unwrap();
+        assert_eq!(subscribed["status"], "success");
+        let refused = mover
+            .send(json!({
+                "type": "move", "controller": "stage", "axis": "X", "target": 2.0,
+                "stream_progress": true,
+            }))
+            .await
+            .unwrap();
+        assert_eq!(refused["code"], "SUBSCRIPTION_LIMIT");
+
+        watcher.close().await.unwrap();
+        mover.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_attribute_changes_reach_other_clients() {
+        let stage = ScriptedController::new("stage", &["X"]);
+        stage.control().set_attribute("X", "velocity", 5.0);
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        manager
+            .register_controller("stage".to_string(), Arc::new(stage))
+            .await
+            .unwrap();
+        let mut watcher =
+            PairedClient::connect(manager.clone(), SocketServerConfig::default()).unwrap();
+        let mut tuner = PairedClient::connect(manager, SocketServerConfig::default()).unwrap();
+
+        let subscribed = watcher
+            .send(json!({"type": "subscribe", "controller": "stage", "axis": "X"}))
+            .await
+            .unwrap();
+        let set = tuner
+            .send(json!({
+                "type": "set_attribute", "controller": "stage", "axis": "X",
+                "attribute": "velocity", "value": 12.5,
+            }))
+            .await
+            .unwrap();
+        assert_eq!(set["status"], "success");
+
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), watcher.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            event["data"]["subscription"],
+            subscribed["data"]["subscription"]
+        );
+        assert_eq!(event["data"]["event"], "attribute_changed");
+        assert_eq!(event["data"]["attribute"], "velocity");
+        assert_eq!(event["data"]["value"], 12.5);
+        watcher.close().await.unwrap();
+        tuner.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_array_attribute_round_trips_and_caches() {
+        let (mut client, control) = stage_client().await;
+        control.set_attribute_array("X", "phase_currents", &[0.5, -1.25, 0.75]);
+        let read = json!({
+            "type": "get_attribute_array", "controller": "stage", "axis": "X",
+            "attribute": "phase_currents",
+        });
+
+        let first = client.send(read.clone()).await.unwrap();
+        assert_eq!(first["status"], "success");
+        assert_eq!(first["data"]["value"], json!([0.5, -1.25, 0.75]));
+
+        // Served from the cache, not the axis.
+        control.set_attribute_array("X", "phase_currents", &[9.0]);
+        let second = client.send(read).await.unwrap();
+        assert_eq!(second["data"]["value"], first["data"]["value"]);
+
+        let scalar = client
+            .send(json!({
+                "type": "get_attribute", "controller": "stage", "axis": "X",
+                "attribute": "phase_currents",
+            }))
+            .await
+            .unwrap();
+        assert_eq!(scalar["status"], "error");
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reachable_range_within_soft_limits() {
+        let (mut client, control) = stage_client().await;
+        let read = json!({
+            "type": "reachable_range", "controller": "stage", "axis": "X", "time_ms": 1500,
+        });
+
+        assert_eq!(
+            client.send(read.clone()).await.unwrap()["code"],
+            "NO_LIMITS"
+        );
+        control.set_attribute("X", "min_position", -5.0);
+        control.set_attribute("X", "max_position", 100.0);
+        assert_eq!(
+            client.send(read.clone()).await.unwrap()["code"],
+            "NO_VELOCITY"
+        );
+
+        control.set_attribute("X", "velocity", 10.0);
+        let reply = client.send(read.clone()).await.unwrap();
+        assert_eq!(reply["data"]["min"], -5.0);
+        assert_eq!(reply["data"]["max"], 15.0);
+
+        // 0.5 s ramping up to 10 covers 2.5, then 1 s at full speed.
+        control.set_attribute("X", "acceleration", 20.0);
+        let reply = client.send(read.clone()).await.unwrap();
+        assert_eq!(reply["data"]["min"], -5.0);
+        assert_eq!(reply["data"]["max"], 12.5);
+        client.close().await.unwrap();
+    }
+
+    async fn confirming_client(ttl: std::time::Duration) -> (PairedClient, MockControl) {
+        let stage = ScriptedController::new("stage", &["X"]);
+        let control = stage.control();
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        manager
+            .register_controller("stage".to_string(), Arc::new(stage))
+            .await
+            .unwrap();
+        let config = SocketServerConfig {
+            confirm_token_ttl: Some(ttl),
+            ..Default::default()
+        };
+        (PairedClient::connect(manager, config).unwrap(), control)
+    }
+
+    #[tokio::test]
+    async fn test_prepared_reset_fault_runs_once() {
+        let (mut client, control) = confirming_client(std::time::Duration::from_secs(5)).await;
+        control.fault("X", "overcurrent");
+        let reset = json!({"type": "reset_fault", "controller": "stage", "axis": "X"});
+
+        let prepared = client
+            .send(json!({"type": "prepare", "command": reset}))
+            .await
+            .unwrap();
+        assert_eq!(prepared["data"]["command"], "reset_fault");
+        let token = prepared["data"]["confirm_token"].clone();
+
+        let mut confirmed = reset.clone();
+        confirmed["confirm_token"] = token;
+        let done = client.send(confirmed.clone()).await.unwrap();
+        assert_eq!(done["status"], "success");
+        assert_eq!(control.state("X"), AxisState::On);
+
+        // Tokens are single-use.
+        let replayed = client.send(confirmed).await.unwrap();
+        assert_eq!(replayed["code"], "CONFIRM_TOKEN_INVALID");
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unconfirmed_destructive_commands_are_refused() {
+        let (mut client, control) = confirming_client(std::time::Duration::from_millis(20)).await;
+        control.fault("X", "overcurrent");
+
+        let missing = client
+            .send(json!({"type": "stop_controller", "controller": "stage"}))
+            .await
+            .unwrap();
+        assert_eq!(missing["code"], "CONFIRMATION_REQUIRED");
+
+        let prepared = client
+            .send(json!({
+                "type": "prepare",
+                "command": {"type": "reset_fault", "controller": "stage", "axis": "X"},
+            }))
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let expired = client
+            .send(json!({
+                "type": "reset_fault", "controller": "stage", "axis": "X",
+                "confirm_token": prepared["data"]["confirm_token"],
+            }))
+            .await
+            .unwrap();
+        assert_eq!(expired["code"], "CONFIRM_TOKEN_INVALID");
+        assert_eq!(control.state("X"), AxisState::Fault);
+
+        // Everything else runs as before.
+        let position = client
+            .send(json!({"type": "get_position", "controller": "stage", "axis": "X"}))
+            .await
+            .unwrap();
+        assert_eq!(position["status"], "success");
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retried_move_runs_once() {
+        let stage = ScriptedController::new("stage", &["X"]);
+        let control = stage.control();
+        let manager = Arc::new(ControllerManager::new(ManagerConfig::default()));
+        manager
+            .register_controller("stage".to_string(), Arc::new(stage))
+            .await
+            .unwrap();
+        let config = SocketServerConfig {
+            idempotency_ttl: Some(std::time::Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let mut client = PairedClient::connect(manager, config).unwrap();
+        let move_to = |target: f64, id: &str| json!({"type": "move", "controller": "stage", "axis": "X", "target": target, "id": id});
+
+        let first = client.send(move_to(5.0, "m1")).await.unwrap();
+        assert_eq!(first["status"], "success");
+        let retried = client.send(move_to(5.0, "m1")).await.unwrap();
+        assert_eq!(retried["data"], first["data"]);
+        assert_eq!(control.moves("X"), [5.0]);
+        let reused = client.send(move_to(7.0, "m1")).await.unwrap();
+        assert_eq!(reused["code"], "IDEMPOTENCY_MISMATCH");
+        assert_eq!(control.moves("X"), [5.0]);
+
+        client.send(move_to(6.0, "m2")).await.unwrap();
+        assert_eq!(control.moves("X"), [5.0, 6.0]);
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_move_echoes_axis_message() {
+        let (mut client, control) = stage_client().await;
+        control.fail_next_move("X", "following error");
+        let failed = client
+            .send(json!({"type": "move", "controller": "stage", "axis": "X", "target": 1.0}))
+            .await
+            .unwrap();
+        assert_eq!(failed["code"], "MOVE_FAILED");
+        assert_eq!(failed["details"]["axis_message"], "following error");
+        client.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_paired_errors_keep_the_connection_open() {
+        let (mut client, _) = stage_client().await;
+        let missing = client
+            .send(json!({"type": "move", "controller": "stage", "axis": "Z", "target": 1.0}))
+            .await
+            .unwrap();
+        assert_eq!(missing["status"], "error");
+        assert!(missing["message"]
+            .as_str()
+            .unwrap()
+            .contains("Axis not found: Z"));
+
+        let garbled = client.send_line(r#"{"type": "ping""#).await.unwrap();
+        assert_eq!(garbled["code"], "PARSE_ERROR");
+
+        let pong = client.send(json!({"type": "ping"})).await.unwrap();
+        assert_eq!(pong["status"], "success");
+        client.close().await.unwrap();
+    }
+}